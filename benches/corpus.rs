@@ -0,0 +1,72 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use libc::{c_char, c_int, c_void};
+use lindera::dictionary::{load_dictionary_from_kind, DictionaryKind};
+use lindera::mode::Mode;
+use lindera::segmenter::Segmenter;
+use lindera::tokenizer::Tokenizer;
+
+use lindera_sqlite::{lindera_fts5_tokenize, Fts5Tokenizer, SQLITE_OK};
+
+/// 60KB Wikipedia excerpts per language, fetched by
+/// `scripts/fetch-bench-corpus.sh`; see `corpus/SOURCES` for their articles
+/// and license.
+const CORPORA: [(&str, DictionaryKind, &str); 3] = [
+    ("ja", DictionaryKind::IPADIC, include_str!("corpus/ja.txt")),
+    (
+        "zh",
+        DictionaryKind::CcCedict,
+        include_str!("corpus/zh.txt"),
+    ),
+    ("ko", DictionaryKind::KoDic, include_str!("corpus/ko.txt")),
+];
+
+extern "C" fn count_callback(
+    ctx: *mut c_void,
+    _flags: c_int,
+    _token: *const c_char,
+    _token_len: c_int,
+    _start: c_int,
+    _end: c_int,
+) -> c_int {
+    unsafe { *(ctx as *mut usize) += 1 };
+    SQLITE_OK
+}
+
+fn tokenize(tokenizer: &mut Fts5Tokenizer, input: &str) -> usize {
+    let mut count = 0;
+    lindera_fts5_tokenize(
+        tokenizer,
+        &mut count as *mut usize as *mut c_void,
+        0,
+        input.as_bytes().as_ptr() as *const c_char,
+        input.len() as i32,
+        count_callback,
+    );
+    count
+}
+
+fn corpus_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize corpus 60kb");
+
+    for (lang, kind, input) in CORPORA {
+        // Languages whose dictionary is not included are skipped.
+        let Ok(dictionary) = load_dictionary_from_kind(kind) else {
+            continue;
+        };
+        let mut tokenizer = Fts5Tokenizer::new(Tokenizer::new(Segmenter::new(
+            Mode::Normal,
+            dictionary,
+            None,
+        )));
+
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_function(lang, |b| {
+            b.iter(|| tokenize(&mut tokenizer, black_box(input)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, corpus_benchmark);
+criterion_main!(benches);
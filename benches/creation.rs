@@ -0,0 +1,87 @@
+use core::ptr::null_mut;
+use std::sync::Arc;
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use libc::{c_char, c_int, c_void};
+
+use lindera_sqlite::{lindera_fts5_tokenize, load_tokenizer, Fts5Tokenizer, SQLITE_OK};
+
+extern "C" fn noop_callback(
+    _ctx: *mut c_void,
+    _flags: c_int,
+    _token: *const c_char,
+    _token_len: c_int,
+    _start: c_int,
+    _end: c_int,
+) -> c_int {
+    SQLITE_OK
+}
+
+fn tokenize(tokenizer: &mut Fts5Tokenizer, input: &str) {
+    lindera_fts5_tokenize(
+        tokenizer,
+        null_mut(),
+        0,
+        input.as_bytes().as_ptr() as *const c_char,
+        input.len() as i32,
+        noop_callback,
+    );
+}
+
+fn creation_benchmark(c: &mut Criterion) {
+    let document = "Ｌｉｎｄｅｒａは形態素解析ｴﾝｼﾞﾝです。ユーザー辞書も利用可能です。";
+
+    c.bench_function("create tokenizer per document", |b| {
+        b.iter(|| {
+            let mut tokenizer =
+                Fts5Tokenizer::new(load_tokenizer().expect("Failed to load tokenizer"));
+            tokenize(&mut tokenizer, black_box(document));
+        })
+    });
+
+    let mut tokenizer = Fts5Tokenizer::new(load_tokenizer().expect("Failed to load tokenizer"));
+    c.bench_function("reuse tokenizer", |b| {
+        b.iter(|| tokenize(&mut tokenizer, black_box(document)))
+    });
+
+    let shared = Arc::new(load_tokenizer().expect("Failed to load tokenizer"));
+    c.bench_function("clone shared tokenizer per document", |b| {
+        b.iter(|| {
+            let mut tokenizer = Fts5Tokenizer::new((*shared).clone());
+            tokenize(&mut tokenizer, black_box(document));
+        })
+    });
+}
+
+/// Compares building a tokenizer from the configuration with getting one from
+/// the cache behind `fts5_create_lindera_tokenizer` once it has been built.
+fn bench_tokenizer_creation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bench_tokenizer_creation");
+    group.measurement_time(Duration::from_secs(30));
+
+    group.bench_function("cold", |b| {
+        b.iter(|| black_box(load_tokenizer().expect("Failed to load tokenizer")))
+    });
+
+    #[cfg(feature = "extension")]
+    group.bench_function("warm", |b| {
+        use lindera_sqlite::{fts5_create_lindera_tokenizer, fts5_delete_lindera_tokenizer};
+
+        let create = || {
+            let mut tokenizer = null_mut::<Fts5Tokenizer>();
+            let rc =
+                fts5_create_lindera_tokenizer(null_mut(), core::ptr::null(), 0, &mut tokenizer);
+            assert_eq!(rc, SQLITE_OK, "Failed to create tokenizer");
+            tokenizer
+        };
+        fts5_delete_lindera_tokenizer(create());
+
+        b.iter(|| fts5_delete_lindera_tokenizer(black_box(create())))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, creation_benchmark, bench_tokenizer_creation);
+criterion_main!(benches);
@@ -0,0 +1,83 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    // Expose the enabled cargo features to `lindera_sqlite_api_info`.
+    let mut features = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect::<Vec<_>>();
+    features.sort();
+
+    println!(
+        "cargo:rustc-env=LINDERA_SQLITE_FEATURES={}",
+        features.join(",")
+    );
+
+    // Expose the version of the Lindera it is built with to `lindera_info()`,
+    // from the lock file of the build. lindera-sqlite is released in step
+    // with Lindera, so its own version stands in without one.
+    let lindera_version =
+        lindera_version().unwrap_or_else(|| env::var("CARGO_PKG_VERSION").unwrap());
+    println!("cargo:rustc-env=LINDERA_VERSION={}", lindera_version);
+
+    // Export the extension's entry points from the DLL explicitly, as
+    // `#[no_mangle]` alone does not guarantee it on every MSVC setup.
+    println!("cargo:rerun-if-changed=lindera_sqlite.def");
+    if env::var("CARGO_CFG_TARGET_ENV").as_deref() == Ok("msvc")
+        && env::var_os("CARGO_FEATURE_EXTENSION").is_some()
+    {
+        let def = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("lindera_sqlite.def");
+        let out = Path::new(&env::var("OUT_DIR").unwrap()).join("lindera_sqlite.def");
+        fs::write(&out, enabled_exports(&fs::read_to_string(def).unwrap())).unwrap();
+        println!("cargo:rustc-link-arg-cdylib=/DEF:{}", out.display());
+    }
+}
+
+/// The module definition `def` without the exports of features that are not
+/// enabled, which the linker would fail to find. Such exports are marked
+/// with a comment such as `; feature = "ipadic"`.
+fn enabled_exports(def: &str) -> String {
+    def.lines()
+        .filter_map(|line| match line.split_once(';') {
+            Some((export, comment)) => {
+                let feature = comment
+                    .trim()
+                    .strip_prefix("feature = \"")?
+                    .strip_suffix('"')?;
+                let key = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+                env::var_os(key).map(|_| export.trim_end())
+            }
+            None => Some(line),
+        })
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// The version of `lindera` in the `Cargo.lock` of the package or of the
+/// workspace the build output is in.
+fn lindera_version() -> Option<String> {
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR")?);
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR")?);
+    for dir in manifest_dir.ancestors().chain(out_dir.ancestors()) {
+        let lock_file = dir.join("Cargo.lock");
+        let Ok(lock) = fs::read_to_string(&lock_file) else {
+            continue;
+        };
+        println!("cargo:rerun-if-changed={}", lock_file.display());
+        let mut lines = lock.lines();
+        while let Some(line) = lines.next() {
+            if line == "name = \"lindera\"" {
+                return lines
+                    .next()?
+                    .strip_prefix("version = \"")?
+                    .strip_suffix('"')
+                    .map(str::to_string);
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,41 @@
+//! Feeds arbitrary `az_arg`/`n_arg` pairs to `TokenizerArgs::parse`.
+//!
+//! The input is a little-endian `i32` used as `n_arg`, followed by the
+//! arguments separated by nul bytes. `n_arg` may be zero, negative or larger
+//! than the number of arguments; the pointer array is padded with nulls so
+//! that it always covers `n_arg` entries, as FTS5 guarantees, and
+//! AddressSanitizer reports any read past it.
+
+#![no_main]
+
+use std::ffi::CString;
+
+use libfuzzer_sys::fuzz_target;
+use lindera_sqlite::TokenizerArgs;
+
+/// Bounds the pointer array built for large `n_arg` values.
+const MAX_N_ARG: i32 = 1 << 16;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((n_arg, rest)) = data.split_first_chunk::<4>() else {
+        return;
+    };
+    let n_arg = i32::from_le_bytes(*n_arg).min(MAX_N_ARG);
+
+    let args = rest
+        .split(|&byte| byte == 0)
+        .map(|arg| CString::new(arg).unwrap())
+        .collect::<Vec<_>>();
+    let mut az_arg = args
+        .iter()
+        .map(|arg| arg.as_ptr() as *const u8)
+        .collect::<Vec<_>>();
+    if n_arg > 0 && az_arg.len() < n_arg as usize {
+        az_arg.resize(n_arg as usize, std::ptr::null());
+    }
+
+    match unsafe { TokenizerArgs::parse(az_arg.as_ptr(), n_arg) } {
+        Ok(_) => {}
+        Err(e) => assert!(!e.to_string().is_empty()),
+    }
+});
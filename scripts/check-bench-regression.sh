@@ -0,0 +1,48 @@
+#!/usr/bin/env bash
+#
+# Usage: check-bench-regression.sh BASELINE CURRENT [THRESHOLD]
+#
+# Compares two `critcmp --export` files with critcmp and fails if any
+# benchmark in CURRENT is more than THRESHOLD percent (default 10) slower
+# than in BASELINE, or if there is no BASELINE.
+
+set -euo pipefail
+
+baseline=$1
+current=$2
+threshold=${3:-10}
+
+if [ ! -f "${baseline}" ]; then
+  echo "No benchmark baseline at ${baseline}; run 'make update-bench-baseline' to record one." >&2
+  exit 1
+fi
+
+critcmp "${baseline}" "${current}"
+
+# With --threshold, critcmp only lists the benchmarks that changed by more
+# than THRESHOLD percent. Each row gives every baseline a ratio to the
+# fastest one, followed by its time ("12.3±0.50µs"); the benchmark regressed
+# when the ratio in the column of CURRENT is not 1.00.
+name=$(jq -r .name "${current}")
+regressions=$(critcmp "${baseline}" "${current}" --threshold "${threshold}" | awk -v name="${name}" '
+  NR == 1 {
+    for (i = 2; i <= NF; i++) {
+      if ($i == name) column = i - 1
+    }
+    next
+  }
+  /^-/ { next }
+  {
+    n = 0
+    for (i = 2; i < NF; i++) {
+      if ($(i + 1) ~ /±/ && ++n == column && $i != "1.00") {
+        printf "%s: %s times the baseline\n", $1, $i
+      }
+    }
+  }')
+
+if [ -n "${regressions}" ]; then
+  echo "Benchmarks regressed by more than ${threshold}%:"
+  echo "${regressions}"
+  exit 1
+fi
@@ -0,0 +1,41 @@
+#!/usr/bin/env bash
+#
+# Usage: fetch-bench-corpus.sh
+#
+# Replaces benches/corpus/{ja,zh,ko}.txt with the plain-text extracts of one
+# Wikipedia article per language, cut to 60KB, and writes their attribution
+# to benches/corpus/SOURCES. Wikipedia text is licensed under CC BY-SA 4.0,
+# so commit SOURCES with the files.
+
+set -euo pipefail
+
+corpus_dir=$(dirname "$0")/../benches/corpus
+size=$((60 * 1024))
+
+fetch() {
+  local lang=$1
+  local title=$2
+
+  curl -sSf --get "https://${lang}.wikipedia.org/w/api.php" \
+    --data-urlencode "action=query" \
+    --data-urlencode "format=json" \
+    --data-urlencode "prop=extracts" \
+    --data-urlencode "explaintext=1" \
+    --data-urlencode "titles=${title}" |
+    jq -r '.query.pages[].extract' |
+    head -c "${size}" |
+    iconv -f UTF-8 -t UTF-8 -c > "${corpus_dir}/${lang}.txt"
+
+  echo "${lang}: https://${lang}.wikipedia.org/wiki/${title}" >> "${corpus_dir}/SOURCES"
+}
+
+cat > "${corpus_dir}/SOURCES" <<EOF
+The files in this directory are excerpts of the following Wikipedia articles,
+by their contributors, licensed under CC BY-SA 4.0
+(https://creativecommons.org/licenses/by-sa/4.0/). They are cut to 60KB and
+otherwise unmodified.
+
+EOF
+fetch ja "日本"
+fetch zh "中国"
+fetch ko "대한민국"
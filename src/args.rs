@@ -0,0 +1,1377 @@
+use core::ffi::CStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use libc::{c_int, c_uchar};
+
+use lindera::character_filter::CharacterFilterLoader;
+use lindera::dictionary::DictionaryKind;
+use lindera::mode::{Mode, Penalty};
+use lindera::token_filter::TokenFilterLoader;
+use lindera::tokenizer::{Tokenizer, TokenizerConfig};
+use serde_json::{json, Value};
+
+use crate::chinese_variants::ChineseVariants;
+use crate::common::{BaseForm, Josa, Kana, RemoveDiacritics, Unicode61};
+use crate::config::{
+    append_filter, env_config, merge_config, parse_config, pipeline_config, profile_config,
+    read_config,
+};
+use crate::error::{LinderaSqliteError, TokenizerArgumentError};
+use crate::logging::log_debug;
+use crate::stopwords::Stopwords;
+use crate::synonyms::Synonyms;
+
+/// Parameters that may also be given positionally, in this order, as in
+/// `tokenize='lindera_tokenizer ja search'`.
+const POSITIONAL: [&str; 2] = ["lang", "mode"];
+
+/// Parameters that may also be given as the key followed by the value, as in
+/// `tokenize='lindera_tokenizer config_path /path/to/lindera.yml'`, which
+/// FTS5 splits into two arguments.
+const SEPARATE: [&str; 14] = [
+    "base_form",
+    "config",
+    "config_name",
+    "config_path",
+    "dictionary",
+    "josa",
+    "kana",
+    "lang",
+    "mode",
+    "numbers",
+    "remove_diacritics",
+    "separators",
+    "tokenchars",
+    "user_dictionary",
+];
+
+/// Arguments given after the tokenizer name that select and configure the
+/// tokenizer, parsed with the [`EmissionOptions`] of the table.
+///
+/// Each argument is one of:
+///
+/// - `key=value`, e.g. `config=/path/to/lindera.yml`, `lang=ja` or
+///   `ascii_fast_path=true`, `keep_original=true` or `base_form=colocate`;
+/// - a key followed by its value, for the keys in `SEPARATE`, e.g.
+///   `config_path /path/to/lindera.yml`, `dictionary ko-dic`,
+///   `base_form colocate` or `numbers kanji`;
+/// - a flag, `lowercase`, `normalize`, `long_vowel`, `small_kana`,
+///   `unicode61`, `jamo` or `pinyin`;
+/// - a bare value for the next of `lang` and `mode`.
+///
+/// A `key=value` argument wins over a positional one for the same key.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct TokenizerArgs {
+    /// Overrides the connection's configuration for this table.
+    pub(crate) config_path: Option<PathBuf>,
+    /// Names the row of the database's `lindera_config` table to use.
+    pub(crate) config_name: Option<String>,
+    /// The configuration read from the database's `lindera_config` table,
+    /// used when there is no `config_path`. Set by the connection, not by
+    /// arguments.
+    pub(crate) database_config: Option<String>,
+    /// Stands for the default configuration of an embedded dictionary when
+    /// there is no `config_path`. Set by the connection, not by arguments.
+    pub(crate) embedded_dictionary: Option<EmbeddedDictionary>,
+    /// Selects an entry of the `tokenizers` map of the connection's
+    /// configuration rather than the configuration itself. Set by the
+    /// connection, not by arguments.
+    pub(crate) profile: Option<String>,
+    /// Overrides the dictionary of the configuration.
+    pub(crate) lang: Option<Language>,
+    /// Overrides the dictionary of the configuration, and `lang`.
+    pub(crate) dictionary: Option<EmbeddedDictionary>,
+    /// Overrides the segmenter mode of the configuration.
+    pub(crate) mode: Option<SegmenterMode>,
+    /// Overrides the user dictionary of the configuration, a CSV file for
+    /// the configured dictionary or a compiled `.bin` file.
+    pub(crate) user_dictionary: Option<PathBuf>,
+    /// The user dictionary compiled from the database's `lindera_userdict`
+    /// table, or the configuration's `user_dictionary_table`, used instead of
+    /// the configuration's user dictionary while the file exists and there
+    /// is no `user_dictionary`. Set by the connection, not by arguments.
+    pub(crate) database_user_dictionary: Option<PathBuf>,
+    /// Lowercases tokens.
+    pub(crate) lowercase: bool,
+    /// Applies NFKC normalization before segmenting.
+    pub(crate) normalize: bool,
+    /// Writes numbers in ASCII digits.
+    pub(crate) numbers: Option<Numbers>,
+}
+
+/// How a table emits the tokens of its tokenizer, parsed from its arguments
+/// alongside the [`TokenizerArgs`].
+///
+/// These do not change the tokenizer that is built, so they are left out of
+/// the arguments its cache entry is found by, and tables that differ only in
+/// them share one tokenizer.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EmissionOptions {
+    /// Splits ASCII-only text on its own rather than with Lindera.
+    pub(crate) ascii_fast_path: bool,
+    /// Indexes the original surface of tokens the filters rewrote as well.
+    pub(crate) keep_original: bool,
+    /// Indexes Hangul tokens written as jamo as well.
+    pub(crate) jamo: bool,
+    /// Indexes the pinyin of Chinese words as well.
+    pub(crate) pinyin: bool,
+    /// Indexes the base form of inflected words, instead of the
+    /// configuration's `base_form`.
+    pub(crate) base_form: Option<BaseForm>,
+    /// Strips the particles of Korean words, instead of the configuration's
+    /// `josa`.
+    pub(crate) josa: Option<Josa>,
+    /// Folds kana into one script, instead of the configuration's `kana`.
+    pub(crate) kana: Option<Kana>,
+    /// Normalizes prolonged sound marks.
+    pub(crate) long_vowel: bool,
+    /// Writes small kana full size.
+    pub(crate) small_kana: bool,
+    /// Tokenizes text outside Chinese, Japanese and Korean as SQLite's
+    /// `unicode61` tokenizer does.
+    pub(crate) unicode61: bool,
+    /// How `unicode61` removes diacritics, `1` by default as in SQLite.
+    pub(crate) remove_diacritics: Option<RemoveDiacritics>,
+    /// Characters `unicode61` takes as part of tokens.
+    pub(crate) tokenchars: Option<String>,
+    /// Characters `unicode61` takes as separators.
+    pub(crate) separators: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Language {
+    Japanese,
+    Korean,
+    Chinese,
+}
+
+impl Language {
+    /// The ISO 639-1 code of the language.
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Language::Japanese => "ja",
+            Language::Korean => "ko",
+            Language::Chinese => "zh",
+        }
+    }
+
+    fn dictionary_kind(self) -> DictionaryKind {
+        match self {
+            Language::Japanese => DictionaryKind::IPADIC,
+            Language::Korean => DictionaryKind::KoDic,
+            Language::Chinese => DictionaryKind::CcCedict,
+        }
+    }
+
+    /// The embedded dictionary for text in the language.
+    pub(crate) fn embedded_dictionary(self) -> EmbeddedDictionary {
+        match self {
+            Language::Japanese => EmbeddedDictionary::Ipadic,
+            Language::Korean => EmbeddedDictionary::KoDic,
+            Language::Chinese => EmbeddedDictionary::CcCedict,
+        }
+    }
+}
+
+/// A dictionary compiled into the library, chosen by the `dictionary`
+/// argument or used with its default settings by the
+/// `lindera_fts5_tokenizer_init_*` entry points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum EmbeddedDictionary {
+    Ipadic,
+    IpadicNeologd,
+    UniDic,
+    KoDic,
+    CcCedict,
+}
+
+impl EmbeddedDictionary {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ipadic" => Some(EmbeddedDictionary::Ipadic),
+            "ipadic-neologd" => Some(EmbeddedDictionary::IpadicNeologd),
+            "unidic" => Some(EmbeddedDictionary::UniDic),
+            "ko-dic" => Some(EmbeddedDictionary::KoDic),
+            "cc-cedict" => Some(EmbeddedDictionary::CcCedict),
+            _ => None,
+        }
+    }
+
+    /// The name `from_name` takes.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            EmbeddedDictionary::Ipadic => "ipadic",
+            EmbeddedDictionary::IpadicNeologd => "ipadic-neologd",
+            EmbeddedDictionary::UniDic => "unidic",
+            EmbeddedDictionary::KoDic => "ko-dic",
+            EmbeddedDictionary::CcCedict => "cc-cedict",
+        }
+    }
+
+    fn dictionary_kind(self) -> DictionaryKind {
+        match self {
+            EmbeddedDictionary::Ipadic => DictionaryKind::IPADIC,
+            EmbeddedDictionary::IpadicNeologd => DictionaryKind::IPADICNEologd,
+            EmbeddedDictionary::UniDic => DictionaryKind::UniDic,
+            EmbeddedDictionary::KoDic => DictionaryKind::KoDic,
+            EmbeddedDictionary::CcCedict => DictionaryKind::CcCedict,
+        }
+    }
+
+    fn language(self) -> Language {
+        match self {
+            EmbeddedDictionary::Ipadic
+            | EmbeddedDictionary::IpadicNeologd
+            | EmbeddedDictionary::UniDic => Language::Japanese,
+            EmbeddedDictionary::KoDic => Language::Korean,
+            EmbeddedDictionary::CcCedict => Language::Chinese,
+        }
+    }
+}
+
+/// Where the details of a dictionary's tokens hold what the SQL functions
+/// read from them.
+pub(crate) struct DetailFields {
+    /// The number of leading fields that make up the part of speech.
+    pub(crate) part_of_speech: usize,
+    pub(crate) reading: Option<usize>,
+    pub(crate) base_form: Option<usize>,
+    /// The pinyin of Chinese words, with tone numbers.
+    pub(crate) pinyin: Option<usize>,
+    /// The parts of speech, as the first field, of content words, or none if
+    /// the dictionary does not tell them apart.
+    pub(crate) content_words: &'static [&'static str],
+    /// The content words that inflect, which are known by their base form.
+    pub(crate) inflected_words: &'static [&'static str],
+}
+
+impl DetailFields {
+    /// The fields of `dictionary`, or of a dictionary of an unknown kind,
+    /// whose first field is taken to be the part of speech.
+    pub(crate) fn of(dictionary: Option<EmbeddedDictionary>) -> Self {
+        const JAPANESE_CONTENT_WORDS: &[&str] = &["名詞", "動詞", "形容詞"];
+        const JAPANESE_INFLECTED_WORDS: &[&str] = &["動詞", "形容詞"];
+
+        let (part_of_speech, reading, base_form) = match dictionary {
+            Some(EmbeddedDictionary::Ipadic | EmbeddedDictionary::IpadicNeologd) => {
+                (4, Some(7), Some(6))
+            }
+            Some(EmbeddedDictionary::UniDic) => (4, Some(6), Some(10)),
+            Some(EmbeddedDictionary::KoDic) => (1, Some(3), None),
+            Some(EmbeddedDictionary::CcCedict) => (4, Some(4), None),
+            None => (1, None, None),
+        };
+        let (content_words, inflected_words) = match dictionary {
+            Some(
+                EmbeddedDictionary::Ipadic
+                | EmbeddedDictionary::IpadicNeologd
+                | EmbeddedDictionary::UniDic,
+            ) => (JAPANESE_CONTENT_WORDS, JAPANESE_INFLECTED_WORDS),
+            // Nouns, verbs, adjectives, roots, foreign words and hanja.
+            Some(EmbeddedDictionary::KoDic) => {
+                (&["NNG", "NNP", "VV", "VA", "XR", "SL", "SH"][..], &[][..])
+            }
+            Some(EmbeddedDictionary::CcCedict) | None => (&[][..], &[][..]),
+        };
+        DetailFields {
+            part_of_speech,
+            reading,
+            base_form,
+            pinyin: match dictionary {
+                Some(EmbeddedDictionary::CcCedict) => reading,
+                _ => None,
+            },
+            content_words,
+            inflected_words,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum SegmenterMode {
+    Normal,
+    /// Lindera's `decompose` mode, which also splits compound words.
+    Search,
+    /// The normal mode, with the parts `decompose` splits compound words
+    /// into indexed at their position too. The tokenizer that is built is
+    /// the one of the normal mode.
+    Hybrid,
+}
+
+/// How the `numbers` argument writes numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Numbers {
+    /// Full-width digits, as in `１２３`, as ASCII digits.
+    Digits,
+    /// Kanji numerals, as in `三百`, as ASCII digits too, with a Japanese
+    /// dictionary.
+    Kanji,
+}
+
+/// The tokenizers of a table, built from one configuration.
+#[derive(Clone)]
+pub(crate) struct Pipelines {
+    /// Tokenizes the table's documents.
+    pub(crate) index: Arc<Tokenizer>,
+    /// Tokenizes queries, if the configuration has a `query` pipeline.
+    pub(crate) query: Option<Arc<Tokenizer>>,
+    /// Splits the tokens of documents into their parts, in the hybrid mode.
+    pub(crate) decompose: Option<Arc<Tokenizer>>,
+    /// Expands queries, if the configuration names a `synonyms` file.
+    pub(crate) synonyms: Option<Arc<Synonyms>>,
+    /// Leaves words out, if the configuration names `stopwords` lists.
+    pub(crate) stopwords: Option<Arc<Stopwords>>,
+    /// Converts Chinese characters, if the configuration has
+    /// `chinese_variants`.
+    pub(crate) chinese_variants: Option<Arc<ChineseVariants>>,
+    /// What the configuration sets besides the pipelines.
+    pub(crate) settings: ConfigSettings,
+}
+
+/// The settings of a configuration besides its pipelines, read from the
+/// same configuration the tokenizers are built from. The arguments of a
+/// table take precedence over them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ConfigSettings {
+    /// The kind of dictionary, or `None` if the configuration does not name
+    /// one of the known kinds.
+    pub(crate) dictionary_kind: Option<EmbeddedDictionary>,
+    /// How base forms are indexed, as `base_form` sets it.
+    pub(crate) base_form: BaseForm,
+    /// How Korean particles are indexed, as `josa` sets it.
+    pub(crate) josa: Josa,
+    /// The script kana are folded into, as `kana` sets it.
+    pub(crate) kana: Kana,
+}
+
+impl ConfigSettings {
+    /// Reads the settings of `config`, with the arguments already merged
+    /// onto it.
+    fn read(config: &TokenizerConfig) -> Result<Self, LinderaSqliteError> {
+        Ok(ConfigSettings {
+            dictionary_kind: config["segmenter"]["dictionary"]["kind"]
+                .as_str()
+                .and_then(EmbeddedDictionary::from_name),
+            base_form: config_setting(
+                config,
+                "base_form",
+                "off, replace or colocate",
+                BaseForm::from_name,
+            )?,
+            josa: config_setting(config, "josa", "keep, strip or colocate", Josa::from_name)?,
+            kana: config_setting(
+                config,
+                "kana",
+                "keep, hiragana or katakana",
+                Kana::from_name,
+            )?,
+        })
+    }
+
+    /// The language of the dictionary, or `None` if the configuration does
+    /// not name one of the known kinds.
+    pub(crate) fn language(&self) -> Option<Language> {
+        self.dictionary_kind.map(EmbeddedDictionary::language)
+    }
+}
+
+/// The setting at the top-level `key` of `config`, read by `from_name` from
+/// one of `names`, or the default without one.
+fn config_setting<T: Default>(
+    config: &TokenizerConfig,
+    key: &str,
+    names: &str,
+    from_name: fn(&str) -> Option<T>,
+) -> Result<T, LinderaSqliteError> {
+    match &config[key] {
+        Value::Null => Ok(T::default()),
+        value => value.as_str().and_then(from_name).ok_or_else(|| {
+            LinderaSqliteError::ConfigInvalid(format!("{} must be {}, not {}", key, names, value))
+        }),
+    }
+}
+
+impl TokenizerArgs {
+    /// Parses the arguments FTS5 passes to `xCreate`.
+    ///
+    /// # Safety
+    ///
+    /// Unless `az_arg` is null or `n_arg` is not positive, `az_arg` must point
+    /// to `n_arg` pointers, each null or to a nul-terminated string.
+    pub unsafe fn parse(
+        az_arg: *const *const c_uchar,
+        n_arg: c_int,
+    ) -> Result<(Self, EmissionOptions), TokenizerArgumentError> {
+        if az_arg.is_null() || n_arg <= 0 {
+            return Self::from_args([]);
+        }
+
+        let args = core::slice::from_raw_parts(az_arg, n_arg as usize);
+        Self::from_args(
+            args.iter()
+                .filter(|arg| !arg.is_null())
+                .map(|&arg| CStr::from_ptr(arg.cast()).to_bytes()),
+        )
+    }
+
+    fn from_args<'a>(
+        args: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Result<(Self, EmissionOptions), TokenizerArgumentError> {
+        let mut parsed = TokenizerArgs::default();
+        let mut options = EmissionOptions::default();
+        let mut positional = Vec::new();
+        let mut keyed = Vec::new();
+
+        let mut args = args
+            .into_iter()
+            .map(|arg| String::from_utf8_lossy(arg).into_owned());
+        while let Some(arg) = args.next() {
+            match arg.split_once('=') {
+                Some((key, value)) => keyed.push((key.to_string(), value.to_string())),
+                None => match arg.as_str() {
+                    "lowercase" => parsed.lowercase = true,
+                    "normalize" => parsed.normalize = true,
+                    "long_vowel" => options.long_vowel = true,
+                    "small_kana" => options.small_kana = true,
+                    "unicode61" => options.unicode61 = true,
+                    "jamo" => options.jamo = true,
+                    "pinyin" => options.pinyin = true,
+                    key if SEPARATE.contains(&key) => match args.next() {
+                        Some(value) => keyed.push((arg, value)),
+                        None => return Err(TokenizerArgumentError::MissingRequiredKey(arg)),
+                    },
+                    _ => positional.push(arg),
+                },
+            }
+        }
+
+        if positional.len() > POSITIONAL.len() {
+            return Err(TokenizerArgumentError::UnknownKey(
+                positional[POSITIONAL.len()].clone(),
+            ));
+        }
+        let positional = POSITIONAL
+            .iter()
+            .zip(positional)
+            .map(|(key, value)| (key.to_string(), value));
+
+        // Keyed arguments come last so that they override positional ones.
+        for (key, value) in positional.chain(keyed) {
+            parsed.set(&mut options, &key, &value)?;
+        }
+
+        Ok((parsed, options))
+    }
+
+    /// Sets `key` to `value`, or the option of `options` it names.
+    fn set(
+        &mut self,
+        options: &mut EmissionOptions,
+        key: &str,
+        value: &str,
+    ) -> Result<(), TokenizerArgumentError> {
+        let invalid = |reason: &str| TokenizerArgumentError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: reason.to_string(),
+        };
+
+        match key {
+            "config" | "config_path" => {
+                if value.is_empty() {
+                    return Err(invalid("the path is empty"));
+                }
+                let path = PathBuf::from(value);
+                if !path.is_file() {
+                    return Err(invalid("no such file"));
+                }
+                self.config_path = Some(path);
+            }
+            "config_name" => {
+                if value.is_empty() {
+                    return Err(invalid("the name is empty"));
+                }
+                self.config_name = Some(value.to_string());
+            }
+            "user_dictionary" => {
+                if value.is_empty() {
+                    return Err(invalid("the path is empty"));
+                }
+                let path = PathBuf::from(value);
+                if !path.is_file() {
+                    return Err(invalid("no such file"));
+                }
+                self.user_dictionary = Some(path);
+            }
+            "lang" => {
+                self.lang = Some(match value {
+                    "ja" => Language::Japanese,
+                    "ko" => Language::Korean,
+                    "zh" => Language::Chinese,
+                    _ => return Err(invalid("expected ja, ko or zh")),
+                });
+            }
+            "dictionary" => {
+                self.dictionary = Some(EmbeddedDictionary::from_name(value).ok_or_else(|| {
+                    invalid("expected ipadic, ipadic-neologd, unidic, ko-dic or cc-cedict")
+                })?);
+            }
+            "mode" => {
+                self.mode = Some(match value {
+                    "normal" => SegmenterMode::Normal,
+                    "search" | "decompose" => SegmenterMode::Search,
+                    "hybrid" => SegmenterMode::Hybrid,
+                    _ => return Err(invalid("expected normal, search or hybrid")),
+                });
+            }
+            "numbers" => {
+                self.numbers = Some(match value {
+                    "digits" => Numbers::Digits,
+                    "kanji" => Numbers::Kanji,
+                    _ => return Err(invalid("expected digits or kanji")),
+                });
+            }
+            "lowercase" | "normalize" | "long_vowel" | "small_kana" | "unicode61" | "jamo"
+            | "pinyin" => return Err(invalid("flags take no value")),
+            _ => return options.set(key, value),
+        }
+
+        Ok(())
+    }
+
+    /// Builds the tokenizers of the configuration at `config_path`, or the
+    /// one set by the environment, with the other arguments applied on top.
+    pub(crate) fn build(&self) -> Result<Pipelines, LinderaSqliteError> {
+        log_debug!(
+            "Building tokenizer: config {:?}, embedded dictionary {:?}, dictionary {:?}",
+            self.config_path
+                .clone()
+                .or_else(|| std::env::var_os("LINDERA_CONFIG_PATH").map(PathBuf::from)),
+            self.embedded_dictionary,
+            self.dictionary
+                .map(EmbeddedDictionary::dictionary_kind)
+                .or(self.lang.map(Language::dictionary_kind))
+        );
+
+        let config = self.config("index")?;
+        let settings = ConfigSettings::read(&config)?;
+        let index =
+            Tokenizer::from_config(&config).map_err(LinderaSqliteError::from_build_error)?;
+        let query = match config.get("query") {
+            Some(_) => Some(Arc::new(filtered_tokenizer(
+                &index,
+                &self.config("query")?,
+            )?)),
+            None => None,
+        };
+        let synonyms = match config["synonyms"].as_str() {
+            Some(path) => Some(Arc::new(Synonyms::load(Path::new(path))?)),
+            None => None,
+        };
+        let stopwords = match &config["stopwords"] {
+            Value::Null => None,
+            value => Some(Arc::new(Stopwords::from_config(value)?)),
+        };
+        let chinese_variants = match &config["chinese_variants"] {
+            Value::Null => None,
+            value => Some(Arc::new(ChineseVariants::from_config(value)?)),
+        };
+
+        let decompose = (self.mode == Some(SegmenterMode::Hybrid))
+            .then(|| Arc::new(crate::decomposing(&index)));
+
+        Ok(Pipelines {
+            index: Arc::new(index),
+            query,
+            decompose,
+            synonyms,
+            stopwords,
+            chinese_variants,
+            settings,
+        })
+    }
+
+    /// The kind of dictionary the tokenizer is built with, or `None` if the
+    /// configuration does not name one of the known kinds.
+    pub(crate) fn dictionary_kind(&self) -> Option<EmbeddedDictionary> {
+        if let Some(dictionary) = self.dictionary {
+            return Some(dictionary);
+        }
+        if let Some(lang) = self.lang {
+            return Some(lang.embedded_dictionary());
+        }
+        let config = self.config("index").ok()?;
+        config["segmenter"]["dictionary"]["kind"]
+            .as_str()
+            .and_then(EmbeddedDictionary::from_name)
+    }
+
+    /// The table of the database the configuration's user dictionary comes
+    /// from, if it names one as `user_dictionary_table`.
+    pub(crate) fn user_dictionary_table(&self) -> Option<String> {
+        let config = self.config("index").ok()?;
+        config["user_dictionary_table"].as_str().map(str::to_string)
+    }
+
+    /// Resolves the configuration of `pipeline`: the one of `config_path`, of
+    /// the database, of the embedded dictionary or of the process as a base,
+    /// with the other arguments merged onto it.
+    fn config(&self, pipeline: &str) -> Result<TokenizerConfig, LinderaSqliteError> {
+        let mut config = if let Some(path) = &self.config_path {
+            read_config(path)?
+        } else if let Some(config) = &self.database_config {
+            parse_config(config)?
+        } else if let Some(dictionary) = self.embedded_dictionary {
+            json!({
+                "segmenter": {
+                    "mode": "normal",
+                    "dictionary": { "kind": dictionary.dictionary_kind().as_str() },
+                },
+            })
+        } else {
+            match env_config()? {
+                Some(config) => config,
+                // The arguments may still name a dictionary.
+                None if self.dictionary.is_some() || self.lang.is_some() => {
+                    json!({ "segmenter": {} })
+                }
+                None => return Err(LinderaSqliteError::ConfigMissing),
+            }
+        };
+        if let Some(name) = &self.profile {
+            config = profile_config(&config, name)?;
+        }
+        config = pipeline_config(&config, pipeline);
+
+        let overrides = self.overrides(&config);
+        merge_config(&mut config, overrides);
+        if self.normalize {
+            append_filter(
+                &mut config,
+                "character_filters",
+                "unicode_normalize",
+                json!({ "kind": "nfkc" }),
+            );
+        }
+        if let Some(numbers) = self.numbers {
+            append_number_filters(&mut config, numbers);
+        }
+        if self.lowercase {
+            append_filter(&mut config, "token_filters", "lowercase", json!({}));
+        }
+
+        Ok(config)
+    }
+
+    /// The part of the configuration set by the arguments, to merge onto
+    /// `base`.
+    fn overrides(&self, base: &TokenizerConfig) -> TokenizerConfig {
+        let mut overrides = json!({});
+
+        let dictionary_kind = self
+            .dictionary
+            .map(EmbeddedDictionary::dictionary_kind)
+            .or(self.lang.map(Language::dictionary_kind));
+        if let Some(kind) = &dictionary_kind {
+            overrides["segmenter"]["dictionary"]["kind"] = json!(kind.as_str());
+        }
+        let user_dictionary = self.user_dictionary.as_ref().or(self
+            .database_user_dictionary
+            .as_ref()
+            .filter(|path| path.is_file()));
+        if let Some(path) = user_dictionary {
+            // A CSV user dictionary is read with the columns of the main one.
+            let kind = match &dictionary_kind {
+                Some(kind) => json!(kind.as_str()),
+                None => base["segmenter"]["dictionary"]["kind"].clone(),
+            };
+            overrides["segmenter"]["user_dictionary"] = json!({
+                "path": path.to_string_lossy(),
+                "kind": kind,
+            });
+        }
+        match self.mode {
+            Some(SegmenterMode::Normal | SegmenterMode::Hybrid) => {
+                overrides["segmenter"]["mode"] = json!(Mode::Normal)
+            }
+            Some(SegmenterMode::Search) => {
+                overrides["segmenter"]["mode"] = json!(Mode::Decompose(Penalty::default()))
+            }
+            None => {}
+        }
+
+        overrides
+    }
+}
+
+impl EmissionOptions {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), TokenizerArgumentError> {
+        let invalid = |reason: &str| TokenizerArgumentError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: reason.to_string(),
+        };
+
+        match key {
+            "ascii_fast_path" | "keep_original" => {
+                let enabled = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(invalid("expected true or false")),
+                };
+                match key {
+                    "ascii_fast_path" => self.ascii_fast_path = enabled,
+                    _ => self.keep_original = enabled,
+                }
+            }
+            "base_form" => {
+                self.base_form = Some(
+                    BaseForm::from_name(value)
+                        .ok_or_else(|| invalid("expected off, replace or colocate"))?,
+                );
+            }
+            "josa" => {
+                self.josa = Some(
+                    Josa::from_name(value)
+                        .ok_or_else(|| invalid("expected keep, strip or colocate"))?,
+                );
+            }
+            "kana" => {
+                self.kana = Some(
+                    Kana::from_name(value)
+                        .ok_or_else(|| invalid("expected keep, hiragana or katakana"))?,
+                );
+            }
+            // Flags are read first, so whether `unicode61` is given is known.
+            "remove_diacritics" | "tokenchars" | "separators" if !self.unicode61 => {
+                return Err(invalid("only applies with the unicode61 flag"))
+            }
+            "remove_diacritics" => {
+                self.remove_diacritics = Some(
+                    RemoveDiacritics::from_name(value)
+                        .ok_or_else(|| invalid("expected 0, 1 or 2"))?,
+                );
+            }
+            "tokenchars" => self.tokenchars = Some(value.to_string()),
+            "separators" => self.separators = Some(value.to_string()),
+            _ => return Err(TokenizerArgumentError::UnknownKey(key.to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// How text outside Chinese, Japanese and Korean is tokenized, if as by
+    /// `unicode61`.
+    pub(crate) fn unicode61(&self) -> Option<Unicode61> {
+        self.unicode61.then(|| Unicode61 {
+            remove_diacritics: self.remove_diacritics.unwrap_or_default(),
+            tokenchars: self.tokenchars.clone().unwrap_or_default(),
+            separators: self.separators.clone().unwrap_or_default(),
+        })
+    }
+}
+
+/// Appends the filters that write the numbers of `config` in ASCII digits.
+///
+/// Full-width digits are mapped before segmenting, with any dictionary.
+/// Kanji numerals are only known to the Japanese dictionaries, which tag
+/// them: the numerals a number is split into are joined into one token,
+/// which is then converted, so that `三百` is indexed as `300`.
+fn append_number_filters(config: &mut TokenizerConfig, numbers: Numbers) {
+    let mapping = ('０'..='９')
+        .zip('0'..='9')
+        .map(|(full_width, digit)| (full_width.to_string(), json!(digit.to_string())))
+        .collect::<serde_json::Map<_, _>>();
+    append_filter(
+        config,
+        "character_filters",
+        "mapping",
+        json!({ "mapping": mapping }),
+    );
+    if numbers != Numbers::Kanji {
+        return;
+    }
+    let kind = config["segmenter"]["dictionary"]["kind"].clone();
+    let tag = match kind.as_str() {
+        Some("ipadic" | "ipadic-neologd") => "名詞,数",
+        Some("unidic") => "名詞,数詞",
+        _ => return,
+    };
+    append_filter(
+        config,
+        "token_filters",
+        "japanese_compound_word",
+        json!({ "kind": kind, "tags": [tag], "new_tag": tag }),
+    );
+    append_filter(
+        config,
+        "token_filters",
+        "japanese_number",
+        json!({ "tags": [tag] }),
+    );
+}
+
+/// A tokenizer with the segmenter of `tokenizer` and the filters of
+/// `config`, so that a second pipeline does not load the dictionaries again.
+fn filtered_tokenizer(
+    tokenizer: &Tokenizer,
+    config: &TokenizerConfig,
+) -> Result<Tokenizer, LinderaSqliteError> {
+    let mut filtered = Tokenizer::new(tokenizer.segmenter.clone());
+    for filter in config["character_filters"].as_array().into_iter().flatten() {
+        if let Some(kind) = filter["kind"].as_str() {
+            filtered.append_character_filter(CharacterFilterLoader::load_from_value(
+                kind,
+                &filter["args"],
+            )?);
+        }
+    }
+    for filter in config["token_filters"].as_array().into_iter().flatten() {
+        if let Some(kind) = filter["kind"].as_str() {
+            filtered
+                .append_token_filter(TokenFilterLoader::load_from_value(kind, &filter["args"])?);
+        }
+    }
+
+    Ok(filtered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<TokenizerArgs, TokenizerArgumentError> {
+        TokenizerArgs::from_args(args.iter().map(|arg| arg.as_bytes())).map(|(args, _)| args)
+    }
+
+    fn parse_options(args: &[&str]) -> Result<EmissionOptions, TokenizerArgumentError> {
+        TokenizerArgs::from_args(args.iter().map(|arg| arg.as_bytes())).map(|(_, options)| options)
+    }
+
+    #[test]
+    fn it_parses_no_arguments() {
+        assert_eq!(parse(&[]), Ok(TokenizerArgs::default()));
+        assert_eq!(parse_options(&[]), Ok(EmissionOptions::default()));
+        assert_eq!(
+            unsafe { TokenizerArgs::parse(core::ptr::null(), -1) },
+            Ok((TokenizerArgs::default(), EmissionOptions::default()))
+        );
+    }
+
+    #[test]
+    fn it_parses_config_path() {
+        assert_eq!(
+            parse(&["config=./resources/lindera.yml"]),
+            Ok(TokenizerArgs {
+                config_path: Some(PathBuf::from("./resources/lindera.yml")),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_separate_config_path() {
+        let expected = Ok(TokenizerArgs {
+            config_path: Some(PathBuf::from("./resources/lindera.yml")),
+            lang: Some(Language::Japanese),
+            ..Default::default()
+        });
+        assert_eq!(
+            parse(&["config_path", "./resources/lindera.yml", "ja"]),
+            expected
+        );
+        assert_eq!(
+            parse(&["ja", "config_path=./resources/lindera.yml"]),
+            expected
+        );
+        assert_eq!(
+            parse(&["config_path"]),
+            Err(TokenizerArgumentError::MissingRequiredKey(
+                "config_path".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn it_parses_config_name() {
+        assert_eq!(
+            parse(&["config_name", "news"]),
+            Ok(TokenizerArgs {
+                config_name: Some("news".to_string()),
+                ..Default::default()
+            })
+        );
+        assert!(matches!(
+            parse(&["config_name="]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn it_parses_positional_arguments() {
+        assert_eq!(
+            parse(&["ja", "search"]),
+            Ok(TokenizerArgs {
+                lang: Some(Language::Japanese),
+                mode: Some(SegmenterMode::Search),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_keyed_arguments() {
+        assert_eq!(
+            parse(&["mode=normal", "lang=ko"]),
+            Ok(TokenizerArgs {
+                lang: Some(Language::Korean),
+                mode: Some(SegmenterMode::Normal),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_separate_mode() {
+        assert_eq!(
+            parse(&["mode", "decompose"]),
+            Ok(TokenizerArgs {
+                mode: Some(SegmenterMode::Search),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["ja", "mode", "normal"]),
+            Ok(TokenizerArgs {
+                lang: Some(Language::Japanese),
+                mode: Some(SegmenterMode::Normal),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["mode", "hybrid"]),
+            Ok(TokenizerArgs {
+                mode: Some(SegmenterMode::Hybrid),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_flags() {
+        let flags = [
+            "lowercase",
+            "normalize",
+            "long_vowel",
+            "small_kana",
+            "jamo",
+            "pinyin",
+        ];
+        assert_eq!(
+            parse(&flags),
+            Ok(TokenizerArgs {
+                lowercase: true,
+                normalize: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_options(&flags),
+            Ok(EmissionOptions {
+                long_vowel: true,
+                small_kana: true,
+                jamo: true,
+                pinyin: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_ascii_fast_path() {
+        assert_eq!(
+            parse_options(&["ascii_fast_path=true"]),
+            Ok(EmissionOptions {
+                ascii_fast_path: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_options(&["ascii_fast_path=false"]),
+            Ok(EmissionOptions::default())
+        );
+        assert!(matches!(
+            parse_options(&["ascii_fast_path=yes"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn it_parses_keep_original() {
+        assert_eq!(
+            parse_options(&["keep_original=true"]),
+            Ok(EmissionOptions {
+                keep_original: true,
+                ..Default::default()
+            })
+        );
+        assert!(matches!(
+            parse_options(&["keep_original=yes"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn it_parses_base_form() {
+        assert_eq!(
+            parse_options(&["base_form=colocate"]),
+            Ok(EmissionOptions {
+                base_form: Some(BaseForm::Colocate),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_options(&["base_form", "colocate"]),
+            parse_options(&["base_form=colocate"])
+        );
+        assert!(matches!(
+            parse_options(&["base_form=lemma"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn it_reads_base_form_from_config() {
+        let args = TokenizerArgs {
+            database_config: Some(
+                "segmenter:\n  dictionary:\n    kind: ipadic\nbase_form: replace\n".to_string(),
+            ),
+            ..Default::default()
+        };
+        let settings = ConfigSettings::read(&args.config("index").unwrap()).unwrap();
+        assert_eq!(settings.base_form, BaseForm::Replace);
+        assert_eq!(settings.dictionary_kind, Some(EmbeddedDictionary::Ipadic));
+    }
+
+    #[test]
+    fn it_parses_josa() {
+        assert_eq!(
+            parse_options(&["josa", "strip"]),
+            Ok(EmissionOptions {
+                josa: Some(Josa::Strip),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_options(&["josa=colocate"]),
+            parse_options(&["josa", "colocate"])
+        );
+        assert!(matches!(
+            parse_options(&["josa=drop"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn it_parses_kana() {
+        assert_eq!(
+            parse_options(&["kana", "katakana"]),
+            Ok(EmissionOptions {
+                kana: Some(Kana::Katakana),
+                ..Default::default()
+            })
+        );
+        assert!(matches!(
+            parse_options(&["kana=romaji"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn it_parses_numbers() {
+        assert_eq!(
+            parse(&["numbers", "kanji"]),
+            Ok(TokenizerArgs {
+                numbers: Some(Numbers::Kanji),
+                ..Default::default()
+            })
+        );
+        assert_eq!(parse(&["numbers=digits"]), parse(&["numbers", "digits"]));
+        assert!(matches!(
+            parse(&["numbers=roman"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn it_parses_unicode61() {
+        assert_eq!(
+            parse_options(&["unicode61", "remove_diacritics", "2"]),
+            Ok(EmissionOptions {
+                unicode61: true,
+                remove_diacritics: Some(RemoveDiacritics::Complex),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_options(&["remove_diacritics=0", "unicode61"]),
+            parse_options(&["unicode61", "remove_diacritics", "0"])
+        );
+        assert_eq!(
+            parse_options(&["unicode61", "tokenchars", "-_", "separators=x"]),
+            Ok(EmissionOptions {
+                unicode61: true,
+                tokenchars: Some("-_".to_string()),
+                separators: Some("x".to_string()),
+                ..Default::default()
+            })
+        );
+        assert!(matches!(
+            parse_options(&["unicode61", "remove_diacritics=3"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+        // The options of unicode61 are no use without it.
+        assert!(matches!(
+            parse_options(&["remove_diacritics=0"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse_options(&["tokenchars", "-"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse_options(&["unicode61=1"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn it_reads_josa_from_config() {
+        let args = TokenizerArgs {
+            database_config: Some(
+                "segmenter:\n  dictionary:\n    kind: ko-dic\njosa: colocate\n".to_string(),
+            ),
+            ..Default::default()
+        };
+        let settings = ConfigSettings::read(&args.config("index").unwrap()).unwrap();
+        assert_eq!(settings.josa, Josa::Colocate);
+        let args = TokenizerArgs {
+            database_config: Some("josa: 1\n".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            args.build(),
+            Err(LinderaSqliteError::ConfigInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn it_parses_dictionary() {
+        let expected = Ok(TokenizerArgs {
+            dictionary: Some(EmbeddedDictionary::KoDic),
+            ..Default::default()
+        });
+        assert_eq!(parse(&["dictionary", "ko-dic"]), expected);
+        assert_eq!(parse(&["dictionary=ko-dic"]), expected);
+        assert!(matches!(
+            parse(&["dictionary", "mecab"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn it_prefers_dictionary_over_lang() {
+        let args = parse(&["lang=ko", "dictionary=ipadic"]).unwrap();
+        assert_eq!(
+            args.config("index").unwrap()["segmenter"]["dictionary"]["kind"],
+            "ipadic"
+        );
+    }
+
+    #[test]
+    fn it_prefers_keyed_over_positional_arguments() {
+        assert_eq!(
+            parse(&["lang=zh", "ja", "lowercase", "search"]),
+            Ok(TokenizerArgs {
+                lang: Some(Language::Chinese),
+                mode: Some(SegmenterMode::Search),
+                lowercase: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_arguments() {
+        assert_eq!(
+            parse(&["dict=ipadic"]),
+            Err(TokenizerArgumentError::UnknownKey("dict".to_string()))
+        );
+        assert_eq!(
+            parse(&["ja", "search", "extra"]),
+            Err(TokenizerArgumentError::UnknownKey("extra".to_string()))
+        );
+        assert_eq!(
+            parse(&["config=badpath"]),
+            Err(TokenizerArgumentError::InvalidValue {
+                key: "config".to_string(),
+                value: "badpath".to_string(),
+                reason: "no such file".to_string(),
+            })
+        );
+        assert_eq!(
+            parse(&["fr"]),
+            Err(TokenizerArgumentError::InvalidValue {
+                key: "lang".to_string(),
+                value: "fr".to_string(),
+                reason: "expected ja, ko or zh".to_string(),
+            })
+        );
+        assert!(matches!(
+            parse(&["lowercase=yes"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn it_builds_with_user_dictionary() {
+        let args = parse(&["ja", "user_dictionary", "./resources/ipadic_simple.csv"]).unwrap();
+        assert_eq!(
+            args.user_dictionary,
+            Some(PathBuf::from("./resources/ipadic_simple.csv"))
+        );
+        let tokenizer = args.build().unwrap().index;
+
+        let texts = tokenizer
+            .tokenize("東京スカイツリーの最寄り駅")
+            .unwrap()
+            .into_iter()
+            .map(|token| token.text.to_string())
+            .collect::<Vec<_>>();
+        assert!(texts.contains(&"東京スカイツリー".to_string()));
+
+        assert!(matches!(
+            parse(&["user_dictionary=missing.csv"]),
+            Err(TokenizerArgumentError::InvalidValue { .. })
+        ));
+    }
+
+    #[cfg(feature = "cc-cedict")]
+    #[test]
+    fn it_colocates_pinyin_from_cc_cedict() {
+        use crate::common::{Fts5Tokenizer, FTS5_TOKEN_COLOCATED};
+
+        let user_dictionary = std::env::temp_dir().join(format!(
+            "lindera-sqlite-cc-cedict-{}.csv",
+            std::process::id()
+        ));
+        std::fs::write(&user_dictionary, "北京,名词,Bei3 jing1\n").unwrap();
+        let args = TokenizerArgs {
+            database_config: Some("segmenter:\n  dictionary:\n    kind: cc-cedict\n".to_string()),
+            user_dictionary: Some(user_dictionary.clone()),
+            ..Default::default()
+        };
+        let pipelines = args.build();
+        std::fs::remove_file(&user_dictionary).unwrap();
+        let pipelines = pipelines.unwrap();
+
+        // CC-CEDICT gives the pinyin after the four part-of-speech fields.
+        let fields = DetailFields::of(pipelines.settings.dictionary_kind);
+        assert_eq!(fields.pinyin, Some(4));
+        let mut tokenizer = Fts5Tokenizer::new(pipelines.index);
+        tokenizer.pinyin = true;
+        tokenizer.pinyin_field = fields.pinyin;
+        assert_eq!(
+            tokenizer
+                .tokenize_str("北京")
+                .map(|token| (token.surface, token.flags))
+                .collect::<Vec<_>>(),
+            [("北京", 0), ("beijing", FTS5_TOKEN_COLOCATED)]
+                .map(|(token, flags)| (token.to_owned(), flags))
+        );
+    }
+
+    #[test]
+    fn it_builds_index_and_query_pipelines() {
+        let surfaces = |tokenizer: &Tokenizer| {
+            tokenizer
+                .tokenize("ＬＩＮＤＥＲＡ")
+                .unwrap()
+                .into_iter()
+                .map(|token| token.text.to_string())
+                .collect::<Vec<_>>()
+        };
+        let args = TokenizerArgs {
+            database_config: Some(
+                "segmenter:\n  dictionary:\n    kind: \"ipadic\"\n\
+                 index:\n  character_filters:\n\
+                 \x20   - kind: \"unicode_normalize\"\n      args:\n        kind: \"nfkc\"\n\
+                 query:\n  character_filters: []\n"
+                    .to_string(),
+            ),
+            lowercase: true,
+            ..Default::default()
+        };
+        let pipelines = args.build().unwrap();
+
+        assert_eq!(surfaces(&pipelines.index), ["lindera"]);
+        // Arguments apply to both pipelines.
+        assert_eq!(
+            surfaces(pipelines.query.as_ref().unwrap()),
+            ["ｌｉｎｄｅｒａ"]
+        );
+
+        let args = TokenizerArgs {
+            database_config: Some("segmenter:\n  dictionary:\n    kind: \"ipadic\"\n".to_string()),
+            ..Default::default()
+        };
+        assert!(args.build().unwrap().query.is_none());
+    }
+
+    #[test]
+    fn it_builds_with_overrides() {
+        let args = TokenizerArgs {
+            mode: Some(SegmenterMode::Search),
+            lowercase: true,
+            normalize: true,
+            ..Default::default()
+        };
+        let tokenizer = args.build().unwrap().index;
+
+        assert!(tokenizer.segmenter.mode.is_search());
+        let texts = tokenizer
+            .tokenize("ＬＩＮＤＥＲＡ")
+            .unwrap()
+            .into_iter()
+            .map(|token| token.text.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["lindera"]);
+    }
+
+    #[test]
+    fn it_builds_with_numbers() {
+        let surfaces = |numbers: Numbers, text: &str| {
+            let args = TokenizerArgs {
+                database_config: Some(
+                    "segmenter:\n  dictionary:\n    kind: \"ipadic\"\n".to_string(),
+                ),
+                numbers: Some(numbers),
+                ..Default::default()
+            };
+            args.build()
+                .unwrap()
+                .index
+                .tokenize(text)
+                .unwrap()
+                .into_iter()
+                .map(|token| token.text.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(surfaces(Numbers::Digits, "１２３"), ["123"]);
+        assert_eq!(surfaces(Numbers::Digits, "三百").concat(), "三百");
+        assert_eq!(surfaces(Numbers::Kanji, "三百"), ["300"]);
+        assert_eq!(surfaces(Numbers::Kanji, "１２３"), ["123"]);
+    }
+}
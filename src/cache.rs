@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use libc::c_int;
+
+use crate::args::{Pipelines, TokenizerArgs};
+use crate::common::*;
+use crate::error::LinderaSqliteError;
+use crate::logging::log_info;
+
+/// Tokenizers loaded so far, by the arguments they were built from. A
+/// `config_path` of `None` stands for the configuration picked up from
+/// `LINDERA_CONFIG_PATH`, `set_global_config` or `LINDERA_CONFIG`.
+///
+/// Loading a configuration builds its dictionaries, so tables sharing one
+/// share the cached tokenizer instead.
+#[derive(Default)]
+pub(crate) struct TokenizerCache {
+    tokenizers: Mutex<HashMap<TokenizerArgs, CachedTokenizer>>,
+    /// Bumped by [`TokenizerCache::invalidate`]; entries from an earlier
+    /// generation are built again.
+    generation: AtomicU64,
+    /// Bumped by [`TokenizerCache::evict_default_configs`].
+    evictions: AtomicU64,
+}
+
+struct CachedTokenizer {
+    tokenizer: Pipelines,
+    /// The modification time of the configuration file it was reloaded from,
+    /// `None` if it was never reloaded.
+    modified: Option<SystemTime>,
+    generation: u64,
+}
+
+/// What a [`TokenizerCache`] holds at one point in time.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct CacheStats {
+    pub(crate) cached_configs: usize,
+    /// Sorted and deduplicated, with `None` for the default configuration
+    /// first.
+    pub(crate) config_paths: Vec<Option<PathBuf>>,
+}
+
+impl TokenizerCache {
+    /// The cache shared by every connection in the process.
+    pub(crate) fn global() -> &'static TokenizerCache {
+        static CACHE: OnceLock<TokenizerCache> = OnceLock::new();
+        CACHE.get_or_init(TokenizerCache::default)
+    }
+
+    /// Returns the tokenizers for `args`, building them on first use and
+    /// sharing them afterwards.
+    pub(crate) fn tokenizer(&self, args: &TokenizerArgs) -> Result<Pipelines, LinderaSqliteError> {
+        let mut tokenizers = self
+            .tokenizers
+            .lock()
+            .map_err(|_| LinderaSqliteError::Sqlite(SQLITE_INTERNAL))?;
+        let generation = self.generation();
+        if let Some(cached) = tokenizers.get(args) {
+            if cached.generation == generation {
+                return Ok(cached.tokenizer.clone());
+            }
+        }
+
+        let tokenizer = args.build()?;
+        log_info!("Loaded tokenizer for {:?}", args);
+        tokenizers.insert(
+            args.clone(),
+            CachedTokenizer {
+                tokenizer: tokenizer.clone(),
+                modified: None,
+                generation,
+            },
+        );
+
+        Ok(tokenizer)
+    }
+
+    /// Returns the tokenizers for `args` rebuilt from a configuration file
+    /// last modified at `modified`. Tables watching the same file rebuild it once
+    /// between them.
+    pub(crate) fn reload(
+        &self,
+        args: &TokenizerArgs,
+        modified: Option<SystemTime>,
+    ) -> Result<Pipelines, LinderaSqliteError> {
+        let mut tokenizers = self
+            .tokenizers
+            .lock()
+            .map_err(|_| LinderaSqliteError::Sqlite(SQLITE_INTERNAL))?;
+        let generation = self.generation();
+        if let Some(cached) = tokenizers.get(args) {
+            if cached.generation == generation
+                && cached.modified.is_some()
+                && cached.modified == modified
+            {
+                return Ok(cached.tokenizer.clone());
+            }
+        }
+
+        let tokenizer = args.build()?;
+        log_info!("Reloaded tokenizer for {:?}", args);
+        tokenizers.insert(
+            args.clone(),
+            CachedTokenizer {
+                tokenizer: tokenizer.clone(),
+                modified,
+                generation,
+            },
+        );
+
+        Ok(tokenizer)
+    }
+
+    /// Makes every tokenizer be built again from its configuration as it is
+    /// now, the next time it is asked for.
+    pub(crate) fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// How many times the cache was invalidated so far.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Changes whenever tokenizers taken from the cache may be out of date:
+    /// when it is invalidated or the default configurations are evicted.
+    pub(crate) fn version(&self) -> (u64, u64) {
+        (self.generation(), self.evictions.load(Ordering::Acquire))
+    }
+
+    /// Forgets the tokenizers built from the configuration of the process
+    /// rather than from a file or an embedded dictionary's defaults.
+    pub(crate) fn evict_default_configs(&self) -> Result<(), c_int> {
+        let mut tokenizers = self.tokenizers.lock().map_err(|_| SQLITE_INTERNAL)?;
+        tokenizers
+            .retain(|args, _| args.config_path.is_some() || args.embedded_dictionary.is_some());
+        self.evictions.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    pub(crate) fn stats(&self) -> Result<CacheStats, c_int> {
+        let tokenizers = self.tokenizers.lock().map_err(|_| SQLITE_INTERNAL)?;
+        let mut config_paths = tokenizers
+            .keys()
+            .map(|args| args.config_path.clone())
+            .collect::<Vec<_>>();
+        config_paths.sort();
+        config_paths.dedup();
+
+        Ok(CacheStats {
+            cached_configs: tokenizers.len(),
+            config_paths,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn it_loads_each_config_once() {
+        let cache = TokenizerCache::default();
+        let path = PathBuf::from("./resources/lindera.yml");
+        let args = TokenizerArgs {
+            config_path: Some(path.clone()),
+            ..Default::default()
+        };
+        for _ in 0..3 {
+            cache.tokenizer(&args).unwrap();
+        }
+
+        assert_eq!(
+            cache.stats(),
+            Ok(CacheStats {
+                cached_configs: 1,
+                config_paths: vec![Some(path)],
+            })
+        );
+    }
+
+    #[test]
+    fn it_rebuilds_invalidated_configs() {
+        let cache = TokenizerCache::default();
+        let args = TokenizerArgs {
+            config_path: Some(PathBuf::from("./resources/lindera.yml")),
+            ..Default::default()
+        };
+        cache.tokenizer(&args).unwrap();
+        let before = cache.tokenizer(&args).unwrap().index;
+
+        assert!(Arc::ptr_eq(&before, &cache.tokenizer(&args).unwrap().index));
+
+        cache.invalidate();
+        assert!(!Arc::ptr_eq(
+            &before,
+            &cache.tokenizer(&args).unwrap().index
+        ));
+        assert_eq!(cache.stats().unwrap().cached_configs, 1);
+    }
+
+    #[test]
+    fn it_evicts_default_configs() {
+        let cache = TokenizerCache::default();
+        let path = PathBuf::from("./resources/lindera.yml");
+        let with_path = TokenizerArgs {
+            config_path: Some(path.clone()),
+            ..Default::default()
+        };
+        cache.tokenizer(&with_path).unwrap();
+        cache.tokenizer(&TokenizerArgs::default()).unwrap();
+        assert_eq!(cache.stats().unwrap().cached_configs, 2);
+        let version = cache.version();
+
+        cache.evict_default_configs().unwrap();
+        assert_ne!(cache.version(), version);
+        assert_eq!(
+            cache.stats(),
+            Ok(CacheStats {
+                cached_configs: 1,
+                config_paths: vec![Some(path)],
+            })
+        );
+    }
+}
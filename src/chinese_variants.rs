@@ -0,0 +1,157 @@
+//! Traditional and simplified Chinese characters indexed as one, so that a
+//! table serves users typing either script.
+//!
+//! A configuration names the conversion with its `chinese_variants` key:
+//! `t2s`, built in with the `chinese-variants` feature, turns traditional
+//! characters into simplified ones, and any other value is the path of a
+//! dictionary in the text format of OpenCC, such as its `TSCharacters.txt`:
+//!
+//! ```yaml
+//! chinese_variants: t2s
+//! ```
+//!
+//! Tokens are converted after the filters, in documents and queries alike,
+//! as a whole if the dictionary has them, such as the phrases of OpenCC's
+//! `TSPhrases.txt`, or else character by character.
+
+use std::collections::HashMap;
+use std::fs;
+
+use serde_json::Value;
+
+use crate::error::LinderaSqliteError;
+
+/// The names of the built-in conversions.
+const BUILTIN_NAMES: [&str; 1] = ["t2s"];
+
+/// What a tokenizer converts text to.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ChineseVariants {
+    map: HashMap<String, String>,
+}
+
+impl ChineseVariants {
+    /// The conversion named by the `chinese_variants` value of a
+    /// configuration: a built-in one or the path of a dictionary.
+    #[cfg_attr(not(feature = "extension"), allow(dead_code))]
+    pub(crate) fn from_config(value: &Value) -> Result<Self, LinderaSqliteError> {
+        let name = value.as_str().ok_or_else(|| {
+            LinderaSqliteError::ConfigInvalid(format!(
+                "chinese_variants must be {} or the path of an OpenCC dictionary, not {}",
+                BUILTIN_NAMES.join(", "),
+                value
+            ))
+        })?;
+        let dictionary = match builtin(name) {
+            Some(dictionary) => dictionary.to_string(),
+            None if !cfg!(feature = "chinese-variants") && BUILTIN_NAMES.contains(&name) => {
+                return Err(LinderaSqliteError::ConfigInvalid(format!(
+                    "chinese_variants {:?} needs lindera-sqlite built with the chinese-variants feature",
+                    name
+                )))
+            }
+            None => fs::read_to_string(name).map_err(|e| {
+                LinderaSqliteError::ConfigInvalid(format!(
+                    "Failed to read chinese_variants {:?}: {}",
+                    name, e
+                ))
+            })?,
+        };
+        let mut variants = ChineseVariants::default();
+        variants.add_dictionary(&dictionary);
+        Ok(variants)
+    }
+
+    /// Adds the entries of `dictionary`, one per line, each a key, a tab
+    /// and the forms it converts to separated by spaces, of which the first
+    /// is taken. Blank lines and comments starting with `#` are skipped.
+    fn add_dictionary(&mut self, dictionary: &str) {
+        self.map.extend(
+            dictionary
+                .lines()
+                .filter(|line| !line.starts_with('#'))
+                .filter_map(|line| line.split_once('\t'))
+                .filter_map(|(key, forms)| {
+                    let form = forms.split_whitespace().next()?;
+                    Some((key.trim().to_string(), form.to_string()))
+                })
+                .filter(|(key, _)| !key.is_empty()),
+        );
+    }
+
+    /// `text` converted, or `None` if nothing in it converts.
+    pub(crate) fn convert(&self, text: &str) -> Option<String> {
+        // Phrases may keep characters that convert on their own.
+        if let Some(form) = self.map.get(text) {
+            return (form != text).then(|| form.clone());
+        }
+        let mut converted = String::with_capacity(text.len());
+        let mut changed = false;
+        let mut buf = [0; 4];
+        for c in text.chars() {
+            match self.map.get(&*c.encode_utf8(&mut buf)) {
+                Some(form) => {
+                    converted.push_str(form);
+                    changed = true;
+                }
+                None => converted.push(c),
+            }
+        }
+        changed.then_some(converted)
+    }
+}
+
+/// The built-in dictionary called `name`.
+#[cfg(feature = "chinese-variants")]
+fn builtin(name: &str) -> Option<&'static str> {
+    match name {
+        "t2s" => Some(include_str!("../resources/chinese_variants/t2s.txt")),
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "chinese-variants"))]
+fn builtin(_name: &str) -> Option<&'static str> {
+    None
+}
+
+#[cfg(test)]
+impl ChineseVariants {
+    pub(crate) fn from_dictionary(dictionary: &str) -> Self {
+        let mut variants = ChineseVariants::default();
+        variants.add_dictionary(dictionary);
+        variants
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn it_converts_phrases_and_characters() {
+        let variants = ChineseVariants::from_dictionary(
+            "# OpenCC\n語\t语\n詞\t词\n乾\t干 乾\n乾隆\t乾隆\n數\t数\n",
+        );
+        assert_eq!(variants.convert("詞語").as_deref(), Some("词语"));
+        assert_eq!(variants.convert("乾隆"), None);
+        assert_eq!(variants.convert("乾燥").as_deref(), Some("干燥"));
+        assert_eq!(variants.convert("词语"), None);
+    }
+
+    #[test]
+    fn it_rejects_invalid_configs() {
+        assert!(ChineseVariants::from_config(&json!("/no/such/dictionary.txt")).is_err());
+        assert!(ChineseVariants::from_config(&json!(["t2s"])).is_err());
+    }
+
+    #[cfg(feature = "chinese-variants")]
+    #[test]
+    fn it_converts_with_the_builtin_dictionary() {
+        let variants = ChineseVariants::from_config(&json!("t2s")).unwrap();
+        assert_eq!(variants.convert("資料庫").as_deref(), Some("资料库"));
+        assert_eq!(variants.convert("中文"), None);
+    }
+}
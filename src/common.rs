@@ -1,14 +1,272 @@
+use core::fmt;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::sync::{Arc, OnceLock};
+
 use libc::{c_char, c_int, c_void};
 
 use lindera::tokenizer::Tokenizer;
 
+use crate::chinese_variants::ChineseVariants;
+#[cfg(feature = "extension")]
+use crate::logging::log_warn;
+use crate::logging::{self, log_debug, log_error, LogLevel};
+use crate::stopwords::Stopwords;
+use crate::synonyms::Synonyms;
+
 // sqlite3.h
 pub const SQLITE_OK: c_int = 0;
+pub const SQLITE_ERROR: c_int = 1;
 pub const SQLITE_INTERNAL: c_int = 2;
+pub const SQLITE_IOERR: c_int = 10;
+pub const SQLITE_CANTOPEN: c_int = 14;
+pub const SQLITE_TOOBIG: c_int = 18;
+pub const SQLITE_CONSTRAINT: c_int = 19;
 pub const SQLITE_MISUSE: c_int = 21;
+pub const SQLITE_FORMAT: c_int = 24;
+pub const SQLITE_RANGE: c_int = 25;
+pub const SQLITE_ROW: c_int = 100;
+pub const SQLITE_DONE: c_int = 101;
 
+// fts5.h
+pub const FTS5_TOKENIZE_QUERY: c_int = 0x0001;
+pub const FTS5_TOKENIZE_PREFIX: c_int = 0x0002;
+pub const FTS5_TOKENIZE_DOCUMENT: c_int = 0x0004;
+pub const FTS5_TOKENIZE_AUX: c_int = 0x0008;
+pub const FTS5_TOKEN_COLOCATED: c_int = 0x0001;
+
+/// The tokenizer FTS5 holds for one table.
+///
+/// Dropping it frees the segmenter's dictionaries and the filters, through
+/// the `Arc`s other tables may share them with, along with the locale
+/// tokenizers, the reload source and the stats of the table. Lindera keeps
+/// dictionaries in owned buffers, with no file handles or memory maps, and
+/// has no cleanup API beyond that.
 pub struct Fts5Tokenizer {
-    pub tokenizer: Tokenizer,
+    /// Shared with the other tables of the same configuration, since cloning
+    /// a tokenizer copies its dictionaries.
+    pub tokenizer: Arc<Tokenizer>,
+    /// Tokenizes queries instead of `tokenizer`, for configurations with a
+    /// `query` pipeline.
+    pub query_tokenizer: Option<Arc<Tokenizer>>,
+    /// Splits ASCII-only text on anything but letters and digits instead of
+    /// running Lindera on it.
+    pub ascii_fast_path: bool,
+    /// Indexes the original surface of a token, colocated with it, when the
+    /// filters rewrote it, so that queries for the text as written match too.
+    pub keep_original: bool,
+    /// Follows each token of a document with the parts Lindera's `decompose`
+    /// mode splits it into, colocated with it, so that both compound words
+    /// and their parts are found.
+    pub hybrid: bool,
+    /// `tokenizer` in Lindera's `decompose` mode, for the hybrid mode, built
+    /// once and shared like it.
+    pub(crate) decompose_tokenizer: Option<Arc<Tokenizer>>,
+    /// Indexes the dictionary base form of inflected words, so that `食べる`
+    /// also finds `食べた`.
+    pub base_form: BaseForm,
+    /// The field of the token details that holds the base form, which
+    /// depends on the dictionary. Without one, `base_form` has no effect.
+    pub base_form_field: Option<usize>,
+    /// Indexes Korean words without the particles attached to them, so that
+    /// `서울` also finds `서울에서`.
+    pub josa: Josa,
+    /// Folds hiragana and katakana into one script, so that `りんご` and
+    /// `リンゴ` match each other.
+    pub kana: Kana,
+    /// Normalizes the prolonged sound mark, so that `サーバー` and `サーバ`
+    /// match each other.
+    pub long_vowel: bool,
+    /// Writes small kana full size, so that `ウィンドウ` and `ウインドウ`
+    /// match each other.
+    pub small_kana: bool,
+    /// Tokenizes text outside Chinese, Japanese and Korean as SQLite's
+    /// `unicode61` tokenizer does, with the options given, rather than
+    /// running Lindera on it.
+    pub unicode61: Option<Unicode61>,
+    /// Indexes Hangul tokens written as the jamo they are typed with,
+    /// colocated with them, so that prefix queries find words from what is
+    /// typed while a syllable is being composed.
+    pub jamo: bool,
+    /// Indexes the pinyin of Chinese words, colocated with them, so that
+    /// `beijing` also finds `北京`.
+    pub pinyin: bool,
+    /// The field of the token details that holds pinyin, which only
+    /// CC-CEDICT has. Without one, `pinyin` has no effect.
+    pub pinyin_field: Option<usize>,
+    /// Expands the tokens of queries with their synonyms, colocated with
+    /// them, for configurations with a `synonyms` file.
+    pub(crate) synonyms: Option<Arc<Synonyms>>,
+    /// Leaves out the words of the configuration's `stopwords` lists.
+    pub(crate) stopwords: Option<Arc<Stopwords>>,
+    /// Converts traditional and simplified Chinese characters alike, for
+    /// configurations with `chinese_variants`.
+    pub(crate) chinese_variants: Option<Arc<ChineseVariants>>,
+    /// What to rebuild the tokenizer from on `lindera_reload()` or, with
+    /// `LINDERA_CONFIG_RELOAD` set, when its configuration file changes.
+    #[cfg(feature = "extension")]
+    pub(crate) source: Option<crate::reload::TokenizerSource>,
+    /// The tokenizers for the locales of rows, for tables created through
+    /// the extension.
+    #[cfg(feature = "extension")]
+    pub(crate) locales: Option<crate::locale::LocaleTokenizers>,
+    /// What the tokenizer has done, for `lindera_stats`, for tables created
+    /// through the extension.
+    #[cfg(feature = "extension")]
+    pub(crate) stats: Option<std::sync::Arc<crate::stats::TokenizerStats>>,
+}
+
+impl Fts5Tokenizer {
+    pub fn new(tokenizer: impl Into<Arc<Tokenizer>>) -> Self {
+        Fts5Tokenizer {
+            tokenizer: tokenizer.into(),
+            query_tokenizer: None,
+            ascii_fast_path: false,
+            keep_original: false,
+            hybrid: false,
+            decompose_tokenizer: None,
+            base_form: BaseForm::Off,
+            base_form_field: None,
+            josa: Josa::Keep,
+            kana: Kana::Keep,
+            long_vowel: false,
+            small_kana: false,
+            unicode61: None,
+            jamo: false,
+            pinyin: false,
+            pinyin_field: None,
+            synonyms: None,
+            stopwords: None,
+            chinese_variants: None,
+            #[cfg(feature = "extension")]
+            source: None,
+            #[cfg(feature = "extension")]
+            locales: None,
+            #[cfg(feature = "extension")]
+            stats: None,
+        }
+    }
+}
+
+/// What a tokenizer indexes for a word the dictionary gives a base form for,
+/// such as `食べる` for `食べた`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BaseForm {
+    /// The token as the filters leave it.
+    #[default]
+    Off,
+    /// The base form in place of the token, in documents and queries alike.
+    Replace,
+    /// The token, and the base form colocated with it in documents, so that
+    /// queries find both the form they give and every form of the word.
+    Colocate,
+}
+
+impl BaseForm {
+    /// The setting called `name` in tokenizer arguments and configurations.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(BaseForm::Off),
+            "replace" => Some(BaseForm::Replace),
+            "colocate" => Some(BaseForm::Colocate),
+            _ => None,
+        }
+    }
+}
+
+/// What a tokenizer indexes for a Korean word with particles (josa)
+/// attached, such as `서울에서`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Josa {
+    /// The token as the filters leave it.
+    #[default]
+    Keep,
+    /// The word without its particles in place of the token, in documents
+    /// and queries alike.
+    Strip,
+    /// The token, and the word without its particles colocated with it in
+    /// documents.
+    Colocate,
+}
+
+impl Josa {
+    /// The setting called `name` in tokenizer arguments and configurations.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "keep" => Some(Josa::Keep),
+            "strip" => Some(Josa::Strip),
+            "colocate" => Some(Josa::Colocate),
+            _ => None,
+        }
+    }
+}
+
+/// The script a tokenizer folds the kana of tokens into.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kana {
+    /// Kana as the filters leave them.
+    #[default]
+    Keep,
+    Hiragana,
+    Katakana,
+}
+
+impl Kana {
+    /// The setting called `name` in tokenizer arguments and configurations.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "keep" => Some(Kana::Keep),
+            "hiragana" => Some(Kana::Hiragana),
+            "katakana" => Some(Kana::Katakana),
+            _ => None,
+        }
+    }
+}
+
+/// How a tokenizer splits and folds text outside Chinese, Japanese and
+/// Korean, with the options of SQLite's `unicode61` tokenizer.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Unicode61 {
+    pub remove_diacritics: RemoveDiacritics,
+    /// Characters taken as part of tokens besides letters and numbers.
+    pub tokenchars: String,
+    /// Characters taken as separators besides anything else, even letters
+    /// and numbers. A character in `tokenchars` too is a separator.
+    pub separators: String,
+}
+
+/// How a tokenizer removes the diacritics of Latin letters, as the
+/// `remove_diacritics` option of SQLite's `unicode61` tokenizer does.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RemoveDiacritics {
+    /// Letters keep their diacritics.
+    Off,
+    /// Letters with one diacritic lose it, so that `é` is indexed as `e`.
+    #[default]
+    Simple,
+    /// Letters lose all of their diacritics, so that `ǖ` is indexed as `u`
+    /// too.
+    Complex,
+}
+
+impl RemoveDiacritics {
+    /// The setting called `name` in tokenizer arguments, the digit
+    /// `unicode61` takes.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "0" => Some(RemoveDiacritics::Off),
+            "1" => Some(RemoveDiacritics::Simple),
+            "2" => Some(RemoveDiacritics::Complex),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for Fts5Tokenizer {
+    fn drop(&mut self) {
+        // Nothing besides the fields needs freeing; see the type's docs.
+        log_debug!("Dropping tokenizer");
+    }
 }
 
 pub type TokenFunction = extern "C" fn(
@@ -19,3 +277,208 @@ pub type TokenFunction = extern "C" fn(
     i_start: c_int,
     i_end: c_int,
 ) -> c_int;
+
+/// The `xToken` callback FTS5 passes to `xTokenize`, together with its
+/// context pointer.
+#[derive(Clone, Copy)]
+pub struct TokenCallback {
+    x_token: TokenFunction,
+    p_ctx: *mut c_void,
+}
+
+impl TokenCallback {
+    pub fn new(x_token: TokenFunction, p_ctx: *mut c_void) -> Self {
+        TokenCallback { x_token, p_ctx }
+    }
+
+    /// Passes one token and its byte range in the input text to FTS5.
+    pub fn emit(&self, token: &[u8], byte_start: usize, byte_end: usize) -> Result<(), c_int> {
+        self.emit_with_flags(token, byte_start, byte_end, 0)
+    }
+
+    /// Passes a token at the same position as the one before it, such as a
+    /// synonym.
+    pub fn emit_colocated(
+        &self,
+        token: &[u8],
+        byte_start: usize,
+        byte_end: usize,
+    ) -> Result<(), c_int> {
+        self.emit_with_flags(token, byte_start, byte_end, FTS5_TOKEN_COLOCATED)
+    }
+
+    /// Passes one token to FTS5 with the given `FTS5_TOKEN_*` flags.
+    ///
+    /// `token` need only stay valid until this returns, as FTS5 copies the
+    /// tokens it keeps.
+    pub fn emit_with_flags(
+        &self,
+        token: &[u8],
+        byte_start: usize,
+        byte_end: usize,
+        flags: c_int,
+    ) -> Result<(), c_int> {
+        let rc = (self.x_token)(
+            self.p_ctx,
+            flags,
+            token.as_ptr() as *const c_char,
+            cast_usize_to_c_int(token.len())?,
+            cast_usize_to_c_int(byte_start)?,
+            cast_usize_to_c_int(byte_end)?,
+        );
+        if rc != SQLITE_OK {
+            return Err(rc);
+        }
+
+        Ok(())
+    }
+}
+
+/// A [`TokenCallback`] that may be moved to another thread, for handing
+/// tokenization to worker threads.
+///
+/// FTS5 makes no promise that its `xToken` context may be used off the
+/// calling thread, so `lindera_fts5_tokenize` keeps using [`TokenCallback`].
+#[derive(Clone, Copy)]
+pub struct SendableTokenCallback(TokenCallback);
+
+// SAFETY: `SendableTokenCallback::new` requires the context pointer to be
+// usable from any thread.
+unsafe impl Send for SendableTokenCallback {}
+
+impl SendableTokenCallback {
+    /// # Safety
+    ///
+    /// The context pointer of `callback` must stay valid, and `x_token` must
+    /// be safe to call with it, from whichever thread the wrapper is sent to.
+    pub unsafe fn new(callback: TokenCallback) -> Self {
+        SendableTokenCallback(callback)
+    }
+
+    pub fn callback(&self) -> &TokenCallback {
+        &self.0
+    }
+}
+
+/// `sqlite3_log`, which passes a message to the error log callback set with
+/// `SQLITE_CONFIG_LOG`.
+pub(crate) type SqliteLog = unsafe extern "C" fn(code: c_int, format: *const c_char, ...);
+
+/// The `sqlite3_log` of the SQLite the extension was first loaded into.
+static SQLITE_LOG: OnceLock<SqliteLog> = OnceLock::new();
+
+/// Sends errors to `log` from now on, besides stderr. A process links one
+/// SQLite, so only the first call takes effect.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn set_sqlite_log(log: SqliteLog) {
+    let _ = SQLITE_LOG.set(log);
+}
+
+thread_local! {
+    /// The last error reported on this thread, for `lindera_last_error()`.
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Makes `message` what `lindera_last_error()` returns on this thread.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn set_last_error(message: String) {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// The last error reported on this thread, if any.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn last_error() -> Option<String> {
+    LAST_ERROR.with(|last_error| last_error.borrow().clone())
+}
+
+/// Reports an error to stderr and, once the extension is loaded, to SQLite's
+/// error log under `code`, unless `LINDERA_LOG_LEVEL` is `none`. Either way,
+/// it becomes the thread's last error.
+pub(crate) fn report_error(code: c_int, message: fmt::Arguments<'_>) {
+    let message = message.to_string();
+    if logging::enabled(LogLevel::Error) {
+        log_error!("{}", message);
+        sqlite_log(code, &message);
+    }
+    set_last_error(message);
+}
+
+/// Reports a failure the crate recovers from, as [`report_error`] does but
+/// only down to `LINDERA_LOG_LEVEL=warn`.
+#[cfg(feature = "extension")]
+pub(crate) fn report_warning(code: c_int, message: fmt::Arguments<'_>) {
+    let message = message.to_string();
+    if logging::enabled(LogLevel::Warn) {
+        log_warn!("{}", message);
+        sqlite_log(code, &message);
+    }
+    set_last_error(message);
+}
+
+fn sqlite_log(code: c_int, message: &str) {
+    if let Some(log) = SQLITE_LOG.get() {
+        // Messages only carry an interior nul if the text being tokenized
+        // does, in which case the log goes without it.
+        if let Ok(message) = CString::new(message) {
+            unsafe { log(code, c"%s".as_ptr(), message.as_ptr()) };
+        }
+    }
+}
+
+pub(crate) fn cast_usize_to_c_int(value: usize) -> Result<c_int, c_int> {
+    c_int::try_from(value).map_err(|_| SQLITE_INTERNAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_casts_usize_within_c_int_range() {
+        assert_eq!(cast_usize_to_c_int(0), Ok(0));
+        assert_eq!(cast_usize_to_c_int(c_int::MAX as usize), Ok(c_int::MAX));
+    }
+
+    #[test]
+    fn it_rejects_usize_beyond_c_int_range() {
+        assert_eq!(
+            cast_usize_to_c_int(c_int::MAX as usize + 1),
+            Err(SQLITE_INTERNAL)
+        );
+        assert_eq!(cast_usize_to_c_int(usize::MAX), Err(SQLITE_INTERNAL));
+    }
+
+    /// Lets Miri check that dropping a tokenizer frees all it allocated, with
+    /// a dictionary of no words, since loading a real one takes too long
+    /// under Miri.
+    #[cfg(miri)]
+    #[test]
+    fn it_frees_tokenizers_on_drop() {
+        use lindera::dictionary::Dictionary;
+        use lindera::mode::Mode;
+        use lindera::segmenter::Segmenter;
+
+        let dictionary: Dictionary = serde_json::from_value(serde_json::json!({
+            "prefix_dictionary": {
+                "da": [],
+                "vals_data": [],
+                "words_idx_data": [],
+                "words_data": [],
+                "is_system": true,
+            },
+            "connection_cost_matrix": { "costs_data": [0, 0], "backward_size": 1 },
+            "character_definition": {
+                "category_definitions": [],
+                "category_names": [],
+                "mapping": { "boundaries": [0], "values": [[]] },
+            },
+            "unknown_dictionary": { "category_references": [], "costs": [] },
+        }))
+        .unwrap();
+
+        for _ in 0..16 {
+            let segmenter = Segmenter::new(Mode::Normal, dictionary.clone(), None);
+            drop(Fts5Tokenizer::new(Tokenizer::new(segmenter)));
+        }
+    }
+}
@@ -0,0 +1,644 @@
+//! Tokenizer configurations: built in Rust, or resolved from, in order of
+//! precedence,
+//!
+//! 1. the arguments of a table, merged onto the base configuration below, or
+//!    onto a file they name;
+//! 2. the row of the database's `lindera_config(name, yaml)` table that
+//!    `config_name` names, `default` without it;
+//! 3. the file named by `LINDERA_CONFIG_PATH`;
+//! 4. [`set_global_config`];
+//! 5. the text of `LINDERA_CONFIG`;
+//! 6. `resources/lindera.yml`, with the `default-config` feature;
+//! 7. Lindera's own default.
+//!
+//! Besides the tokenizer it describes, a configuration may define named
+//! profiles in a `tokenizers` map, each a configuration of its own that the
+//! extension registers as a tokenizer of that name:
+//!
+//! ```yaml
+//! tokenizers:
+//!   lindera_ja:
+//!     segmenter:
+//!       dictionary:
+//!         kind: "ipadic"
+//!   lindera_ko:
+//!     segmenter:
+//!       dictionary:
+//!         kind: "ko-dic"
+//! ```
+
+use std::ffi::{CStr, OsStr};
+use std::path::Path;
+use std::sync::RwLock;
+
+use libc::{c_char, c_int};
+use lindera::dictionary::DictionaryKind;
+use lindera::mode::{Mode, Penalty};
+use lindera::tokenizer::{Tokenizer, TokenizerConfig};
+use serde_json::{json, Value};
+
+use crate::common::*;
+use crate::error::LinderaSqliteError;
+use crate::logging::log_debug;
+use crate::report_tokenizer_error;
+
+/// A tokenizer configuration built in Rust, as an alternative to a YAML or
+/// JSON file. It mirrors the schema of Lindera's configuration files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinderaConfig {
+    pub dictionary: DictionaryType,
+    pub segmentation_mode: SegmentationMode,
+    pub character_filters: Vec<CharacterFilterConfig>,
+    pub token_filters: Vec<TokenFilterConfig>,
+}
+
+/// One of the dictionaries Lindera can embed, `segmenter.dictionary.kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DictionaryType {
+    Ipadic,
+    IpadicNeologd,
+    Unidic,
+    KoDic,
+    CcCedict,
+}
+
+/// `segmenter.mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentationMode {
+    Normal,
+    /// Also splits compound words, with Lindera's default penalties.
+    Decompose,
+}
+
+/// An entry of `character_filters`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharacterFilterConfig {
+    pub kind: String,
+    pub args: Value,
+}
+
+/// An entry of `token_filters`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenFilterConfig {
+    pub kind: String,
+    pub args: Value,
+}
+
+impl DictionaryType {
+    fn kind(self) -> DictionaryKind {
+        match self {
+            DictionaryType::Ipadic => DictionaryKind::IPADIC,
+            DictionaryType::IpadicNeologd => DictionaryKind::IPADICNEologd,
+            DictionaryType::Unidic => DictionaryKind::UniDic,
+            DictionaryType::KoDic => DictionaryKind::KoDic,
+            DictionaryType::CcCedict => DictionaryKind::CcCedict,
+        }
+    }
+}
+
+impl SegmentationMode {
+    fn mode(self) -> Mode {
+        match self {
+            SegmentationMode::Normal => Mode::Normal,
+            SegmentationMode::Decompose => Mode::Decompose(Penalty::default()),
+        }
+    }
+}
+
+impl Default for LinderaConfig {
+    fn default() -> Self {
+        LinderaConfig {
+            dictionary: DictionaryType::Ipadic,
+            segmentation_mode: SegmentationMode::Normal,
+            character_filters: Vec::new(),
+            token_filters: Vec::new(),
+        }
+    }
+}
+
+impl LinderaConfig {
+    pub fn builder() -> LinderaConfigBuilder {
+        LinderaConfigBuilder::default()
+    }
+
+    /// Returns the configuration in the form Lindera reads it.
+    pub fn to_value(&self) -> TokenizerConfig {
+        let character_filters = self
+            .character_filters
+            .iter()
+            .map(|filter| json!({ "kind": filter.kind, "args": filter.args }))
+            .collect::<Vec<_>>();
+        let token_filters = self
+            .token_filters
+            .iter()
+            .map(|filter| json!({ "kind": filter.kind, "args": filter.args }))
+            .collect::<Vec<_>>();
+
+        json!({
+            "segmenter": {
+                "mode": self.segmentation_mode.mode(),
+                "dictionary": { "kind": self.dictionary.kind().as_str() },
+            },
+            "character_filters": character_filters,
+            "token_filters": token_filters,
+        })
+    }
+
+    /// Returns the configuration as the YAML of a Lindera configuration file.
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(&self.to_value()).expect("JSON values serialize to YAML")
+    }
+}
+
+/// Builds a [`LinderaConfig`], starting from IPADIC in normal mode with no
+/// filters.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinderaConfigBuilder {
+    config: LinderaConfig,
+}
+
+impl LinderaConfigBuilder {
+    pub fn dictionary(mut self, dictionary: DictionaryType) -> Self {
+        self.config.dictionary = dictionary;
+        self
+    }
+
+    pub fn segmentation_mode(mut self, mode: SegmentationMode) -> Self {
+        self.config.segmentation_mode = mode;
+        self
+    }
+
+    /// Appends a character filter, e.g. `("unicode_normalize", json!({ "kind": "nfkc" }))`.
+    pub fn character_filter(mut self, kind: &str, args: Value) -> Self {
+        self.config.character_filters.push(CharacterFilterConfig {
+            kind: kind.to_string(),
+            args,
+        });
+        self
+    }
+
+    /// Appends a token filter, e.g. `("lowercase", json!({}))`.
+    pub fn token_filter(mut self, kind: &str, args: Value) -> Self {
+        self.config.token_filters.push(TokenFilterConfig {
+            kind: kind.to_string(),
+            args,
+        });
+        self
+    }
+
+    pub fn build(self) -> LinderaConfig {
+        self.config
+    }
+}
+
+impl Fts5Tokenizer {
+    /// Creates a tokenizer from `config` without going through a file.
+    pub fn from_config(config: &LinderaConfig) -> Result<Self, LinderaSqliteError> {
+        let tokenizer = Tokenizer::from_config(&config.to_value())
+            .map_err(LinderaSqliteError::from_build_error)?;
+        Ok(Fts5Tokenizer::new(tokenizer))
+    }
+}
+
+/// Changes to the configuration [`crate::load_tokenizer`] reads, for when only
+/// a few settings differ from it. Options left unset keep what that
+/// configuration says, and filters are appended after its own.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fts5TokenizerOptions {
+    dictionary: Option<DictionaryType>,
+    segmentation_mode: Option<SegmentationMode>,
+    /// Collects the filters to append; its dictionary and mode are unused.
+    filters: LinderaConfigBuilder,
+}
+
+impl Fts5TokenizerOptions {
+    pub fn new() -> Self {
+        Fts5TokenizerOptions::default()
+    }
+
+    pub fn dictionary(mut self, dictionary: DictionaryType) -> Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    pub fn segmentation_mode(mut self, mode: SegmentationMode) -> Self {
+        self.segmentation_mode = Some(mode);
+        self
+    }
+
+    /// Appends a character filter, as [`LinderaConfigBuilder::character_filter`]
+    /// does.
+    pub fn character_filter(mut self, kind: &str, args: Value) -> Self {
+        self.filters = self.filters.character_filter(kind, args);
+        self
+    }
+
+    /// Appends a token filter, as [`LinderaConfigBuilder::token_filter`] does.
+    pub fn token_filter(mut self, kind: &str, args: Value) -> Self {
+        self.filters = self.filters.token_filter(kind, args);
+        self
+    }
+
+    /// Returns the configuration of the environment with the options applied.
+    pub fn to_value(&self) -> Result<TokenizerConfig, LinderaSqliteError> {
+        let mut config = env_config()?.unwrap_or_else(|| json!({ "segmenter": {} }));
+
+        let mut overrides = json!({});
+        if let Some(dictionary) = self.dictionary {
+            overrides["segmenter"]["dictionary"]["kind"] = json!(dictionary.kind().as_str());
+        }
+        if let Some(mode) = self.segmentation_mode {
+            overrides["segmenter"]["mode"] = json!(mode.mode());
+        }
+        merge_config(&mut config, overrides);
+        let filters = &self.filters.config;
+        for filter in &filters.character_filters {
+            append_filter(
+                &mut config,
+                "character_filters",
+                &filter.kind,
+                filter.args.clone(),
+            );
+        }
+        for filter in &filters.token_filters {
+            append_filter(
+                &mut config,
+                "token_filters",
+                &filter.kind,
+                filter.args.clone(),
+            );
+        }
+
+        Ok(config)
+    }
+
+    pub fn build(&self) -> Result<Tokenizer, LinderaSqliteError> {
+        Tokenizer::from_config(&self.to_value()?).map_err(LinderaSqliteError::from_build_error)
+    }
+}
+
+impl Fts5Tokenizer {
+    /// Creates a tokenizer from the configuration of the environment with
+    /// `options` applied.
+    pub fn from_options(options: &Fts5TokenizerOptions) -> Result<Self, LinderaSqliteError> {
+        Ok(Fts5Tokenizer::new(options.build()?))
+    }
+}
+
+/// Reads the configuration set by `LINDERA_CONFIG_PATH`, [`set_global_config`]
+/// or `LINDERA_CONFIG`, as [`crate::load_tokenizer`] describes, or `None` if none is
+/// set.
+pub(crate) fn env_config() -> Result<Option<TokenizerConfig>, LinderaSqliteError> {
+    let config_path = std::env::var_os("LINDERA_CONFIG_PATH");
+    let global_config = GLOBAL_CONFIG
+        .read()
+        .map_err(|_| LinderaSqliteError::Sqlite(SQLITE_INTERNAL))?;
+    let inline_config = std::env::var("LINDERA_CONFIG").ok();
+    log_debug!(
+        "Loading tokenizer from LINDERA_CONFIG_PATH={:?}, global config set: {}, LINDERA_CONFIG set: {}",
+        config_path,
+        global_config.is_some(),
+        inline_config.is_some()
+    );
+    config_from_env(
+        config_path.as_deref(),
+        global_config.as_ref(),
+        inline_config.as_deref(),
+    )
+}
+
+fn config_from_env(
+    config_path: Option<&OsStr>,
+    global_config: Option<&TokenizerConfig>,
+    inline_config: Option<&str>,
+) -> Result<Option<TokenizerConfig>, LinderaSqliteError> {
+    match (config_path, global_config, inline_config) {
+        (Some(path), _, _) => read_config(path.as_ref()).map(Some),
+        (None, Some(config), _) => Ok(Some(config.clone())),
+        (None, None, Some(config)) => parse_config(config).map(Some),
+        (None, None, None) => builtin_config(),
+    }
+}
+
+/// Whether [`set_global_config`] has set a configuration.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn global_config_is_set() -> bool {
+    GLOBAL_CONFIG.read().is_ok_and(|config| config.is_some())
+}
+
+/// The configuration set by [`set_global_config`].
+static GLOBAL_CONFIG: RwLock<Option<TokenizerConfig>> = RwLock::new(None);
+
+/// Configures tokenizers created from now on, given as JSON if it starts with
+/// `{`, or as YAML otherwise, for applications that cannot set `LINDERA_CONFIG`.
+///
+/// Tokenizers that already exist keep their configuration, and a file set
+/// by `LINDERA_CONFIG_PATH` or by a table still wins.
+pub fn set_global_config(config: &str) -> Result<(), LinderaSqliteError> {
+    let config = parse_config(config).map_err(report_tokenizer_error)?;
+    replace_global_config(Some(config))
+}
+
+/// Undoes [`set_global_config`] for tokenizers created from now on.
+pub fn clear_global_config() -> Result<(), LinderaSqliteError> {
+    replace_global_config(None)
+}
+
+fn replace_global_config(config: Option<TokenizerConfig>) -> Result<(), LinderaSqliteError> {
+    *GLOBAL_CONFIG
+        .write()
+        .map_err(|_| LinderaSqliteError::Sqlite(SQLITE_INTERNAL))? = config;
+    // Tables created from now on must not reuse tokenizers built from the
+    // previous configuration.
+    #[cfg(feature = "extension")]
+    crate::cache::TokenizerCache::global().evict_default_configs()?;
+    Ok(())
+}
+
+/// Calls [`set_global_config`] with the nul-terminated `config`, or
+/// [`clear_global_config`] if `config` is null.
+///
+/// # Safety
+///
+/// `config` must be null or point to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lindera_set_config(config: *const c_char) -> c_int {
+    let result = if config.is_null() {
+        clear_global_config()
+    } else {
+        match CStr::from_ptr(config).to_str() {
+            Ok(config) => set_global_config(config),
+            Err(_) => Err(LinderaSqliteError::Sqlite(SQLITE_MISUSE)),
+        }
+    };
+
+    match result {
+        Ok(()) => SQLITE_OK,
+        Err(e) => e.code(),
+    }
+}
+
+#[cfg(feature = "default-config")]
+fn builtin_config() -> Result<Option<TokenizerConfig>, LinderaSqliteError> {
+    parse_yaml_config(include_str!("../resources/lindera.yml")).map(Some)
+}
+
+#[cfg(not(feature = "default-config"))]
+fn builtin_config() -> Result<Option<TokenizerConfig>, LinderaSqliteError> {
+    Ok(None)
+}
+
+/// Reads the configuration file at `path`, in JSON or YAML.
+pub(crate) fn read_config(path: &Path) -> Result<TokenizerConfig, LinderaSqliteError> {
+    let config = std::fs::read_to_string(path)
+        .map_err(|e| LinderaSqliteError::Io(format!("failed to read {}: {}", path.display(), e)))?;
+    parse_config(&config)
+}
+
+/// Parses a configuration as JSON if it starts with `{`, or as YAML otherwise.
+pub(crate) fn parse_config(config: &str) -> Result<TokenizerConfig, LinderaSqliteError> {
+    if config.trim_start().starts_with('{') {
+        parse_json_config(config)
+    } else {
+        parse_yaml_config(config)
+    }
+}
+
+pub(crate) fn parse_json_config(json: &str) -> Result<TokenizerConfig, LinderaSqliteError> {
+    serde_json::from_str(json).map_err(|e| LinderaSqliteError::ConfigInvalid(e.to_string()))
+}
+
+pub(crate) fn parse_yaml_config(yaml: &str) -> Result<TokenizerConfig, LinderaSqliteError> {
+    serde_yaml::from_str(yaml).map_err(|e| LinderaSqliteError::ConfigInvalid(e.to_string()))
+}
+
+/// Merges `overrides` into `base`: objects key by key, recursively, and any
+/// other value, arrays included, by replacing it.
+pub(crate) fn merge_config(base: &mut TokenizerConfig, overrides: TokenizerConfig) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge_config(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// The names of the profiles in the `tokenizers` map of `config`.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn profile_names(config: &TokenizerConfig) -> Vec<String> {
+    config["tokenizers"]
+        .as_object()
+        .map(|profiles| profiles.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// The profile `name` of the `tokenizers` map of `config`.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn profile_config(
+    config: &TokenizerConfig,
+    name: &str,
+) -> Result<TokenizerConfig, LinderaSqliteError> {
+    match &config["tokenizers"][name] {
+        Value::Null => Err(LinderaSqliteError::ConfigInvalid(format!(
+            "no tokenizer profile {:?} in the configuration",
+            name
+        ))),
+        profile => Ok(profile.clone()),
+    }
+}
+
+/// The configuration of the `pipeline` of `config`, `index` or `query`: the
+/// filter lists of the section of that name take the place of the top-level
+/// ones. The segmenter is shared, so that queries are split into the terms
+/// the index holds.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn pipeline_config(config: &TokenizerConfig, pipeline: &str) -> TokenizerConfig {
+    let mut pipeline_config = config.clone();
+    if let Some(section) = config.get(pipeline) {
+        for filters in ["character_filters", "token_filters"] {
+            if let Some(section_filters) = section.get(filters) {
+                pipeline_config[filters] = section_filters.clone();
+            }
+        }
+    }
+    pipeline_config
+}
+
+/// Appends a filter to the `filters` list of `config`, creating the list if
+/// needed.
+pub(crate) fn append_filter(config: &mut TokenizerConfig, filters: &str, kind: &str, args: Value) {
+    if !config[filters].is_array() {
+        config[filters] = json!([]);
+    }
+    if let Some(filters) = config[filters].as_array_mut() {
+        filters.push(json!({ "kind": kind, "args": args }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surfaces(tokenizer: &Tokenizer, text: &str) -> Vec<String> {
+        tokenizer
+            .tokenize(text)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.text.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn it_builds_configs() {
+        let config = LinderaConfig::builder()
+            .segmentation_mode(SegmentationMode::Decompose)
+            .character_filter("unicode_normalize", json!({ "kind": "nfkc" }))
+            .token_filter("lowercase", json!({}))
+            .build();
+
+        assert_eq!(config.dictionary, DictionaryType::Ipadic);
+        assert_eq!(config.segmentation_mode, SegmentationMode::Decompose);
+        assert_eq!(config.character_filters[0].kind, "unicode_normalize");
+        assert_eq!(config.token_filters[0].kind, "lowercase");
+    }
+
+    #[test]
+    fn it_creates_tokenizers_from_configs() {
+        let config = LinderaConfig::builder()
+            .segmentation_mode(SegmentationMode::Decompose)
+            .character_filter("unicode_normalize", json!({ "kind": "nfkc" }))
+            .token_filter("lowercase", json!({}))
+            .build();
+
+        let tokenizer = &Fts5Tokenizer::from_config(&config).unwrap().tokenizer;
+        assert!(tokenizer.segmenter.mode.is_search());
+        assert_eq!(surfaces(tokenizer, "ＬＩＮＤＥＲＡ"), ["lindera"]);
+
+        let from_yaml = crate::load_tokenizer_from_yaml(&config.to_yaml()).unwrap();
+        assert!(from_yaml.segmenter.mode.is_search());
+        assert_eq!(surfaces(&from_yaml, "ＬＩＮＤＥＲＡ"), ["lindera"]);
+    }
+
+    #[test]
+    fn it_applies_options_to_the_environment_config() {
+        let base = env_config()
+            .unwrap()
+            .unwrap_or_else(|| json!({ "segmenter": {} }));
+        assert_eq!(Fts5TokenizerOptions::new().to_value().unwrap(), base);
+
+        let options = Fts5TokenizerOptions::new()
+            .dictionary(DictionaryType::Ipadic)
+            .segmentation_mode(SegmentationMode::Decompose)
+            .token_filter("lowercase", json!({}));
+        let config = options.to_value().unwrap();
+        assert_eq!(config["segmenter"]["dictionary"]["kind"], "ipadic");
+        assert_eq!(
+            config["character_filters"],
+            base.get("character_filters").cloned().unwrap_or(json!([]))
+        );
+        let token_filters = config["token_filters"].as_array().unwrap();
+        assert_eq!(
+            token_filters.last(),
+            Some(&json!({ "kind": "lowercase", "args": {} }))
+        );
+
+        let tokenizer = &Fts5Tokenizer::from_options(&options).unwrap().tokenizer;
+        assert!(tokenizer.segmenter.mode.is_search());
+        assert_eq!(surfaces(tokenizer, "LINDERA"), ["lindera"]);
+    }
+
+    #[test]
+    fn it_prefers_config_path_over_inline_config() {
+        let inline = "segmenter:\n  mode: \"decompose\"\n  dictionary:\n    kind: \"ipadic\"\n";
+        let path = OsStr::new("./resources/lindera.yml");
+
+        let config = config_from_env(None, None, Some(inline)).unwrap().unwrap();
+        assert_eq!(config["segmenter"]["mode"], "decompose");
+        let config = config_from_env(Some(path), None, Some(inline))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config["segmenter"]["mode"], "normal");
+        assert_eq!(
+            config_from_env(None, None, None).unwrap().is_some(),
+            cfg!(feature = "default-config")
+        );
+        assert!(config_from_env(None, None, Some("segmenter: [")).is_err());
+    }
+
+    #[test]
+    fn it_prefers_global_config_over_inline_config() {
+        let global = serde_json::json!({ "segmenter": { "mode": "decompose" } });
+        let inline = "segmenter:\n  mode: \"normal\"\n";
+        let path = OsStr::new("./resources/lindera.yml");
+
+        let config = config_from_env(None, Some(&global), Some(inline))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config, global);
+        let config = config_from_env(Some(path), Some(&global), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(config["segmenter"]["dictionary"]["kind"], "ipadic");
+    }
+
+    #[test]
+    fn it_rejects_invalid_global_config() {
+        // Neither call changes the configuration other tests run with.
+        assert_eq!(
+            set_global_config("segmenter: [").map_err(|e| e.code()),
+            Err(SQLITE_FORMAT)
+        );
+        assert_eq!(
+            unsafe { lindera_set_config(c"\xff".as_ptr()) },
+            SQLITE_MISUSE
+        );
+        assert!(GLOBAL_CONFIG.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn it_selects_tokenizer_profiles() {
+        let config = json!({
+            "segmenter": { "mode": "normal" },
+            "tokenizers": {
+                "lindera_ja": { "segmenter": { "dictionary": { "kind": "ipadic" } } },
+                "lindera_search": { "segmenter": { "mode": "decompose" } },
+            },
+        });
+
+        assert_eq!(profile_names(&config), ["lindera_ja", "lindera_search"]);
+        assert_eq!(
+            profile_config(&config, "lindera_search").unwrap(),
+            json!({ "segmenter": { "mode": "decompose" } })
+        );
+        assert!(profile_config(&config, "lindera_ko").is_err());
+        assert!(profile_names(&json!({ "segmenter": {} })).is_empty());
+    }
+
+    #[test]
+    fn it_merges_partial_overrides() {
+        let mut config = json!({
+            "segmenter": {
+                "mode": "normal",
+                "dictionary": { "kind": "ipadic" },
+            },
+            "token_filters": [{ "kind": "lowercase", "args": {} }],
+        });
+        merge_config(
+            &mut config,
+            json!({ "segmenter": { "mode": "decompose" }, "token_filters": [] }),
+        );
+
+        assert_eq!(
+            config,
+            json!({
+                "segmenter": {
+                    "mode": "decompose",
+                    "dictionary": { "kind": "ipadic" },
+                },
+                "token_filters": [],
+            })
+        );
+    }
+}
@@ -0,0 +1,1547 @@
+use core::ffi::CStr;
+use core::ptr::null_mut;
+use libc::{c_char, c_int, c_void};
+use std::ffi::CString;
+
+use rusqlite::{ffi, Connection};
+
+use crate::common::{cast_usize_to_c_int, report_error, set_sqlite_log};
+use crate::error::{InitError, LinderaSqliteError};
+use crate::extension::{
+    lindera_fts_tokenizer_internal_init, quote_identifier, ScalarFunction, Sqlite3, Sqlite3Context,
+    Sqlite3Stmt, Sqlite3Value, SqliteApi,
+};
+use crate::fts3::register_lindera_fts3_tokenizer;
+use crate::vtab::Sqlite3Module;
+
+/// Registers `lindera_tokenizer` on a [`rusqlite::Connection`] without going
+/// through `load_extension`.
+pub trait LinderaConnectionExt {
+    /// Makes `lindera_tokenizer` available to FTS5 tables on this connection.
+    fn load_lindera_tokenizer(&self) -> rusqlite::Result<()>;
+
+    /// Makes `lindera_tokenizer` available to FTS3 and FTS4 tables on this
+    /// connection as well.
+    fn load_lindera_fts3_tokenizer(&self) -> rusqlite::Result<()>;
+
+    /// Creates an FTS5 table named `table_name` with the given columns that
+    /// tokenizes its content with `lindera_tokenizer`.
+    fn create_lindera_table(&self, table_name: &str, columns: &[&str]) -> rusqlite::Result<()>;
+}
+
+impl LinderaConnectionExt for Connection {
+    fn load_lindera_tokenizer(&self) -> rusqlite::Result<()> {
+        let db = unsafe { self.handle() };
+        set_sqlite_log(ffi::sqlite3_log);
+        lindera_fts_tokenizer_internal_init(db as *mut Sqlite3, &LinkedSqliteApi)
+            .map_err(|e| rusqlite::Error::SqliteFailure(ffi::Error::new(e.code()), Some(e.message)))
+    }
+
+    fn load_lindera_fts3_tokenizer(&self) -> rusqlite::Result<()> {
+        let db = unsafe { self.handle() };
+        set_sqlite_log(ffi::sqlite3_log);
+        register_lindera_fts3_tokenizer(db as *mut Sqlite3, &LinkedSqliteApi)
+            .map_err(|e| rusqlite::Error::SqliteFailure(ffi::Error::new(e.code()), Some(e.message)))
+    }
+
+    fn create_lindera_table(&self, table_name: &str, columns: &[&str]) -> rusqlite::Result<()> {
+        let columns = columns
+            .iter()
+            .map(|column| quote_identifier(column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.execute_batch(&format!(
+            "CREATE VIRTUAL TABLE {} USING fts5({}, tokenize='lindera_tokenizer');",
+            quote_identifier(table_name),
+            columns
+        ))
+    }
+}
+
+/// Registers `lindera_tokenizer` and the SQL functions that come with it on
+/// the connection `db` of the SQLite linked into the process, for hosts that
+/// hold a raw handle rather than a [`Connection`]. A configuration that fails
+/// to load is reported as what went wrong with it, and a failure of SQLite
+/// as its result code, with the message in SQLite's error log.
+///
+/// # Safety
+///
+/// `db` must be an open connection of the SQLite that `rusqlite` links.
+pub unsafe fn register_with_db(db: *mut ffi::sqlite3) -> Result<(), LinderaSqliteError> {
+    set_sqlite_log(ffi::sqlite3_log);
+    lindera_fts_tokenizer_internal_init(db as *mut Sqlite3, &LinkedSqliteApi).map_err(|e| {
+        report_error(
+            e.code(),
+            format_args!("Failed to load lindera_tokenizer: {}", e),
+        );
+        e.error
+    })
+}
+
+/// Registers `lindera_tokenizer` and the SQL functions that come with it on
+/// `conn`, as [`LinderaConnectionExt::load_lindera_tokenizer`] does.
+pub fn register(conn: &Connection) -> rusqlite::Result<()> {
+    conn.load_lindera_tokenizer()
+}
+
+/// Registers `lindera_tokenizer` on every connection opened from then on,
+/// through `sqlite3_auto_extension`, for applications that compile SQLite in
+/// rather than load extensions. Registering again has no further effect.
+///
+/// A configuration that fails to load makes opening connections fail, as
+/// with any automatic extension.
+pub fn register_auto_extension() -> rusqlite::Result<()> {
+    match lindera_register_auto_extension() {
+        ffi::SQLITE_OK => Ok(()),
+        rc => Err(rusqlite::Error::SqliteFailure(ffi::Error::new(rc), None)),
+    }
+}
+
+/// [`register_auto_extension`] for C, returning the SQLite result code.
+#[no_mangle]
+pub extern "C" fn lindera_register_auto_extension() -> c_int {
+    unsafe { ffi::sqlite3_auto_extension(Some(lindera_auto_extension_init)) }
+}
+
+/// The entry point of the automatic extension. It calls SQLite directly
+/// rather than through the routines it is handed, which builds without
+/// extension loading do not have.
+unsafe extern "C" fn lindera_auto_extension_init(
+    db: *mut ffi::sqlite3,
+    pz_err_msg: *mut *mut c_char,
+    _p_api: *const ffi::sqlite3_api_routines,
+) -> c_int {
+    set_sqlite_log(ffi::sqlite3_log);
+    let result = std::panic::catch_unwind(|| {
+        lindera_fts_tokenizer_internal_init(db as *mut Sqlite3, &LinkedSqliteApi)
+    })
+    .unwrap_or_else(|_| {
+        Err(InitError::new(
+            ffi::SQLITE_INTERNAL,
+            "lindera_tokenizer panicked while loading",
+        ))
+    });
+    match result {
+        Ok(()) => ffi::SQLITE_OK,
+        Err(e) => {
+            report_error(
+                e.code(),
+                format_args!("Failed to load lindera_tokenizer: {}", e),
+            );
+            // SQLite frees the message with sqlite3_free.
+            if !pz_err_msg.is_null() {
+                if let Ok(message) = CString::new(e.message.as_str()) {
+                    *pz_err_msg = ffi::sqlite3_mprintf(c"%s".as_ptr(), message.as_ptr());
+                }
+            }
+            e.code()
+        }
+    }
+}
+
+/// Calls the SQLite library linked into the process through `rusqlite`.
+struct LinkedSqliteApi;
+
+impl SqliteApi for LinkedSqliteApi {
+    fn get() -> Option<&'static Self> {
+        Some(&LinkedSqliteApi)
+    }
+
+    fn libversion_number(&self) -> c_int {
+        unsafe { ffi::sqlite3_libversion_number() }
+    }
+
+    fn prepare(&self, db: *mut Sqlite3, query: &CStr, stmt: *mut *mut Sqlite3Stmt) -> c_int {
+        unsafe {
+            ffi::sqlite3_prepare_v2(
+                db as *mut ffi::sqlite3,
+                query.as_ptr(),
+                -1,
+                stmt as *mut *mut ffi::sqlite3_stmt,
+                null_mut(),
+            )
+        }
+    }
+
+    fn bind_pointer(
+        &self,
+        stmt: *mut Sqlite3Stmt,
+        index: c_int,
+        ptr: *mut c_void,
+        name: &CStr,
+    ) -> c_int {
+        unsafe {
+            ffi::sqlite3_bind_pointer(
+                stmt as *mut ffi::sqlite3_stmt,
+                index,
+                ptr,
+                name.as_ptr(),
+                None,
+            )
+        }
+    }
+
+    fn bind_blob(&self, stmt: *mut Sqlite3Stmt, index: c_int, blob: &[u8]) -> c_int {
+        let len = match cast_usize_to_c_int(blob.len()) {
+            Ok(len) => len,
+            Err(rc) => return rc,
+        };
+        unsafe {
+            ffi::sqlite3_bind_blob(
+                stmt as *mut ffi::sqlite3_stmt,
+                index,
+                blob.as_ptr() as *const c_void,
+                len,
+                ffi::SQLITE_TRANSIENT(),
+            )
+        }
+    }
+
+    fn bind_int64(&self, stmt: *mut Sqlite3Stmt, index: c_int, value: i64) -> c_int {
+        unsafe { ffi::sqlite3_bind_int64(stmt as *mut ffi::sqlite3_stmt, index, value) }
+    }
+
+    fn bind_text(&self, stmt: *mut Sqlite3Stmt, index: c_int, text: &str) -> c_int {
+        let len = match cast_usize_to_c_int(text.len()) {
+            Ok(len) => len,
+            Err(rc) => return rc,
+        };
+        unsafe {
+            ffi::sqlite3_bind_text(
+                stmt as *mut ffi::sqlite3_stmt,
+                index,
+                text.as_ptr() as *const c_char,
+                len,
+                ffi::SQLITE_TRANSIENT(),
+            )
+        }
+    }
+
+    fn step(&self, stmt: *mut Sqlite3Stmt) -> c_int {
+        unsafe { ffi::sqlite3_step(stmt as *mut ffi::sqlite3_stmt) }
+    }
+
+    fn column_text(&self, stmt: *mut Sqlite3Stmt, column: c_int) -> String {
+        let stmt = stmt as *mut ffi::sqlite3_stmt;
+        let text = unsafe { ffi::sqlite3_column_text(stmt, column) };
+        if text.is_null() {
+            return String::new();
+        }
+        let len = unsafe { ffi::sqlite3_column_bytes(stmt, column) } as usize;
+        let bytes = unsafe { core::slice::from_raw_parts(text, len) };
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    fn finalize(&self, stmt: *mut Sqlite3Stmt) -> c_int {
+        unsafe { ffi::sqlite3_finalize(stmt as *mut ffi::sqlite3_stmt) }
+    }
+
+    fn create_function(
+        &self,
+        db: *mut Sqlite3,
+        name: &CStr,
+        n_arg: c_int,
+        flags: c_int,
+        user_data: *mut c_void,
+        x_func: ScalarFunction,
+        x_destroy: Option<extern "C" fn(user_data: *mut c_void)>,
+    ) -> c_int {
+        unsafe {
+            ffi::sqlite3_create_function_v2(
+                db as *mut ffi::sqlite3,
+                name.as_ptr(),
+                n_arg,
+                flags,
+                user_data,
+                Some(core::mem::transmute::<
+                    ScalarFunction,
+                    unsafe extern "C" fn(
+                        *mut ffi::sqlite3_context,
+                        c_int,
+                        *mut *mut ffi::sqlite3_value,
+                    ),
+                >(x_func)),
+                None,
+                None,
+                x_destroy.map(|x_destroy| {
+                    core::mem::transmute::<
+                        extern "C" fn(*mut c_void),
+                        unsafe extern "C" fn(*mut c_void),
+                    >(x_destroy)
+                }),
+            )
+        }
+    }
+
+    fn user_data(&self, ctx: *mut Sqlite3Context) -> *mut c_void {
+        unsafe { ffi::sqlite3_user_data(ctx as *mut ffi::sqlite3_context) }
+    }
+
+    fn result_text(&self, ctx: *mut Sqlite3Context, text: &str) {
+        let len = match cast_usize_to_c_int(text.len()) {
+            Ok(len) => len,
+            Err(rc) => {
+                report_error(
+                    rc,
+                    format_args!("Result of {} bytes is too long for SQLite", text.len()),
+                );
+                return self.result_null(ctx);
+            }
+        };
+        unsafe {
+            ffi::sqlite3_result_text(
+                ctx as *mut ffi::sqlite3_context,
+                text.as_ptr() as *const c_char,
+                len,
+                ffi::SQLITE_TRANSIENT(),
+            )
+        }
+    }
+
+    fn result_int64(&self, ctx: *mut Sqlite3Context, value: i64) {
+        unsafe { ffi::sqlite3_result_int64(ctx as *mut ffi::sqlite3_context, value) }
+    }
+
+    fn result_double(&self, ctx: *mut Sqlite3Context, value: f64) {
+        unsafe { ffi::sqlite3_result_double(ctx as *mut ffi::sqlite3_context, value) }
+    }
+
+    fn result_null(&self, ctx: *mut Sqlite3Context) {
+        unsafe { ffi::sqlite3_result_null(ctx as *mut ffi::sqlite3_context) }
+    }
+
+    fn value_type(&self, value: *mut Sqlite3Value) -> c_int {
+        unsafe { ffi::sqlite3_value_type(value as *mut ffi::sqlite3_value) }
+    }
+
+    fn value_int64(&self, value: *mut Sqlite3Value) -> i64 {
+        unsafe { ffi::sqlite3_value_int64(value as *mut ffi::sqlite3_value) }
+    }
+
+    fn value_double(&self, value: *mut Sqlite3Value) -> f64 {
+        unsafe { ffi::sqlite3_value_double(value as *mut ffi::sqlite3_value) }
+    }
+
+    fn value_text(&self, value: *mut Sqlite3Value) -> String {
+        let value = value as *mut ffi::sqlite3_value;
+        let text = unsafe { ffi::sqlite3_value_text(value) };
+        if text.is_null() {
+            return String::new();
+        }
+        let len = unsafe { ffi::sqlite3_value_bytes(value) } as usize;
+        let bytes = unsafe { core::slice::from_raw_parts(text, len) };
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    fn value_pointer(&self, value: *mut Sqlite3Value, name: &CStr) -> *mut c_void {
+        unsafe { ffi::sqlite3_value_pointer(value as *mut ffi::sqlite3_value, name.as_ptr()) }
+    }
+
+    fn create_module(
+        &self,
+        db: *mut Sqlite3,
+        name: &CStr,
+        module: *const Sqlite3Module,
+        client_data: *mut c_void,
+        x_destroy: extern "C" fn(client_data: *mut c_void),
+    ) -> c_int {
+        unsafe {
+            ffi::sqlite3_create_module_v2(
+                db as *mut ffi::sqlite3,
+                name.as_ptr(),
+                module as *const ffi::sqlite3_module,
+                client_data,
+                Some(x_destroy),
+            )
+        }
+    }
+
+    fn declare_vtab(&self, db: *mut Sqlite3, sql: &CStr) -> c_int {
+        unsafe { ffi::sqlite3_declare_vtab(db as *mut ffi::sqlite3, sql.as_ptr()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::extension::{register_lindera_tokenizer, TokenizerContext};
+
+    fn indexed_terms(conn: &Connection, text: &str) -> Vec<String> {
+        conn.create_lindera_table("example", &["content"]).unwrap();
+        table_terms(conn, "example", text)
+    }
+
+    /// Inserts `text` into the FTS5 table `table` and lists its terms.
+    fn table_terms(conn: &Connection, table: &str, text: &str) -> Vec<String> {
+        conn.execute(
+            &format!("INSERT INTO {}(content) VALUES (?1)", table),
+            [text],
+        )
+        .unwrap();
+        conn.execute_batch(&format!(
+            "CREATE VIRTUAL TABLE {0}_vocab USING fts5vocab({0}, row);",
+            table
+        ))
+        .unwrap();
+
+        let mut stmt = conn
+            .prepare(&format!("SELECT term FROM {}_vocab ORDER BY term", table))
+            .unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn it_searches_lindera_table() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.create_lindera_table("example", &["content"]).unwrap();
+
+        conn.execute(
+            "INSERT INTO example(content) VALUES (?1)",
+            ["Ｌｉｎｄｅｒａは形態素解析ｴﾝｼﾞﾝです。ユーザー辞書も利用可能です。"],
+        )
+        .unwrap();
+
+        let count: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM example WHERE content MATCH '辞書'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn it_registers_on_a_connection() {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::register(&conn).unwrap();
+
+        assert!(indexed_terms(&conn, "ユーザー辞書").contains(&"辞書".to_string()));
+    }
+
+    #[test]
+    fn it_registers_with_a_raw_handle() {
+        let conn = Connection::open_in_memory().unwrap();
+        unsafe { crate::register_with_db(conn.handle()) }.unwrap();
+
+        assert!(indexed_terms(&conn, "ユーザー辞書").contains(&"辞書".to_string()));
+    }
+
+    #[test]
+    fn it_searches_fts4_tables() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_fts3_tokenizer().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE legacy USING fts4(content, tokenize=lindera_tokenizer);",
+        )
+        .unwrap();
+
+        conn.execute(
+            "INSERT INTO legacy(content) VALUES (?1)",
+            ["Ｌｉｎｄｅｒａは形態素解析ｴﾝｼﾞﾝです。ユーザー辞書も利用可能です。"],
+        )
+        .unwrap();
+
+        let mut stmt = conn
+            .prepare("SELECT offsets(legacy) FROM legacy WHERE content MATCH '辞書'")
+            .unwrap();
+        let offsets = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .unwrap();
+        // Column 0, term 0, at the byte offset of 辞書, 6 bytes long.
+        let start = "Ｌｉｎｄｅｒａは形態素解析ｴﾝｼﾞﾝです。ユーザー".len();
+        assert_eq!(offsets, [format!("0 0 {} 6", start)]);
+    }
+
+    #[test]
+    fn it_is_compatible_with_fts5vocab() {
+        extern "C" fn collect_term(
+            ctx: *mut c_void,
+            _flags: c_int,
+            token: *const c_char,
+            token_len: c_int,
+            _start: c_int,
+            _end: c_int,
+        ) -> c_int {
+            let terms = unsafe { &mut *(ctx as *mut BTreeMap<String, i64>) };
+            let token =
+                unsafe { core::slice::from_raw_parts(token as *const u8, token_len as usize) };
+            *terms
+                .entry(String::from_utf8_lossy(token).into_owned())
+                .or_default() += 1;
+            crate::SQLITE_OK
+        }
+
+        let documents = [
+            "Ｌｉｎｄｅｒａは形態素解析ｴﾝｼﾞﾝです。",
+            "ユーザー辞書も利用可能です。",
+            "形態素解析の辞書を利用します。",
+        ];
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.create_lindera_table("example", &["content"]).unwrap();
+        for document in documents {
+            conn.execute("INSERT INTO example(content) VALUES (?1)", [document])
+                .unwrap();
+        }
+        conn.execute_batch("CREATE VIRTUAL TABLE example_vocab USING fts5vocab(example, row);")
+            .unwrap();
+
+        let mut tokenizer = crate::load_tokenizer().unwrap();
+        let mut expected = BTreeMap::new();
+        let callback =
+            crate::TokenCallback::new(collect_term, &mut expected as *mut _ as *mut c_void);
+        for document in documents {
+            crate::lindera_fts5_tokenize_ref(&mut tokenizer, document, &callback).unwrap();
+        }
+
+        let mut stmt = conn
+            .prepare("SELECT term, cnt FROM example_vocab ORDER BY term")
+            .unwrap();
+        let vocab = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<BTreeMap<_, _>>>()
+            .unwrap();
+        assert!(!vocab.is_empty());
+        assert_eq!(vocab, expected);
+
+        // Every indexed term is found again by a MATCH query.
+        let doc_counts = conn
+            .prepare("SELECT term, doc FROM example_vocab")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        for (term, doc) in doc_counts {
+            let matches: i64 = conn
+                .query_row(
+                    "SELECT count(*) FROM example WHERE example MATCH ?1",
+                    [format!("\"{}\"", term.replace('"', "\"\""))],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(matches, doc, "documents matching {:?}", term);
+        }
+    }
+
+    #[test]
+    fn it_uses_per_connection_config() {
+        let plain_config =
+            std::env::temp_dir().join(format!("lindera-sqlite-plain-{}.yml", std::process::id()));
+        std::fs::write(
+            &plain_config,
+            "segmenter:\n  mode: \"normal\"\n  dictionary:\n    kind: \"ipadic\"\n",
+        )
+        .unwrap();
+
+        let filtered = Connection::open_in_memory().unwrap();
+        let context = TokenizerContext::new(Some(PathBuf::from("./resources/lindera.yml")));
+        register_lindera_tokenizer(
+            unsafe { filtered.handle() } as *mut Sqlite3,
+            &LinkedSqliteApi,
+            context,
+        )
+        .unwrap();
+
+        let plain = Connection::open_in_memory().unwrap();
+        let context = TokenizerContext::new(Some(plain_config.clone()));
+        register_lindera_tokenizer(
+            unsafe { plain.handle() } as *mut Sqlite3,
+            &LinkedSqliteApi,
+            context,
+        )
+        .unwrap();
+
+        let text = "辞書は利用可能です。";
+        let filtered_terms = indexed_terms(&filtered, text);
+        let plain_terms = indexed_terms(&plain, text);
+        std::fs::remove_file(&plain_config).unwrap();
+
+        assert!(!filtered_terms.contains(&"は".to_string()));
+        assert!(plain_terms.contains(&"は".to_string()));
+    }
+
+    #[test]
+    fn it_uses_per_table_config() {
+        let plain_config = std::env::temp_dir().join(format!(
+            "lindera-sqlite-per-table-{}.yml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &plain_config,
+            "segmenter:\n  mode: \"normal\"\n  dictionary:\n    kind: \"ipadic\"\n",
+        )
+        .unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        for (table, config) in [
+            ("filtered", PathBuf::from("./resources/lindera.yml")),
+            ("plain", plain_config.clone()),
+        ] {
+            conn.execute_batch(&format!(
+                "CREATE VIRTUAL TABLE {} USING fts5(content, tokenize = \"lindera_tokenizer config_path '{}'\");",
+                table,
+                config.display()
+            ))
+            .unwrap();
+        }
+
+        let text = "辞書は利用可能です。";
+        let filtered_terms = table_terms(&conn, "filtered", text);
+        let plain_terms = table_terms(&conn, "plain", text);
+        std::fs::remove_file(&plain_config).unwrap();
+
+        assert!(!filtered_terms.contains(&"は".to_string()));
+        assert!(plain_terms.contains(&"は".to_string()));
+    }
+
+    #[test]
+    fn it_registers_tokenizer_profiles() {
+        let config = std::env::temp_dir().join(format!(
+            "lindera-sqlite-profiles-{}.yml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &config,
+            "segmenter:\n  mode: \"normal\"\n  dictionary:\n    kind: \"ipadic\"\n\
+             tokenizers:\n\
+             \x20 lindera_ja:\n    segmenter:\n      dictionary:\n        kind: \"ipadic\"\n\
+             \x20 lindera_search:\n    segmenter:\n      dictionary:\n        kind: \"ipadic\"\n\
+             \x20   token_filters:\n      - kind: \"lowercase\"\n        args: {}\n",
+        )
+        .unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        register_lindera_tokenizer(
+            unsafe { conn.handle() } as *mut Sqlite3,
+            &LinkedSqliteApi,
+            TokenizerContext::new(Some(config.clone())),
+        )
+        .unwrap();
+        for (table, tokenizer) in [
+            ("plain", "lindera_tokenizer"),
+            ("ja", "lindera_ja"),
+            ("search", "lindera_search"),
+        ] {
+            conn.execute_batch(&format!(
+                "CREATE VIRTUAL TABLE {} USING fts5(content, tokenize='{}');",
+                table, tokenizer
+            ))
+            .unwrap();
+        }
+
+        let text = "LINDERAは辞書です。";
+        let plain_terms = table_terms(&conn, "plain", text);
+        let ja_terms = table_terms(&conn, "ja", text);
+        let search_terms = table_terms(&conn, "search", text);
+        std::fs::remove_file(&config).unwrap();
+
+        assert!(plain_terms.contains(&"LINDERA".to_string()));
+        assert_eq!(ja_terms, plain_terms);
+        assert!(search_terms.contains(&"lindera".to_string()));
+        assert!(!search_terms.contains(&"LINDERA".to_string()));
+    }
+
+    #[test]
+    fn it_expands_queries_with_synonyms() {
+        let dir = std::env::temp_dir();
+        let synonyms = dir.join(format!(
+            "lindera-sqlite-synonyms-{}.tsv",
+            std::process::id()
+        ));
+        std::fs::write(&synonyms, "クルマ\t自動車\n").unwrap();
+        let config = dir.join(format!(
+            "lindera-sqlite-synonyms-{}.yml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &config,
+            format!(
+                "segmenter:\n  dictionary:\n    kind: \"ipadic\"\nsynonyms: \"{}\"\n",
+                synonyms.display()
+            ),
+        )
+        .unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch(&format!(
+            "CREATE VIRTUAL TABLE example USING fts5(content, tokenize = \"lindera_tokenizer config_path '{}'\");",
+            config.display()
+        ))
+        .unwrap();
+        conn.execute(
+            "INSERT INTO example(content) VALUES ('新しい自動車を買う')",
+            [],
+        )
+        .unwrap();
+
+        let count = |query: &str| -> i64 {
+            conn.query_row(
+                "SELECT count(*) FROM example WHERE example MATCH ?1",
+                [query],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        let (synonym, term) = (count("クルマ"), count("自動車"));
+        std::fs::remove_file(&config).unwrap();
+        std::fs::remove_file(&synonyms).unwrap();
+
+        assert_eq!(synonym, 1);
+        assert_eq!(term, 1);
+    }
+
+    #[test]
+    fn it_reloads_tokenizers_on_request() {
+        let config =
+            std::env::temp_dir().join(format!("lindera-sqlite-reload-{}.yml", std::process::id()));
+        std::fs::write(
+            &config,
+            "segmenter:\n  mode: \"normal\"\n  dictionary:\n    kind: \"ipadic\"\n",
+        )
+        .unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch(&format!(
+            "CREATE VIRTUAL TABLE example USING fts5(content, tokenize = \"lindera_tokenizer config_path '{}'\");",
+            config.display()
+        ))
+        .unwrap();
+
+        std::fs::copy("./resources/lindera.yml", &config).unwrap();
+        let instances: i64 = conn
+            .query_row("SELECT lindera_reload()", [], |row| row.get(0))
+            .unwrap();
+        let terms = table_terms(&conn, "example", "辞書は利用可能です。");
+        std::fs::remove_file(&config).unwrap();
+
+        assert!(instances >= 1);
+        assert!(!terms.contains(&"は".to_string()));
+    }
+
+    #[test]
+    fn it_manages_a_user_dictionary_in_the_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE example USING fts5(content, tokenize = 'lindera_tokenizer');",
+        )
+        .unwrap();
+        let wakati = |text: &str| -> String {
+            conn.query_row("SELECT lindera_wakati(?1)", [text], |row| row.get(0))
+                .unwrap()
+        };
+        let call =
+            |sql: &str| -> Option<i64> { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        let before = wakati("東京スカイツリーの辞書");
+
+        assert_eq!(
+            call("SELECT lindera_userdict_add('東京スカイツリー', 'トウキョウスカイツリー', 'カスタム名詞')"),
+            Some(1)
+        );
+        assert_eq!(
+            call("SELECT lindera_userdict_add('東武スカイツリーライン', 'トウブスカイツリーライン', 'カスタム名詞')"),
+            Some(1)
+        );
+        assert_eq!(
+            call("SELECT lindera_userdict_add('辞書', '', '名詞')"),
+            None
+        );
+        assert_eq!(call("SELECT lindera_userdict_compile()"), Some(2));
+        let added = wakati("東京スカイツリーの辞書");
+        let terms = table_terms(&conn, "example", "東京スカイツリーの辞書");
+
+        assert_eq!(
+            call("SELECT lindera_userdict_remove('東京スカイツリー')"),
+            Some(1)
+        );
+        assert_eq!(
+            call("SELECT lindera_userdict_remove('東京スカイツリー')"),
+            Some(0)
+        );
+        assert_eq!(call("SELECT lindera_userdict_compile()"), Some(1));
+        let removed = wakati("東京スカイツリーの辞書");
+
+        assert_ne!(before, "東京スカイツリー の 辞書");
+        assert_eq!(added, "東京スカイツリー の 辞書");
+        assert!(terms.contains(&"東京スカイツリー".to_string()));
+        assert_eq!(removed, before);
+    }
+
+    #[test]
+    fn it_finds_inflected_words_by_their_base_form() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE example USING fts5(content, tokenize='lindera_tokenizer base_form colocate');
+             INSERT INTO example(content) VALUES ('辞書も食べました');",
+        )
+        .unwrap();
+
+        let count = |query: &str| -> i64 {
+            conn.query_row(
+                "SELECT count(*) FROM example WHERE example MATCH ?1",
+                [query],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(count("食べる"), 1);
+        assert_eq!(count("食べました"), 1);
+    }
+
+    #[test]
+    fn it_prefers_arguments_over_config_settings() {
+        let config = "segmenter:\n  mode: \"normal\"\n  dictionary:\n    kind: \"ipadic\"\nbase_form: replace\n";
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch("CREATE TABLE lindera_config(name TEXT PRIMARY KEY, yaml TEXT);")
+            .unwrap();
+        conn.execute(
+            "INSERT INTO lindera_config VALUES ('default', ?1)",
+            [config],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE replaced USING fts5(content, tokenize='lindera_tokenizer');
+             CREATE VIRTUAL TABLE surfaces USING fts5(content, tokenize='lindera_tokenizer base_form off');",
+        )
+        .unwrap();
+
+        let text = "食べました";
+        assert!(table_terms(&conn, "replaced", text).contains(&"食べる".to_string()));
+        assert!(!table_terms(&conn, "surfaces", text).contains(&"食べる".to_string()));
+    }
+
+    #[test]
+    fn it_matches_hiragana_and_katakana_alike() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE example USING fts5(content, tokenize=\"lindera_tokenizer kana katakana 'keep_original=true'\");
+             INSERT INTO example(content) VALUES ('りんご');",
+        )
+        .unwrap();
+
+        let count = |query: &str| -> i64 {
+            conn.query_row(
+                "SELECT count(*) FROM example WHERE example MATCH ?1",
+                [query],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(count("リンゴ"), 1);
+        assert_eq!(count("りんご"), 1);
+    }
+
+    #[test]
+    fn it_folds_case_on_the_ascii_fast_path() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE example USING fts5(content, tokenize=\"lindera_tokenizer 'ascii_fast_path=true' lowercase\");
+             INSERT INTO example(content) VALUES ('Lindera は');",
+        )
+        .unwrap();
+
+        let count: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM example WHERE example MATCH 'Lindera'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn it_matches_spelling_variants_of_kana() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE example USING fts5(content, tokenize='lindera_tokenizer long_vowel small_kana');
+             INSERT INTO example(content) VALUES ('ウィンドウ');",
+        )
+        .unwrap();
+
+        let count: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM example WHERE example MATCH 'ウインドウ'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn it_tokenizes_other_scripts_like_unicode61() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        let text = "Don't STOP_me now, O'Brien! Crème Brûlée naïve Ǖ e\u{301} \
+                    МОСКВА Ἀθῆναι ΣΟΦΊΑ x² ⓐ ﬁle 𐐀 3.14 e-mail";
+        let terms = |tokenize: &str| -> Vec<String> {
+            conn.execute_batch(&format!(
+                "DROP TABLE IF EXISTS example;
+                 CREATE VIRTUAL TABLE example USING fts5(content, tokenize=\"{}\");
+                 CREATE VIRTUAL TABLE IF NOT EXISTS temp.example_vocab
+                     USING fts5vocab(main, example, instance);",
+                tokenize
+            ))
+            .unwrap();
+            conn.execute("INSERT INTO example(content) VALUES (?1)", [text])
+                .unwrap();
+            let mut statement = conn
+                .prepare("SELECT term FROM example_vocab ORDER BY offset")
+                .unwrap();
+            let terms = statement
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<Result<Vec<String>, _>>()
+                .unwrap();
+            terms
+        };
+
+        for options in [
+            "remove_diacritics 0",
+            "remove_diacritics 1",
+            "remove_diacritics 2",
+            "tokenchars '-.' separators 'xé'",
+        ] {
+            assert_eq!(
+                terms(&format!("lindera_tokenizer unicode61 {}", options)),
+                terms(&format!("unicode61 {}", options)),
+                "{}",
+                options
+            );
+        }
+
+        // Runs in Chinese, Japanese or Korean are left to Lindera.
+        conn.execute_batch(
+            "DROP TABLE example;
+             CREATE VIRTUAL TABLE example USING fts5(content, tokenize='lindera_tokenizer unicode61');
+             INSERT INTO example(content) VALUES ('Crème Brûléeの辞書');",
+        )
+        .unwrap();
+        let count = |query: &str| -> i64 {
+            conn.query_row(
+                "SELECT count(*) FROM example WHERE example MATCH ?1",
+                [query],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(count("creme"), 1);
+        assert_eq!(count("BRÛLÉE"), 1);
+        assert_eq!(count("辞書"), 1);
+        assert_eq!(count("creme AND 辞書"), 1);
+    }
+
+    #[test]
+    fn it_finds_korean_words_as_they_are_typed() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE example USING fts5(content, tokenize='lindera_tokenizer jamo');
+             INSERT INTO example(content) VALUES ('서울');",
+        )
+        .unwrap();
+
+        let count = |query: &str| -> i64 {
+            conn.query_row(
+                "SELECT count(*) FROM example WHERE example MATCH ?1",
+                [query],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(count("서울"), 1);
+        // The syllables 서울 goes through while it is typed.
+        assert_eq!(count("성*"), 1);
+        assert_eq!(count("서우*"), 1);
+        assert_eq!(count("서우"), 0);
+    }
+
+    #[test]
+    fn it_reports_why_tables_cannot_be_created() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        assert!(conn
+            .execute_batch(
+                "CREATE VIRTUAL TABLE example USING fts5(content, tokenize = 'lindera_tokenizer config_name missing');",
+            )
+            .is_err());
+
+        let last_error: String = conn
+            .query_row("SELECT lindera_last_error()", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(
+            last_error,
+            "Failed to create tokenizer: invalid value \"missing\" for lindera_tokenizer argument config_name: no such configuration in lindera_config"
+        );
+    }
+
+    #[test]
+    fn it_reports_why_functions_return_null() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE lindera_config(name TEXT PRIMARY KEY, yaml TEXT);
+             INSERT INTO lindera_config VALUES ('default', 'segmenter: [');",
+        )
+        .unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let words: Option<String> = conn
+            .query_row("SELECT lindera_wakati('辞書')", [], |row| row.get(0))
+            .unwrap();
+        let last_error: String = conn
+            .query_row("SELECT lindera_last_error()", [], |row| row.get(0))
+            .unwrap();
+
+        assert_eq!(words, None);
+        assert!(
+            last_error.starts_with("Failed to create tokenizer: "),
+            "{}",
+            last_error
+        );
+    }
+
+    #[test]
+    fn it_reads_config_from_database() {
+        let plain = "segmenter:\n  mode: \"normal\"\n  dictionary:\n    kind: \"ipadic\"\n";
+        let filtered = std::fs::read_to_string("./resources/lindera.yml").unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch("CREATE TABLE lindera_config(name TEXT PRIMARY KEY, yaml TEXT);")
+            .unwrap();
+        conn.execute(
+            "INSERT INTO lindera_config VALUES ('default', ?1), ('filtered', ?2)",
+            [plain, filtered.as_str()],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE named USING fts5(content, tokenize='lindera_tokenizer config_name filtered');",
+        )
+        .unwrap();
+
+        let text = "辞書は利用可能です。";
+        assert!(indexed_terms(&conn, text).contains(&"は".to_string()));
+        assert!(!table_terms(&conn, "named", text).contains(&"は".to_string()));
+        assert!(conn
+            .execute_batch(
+                "CREATE VIRTUAL TABLE missing USING fts5(content, tokenize='lindera_tokenizer config_name missing');",
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn it_reads_user_dictionary_tables() {
+        let terms = "segmenter:\n  mode: \"normal\"\n  dictionary:\n    kind: \"ipadic\"\nuser_dictionary_table: \"my terms\"\n";
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE lindera_config(name TEXT PRIMARY KEY, yaml TEXT);
+             CREATE TABLE \"my terms\"(surface TEXT, part_of_speech TEXT, reading TEXT);
+             INSERT INTO \"my terms\" VALUES ('東京スカイツリー', 'カスタム名詞', 'トウキョウスカイツリー');",
+        )
+        .unwrap();
+        conn.execute("INSERT INTO lindera_config VALUES ('terms', ?1)", [terms])
+            .unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE products USING fts5(content, tokenize='lindera_tokenizer config_name terms');
+             CREATE VIRTUAL TABLE plain USING fts5(content, tokenize='lindera_tokenizer');",
+        )
+        .unwrap();
+
+        let text = "東京スカイツリーの辞書";
+        assert!(table_terms(&conn, "products", text).contains(&"東京スカイツリー".to_string()));
+        assert!(!table_terms(&conn, "plain", text).contains(&"東京スカイツリー".to_string()));
+    }
+
+    #[cfg(feature = "stopwords")]
+    #[test]
+    fn it_leaves_out_builtin_stopwords() {
+        let config = "segmenter:\n  mode: \"normal\"\n  dictionary:\n    kind: \"ipadic\"\nstopwords: ja_default\n";
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch("CREATE TABLE lindera_config(name TEXT PRIMARY KEY, yaml TEXT);")
+            .unwrap();
+        conn.execute(
+            "INSERT INTO lindera_config VALUES ('default', ?1)",
+            [config],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE example USING fts5(content, tokenize='lindera_tokenizer');",
+        )
+        .unwrap();
+
+        assert_eq!(
+            table_terms(&conn, "example", "形態素解析の辞書"),
+            ["形態素", "解析", "辞書"]
+        );
+    }
+
+    #[cfg(feature = "ipadic")]
+    #[test]
+    fn it_uses_embedded_dictionary_defaults() {
+        let conn = Connection::open_in_memory().unwrap();
+        register_lindera_tokenizer(
+            unsafe { conn.handle() } as *mut Sqlite3,
+            &LinkedSqliteApi,
+            TokenizerContext::embedded(crate::args::EmbeddedDictionary::Ipadic),
+        )
+        .unwrap();
+
+        // Without the filters of LINDERA_CONFIG_PATH, particles are indexed.
+        assert!(indexed_terms(&conn, "辞書は利用可能です。").contains(&"は".to_string()));
+        let config_path: String = conn
+            .query_row("SELECT lindera_config_path()", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(config_path, "<embedded-default>");
+    }
+
+    #[test]
+    fn it_registers_api_info_function() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let info: String = conn
+            .query_row("SELECT lindera_api_info()", [], |row| row.get(0))
+            .unwrap();
+        let expected = unsafe { CStr::from_ptr(crate::extension::lindera_sqlite_api_info()) };
+        assert_eq!(info.as_bytes(), expected.to_bytes());
+    }
+
+    #[test]
+    fn it_reports_version_and_info() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let version: String = conn
+            .query_row("SELECT lindera_version()", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, env!("CARGO_PKG_VERSION"));
+
+        let info = || -> serde_json::Value {
+            let info: String = conn
+                .query_row("SELECT lindera_info()", [], |row| row.get(0))
+                .unwrap();
+            serde_json::from_str(&info).unwrap()
+        };
+        let file_info = info();
+        assert_eq!(file_info["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(file_info["lindera_version"], env!("LINDERA_VERSION"));
+        assert!(file_info["embedded_dictionaries"]
+            .as_array()
+            .unwrap()
+            .contains(&"ipadic".into()));
+        assert_eq!(file_info["dictionary"], "ipadic");
+        assert_eq!(file_info["config"]["source"], "file");
+        assert!(file_info["config"]["path"]
+            .as_str()
+            .unwrap()
+            .ends_with("lindera.yml"));
+
+        conn.execute_batch(
+            "CREATE TABLE lindera_config(name TEXT PRIMARY KEY, yaml TEXT);
+             INSERT INTO lindera_config VALUES ('default', 'segmenter: { dictionary: { kind: ipadic } }');",
+        )
+        .unwrap();
+        let database_info = info();
+        assert_eq!(
+            database_info["config"],
+            serde_json::json!({ "source": "database", "name": "default" })
+        );
+    }
+
+    #[test]
+    fn it_detects_languages() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let languages: (String, String, String, String, Option<String>) = conn
+            .query_row(
+                "SELECT lindera_detect_language('形態素解析の辞書'), lindera_detect_language('형태소 분석'), \
+                 lindera_detect_language('中文分词'), lindera_detect_language('SQLite'), \
+                 lindera_detect_language(NULL)",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            languages,
+            (
+                "ja".to_string(),
+                "ko".to_string(),
+                "zh".to_string(),
+                "other".to_string(),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn it_compares_texts_by_tokens() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let similar =
+            |sql: &str| -> Option<f64> { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        // Particles are not indexed, so only 辞書 and 形態素 count.
+        assert_eq!(
+            similar("SELECT lindera_similar('形態素の辞書', '辞書')"),
+            Some(0.5)
+        );
+        assert_eq!(
+            similar("SELECT lindera_similar('辞書の辞書', '辞書', 'cosine')"),
+            Some(1.0)
+        );
+        assert_eq!(similar("SELECT lindera_similar('辞書', NULL)"), None);
+        assert_eq!(
+            similar("SELECT lindera_similar('辞書', '辞書', 'levenshtein')"),
+            None
+        );
+    }
+
+    #[test]
+    fn it_builds_match_queries_from_user_input() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let match_query = |text: &str| -> Option<String> {
+            conn.query_row("SELECT lindera_match_query(?1)", [text], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(
+            match_query("関西国際空港の辞書").as_deref(),
+            Some("\"関西国際空港の辞書\"")
+        );
+        assert_eq!(
+            match_query("ＳＱＬｉｔｅ　辞書").as_deref(),
+            Some("SQLite 辞書")
+        );
+        assert_eq!(match_query("辞書 AND").as_deref(), Some("辞書 \"AND\""));
+        assert_eq!(
+            match_query("\"辞書 NEAR(").as_deref(),
+            Some("辞書 \"NEAR\"")
+        );
+        assert_eq!(match_query("形態素* -解析").as_deref(), Some("形態素 解析"));
+        assert_eq!(
+            match_query("ＳＱＬｉｔｅ　関西国際空港の辞書 NEAR(").as_deref(),
+            Some("SQLite \"関西国際空港の辞書\" \"NEAR\"")
+        );
+        assert_eq!(match_query("の 。"), None);
+
+        conn.create_lindera_table("example", &["content"]).unwrap();
+        conn.execute(
+            "INSERT INTO example VALUES ('SQLiteで関西国際空港の辞書を引く')",
+            [],
+        )
+        .unwrap();
+        let matches = |text: &str| -> i64 {
+            conn.query_row(
+                "SELECT count(*) FROM example WHERE example MATCH lindera_match_query(?1)",
+                [text],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(matches("ＳＱＬｉｔｅ　関西国際空港の辞書"), 1);
+        assert_eq!(matches("\"辞書 NEAR("), 0);
+    }
+
+    #[test]
+    fn it_highlights_whole_characters() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.create_lindera_table("example", &["content"]).unwrap();
+        conn.execute(
+            "INSERT INTO example VALUES ('ﾘﾝﾃﾞﾗは形態素解析ｴﾝｼﾞﾝ'), ('ＳＱＬｉｔｅの関西国際空港の辞書')",
+            [],
+        )
+        .unwrap();
+
+        let highlight = |query: &str| -> Vec<String> {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT lindera_highlight(example, 0, '[', ']') FROM example \
+                     WHERE example MATCH ?1 ORDER BY rowid",
+                )
+                .unwrap();
+            stmt.query_map([query], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .unwrap()
+        };
+        // The character filters rewrite the halfwidth and fullwidth text.
+        assert_eq!(highlight("Lindera"), ["[ﾘﾝﾃﾞﾗ]は形態素解析ｴﾝｼﾞﾝ"]);
+        assert_eq!(highlight("エンジン"), ["ﾘﾝﾃﾞﾗは形態素解析[ｴﾝｼﾞﾝ]"]);
+        assert_eq!(highlight("SQLite"), ["[ＳＱＬｉｔｅ]の関西国際空港の辞書"]);
+        // Each phrase is highlighted on its own, and a phrase as a whole.
+        assert_eq!(
+            highlight("関西国際空港 辞書"),
+            ["ＳＱＬｉｔｅの[関西国際空港]の[辞書]"]
+        );
+        assert_eq!(
+            highlight("\"関西国際空港の辞書\""),
+            ["ＳＱＬｉｔｅの[関西国際空港の辞書]"]
+        );
+    }
+
+    #[test]
+    fn it_cuts_snippets_at_sentences() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.create_lindera_table("example", &["title", "content"])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO example VALUES ('辞書', '関西国際空港に着いた。形態素解析の辞書を引いた。解析は速い。')",
+            [],
+        )
+        .unwrap();
+
+        let snippet = |query: &str, col: i64, max_tokens: i64| -> String {
+            conn.query_row(
+                "SELECT lindera_snippet(example, ?2, '[', ']', '…', ?3) FROM example \
+                 WHERE example MATCH ?1",
+                rusqlite::params![query, col, max_tokens],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(snippet("辞書", 1, 10), "…形態素解析の[辞書]を引いた。…");
+        assert_eq!(
+            snippet("content:辞書", -1, 10),
+            "…形態素解析の[辞書]を引いた。…"
+        );
+        assert_eq!(snippet("title:辞書", -1, 10), "[辞書]");
+        assert_eq!(snippet("関西国際空港", 1, 10), "[関西国際空港]に着いた。…");
+    }
+
+    #[test]
+    fn it_reports_config_path() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let config_path: String = conn
+            .query_row("SELECT lindera_config_path()", [], |row| row.get(0))
+            .unwrap();
+        let expected = match std::env::var_os("LINDERA_CONFIG_PATH") {
+            Some(path) => std::fs::canonicalize(path)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned(),
+            None => "<embedded-default>".to_string(),
+        };
+        assert_eq!(config_path, expected);
+
+        let conn = Connection::open_in_memory().unwrap();
+        register_lindera_tokenizer(
+            unsafe { conn.handle() } as *mut Sqlite3,
+            &LinkedSqliteApi,
+            TokenizerContext::new(None),
+        )
+        .unwrap();
+
+        let config_path: String = conn
+            .query_row("SELECT lindera_config_path()", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(config_path, "<embedded-default>");
+    }
+
+    #[test]
+    fn it_checks_lindera_version() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let compatible = |version: &str| -> Option<i64> {
+            conn.query_row("SELECT lindera_version_compatible(?1)", [version], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        assert_eq!(compatible("0.1.0"), Some(1));
+        assert_eq!(compatible("99.0.0"), Some(0));
+        assert_eq!(compatible("not a version"), None);
+    }
+
+    #[test]
+    fn it_splits_text_into_words() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let wakati = |text: Option<&str>| -> Option<String> {
+            conn.query_row("SELECT lindera_wakati(?1)", [text], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(
+            wakati(Some("関西国際空港の辞書")).as_deref(),
+            Some("関西国際空港 の 辞書")
+        );
+        assert_eq!(
+            wakati(Some("形態素解析  の辞書")).as_deref(),
+            Some("形態素 解析 の 辞書")
+        );
+        assert_eq!(wakati(None), None);
+    }
+
+    #[test]
+    fn it_normalizes_text() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let normalize = |text: Option<&str>| -> Option<String> {
+            conn.query_row("SELECT lindera_normalize(?1)", [text], |row| row.get(0))
+                .unwrap()
+        };
+        // NFKC, then the mapping of resources/lindera.yml.
+        assert_eq!(
+            normalize(Some("ﾘﾝﾃﾞﾗは形態素解析ｴﾝｼﾞﾝ")).as_deref(),
+            Some("Linderaは形態素解析エンジン")
+        );
+        assert_eq!(normalize(None), None);
+    }
+
+    #[test]
+    fn it_reads_text() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let reading = |text: Option<&str>| -> Option<String> {
+            conn.query_row("SELECT lindera_reading(?1)", [text], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(
+            reading(Some("関西国際空港の辞書")).as_deref(),
+            Some("カンサイコクサイクウコウノジショ")
+        );
+        assert_eq!(
+            reading(Some("SQLiteの辞書")).as_deref(),
+            Some("SQLiteノジショ")
+        );
+        assert_eq!(reading(None), None);
+    }
+
+    #[test]
+    fn it_romanizes_text() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let romanize = |text: Option<&str>| -> Option<String> {
+            conn.query_row("SELECT lindera_romanize(?1)", [text], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(
+            romanize(Some("関西国際空港の辞書")).as_deref(),
+            Some("kansaikokusaikuukou no jisho")
+        );
+        assert_eq!(
+            romanize(Some("SQLite の辞書")).as_deref(),
+            Some("sqlite no jisho")
+        );
+        assert_eq!(romanize(None), None);
+    }
+
+    #[test]
+    fn it_adds_furigana() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let furigana =
+            |sql: &str| -> Option<String> { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(
+            furigana("SELECT lindera_furigana('SQLiteの辞書')").as_deref(),
+            Some("SQLiteの<ruby>辞書<rt>じしょ</rt></ruby>")
+        );
+        assert_eq!(
+            furigana("SELECT lindera_furigana('形態素解析の辞書', 'bracket')").as_deref(),
+            Some("形態素[けいたいそ]解析[かいせき]の辞書[じしょ]")
+        );
+        assert_eq!(furigana("SELECT lindera_furigana(NULL)"), None);
+        assert_eq!(
+            furigana("SELECT lindera_furigana('辞書', 'markdown')"),
+            None
+        );
+    }
+
+    #[test]
+    fn it_reports_tokenizer_stats() {
+        let config =
+            std::env::temp_dir().join(format!("lindera-sqlite-stats-{}.yml", std::process::id()));
+        std::fs::copy("./resources/lindera.yml", &config).unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        let context = TokenizerContext::new(Some(config.clone()));
+        register_lindera_tokenizer(
+            unsafe { conn.handle() } as *mut Sqlite3,
+            &LinkedSqliteApi,
+            context,
+        )
+        .unwrap();
+        for table_name in ["first", "second", "third"] {
+            conn.create_lindera_table(table_name, &["content"]).unwrap();
+        }
+
+        let stats: String = conn
+            .query_row("SELECT lindera_tokenizer_stats()", [], |row| row.get(0))
+            .unwrap();
+        std::fs::remove_file(&config).unwrap();
+        let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+
+        // Other tests share the process-wide cache, so only this config is
+        // counted exactly.
+        let config = serde_json::json!(config.to_string_lossy());
+        let config_paths = stats["config_paths"].as_array().unwrap();
+        assert_eq!(
+            config_paths.iter().filter(|path| **path == config).count(),
+            1
+        );
+        assert!(stats["cached_configs"].as_u64().unwrap() >= 1);
+        assert!(stats["total_instances"].as_u64().unwrap() >= 3);
+    }
+
+    #[cfg(feature = "memory-stats")]
+    #[test]
+    fn it_reports_peak_memory() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.create_lindera_table("example", &["content"]).unwrap();
+        conn.execute("INSERT INTO example(content) VALUES ('形態素解析')", [])
+            .unwrap();
+
+        let peak: i64 = conn
+            .query_row("SELECT lindera_peak_memory()", [], |row| row.get(0))
+            .unwrap();
+        assert!(peak > 0);
+    }
+
+    #[test]
+    fn it_quotes_identifiers() {
+        assert_eq!(quote_identifier("content"), "\"content\"");
+        assert_eq!(quote_identifier("a\"b"), "\"a\"\"b\"");
+    }
+}
@@ -0,0 +1,273 @@
+use core::fmt;
+
+use libc::c_int;
+
+use lindera::error::{LinderaError, LinderaErrorKind};
+
+use crate::common::*;
+
+/// An invalid argument in `tokenize='lindera_tokenizer ...'`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenizerArgumentError {
+    UnknownKey(String),
+    InvalidValue {
+        key: String,
+        value: String,
+        reason: String,
+    },
+    MissingRequiredKey(String),
+}
+
+impl fmt::Display for TokenizerArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenizerArgumentError::UnknownKey(key) => {
+                write!(f, "unknown lindera_tokenizer argument: {}", key)
+            }
+            TokenizerArgumentError::InvalidValue { key, value, reason } => write!(
+                f,
+                "invalid value {:?} for lindera_tokenizer argument {}: {}",
+                value, key, reason
+            ),
+            TokenizerArgumentError::MissingRequiredKey(key) => {
+                write!(f, "missing required lindera_tokenizer argument: {}", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TokenizerArgumentError {}
+
+/// An error raised by this crate, along with the SQLite result code it is
+/// reported as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinderaSqliteError {
+    TokenizerArgument(TokenizerArgumentError),
+    /// Neither the environment nor the table's arguments name a
+    /// configuration or a dictionary.
+    ConfigMissing,
+    /// The configuration cannot be parsed, or does not describe a tokenizer.
+    ConfigInvalid(String),
+    /// The configuration file, or a file it names besides the dictionaries,
+    /// such as synonyms, cannot be read.
+    Io(String),
+    /// The dictionary of the configuration cannot be loaded, for example
+    /// because it is not compiled in or its files cannot be read.
+    DictionaryLoad(String),
+    /// Lindera failed on the text being tokenized.
+    Tokenize(String),
+    Sqlite(c_int),
+}
+
+impl LinderaSqliteError {
+    /// Returns the SQLite result code for this error.
+    pub fn code(&self) -> c_int {
+        match self {
+            LinderaSqliteError::TokenizerArgument(_) | LinderaSqliteError::ConfigMissing => {
+                SQLITE_ERROR
+            }
+            LinderaSqliteError::ConfigInvalid(_) => SQLITE_FORMAT,
+            LinderaSqliteError::Io(_) => SQLITE_CANTOPEN,
+            LinderaSqliteError::DictionaryLoad(_) | LinderaSqliteError::Tokenize(_) => {
+                SQLITE_INTERNAL
+            }
+            LinderaSqliteError::Sqlite(code) => *code,
+        }
+    }
+
+    /// Classifies an error of Lindera building a tokenizer from a
+    /// configuration: one it cannot make sense of, or a dictionary it cannot
+    /// load, files it cannot read included.
+    pub(crate) fn from_build_error(err: LinderaError) -> Self {
+        match err.kind() {
+            LinderaErrorKind::Args
+            | LinderaErrorKind::Content
+            | LinderaErrorKind::Deserialize
+            | LinderaErrorKind::Mode
+            | LinderaErrorKind::Parse => LinderaSqliteError::ConfigInvalid(err.to_string()),
+            _ => LinderaSqliteError::DictionaryLoad(err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for LinderaSqliteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinderaSqliteError::TokenizerArgument(err) => err.fmt(f),
+            LinderaSqliteError::ConfigMissing => f.write_str(
+                "no Lindera configuration: LINDERA_CONFIG_PATH and LINDERA_CONFIG are not set, \
+                 and the table names no config_path, dictionary or lang",
+            ),
+            LinderaSqliteError::ConfigInvalid(message)
+            | LinderaSqliteError::Io(message)
+            | LinderaSqliteError::DictionaryLoad(message)
+            | LinderaSqliteError::Tokenize(message) => f.write_str(message),
+            LinderaSqliteError::Sqlite(code) => write!(f, "SQLite error code {}", code),
+        }
+    }
+}
+
+impl std::error::Error for LinderaSqliteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LinderaSqliteError::TokenizerArgument(err) => Some(err),
+            LinderaSqliteError::ConfigMissing
+            | LinderaSqliteError::ConfigInvalid(_)
+            | LinderaSqliteError::Io(_)
+            | LinderaSqliteError::DictionaryLoad(_)
+            | LinderaSqliteError::Tokenize(_)
+            | LinderaSqliteError::Sqlite(_) => None,
+        }
+    }
+}
+
+impl From<TokenizerArgumentError> for LinderaSqliteError {
+    fn from(err: TokenizerArgumentError) -> Self {
+        LinderaSqliteError::TokenizerArgument(err)
+    }
+}
+
+/// Classifies an error of Lindera outside of building a tokenizer, such as
+/// one of a filter reading its word list, as [`LinderaSqliteError::Io`] if
+/// it could not read a file.
+impl From<LinderaError> for LinderaSqliteError {
+    fn from(err: LinderaError) -> Self {
+        match err.kind() {
+            LinderaErrorKind::Io => LinderaSqliteError::Io(err.to_string()),
+            _ => LinderaSqliteError::from_build_error(err),
+        }
+    }
+}
+
+impl From<c_int> for LinderaSqliteError {
+    fn from(code: c_int) -> Self {
+        LinderaSqliteError::Sqlite(code)
+    }
+}
+
+impl From<LinderaSqliteError> for c_int {
+    fn from(err: LinderaSqliteError) -> Self {
+        err.code()
+    }
+}
+
+/// A failure to set up the extension on a connection, with the message
+/// handed back to `sqlite3_load_extension`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) struct InitError {
+    pub(crate) error: LinderaSqliteError,
+    pub(crate) message: String,
+}
+
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+impl InitError {
+    pub(crate) fn new(code: c_int, message: impl Into<String>) -> Self {
+        InitError {
+            error: LinderaSqliteError::Sqlite(code),
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn code(&self) -> c_int {
+        self.error.code()
+    }
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<c_int> for InitError {
+    fn from(code: c_int) -> Self {
+        InitError::new(code, LinderaSqliteError::Sqlite(code).to_string())
+    }
+}
+
+impl From<LinderaSqliteError> for InitError {
+    fn from(err: LinderaSqliteError) -> Self {
+        InitError {
+            message: err.to_string(),
+            error: err,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reports_argument_errors_as_sqlite_error() {
+        let err = LinderaSqliteError::from(TokenizerArgumentError::InvalidValue {
+            key: "config".to_string(),
+            value: "badpath".to_string(),
+            reason: "no such file".to_string(),
+        });
+
+        assert_eq!(c_int::from(err.clone()), SQLITE_ERROR);
+        assert_eq!(
+            err.to_string(),
+            "invalid value \"badpath\" for lindera_tokenizer argument config: no such file"
+        );
+    }
+
+    #[test]
+    fn it_maps_lindera_errors_by_kind() {
+        let error = |kind: LinderaErrorKind| {
+            LinderaSqliteError::from(kind.with_error(std::io::Error::other("failed")))
+        };
+
+        assert!(matches!(
+            error(LinderaErrorKind::Io),
+            LinderaSqliteError::Io(_)
+        ));
+        assert!(matches!(
+            error(LinderaErrorKind::Parse),
+            LinderaSqliteError::ConfigInvalid(_)
+        ));
+        assert!(matches!(
+            error(LinderaErrorKind::Deserialize),
+            LinderaSqliteError::ConfigInvalid(_)
+        ));
+        assert!(matches!(
+            error(LinderaErrorKind::Dictionary),
+            LinderaSqliteError::DictionaryLoad(_)
+        ));
+        assert_eq!(error(LinderaErrorKind::Io).code(), SQLITE_CANTOPEN);
+        assert_eq!(error(LinderaErrorKind::Parse).code(), SQLITE_FORMAT);
+        assert_eq!(error(LinderaErrorKind::Dictionary).code(), SQLITE_INTERNAL);
+        assert_eq!(error(LinderaErrorKind::NotFound).code(), SQLITE_INTERNAL);
+
+        let err = LinderaSqliteError::from(
+            LinderaErrorKind::Io.with_error(std::io::Error::other("nope")),
+        );
+        assert_eq!(err.to_string(), "LinderaError(kind=Io, source=nope)");
+    }
+
+    #[test]
+    fn it_names_the_variables_when_no_config_is_set() {
+        let err = LinderaSqliteError::ConfigMissing;
+
+        assert_eq!(err.code(), SQLITE_ERROR);
+        assert!(err.to_string().contains("LINDERA_CONFIG_PATH"));
+    }
+
+    #[test]
+    fn it_describes_init_errors_by_code() {
+        assert_eq!(
+            InitError::from(SQLITE_MISUSE),
+            InitError::new(SQLITE_MISUSE, "SQLite error code 21")
+        );
+    }
+
+    #[test]
+    fn it_keeps_sqlite_codes() {
+        assert_eq!(
+            c_int::from(LinderaSqliteError::from(SQLITE_MISUSE)),
+            SQLITE_MISUSE
+        );
+    }
+}
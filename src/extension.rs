@@ -1,14 +1,66 @@
+use core::ffi::CStr;
 use core::ptr::null_mut;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::collections::HashMap;
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
 use libc::{c_char, c_int, c_uchar, c_void};
+use lindera::tokenizer::TokenizerConfig;
 
+use crate::args::{
+    DetailFields, EmbeddedDictionary, EmissionOptions, Language, Pipelines, SegmenterMode,
+    TokenizerArgs,
+};
+use crate::cache::TokenizerCache;
 use crate::common::*;
-use crate::lindera_fts5_tokenize;
-use crate::load_tokenizer;
+use crate::config::{env_config, global_config_is_set, profile_names, read_config};
+use crate::error::{InitError, LinderaSqliteError, TokenizerArgumentError};
+use crate::fts3::register_lindera_fts3_tokenizer;
+use crate::furigana::{push_word, FuriganaFormat};
+use crate::highlight::lindera_highlight_aux;
+use crate::locale::{detect_language, LocaleTokenizers};
+use crate::reload::{reload_all, reload_enabled, TokenizerSource};
+use crate::romaji::romanize;
+use crate::snippet::lindera_snippet_aux;
+use crate::stats::TokenizerStats;
+use crate::table_functions::{
+    lindera_tokens_aux, LinderaDict, LinderaExplain, LinderaKeywords, LinderaSentences,
+    LinderaStats, LinderaTokenize, LinderaTokens,
+};
+use crate::userdict::{
+    compile_user_dictionary, lindera_userdict_add_func, lindera_userdict_compile_func,
+    lindera_userdict_remove_func, user_dictionary_path, USER_DICTIONARY_TABLE,
+};
+use crate::vtab::{register_table_function, Sqlite3Module, SQLITE_NULL};
+use crate::{
+    analyze_tokens, lindera_fts5_tokenize, match_query, normalize, reading, readings,
+    report_tokenizer_error, wakati, word_readings,
+};
 
 pub const FTS5_API_VERSION: c_int = 2;
+/// The version of `fts5_api` that added `xCreateTokenizer_v2`, in SQLite 3.47.
+const FTS5_API_VERSION_LOCALE: c_int = 3;
+
+/// `sqlite3_bind_pointer` first appeared in SQLite 3.20.0.
+const MIN_SQLITE_VERSION_NUMBER: c_int = 3020000;
+
+// sqlite3.h
+const SQLITE_UTF8: c_int = 1;
+const SQLITE_DETERMINISTIC: c_int = 0x800;
+/// Makes SQLite copy a result string before the call returns.
+const SQLITE_TRANSIENT: isize = -1;
 
 pub struct Sqlite3 {}
-struct Sqlite3Stmt {}
+pub(crate) struct Sqlite3Stmt {}
+pub(crate) struct Sqlite3Context {}
+pub(crate) struct Sqlite3Value {}
+
+pub(crate) type ScalarFunction =
+    extern "C" fn(ctx: *mut Sqlite3Context, argc: c_int, argv: *mut *mut Sqlite3Value);
 
 // fts5.h
 #[repr(C)]
@@ -30,9 +82,32 @@ struct Fts5TokenizerApi {
     ) -> c_int,
 }
 
+/// `fts5_tokenizer_v2`, whose `xTokenize` also takes the locale of the text.
+#[repr(C)]
+struct Fts5TokenizerV2Api {
+    i_version: c_int, // Currently always 2
+    x_create: extern "C" fn(
+        p_context: *mut c_void,
+        az_arg: *const *const c_uchar,
+        n_arg: c_int,
+        fts5_tokenizer: *mut *mut Fts5Tokenizer,
+    ) -> c_int,
+    x_delete: extern "C" fn(fts5_tokenizer: *mut Fts5Tokenizer),
+    x_tokenize: extern "C" fn(
+        tokenizer: *mut Fts5Tokenizer,
+        p_ctx: *mut c_void,
+        flags: c_int,
+        p_text: *const c_char,
+        n_text: c_int,
+        p_locale: *const c_char,
+        n_locale: c_int,
+        x_token: TokenFunction,
+    ) -> c_int,
+}
+
 #[repr(C)]
-struct FTS5API {
-    i_version: c_int, // Currently always set to 2
+pub(crate) struct FTS5API {
+    i_version: c_int, // Set to 2 or later
 
     /* Create a new tokenizer */
     x_create_tokenizer: extern "C" fn(
@@ -42,6 +117,82 @@ struct FTS5API {
         fts5_tokenizer: *mut Fts5TokenizerApi,
         x_destroy: extern "C" fn(module: *mut c_void),
     ) -> c_int,
+
+    /* Find an existing tokenizer */
+    _x_find_tokenizer: Option<extern "C" fn()>,
+
+    /* Create a new auxiliary function */
+    x_create_function: extern "C" fn(
+        fts5_api: *const FTS5API,
+        z_name: *const c_char,
+        p_user_data: *mut c_void,
+        x_function: Fts5ExtensionFunction,
+        x_destroy: Option<extern "C" fn(p_user_data: *mut c_void)>,
+    ) -> c_int,
+}
+
+/// `fts5_api` as of version 3, which only exists behind an `FTS5API` whose
+/// `i_version` is 3 or later.
+#[repr(C)]
+struct Fts5ApiV3 {
+    base: FTS5API,
+
+    /* Create a new tokenizer taking locales */
+    x_create_tokenizer_v2: extern "C" fn(
+        fts5_api: *const FTS5API,
+        z_name: *const c_uchar,
+        p_context: *mut c_void,
+        fts5_tokenizer: *mut Fts5TokenizerV2Api,
+        x_destroy: extern "C" fn(module: *mut c_void),
+    ) -> c_int,
+
+    /* Find an existing tokenizer taking locales */
+    _x_find_tokenizer_v2: Option<extern "C" fn()>,
+}
+
+pub(crate) struct Fts5Context {}
+
+pub(crate) type Fts5ExtensionFunction = extern "C" fn(
+    api: *const Fts5ExtensionApi,
+    fts: *mut Fts5Context,
+    ctx: *mut Sqlite3Context,
+    n_val: c_int,
+    ap_val: *mut *mut Sqlite3Value,
+);
+
+/// The leading members of `Fts5ExtensionApi`, up to the ones auxiliary
+/// functions here use.
+#[repr(C)]
+pub(crate) struct Fts5ExtensionApi {
+    _i_version: c_int,
+    _x_user_data: Option<extern "C" fn()>,
+    _x_column_count: Option<extern "C" fn()>,
+    _x_row_count: Option<extern "C" fn()>,
+    _x_column_total_size: Option<extern "C" fn()>,
+    pub(crate) x_tokenize: extern "C" fn(
+        fts: *mut Fts5Context,
+        p_text: *const c_char,
+        n_text: c_int,
+        p_ctx: *mut c_void,
+        x_token: TokenFunction,
+    ) -> c_int,
+    _x_phrase_count: Option<extern "C" fn()>,
+    pub(crate) x_phrase_size: extern "C" fn(fts: *mut Fts5Context, i_phrase: c_int) -> c_int,
+    pub(crate) x_inst_count: extern "C" fn(fts: *mut Fts5Context, pn_inst: *mut c_int) -> c_int,
+    pub(crate) x_inst: extern "C" fn(
+        fts: *mut Fts5Context,
+        i_idx: c_int,
+        pi_phrase: *mut c_int,
+        pi_col: *mut c_int,
+        pi_off: *mut c_int,
+    ) -> c_int,
+    _x_rowid: Option<extern "C" fn()>,
+    pub(crate) x_column_text: extern "C" fn(
+        fts: *mut Fts5Context,
+        i_col: c_int,
+        pz: *mut *const c_char,
+        pn: *mut c_int,
+    ) -> c_int,
 }
 
 // sqlite3ext.h
@@ -49,15 +200,27 @@ struct FTS5API {
 pub struct Sqlite3APIRoutines {
     _aggregate_context: extern "C" fn(),
     _aggregate_count: extern "C" fn(),
-    _bind_blob: extern "C" fn(),
+    bind_blob: extern "C" fn(
+        stmt: *mut Sqlite3Stmt,
+        index: c_int,
+        value: *const c_void,
+        len: c_int,
+        destructor: isize,
+    ) -> c_int,
     _bind_double: extern "C" fn(),
     _bind_int: extern "C" fn(),
-    _bind_int64: extern "C" fn(),
+    bind_int64: extern "C" fn(stmt: *mut Sqlite3Stmt, index: c_int, value: i64) -> c_int,
     _bind_null: extern "C" fn(),
     _bind_parameter_count: extern "C" fn(),
     _bind_parameter_index: extern "C" fn(),
     _bind_parameter_name: extern "C" fn(),
-    _bind_text: extern "C" fn(),
+    bind_text: extern "C" fn(
+        stmt: *mut Sqlite3Stmt,
+        index: c_int,
+        text: *const c_char,
+        len: c_int,
+        destructor: isize,
+    ) -> c_int,
     _bind_text16: extern "C" fn(),
     _bind_value: extern "C" fn(),
     _busy_handler: extern "C" fn(),
@@ -67,7 +230,7 @@ pub struct Sqlite3APIRoutines {
     _collation_needed: extern "C" fn(),
     _collation_needed16: extern "C" fn(),
     _column_blob: extern "C" fn(),
-    _column_bytes: extern "C" fn(),
+    column_bytes: extern "C" fn(stmt: *mut Sqlite3Stmt, column: c_int) -> c_int,
     _column_bytes16: extern "C" fn(),
     _column_count: extern "C" fn(),
     _column_database_name: extern "C" fn(),
@@ -83,7 +246,7 @@ pub struct Sqlite3APIRoutines {
     _column_origin_name16: extern "C" fn(),
     _column_table_name: extern "C" fn(),
     _column_table_name16: extern "C" fn(),
-    _column_text: extern "C" fn(),
+    column_text: extern "C" fn(stmt: *mut Sqlite3Stmt, column: c_int) -> *const c_uchar,
     _column_text16: extern "C" fn(),
     _column_type: extern "C" fn(),
     _column_value: extern "C" fn(),
@@ -97,7 +260,7 @@ pub struct Sqlite3APIRoutines {
     _create_module: extern "C" fn(),
     _data_count: extern "C" fn(),
     _db_handle: extern "C" fn(),
-    _declare_vtab: extern "C" fn(),
+    declare_vtab: extern "C" fn(db: *mut Sqlite3, sql: *const c_char) -> c_int,
     _enable_shared_cache: extern "C" fn(),
     _errcode: extern "C" fn(),
     _errmsg: extern "C" fn(),
@@ -115,7 +278,7 @@ pub struct Sqlite3APIRoutines {
     _last_insert_rowid: extern "C" fn(),
     _libversion: extern "C" fn(),
     libversion_number: extern "C" fn() -> c_int,
-    _malloc: extern "C" fn(),
+    malloc: extern "C" fn(n: c_int) -> *mut c_void,
     _mprintf: extern "C" fn(),
     _open: extern "C" fn(),
     _open16: extern "C" fn(),
@@ -132,13 +295,14 @@ pub struct Sqlite3APIRoutines {
     _realloc: extern "C" fn(),
     _reset: extern "C" fn(),
     _result_blob: extern "C" fn(),
-    _result_double: extern "C" fn(),
+    result_double: extern "C" fn(ctx: *mut Sqlite3Context, value: f64),
     _result_error: extern "C" fn(),
     _result_error16: extern "C" fn(),
     _result_int: extern "C" fn(),
-    _result_int64: extern "C" fn(),
-    _result_null: extern "C" fn(),
-    _result_text: extern "C" fn(),
+    result_int64: extern "C" fn(ctx: *mut Sqlite3Context, value: i64),
+    result_null: extern "C" fn(ctx: *mut Sqlite3Context),
+    result_text:
+        extern "C" fn(ctx: *mut Sqlite3Context, text: *const c_char, len: c_int, destructor: isize),
     _result_text16: extern "C" fn(),
     _result_text16be: extern "C" fn(),
     _result_text16le: extern "C" fn(),
@@ -154,19 +318,19 @@ pub struct Sqlite3APIRoutines {
     _trace: extern "C" fn(),
     _transfer_bindings: extern "C" fn(),
     _update_hook: extern "C" fn(),
-    _user_data: extern "C" fn(),
+    user_data: extern "C" fn(ctx: *mut Sqlite3Context) -> *mut c_void,
     _value_blob: extern "C" fn(),
-    _value_bytes: extern "C" fn(),
+    value_bytes: extern "C" fn(value: *mut Sqlite3Value) -> c_int,
     _value_bytes16: extern "C" fn(),
-    _value_double: extern "C" fn(),
+    value_double: extern "C" fn(value: *mut Sqlite3Value) -> f64,
     _value_int: extern "C" fn(),
-    _value_int64: extern "C" fn(),
+    value_int64: extern "C" fn(value: *mut Sqlite3Value) -> i64,
     _value_numeric_type: extern "C" fn(),
-    _value_text: extern "C" fn(),
+    value_text: extern "C" fn(value: *mut Sqlite3Value) -> *const c_uchar,
     _value_text16: extern "C" fn(),
     _value_text16be: extern "C" fn(),
     _value_text16le: extern "C" fn(),
-    _value_type: extern "C" fn(),
+    value_type: extern "C" fn(value: *mut Sqlite3Value) -> c_int,
     _vmprintf: extern "C" fn(),
     /* Added ??? */
     _overload_function: extern "C" fn(),
@@ -175,7 +339,13 @@ pub struct Sqlite3APIRoutines {
     _prepare16_v2: extern "C" fn(),
     _clear_bindings: extern "C" fn(),
     /* Added by 3.4.1 */
-    _create_module_v2: extern "C" fn(),
+    create_module_v2: extern "C" fn(
+        db: *mut Sqlite3,
+        name: *const c_char,
+        module: *const Sqlite3Module,
+        p_client_data: *mut c_void,
+        x_destroy: Option<extern "C" fn(p_client_data: *mut c_void)>,
+    ) -> c_int,
     /* Added by 3.5.0 */
     _bind_zeroblob: extern "C" fn(),
     _blob_bytes: extern "C" fn(),
@@ -219,12 +389,22 @@ pub struct Sqlite3APIRoutines {
     _backup_step: extern "C" fn(),
     _compileoption_get: extern "C" fn(),
     _compileoption_used: extern "C" fn(),
-    _create_function_v2: extern "C" fn(),
+    create_function_v2: extern "C" fn(
+        db: *mut Sqlite3,
+        name: *const c_char,
+        n_arg: c_int,
+        e_text_rep: c_int,
+        p_app: *mut c_void,
+        x_func: Option<ScalarFunction>,
+        x_step: Option<ScalarFunction>,
+        x_final: Option<extern "C" fn(ctx: *mut Sqlite3Context)>,
+        x_destroy: Option<extern "C" fn(p_app: *mut c_void)>,
+    ) -> c_int,
     _db_config: extern "C" fn(),
     _db_mutex: extern "C" fn(),
     _db_status: extern "C" fn(),
     _extended_errcode: extern "C" fn(),
-    _log: extern "C" fn(),
+    log: SqliteLog,
     _soft_heap_limit64: extern "C" fn(),
     _sourceid: extern "C" fn(),
     _stmt_status: extern "C" fn(),
@@ -288,112 +468,2181 @@ pub struct Sqlite3APIRoutines {
     bind_pointer: extern "C" fn(
         stmt: *mut Sqlite3Stmt,
         index: c_int,
-        ptr: *mut *mut FTS5API,
+        ptr: *mut c_void,
         name: *const c_uchar,
         cb: *mut c_void,
     ) -> c_int,
+    _result_pointer: extern "C" fn(),
+    value_pointer: extern "C" fn(value: *mut Sqlite3Value, name: *const c_char) -> *mut c_void,
+}
+
+/// The subset of the SQLite C API needed to register the tokenizer and the
+/// functions that come with it.
+///
+/// A loadable extension reaches SQLite through the `sqlite3_api_routines`
+/// table handed to its entry point, whereas a host that links SQLite directly
+/// calls the library symbols itself.
+pub(crate) trait SqliteApi: Sized + 'static {
+    /// Returns the API that callbacks registered through it should use.
+    fn get() -> Option<&'static Self>;
+    fn libversion_number(&self) -> c_int;
+    fn prepare(&self, db: *mut Sqlite3, query: &CStr, stmt: *mut *mut Sqlite3Stmt) -> c_int;
+    fn bind_pointer(
+        &self,
+        stmt: *mut Sqlite3Stmt,
+        index: c_int,
+        ptr: *mut c_void,
+        name: &CStr,
+    ) -> c_int;
+    fn bind_blob(&self, stmt: *mut Sqlite3Stmt, index: c_int, blob: &[u8]) -> c_int;
+    fn bind_int64(&self, stmt: *mut Sqlite3Stmt, index: c_int, value: i64) -> c_int;
+    fn bind_text(&self, stmt: *mut Sqlite3Stmt, index: c_int, text: &str) -> c_int;
+    fn step(&self, stmt: *mut Sqlite3Stmt) -> c_int;
+    /// Returns the column of the current row as text, replacing invalid
+    /// UTF-8.
+    fn column_text(&self, stmt: *mut Sqlite3Stmt, column: c_int) -> String;
+    fn finalize(&self, stmt: *mut Sqlite3Stmt) -> c_int;
+    /// Registers a scalar function. `x_destroy`, if any, is called with
+    /// `user_data` once SQLite no longer needs the function, even if
+    /// registration fails.
+    #[allow(clippy::too_many_arguments)]
+    fn create_function(
+        &self,
+        db: *mut Sqlite3,
+        name: &CStr,
+        n_arg: c_int,
+        flags: c_int,
+        user_data: *mut c_void,
+        x_func: ScalarFunction,
+        x_destroy: Option<extern "C" fn(user_data: *mut c_void)>,
+    ) -> c_int;
+    /// Returns the `user_data` the running function was registered with.
+    fn user_data(&self, ctx: *mut Sqlite3Context) -> *mut c_void;
+    fn result_text(&self, ctx: *mut Sqlite3Context, text: &str);
+    fn result_int64(&self, ctx: *mut Sqlite3Context, value: i64);
+    fn result_double(&self, ctx: *mut Sqlite3Context, value: f64);
+    fn result_null(&self, ctx: *mut Sqlite3Context);
+    fn value_type(&self, value: *mut Sqlite3Value) -> c_int;
+    fn value_int64(&self, value: *mut Sqlite3Value) -> i64;
+    fn value_double(&self, value: *mut Sqlite3Value) -> f64;
+    /// Returns the value as text, replacing invalid UTF-8.
+    fn value_text(&self, value: *mut Sqlite3Value) -> String;
+    fn value_pointer(&self, value: *mut Sqlite3Value, name: &CStr) -> *mut c_void;
+    /// Registers a virtual table module. `x_destroy` is called with
+    /// `client_data` once SQLite no longer needs the module, even if
+    /// registration fails.
+    fn create_module(
+        &self,
+        db: *mut Sqlite3,
+        name: &CStr,
+        module: *const Sqlite3Module,
+        client_data: *mut c_void,
+        x_destroy: extern "C" fn(client_data: *mut c_void),
+    ) -> c_int;
+    fn declare_vtab(&self, db: *mut Sqlite3, sql: &CStr) -> c_int;
+}
+
+/// The routines handed to `lindera_fts5_tokenizer_init`, kept for callbacks
+/// the same way `SQLITE_EXTENSION_INIT2` keeps `sqlite3_api`.
+static SQLITE3_API: AtomicPtr<Sqlite3APIRoutines> = AtomicPtr::new(null_mut());
+
+impl SqliteApi for Sqlite3APIRoutines {
+    fn get() -> Option<&'static Self> {
+        unsafe { SQLITE3_API.load(Ordering::Acquire).as_ref() }
+    }
+
+    fn libversion_number(&self) -> c_int {
+        (self.libversion_number)()
+    }
+
+    fn prepare(&self, db: *mut Sqlite3, query: &CStr, stmt: *mut *mut Sqlite3Stmt) -> c_int {
+        (self.prepare)(db, query.as_ptr().cast(), -1, stmt, null_mut())
+    }
+
+    fn bind_pointer(
+        &self,
+        stmt: *mut Sqlite3Stmt,
+        index: c_int,
+        ptr: *mut c_void,
+        name: &CStr,
+    ) -> c_int {
+        (self.bind_pointer)(stmt, index, ptr, name.as_ptr().cast(), null_mut())
+    }
+
+    fn bind_blob(&self, stmt: *mut Sqlite3Stmt, index: c_int, blob: &[u8]) -> c_int {
+        match cast_usize_to_c_int(blob.len()) {
+            Ok(len) => (self.bind_blob)(stmt, index, blob.as_ptr().cast(), len, SQLITE_TRANSIENT),
+            Err(rc) => rc,
+        }
+    }
+
+    fn bind_int64(&self, stmt: *mut Sqlite3Stmt, index: c_int, value: i64) -> c_int {
+        (self.bind_int64)(stmt, index, value)
+    }
+
+    fn bind_text(&self, stmt: *mut Sqlite3Stmt, index: c_int, text: &str) -> c_int {
+        match cast_usize_to_c_int(text.len()) {
+            Ok(len) => (self.bind_text)(
+                stmt,
+                index,
+                text.as_ptr() as *const c_char,
+                len,
+                SQLITE_TRANSIENT,
+            ),
+            Err(rc) => rc,
+        }
+    }
+
+    fn step(&self, stmt: *mut Sqlite3Stmt) -> c_int {
+        (self.step)(stmt)
+    }
+
+    fn column_text(&self, stmt: *mut Sqlite3Stmt, column: c_int) -> String {
+        let text = (self.column_text)(stmt, column);
+        if text.is_null() {
+            return String::new();
+        }
+        let len = (self.column_bytes)(stmt, column) as usize;
+        let bytes = unsafe { core::slice::from_raw_parts(text, len) };
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    fn finalize(&self, stmt: *mut Sqlite3Stmt) -> c_int {
+        (self.finalize)(stmt)
+    }
+
+    fn create_function(
+        &self,
+        db: *mut Sqlite3,
+        name: &CStr,
+        n_arg: c_int,
+        flags: c_int,
+        user_data: *mut c_void,
+        x_func: ScalarFunction,
+        x_destroy: Option<extern "C" fn(user_data: *mut c_void)>,
+    ) -> c_int {
+        (self.create_function_v2)(
+            db,
+            name.as_ptr(),
+            n_arg,
+            flags,
+            user_data,
+            Some(x_func),
+            None,
+            None,
+            x_destroy,
+        )
+    }
+
+    fn user_data(&self, ctx: *mut Sqlite3Context) -> *mut c_void {
+        (self.user_data)(ctx)
+    }
+
+    fn result_text(&self, ctx: *mut Sqlite3Context, text: &str) {
+        match cast_usize_to_c_int(text.len()) {
+            Ok(len) => {
+                (self.result_text)(ctx, text.as_ptr() as *const c_char, len, SQLITE_TRANSIENT)
+            }
+            Err(rc) => {
+                report_error(
+                    rc,
+                    format_args!("Result of {} bytes is too long for SQLite", text.len()),
+                );
+                self.result_null(ctx)
+            }
+        }
+    }
+
+    fn result_int64(&self, ctx: *mut Sqlite3Context, value: i64) {
+        (self.result_int64)(ctx, value)
+    }
+
+    fn result_double(&self, ctx: *mut Sqlite3Context, value: f64) {
+        (self.result_double)(ctx, value)
+    }
+
+    fn result_null(&self, ctx: *mut Sqlite3Context) {
+        (self.result_null)(ctx)
+    }
+
+    fn value_type(&self, value: *mut Sqlite3Value) -> c_int {
+        (self.value_type)(value)
+    }
+
+    fn value_int64(&self, value: *mut Sqlite3Value) -> i64 {
+        (self.value_int64)(value)
+    }
+
+    fn value_double(&self, value: *mut Sqlite3Value) -> f64 {
+        (self.value_double)(value)
+    }
+
+    fn value_text(&self, value: *mut Sqlite3Value) -> String {
+        let text = (self.value_text)(value);
+        if text.is_null() {
+            return String::new();
+        }
+        let len = (self.value_bytes)(value) as usize;
+        let bytes = unsafe { core::slice::from_raw_parts(text, len) };
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    fn value_pointer(&self, value: *mut Sqlite3Value, name: &CStr) -> *mut c_void {
+        (self.value_pointer)(value, name.as_ptr())
+    }
+
+    fn create_module(
+        &self,
+        db: *mut Sqlite3,
+        name: &CStr,
+        module: *const Sqlite3Module,
+        client_data: *mut c_void,
+        x_destroy: extern "C" fn(client_data: *mut c_void),
+    ) -> c_int {
+        (self.create_module_v2)(db, name.as_ptr(), module, client_data, Some(x_destroy))
+    }
+
+    fn declare_vtab(&self, db: *mut Sqlite3, sql: &CStr) -> c_int {
+        (self.declare_vtab)(db, sql.as_ptr())
+    }
 }
 
 #[no_mangle]
 pub extern "C" fn lindera_fts5_tokenizer_init(
     db: *mut Sqlite3,
-    _pz_err_msg: *mut *mut c_uchar,
+    pz_err_msg: *mut *mut c_uchar,
     p_api: *const c_void,
 ) -> c_int {
-    std::panic::catch_unwind(|| match lindera_fts_tokenizer_internal_init(db, p_api) {
-        Ok(_) => SQLITE_OK,
-        Err(code) => code,
+    init_extension(p_api, pz_err_msg, |api| {
+        lindera_fts_tokenizer_internal_init(db, api)
     })
-    .unwrap_or(SQLITE_INTERNAL)
 }
 
-fn lindera_fts_tokenizer_internal_init(
+/// The entry point SQLite looks for when `liblindera_sqlite` is loaded
+/// without naming one: `sqlite3_` followed by the file name without its
+/// `lib` prefix, extension and non-letters, and `_init`.
+#[no_mangle]
+pub extern "C" fn sqlite3_linderasqlite_init(
     db: *mut Sqlite3,
+    pz_err_msg: *mut *mut c_uchar,
     p_api: *const c_void,
-) -> Result<(), c_int> {
-    let api = unsafe { (p_api as *const Sqlite3APIRoutines).as_ref() }.ok_or(SQLITE_INTERNAL)?;
+) -> c_int {
+    lindera_fts5_tokenizer_init(db, pz_err_msg, p_api)
+}
+
+/// The entry point SQLite falls back to when none derived from the file name
+/// exists, for copies of the library that were renamed.
+#[no_mangle]
+pub extern "C" fn sqlite3_extension_init(
+    db: *mut Sqlite3,
+    pz_err_msg: *mut *mut c_uchar,
+    p_api: *const c_void,
+) -> c_int {
+    lindera_fts5_tokenizer_init(db, pz_err_msg, p_api)
+}
+
+/// Like `lindera_fts5_tokenizer_init`, but with the embedded IPADIC and its
+/// default settings rather than `LINDERA_CONFIG_PATH`.
+#[cfg(feature = "ipadic")]
+#[no_mangle]
+pub extern "C" fn lindera_fts5_tokenizer_init_ipadic(
+    db: *mut Sqlite3,
+    pz_err_msg: *mut *mut c_uchar,
+    p_api: *const c_void,
+) -> c_int {
+    init_extension(p_api, pz_err_msg, |api| {
+        register_lindera_tokenizer(
+            db,
+            api,
+            TokenizerContext::embedded(EmbeddedDictionary::Ipadic),
+        )
+    })
+}
+
+/// Like `lindera_fts5_tokenizer_init`, but with the embedded UniDic and its
+/// default settings rather than `LINDERA_CONFIG_PATH`.
+#[cfg(feature = "unidic")]
+#[no_mangle]
+pub extern "C" fn lindera_fts5_tokenizer_init_unidic(
+    db: *mut Sqlite3,
+    pz_err_msg: *mut *mut c_uchar,
+    p_api: *const c_void,
+) -> c_int {
+    init_extension(p_api, pz_err_msg, |api| {
+        register_lindera_tokenizer(
+            db,
+            api,
+            TokenizerContext::embedded(EmbeddedDictionary::UniDic),
+        )
+    })
+}
+
+/// Like `lindera_fts5_tokenizer_init`, but with the embedded ko-dic and its
+/// default settings rather than `LINDERA_CONFIG_PATH`.
+#[cfg(feature = "ko-dic")]
+#[no_mangle]
+pub extern "C" fn lindera_fts5_tokenizer_init_ko_dic(
+    db: *mut Sqlite3,
+    pz_err_msg: *mut *mut c_uchar,
+    p_api: *const c_void,
+) -> c_int {
+    init_extension(p_api, pz_err_msg, |api| {
+        register_lindera_tokenizer(
+            db,
+            api,
+            TokenizerContext::embedded(EmbeddedDictionary::KoDic),
+        )
+    })
+}
+
+/// Like `lindera_fts5_tokenizer_init`, but with the embedded CC-CEDICT and
+/// its default settings rather than `LINDERA_CONFIG_PATH`.
+#[cfg(feature = "cc-cedict")]
+#[no_mangle]
+pub extern "C" fn lindera_fts5_tokenizer_init_cc_cedict(
+    db: *mut Sqlite3,
+    pz_err_msg: *mut *mut c_uchar,
+    p_api: *const c_void,
+) -> c_int {
+    init_extension(p_api, pz_err_msg, |api| {
+        register_lindera_tokenizer(
+            db,
+            api,
+            TokenizerContext::embedded(EmbeddedDictionary::CcCedict),
+        )
+    })
+}
+
+/// Registers `lindera_tokenizer` for FTS3 and FTS4 tables, for applications
+/// moving such tables to FTS5. Load it next to `lindera_fts5_tokenizer_init`
+/// to serve both kinds of table.
+#[no_mangle]
+pub extern "C" fn lindera_fts3_tokenizer_init(
+    db: *mut Sqlite3,
+    pz_err_msg: *mut *mut c_uchar,
+    p_api: *const c_void,
+) -> c_int {
+    init_extension(p_api, pz_err_msg, |api| {
+        register_lindera_fts3_tokenizer(db, api)
+    })
+}
+
+fn init_extension(
+    p_api: *const c_void,
+    pz_err_msg: *mut *mut c_uchar,
+    register: impl FnOnce(&Sqlite3APIRoutines) -> Result<(), InitError>,
+) -> c_int {
+    let Some(api) = (unsafe { (p_api as *const Sqlite3APIRoutines).as_ref() }) else {
+        return SQLITE_INTERNAL;
+    };
+    SQLITE3_API.store(p_api as *mut Sqlite3APIRoutines, Ordering::Release);
+    set_sqlite_log(api.log);
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| register(api)))
+        .unwrap_or_else(|_| {
+            Err(InitError::new(
+                SQLITE_INTERNAL,
+                "lindera_tokenizer panicked while loading",
+            ))
+        });
+    match result {
+        Ok(()) => SQLITE_OK,
+        Err(e) => {
+            report_error(
+                e.code(),
+                format_args!("Failed to load lindera_tokenizer: {}", e),
+            );
+            set_error_message(api, pz_err_msg, &e.message);
+            e.code()
+        }
+    }
+}
+
+/// Hands `message` to `sqlite3_load_extension` in `*pz_err_msg`, allocated
+/// with `sqlite3_malloc` so that SQLite can free it.
+fn set_error_message(api: &Sqlite3APIRoutines, pz_err_msg: *mut *mut c_uchar, message: &str) {
+    if pz_err_msg.is_null() {
+        return;
+    }
+    let Ok(size) = c_int::try_from(message.len() + 1) else {
+        return;
+    };
+    let buffer = (api.malloc)(size) as *mut c_uchar;
+    if buffer.is_null() {
+        return;
+    }
+    unsafe {
+        core::ptr::copy_nonoverlapping(message.as_ptr(), buffer, message.len());
+        *buffer.add(message.len()) = 0;
+        *pz_err_msg = buffer;
+    }
+}
+
+/// Reads the configuration named by the argument from the `lindera_config`
+/// table of the database.
+type DatabaseConfigReader = fn(db: *mut Sqlite3, name: &str) -> Option<String>;
+
+/// Compiles a user dictionary table of the database for the connection.
+type UserDictionaryCompiler = fn(db: *mut Sqlite3, table: &str);
+
+/// The row of `lindera_config` tables use without a `config_name` argument.
+const DEFAULT_DATABASE_CONFIG: &str = "default";
+
+/// What `lindera_config_path()` returns when no configuration is set.
+const EMBEDDED_DEFAULT_CONFIG: &str = "<embedded-default>";
+
+/// Configuration shared by the tokenizers of one connection.
+///
+/// It is handed to FTS5 as the tokenizer module's context, so FTS5 keeps it
+/// alive for as long as the connection and frees it through
+/// `fts5_destroy_icu_module` when the connection closes.
+#[derive(Clone)]
+pub(crate) struct TokenizerContext {
+    config_path: Option<PathBuf>,
+    embedded_dictionary: Option<EmbeddedDictionary>,
+    /// The connection's database, with how to read its `lindera_config`
+    /// table and compile its `lindera_userdict` table. Set on registration.
+    database: Option<(*mut Sqlite3, DatabaseConfigReader, UserDictionaryCompiler)>,
+    /// The entry of the configuration's `tokenizers` map this tokenizer was
+    /// registered for, if any.
+    profile: Option<String>,
+    /// Whether the `lindera_userdict` table has been compiled since the
+    /// connection registered the tokenizers, shared by all their contexts.
+    user_dictionary_compiled: Arc<AtomicBool>,
+    /// What [`default_pipelines`] resolved last, shared by the clones of this
+    /// context so that the SQL functions of the connection resolve it once.
+    default_pipelines: Arc<Mutex<Option<ResolvedPipelines>>>,
+}
+
+/// The tokenizers of tables that give no arguments, with the
+/// [`TokenizerCache::version`] they were resolved at.
+struct ResolvedPipelines {
+    version: (u64, u64),
+    pipelines: Arc<Pipelines>,
+}
+
+impl TokenizerContext {
+    pub(crate) fn new(config_path: Option<PathBuf>) -> Self {
+        TokenizerContext {
+            config_path,
+            embedded_dictionary: None,
+            database: None,
+            profile: None,
+            user_dictionary_compiled: Arc::new(AtomicBool::new(false)),
+            default_pipelines: Arc::default(),
+        }
+    }
+
+    /// Uses the default settings of `dictionary` instead of a configuration
+    /// file.
+    #[cfg_attr(
+        not(any(
+            feature = "ipadic",
+            feature = "unidic",
+            feature = "ko-dic",
+            feature = "cc-cedict"
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) fn embedded(dictionary: EmbeddedDictionary) -> Self {
+        TokenizerContext {
+            config_path: None,
+            embedded_dictionary: Some(dictionary),
+            database: None,
+            profile: None,
+            user_dictionary_compiled: Arc::new(AtomicBool::new(false)),
+            default_pipelines: Arc::default(),
+        }
+    }
 
-    if (api.libversion_number)() < 302000 {
-        return Err(SQLITE_MISUSE);
+    /// Resolves the configuration from `LINDERA_CONFIG_PATH` at the time the
+    /// tokenizer is registered.
+    pub(crate) fn from_env() -> Self {
+        Self::new(env::var_os("LINDERA_CONFIG_PATH").map(PathBuf::from))
+    }
+
+    /// Describes the configuration tokenizers use unless a table sets its own:
+    /// the canonical path of the file, `<embedded-default>` for Lindera's
+    /// built-in default, or `None` for a configuration that has no path
+    /// because [`set_global_config`](crate::config::set_global_config) or
+    /// `LINDERA_CONFIG` gave it inline.
+    fn describe_config_path(&self) -> Option<String> {
+        match (&self.config_path, self.embedded_dictionary) {
+            (Some(path), _) => Some(
+                fs::canonicalize(path)
+                    .unwrap_or_else(|_| path.clone())
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            (None, Some(_)) => Some(EMBEDDED_DEFAULT_CONFIG.to_string()),
+            (None, None) if global_config_is_set() => None,
+            (None, None) if env::var_os("LINDERA_CONFIG").is_some() => None,
+            (None, None) => Some(EMBEDDED_DEFAULT_CONFIG.to_string()),
+        }
+    }
+
+    /// Describes where tables that do not set their own configuration get
+    /// it from, in the order tokenizers look for it.
+    fn config_source(&self) -> serde_json::Value {
+        if self.profile.is_none() && self.database_config(DEFAULT_DATABASE_CONFIG).is_some() {
+            return serde_json::json!({
+                "source": "database",
+                "name": DEFAULT_DATABASE_CONFIG,
+            });
+        }
+        match (&self.config_path, self.embedded_dictionary) {
+            (Some(_), _) => serde_json::json!({
+                "source": "file",
+                "path": self.describe_config_path(),
+            }),
+            (None, Some(dictionary)) => serde_json::json!({
+                "source": "dictionary",
+                "dictionary": dictionary.name(),
+            }),
+            (None, None) if global_config_is_set() => serde_json::json!({ "source": "global" }),
+            (None, None) if env::var_os("LINDERA_CONFIG").is_some() => {
+                serde_json::json!({ "source": "inline" })
+            }
+            (None, None) if cfg!(feature = "default-config") => {
+                serde_json::json!({ "source": "builtin" })
+            }
+            (None, None) => serde_json::json!({ "source": null }),
+        }
+    }
+
+    /// Loads the configuration tokenizers use unless a table sets its own, or
+    /// `None` for the defaults of an embedded dictionary.
+    fn config(&self) -> Result<Option<TokenizerConfig>, LinderaSqliteError> {
+        match (&self.config_path, self.embedded_dictionary) {
+            (Some(path), _) => read_config(path).map(Some),
+            (None, Some(_)) => Ok(None),
+            (None, None) => env_config(),
+        }
+    }
+
+    /// Reads the configuration `name` from the database's `lindera_config`
+    /// table, if the table and the row exist.
+    fn database_config(&self, name: &str) -> Option<String> {
+        let (db, read, _) = self.database?;
+        read(db, name)
+    }
+
+    /// The user dictionary compiled from the database's `lindera_userdict`
+    /// table. The table is compiled the first time a tokenizer or function
+    /// of the connection is used rather than on registration, where a failed
+    /// query would fail opening the connection; this also removes a file an
+    /// earlier connection with the same handle left.
+    fn database_user_dictionary(&self) -> Option<PathBuf> {
+        let (db, _, compile) = self.database?;
+        if !self.user_dictionary_compiled.swap(true, Ordering::AcqRel) {
+            compile(db, USER_DICTIONARY_TABLE);
+        }
+        Some(user_dictionary_path(db, USER_DICTIONARY_TABLE))
+    }
+
+    /// The user dictionary compiled from the database's `table`, which is
+    /// read again each time.
+    fn table_user_dictionary(&self, table: &str) -> Option<PathBuf> {
+        let (db, _, compile) = self.database?;
+        compile(db, table);
+        Some(user_dictionary_path(db, table))
+    }
+
+    /// The connection the tokenizers and functions are registered on.
+    pub(crate) fn db(&self) -> Option<*mut Sqlite3> {
+        self.database.map(|(db, _, _)| db)
+    }
+}
+
+fn compile_database_user_dictionary<A: SqliteApi>(db: *mut Sqlite3, table: &str) {
+    if let Some(api) = A::get() {
+        let _ = compile_user_dictionary(api, db, table);
+    }
+}
+
+fn read_database_config<A: SqliteApi>(db: *mut Sqlite3, name: &str) -> Option<String> {
+    let api = A::get()?;
+    let mut stmt = null_mut::<Sqlite3Stmt>();
+    let rc = api.prepare(
+        db,
+        c"SELECT yaml FROM lindera_config WHERE name = ?1",
+        &mut stmt,
+    );
+    if rc != SQLITE_OK {
+        // Most databases have no `lindera_config` table.
+        return None;
+    }
+
+    let config = (api.bind_text(stmt, 1, name) == SQLITE_OK && api.step(stmt) == SQLITE_ROW)
+        .then(|| api.column_text(stmt, 0));
+    api.finalize(stmt);
+    config
+}
+
+/// The number of `Fts5Tokenizer`s currently alive, one per open FTS5 table.
+static TOKENIZER_INSTANCES: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn lindera_fts_tokenizer_internal_init(
+    db: *mut Sqlite3,
+    api: &impl SqliteApi,
+) -> Result<(), InitError> {
+    register_lindera_tokenizer(db, api, TokenizerContext::from_env())
+}
+
+pub(crate) fn register_lindera_tokenizer<A: SqliteApi>(
+    db: *mut Sqlite3,
+    api: &A,
+    context: TokenizerContext,
+) -> Result<(), InitError> {
+    if api.libversion_number() < MIN_SQLITE_VERSION_NUMBER {
+        return Err(InitError::new(
+            SQLITE_MISUSE,
+            format!(
+                "lindera_tokenizer requires SQLite {} or later, but this is SQLite {}",
+                format_version_number(MIN_SQLITE_VERSION_NUMBER),
+                format_version_number(api.libversion_number())
+            ),
+        ));
     }
 
     let mut stmt = null_mut::<Sqlite3Stmt>();
-    let rc = (api.prepare)(db, "SELECT fts5(?1)\0".as_ptr(), -1, &mut stmt, null_mut());
+    let rc = api.prepare(db, c"SELECT fts5(?1)", &mut stmt);
 
     if rc != SQLITE_OK {
-        return Err(rc);
+        return Err(InitError::new(
+            rc,
+            "lindera_tokenizer requires FTS5, which this SQLite is built without",
+        ));
     }
 
     let mut p_fts5_api = null_mut::<FTS5API>();
-    let rc = (api.bind_pointer)(
+    let rc = api.bind_pointer(
         stmt,
         1,
-        &mut p_fts5_api,
-        b"fts5_api_ptr\0".as_ptr(),
-        null_mut(),
+        (&mut p_fts5_api as *mut *mut FTS5API).cast(),
+        c"fts5_api_ptr",
     );
     if rc != SQLITE_OK {
-        (api.finalize)(stmt);
-        return Err(rc);
+        api.finalize(stmt);
+        return Err(rc.into());
     }
 
     // Intentionally ignore return value, sqlite3 returns SQLITE_ROW
-    (api.step)(stmt);
+    api.step(stmt);
 
-    let rc = (api.finalize)(stmt);
+    let rc = api.finalize(stmt);
     if rc != SQLITE_OK {
-        return Err(rc);
+        return Err(rc.into());
     }
 
-    let fts5_api = unsafe { p_fts5_api.as_ref() }.ok_or(SQLITE_INTERNAL)?;
+    let fts5_api = unsafe { p_fts5_api.as_ref() }
+        .ok_or_else(|| InitError::new(SQLITE_INTERNAL, "FTS5 did not hand out its API"))?;
 
-    if fts5_api.i_version != FTS5_API_VERSION {
-        return Err(SQLITE_MISUSE);
+    // Later versions of fts5_api only append members, so the layout declared
+    // here stays valid for them.
+    if fts5_api.i_version < FTS5_API_VERSION {
+        return Err(InitError::new(
+            SQLITE_MISUSE,
+            format!(
+                "lindera_tokenizer requires version {} of the FTS5 API, but this is version {}",
+                FTS5_API_VERSION, fts5_api.i_version
+            ),
+        ));
     }
 
-    // Add custom tokenizer
-    let mut tokenizer = Fts5TokenizerApi {
-        x_create: fts5_create_lindera_tokenizer,
-        x_delete: fts5_delete_lindera_tokenizer,
-        x_tokenize: lindera_fts5_tokenize,
+    // Tables only load the configuration when they are created or opened, so
+    // a broken one would otherwise go unnoticed until then.
+    let profiles = context
+        .config()
+        .map_err(|e| InitError {
+            message: format!("invalid Lindera configuration: {}", e),
+            error: e,
+        })?
+        .map(|config| profile_names(&config))
+        .unwrap_or_default();
+
+    let config_path = context.describe_config_path();
+    let database: Option<(*mut Sqlite3, DatabaseConfigReader, UserDictionaryCompiler)> = Some((
+        db,
+        read_database_config::<A>,
+        compile_database_user_dictionary::<A>,
+    ));
+    for profile in profiles {
+        let name = CString::new(profile.as_str()).map_err(|_| {
+            InitError::new(
+                SQLITE_ERROR,
+                format!("invalid tokenizer profile name {:?}", profile),
+            )
+        })?;
+        let profile_context = TokenizerContext {
+            config_path: context.config_path.clone(),
+            embedded_dictionary: None,
+            database,
+            profile: Some(profile),
+            user_dictionary_compiled: context.user_dictionary_compiled.clone(),
+            default_pipelines: Arc::default(),
+        };
+        create_tokenizer_module(fts5_api, &name, profile_context)?;
+    }
+
+    // `lindera_tokenize` uses the configuration of `lindera_tokenizer`.
+    let function_context = TokenizerContext {
+        config_path: context.config_path.clone(),
+        embedded_dictionary: context.embedded_dictionary,
+        database,
+        profile: None,
+        user_dictionary_compiled: context.user_dictionary_compiled.clone(),
+        default_pipelines: Arc::default(),
     };
+    let mut context = context;
+    context.database = database;
+    create_tokenizer_module(fts5_api, c"lindera_tokenizer", context)?;
+
+    let rc = (fts5_api.x_create_function)(
+        fts5_api,
+        c"lindera_tokens_aux".as_ptr(),
+        null_mut(),
+        lindera_tokens_aux::<A>,
+        None,
+    );
+    if rc != SQLITE_OK {
+        return Err(InitError::new(
+            rc,
+            "failed to register lindera_tokens_aux with FTS5",
+        ));
+    }
 
-    (fts5_api.x_create_tokenizer)(
+    let rc = (fts5_api.x_create_function)(
         fts5_api,
-        b"lindera_tokenizer\0".as_ptr(),
+        c"lindera_highlight".as_ptr(),
         null_mut(),
-        &mut tokenizer,
-        fts5_destroy_icu_module,
+        lindera_highlight_aux::<A>,
+        None,
     );
+    if rc != SQLITE_OK {
+        return Err(InitError::new(
+            rc,
+            "failed to register lindera_highlight with FTS5",
+        ));
+    }
 
-    Ok(())
+    let rc = (fts5_api.x_create_function)(
+        fts5_api,
+        c"lindera_snippet".as_ptr(),
+        null_mut(),
+        lindera_snippet_aux::<A>,
+        None,
+    );
+    if rc != SQLITE_OK {
+        return Err(InitError::new(
+            rc,
+            "failed to register lindera_snippet with FTS5",
+        ));
+    }
+
+    register_functions(db, api, config_path, function_context)
+        .map_err(|rc| InitError::new(rc, "failed to register the lindera SQL functions"))
 }
 
-#[no_mangle]
-pub extern "C" fn fts5_create_lindera_tokenizer(
-    _p_context: *mut c_void,
-    _az_arg: *const *const c_uchar,
-    _n_arg: c_int,
-    fts5_tokenizer: *mut *mut Fts5Tokenizer,
-) -> c_int {
-    let tokenizer = match load_tokenizer() {
-        Ok(tokenizer) => Box::new(Fts5Tokenizer { tokenizer }),
-        Err(_) => return SQLITE_INTERNAL,
+/// Registers the Lindera tokenizer with FTS5 as `name`, with `context` for
+/// the tables that use it. FTS5 from SQLite 3.47 on gets the v2 tokenizer,
+/// which picks a dictionary by the locale of each row.
+fn create_tokenizer_module(
+    fts5_api: &FTS5API,
+    name: &CStr,
+    context: TokenizerContext,
+) -> Result<(), InitError> {
+    let p_context = Box::into_raw(Box::new(context)) as *mut c_void;
+    let rc = if fts5_api.i_version >= FTS5_API_VERSION_LOCALE {
+        let fts5_api_v3 = unsafe { &*(fts5_api as *const FTS5API as *const Fts5ApiV3) };
+        let mut tokenizer = Fts5TokenizerV2Api {
+            i_version: 2,
+            x_create: fts5_create_lindera_tokenizer,
+            x_delete: fts5_delete_lindera_tokenizer,
+            x_tokenize: lindera_fts5_tokenize_v2,
+        };
+        (fts5_api_v3.x_create_tokenizer_v2)(
+            fts5_api,
+            name.as_ptr().cast(),
+            p_context,
+            &mut tokenizer,
+            fts5_destroy_icu_module,
+        )
+    } else {
+        let mut tokenizer = Fts5TokenizerApi {
+            x_create: fts5_create_lindera_tokenizer,
+            x_delete: fts5_delete_lindera_tokenizer,
+            x_tokenize: lindera_fts5_tokenize,
+        };
+        (fts5_api.x_create_tokenizer)(
+            fts5_api,
+            name.as_ptr().cast(),
+            p_context,
+            &mut tokenizer,
+            fts5_destroy_icu_module,
+        )
     };
-    unsafe {
-        *fts5_tokenizer = Box::into_raw(tokenizer);
+    if rc != SQLITE_OK {
+        // FTS5 only takes ownership of the context once registration succeeds.
+        fts5_destroy_icu_module(p_context);
+        return Err(InitError::new(
+            rc,
+            format!("failed to register {} with FTS5", name.to_string_lossy()),
+        ));
     }
 
-    SQLITE_OK
+    Ok(())
 }
 
+/// The `xTokenize` of the v2 tokenizer: tokenizes text in a locale of
+/// Japanese, Korean or Chinese, such as one given with `fts5_locale('ko',
+/// ...)`, with the embedded dictionary of that language, and other text like
+/// `lindera_fts5_tokenize`.
+// FTS5 calls this through a safe `extern "C" fn` pointer.
+#[allow(clippy::not_unsafe_ptr_arg_deref, clippy::too_many_arguments)]
 #[no_mangle]
-pub extern "C" fn fts5_delete_lindera_tokenizer(fts5_tokenizer: *mut Fts5Tokenizer) {
+pub extern "C" fn lindera_fts5_tokenize_v2(
+    tokenizer: *mut Fts5Tokenizer,
+    p_ctx: *mut c_void,
+    flags: c_int,
+    p_text: *const c_char,
+    n_text: c_int,
+    p_locale: *const c_char,
+    n_locale: c_int,
+    x_token: TokenFunction,
+) -> c_int {
+    let locale = (!p_locale.is_null() && n_locale > 0)
+        .then(|| unsafe { core::slice::from_raw_parts(p_locale.cast(), n_locale as usize) })
+        .and_then(|locale| core::str::from_utf8(locale).ok());
+    let tokenizer = match locale {
+        // Building the locale's tokenizer runs Lindera, which must not
+        // unwind into SQLite.
+        Some(locale) => std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            unsafe { &mut *tokenizer }.for_locale(locale) as *mut Fts5Tokenizer
+        }))
+        .unwrap_or(tokenizer),
+        None => tokenizer,
+    };
+    lindera_fts5_tokenize(tokenizer, p_ctx, flags, p_text, n_text, x_token)
+}
+
+/// Registers the SQL functions, with `context` for those that tokenize text
+/// like `lindera_tokenizer`.
+fn register_functions<A: SqliteApi>(
+    db: *mut Sqlite3,
+    api: &A,
+    config_path: Option<String>,
+    context: TokenizerContext,
+) -> Result<(), c_int> {
+    let rc = api.create_function(
+        db,
+        c"lindera_api_info",
+        0,
+        SQLITE_UTF8 | SQLITE_DETERMINISTIC,
+        null_mut(),
+        lindera_api_info_func::<A>,
+        None,
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_tokenizer_stats",
+        0,
+        SQLITE_UTF8,
+        null_mut(),
+        lindera_tokenizer_stats_func::<A>,
+        None,
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_last_error",
+        0,
+        SQLITE_UTF8,
+        null_mut(),
+        lindera_last_error_func::<A>,
+        None,
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_reload",
+        0,
+        SQLITE_UTF8,
+        null_mut(),
+        lindera_reload_func::<A>,
+        None,
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_config_path",
+        0,
+        SQLITE_UTF8 | SQLITE_DETERMINISTIC,
+        config_path.map_or(null_mut(), |config_path| {
+            Box::into_raw(Box::new(config_path)) as *mut c_void
+        }),
+        lindera_config_path_func::<A>,
+        Some(drop_config_path),
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_version",
+        0,
+        SQLITE_UTF8 | SQLITE_DETERMINISTIC,
+        null_mut(),
+        lindera_version_func::<A>,
+        None,
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_info",
+        0,
+        SQLITE_UTF8,
+        Box::into_raw(Box::new(context.clone())) as *mut c_void,
+        lindera_info_func::<A>,
+        Some(fts5_destroy_icu_module),
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_version_compatible",
+        1,
+        SQLITE_UTF8 | SQLITE_DETERMINISTIC,
+        null_mut(),
+        lindera_version_compatible_func::<A>,
+        None,
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    // `format` is optional.
+    for n_arg in [1, 2] {
+        let rc = api.create_function(
+            db,
+            c"lindera_furigana",
+            n_arg,
+            SQLITE_UTF8,
+            Box::into_raw(Box::new(context.clone())) as *mut c_void,
+            lindera_furigana_func::<A>,
+            Some(fts5_destroy_icu_module),
+        );
+        if rc != SQLITE_OK {
+            return Err(rc);
+        }
+    }
+
+    // `method` is optional.
+    for n_arg in [2, 3] {
+        let rc = api.create_function(
+            db,
+            c"lindera_similar",
+            n_arg,
+            SQLITE_UTF8,
+            Box::into_raw(Box::new(context.clone())) as *mut c_void,
+            lindera_similar_func::<A>,
+            Some(fts5_destroy_icu_module),
+        );
+        if rc != SQLITE_OK {
+            return Err(rc);
+        }
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_detect_language",
+        1,
+        SQLITE_UTF8 | SQLITE_DETERMINISTIC,
+        null_mut(),
+        lindera_detect_language_func::<A>,
+        None,
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    // Reloading the configuration changes the result, so the function is
+    // not deterministic.
+    let rc = api.create_function(
+        db,
+        c"lindera_wakati",
+        1,
+        SQLITE_UTF8,
+        Box::into_raw(Box::new(context.clone())) as *mut c_void,
+        lindera_wakati_func::<A>,
+        Some(fts5_destroy_icu_module),
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_match_query",
+        1,
+        SQLITE_UTF8,
+        Box::into_raw(Box::new(context.clone())) as *mut c_void,
+        lindera_match_query_func::<A>,
+        Some(fts5_destroy_icu_module),
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_userdict_add",
+        3,
+        SQLITE_UTF8,
+        Box::into_raw(Box::new(context.clone())) as *mut c_void,
+        lindera_userdict_add_func::<A>,
+        Some(fts5_destroy_icu_module),
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_userdict_compile",
+        0,
+        SQLITE_UTF8,
+        Box::into_raw(Box::new(context.clone())) as *mut c_void,
+        lindera_userdict_compile_func::<A>,
+        Some(fts5_destroy_icu_module),
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_userdict_remove",
+        1,
+        SQLITE_UTF8,
+        Box::into_raw(Box::new(context.clone())) as *mut c_void,
+        lindera_userdict_remove_func::<A>,
+        Some(fts5_destroy_icu_module),
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_normalize",
+        1,
+        SQLITE_UTF8,
+        Box::into_raw(Box::new(context.clone())) as *mut c_void,
+        lindera_normalize_func::<A>,
+        Some(fts5_destroy_icu_module),
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_reading",
+        1,
+        SQLITE_UTF8,
+        Box::into_raw(Box::new(context.clone())) as *mut c_void,
+        lindera_reading_func::<A>,
+        Some(fts5_destroy_icu_module),
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    let rc = api.create_function(
+        db,
+        c"lindera_romanize",
+        1,
+        SQLITE_UTF8,
+        Box::into_raw(Box::new(context.clone())) as *mut c_void,
+        lindera_romanize_func::<A>,
+        Some(fts5_destroy_icu_module),
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    #[cfg(feature = "memory-stats")]
+    {
+        let rc = api.create_function(
+            db,
+            c"lindera_peak_memory",
+            0,
+            SQLITE_UTF8,
+            null_mut(),
+            lindera_peak_memory_func::<A>,
+            None,
+        );
+        if rc != SQLITE_OK {
+            return Err(rc);
+        }
+    }
+
+    register_table_function::<A, LinderaTokens>(db, api, c"lindera_tokens", ())?;
+    register_table_function::<A, LinderaSentences>(db, api, c"lindera_sentences", ())?;
+    register_table_function::<A, LinderaStats>(db, api, c"lindera_stats", ())?;
+    register_table_function::<A, LinderaTokenize>(db, api, c"lindera_tokenize", context.clone())?;
+    register_table_function::<A, LinderaExplain>(db, api, c"lindera_explain", context.clone())?;
+    register_table_function::<A, LinderaKeywords>(db, api, c"lindera_keywords", context.clone())?;
+    register_table_function::<A, LinderaDict>(db, api, c"lindera_dict", context)
+}
+
+pub(crate) fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Describes what this build of the extension supports, as JSON.
+fn api_info() -> &'static CStr {
+    static API_INFO: OnceLock<CString> = OnceLock::new();
+    API_INFO.get_or_init(|| {
+        let features = env!("LINDERA_SQLITE_FEATURES")
+            .split(',')
+            .filter(|feature| !feature.is_empty())
+            .collect::<Vec<_>>();
+        let info = serde_json::json!({
+            "fts5_api_version": FTS5_API_VERSION,
+            "min_sqlite_version": format_version_number(MIN_SQLITE_VERSION_NUMBER),
+            "features": features,
+        });
+        CString::new(info.to_string()).expect("JSON has no interior nul")
+    })
+}
+
+/// Formats a `sqlite3_libversion_number()` as `major.minor.patch`.
+fn format_version_number(version: c_int) -> String {
+    format!(
+        "{}.{}.{}",
+        version / 1000000,
+        version / 1000 % 1000,
+        version % 1000
+    )
+}
+
+#[no_mangle]
+pub extern "C" fn lindera_sqlite_api_info() -> *const c_char {
+    api_info().as_ptr()
+}
+
+extern "C" fn lindera_api_info_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    _argc: c_int,
+    _argv: *mut *mut Sqlite3Value,
+) {
+    if let Some(api) = A::get() {
+        if let Ok(info) = api_info().to_str() {
+            api.result_text(ctx, info);
+        }
+    }
+}
+
+extern "C" fn lindera_config_path_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    _argc: c_int,
+    _argv: *mut *mut Sqlite3Value,
+) {
+    if let Some(api) = A::get() {
+        let config_path = api.user_data(ctx) as *const String;
+        match unsafe { config_path.as_ref() } {
+            Some(config_path) => api.result_text(ctx, config_path),
+            None => api.result_null(ctx),
+        }
+    }
+}
+
+/// The version of the embedded Lindera.
+const LINDERA_VERSION: &str = env!("LINDERA_VERSION");
+
+/// The dictionaries compiled into the library.
+const EMBEDDED_DICTIONARIES: &[(&str, bool)] = &[
+    ("ipadic", cfg!(feature = "ipadic")),
+    ("ipadic-neologd", cfg!(feature = "ipadic-neologd")),
+    ("unidic", cfg!(feature = "unidic")),
+    ("ko-dic", cfg!(feature = "ko-dic")),
+    ("cc-cedict", cfg!(feature = "cc-cedict")),
+];
+
+/// `lindera_version()` returns the version of lindera-sqlite.
+extern "C" fn lindera_version_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    _argc: c_int,
+    _argv: *mut *mut Sqlite3Value,
+) {
+    if let Some(api) = A::get() {
+        api.result_text(ctx, env!("CARGO_PKG_VERSION"));
+    }
+}
+
+/// Describes what the connection's `lindera_tokenizer` runs with, as JSON:
+/// the versions, the dictionaries compiled in, the dictionary of the
+/// configuration and where that configuration comes from.
+fn lindera_info(context: &TokenizerContext) -> String {
+    let dictionary = context_args(Some(context), TokenizerArgs::default())
+        .ok()
+        .and_then(|args| args.dictionary_kind())
+        .map(EmbeddedDictionary::name);
+    let embedded_dictionaries = EMBEDDED_DICTIONARIES
+        .iter()
+        .filter(|(_, embedded)| *embedded)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "lindera_version": LINDERA_VERSION,
+        "embedded_dictionaries": embedded_dictionaries,
+        "dictionary": dictionary,
+        "config": context.config_source(),
+    })
+    .to_string()
+}
+
+extern "C" fn lindera_info_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    _argc: c_int,
+    _argv: *mut *mut Sqlite3Value,
+) {
+    if let Some(api) = A::get() {
+        let context = unsafe { &*(api.user_data(ctx) as *const TokenizerContext) };
+        match std::panic::catch_unwind(|| lindera_info(context)) {
+            Ok(info) => api.result_text(ctx, &info),
+            Err(_) => api.result_null(ctx),
+        }
+    }
+}
+
+/// Parses `major.minor.patch`, with missing components as 0 and any
+/// pre-release or build suffix ignored.
+fn parse_version(version: &str) -> Option<[u64; 3]> {
+    let core = version.trim().split(['-', '+']).next()?;
+    let mut parts = [0; 3];
+    for (i, part) in core.split('.').enumerate() {
+        *parts.get_mut(i)? = part.parse().ok()?;
+    }
+    Some(parts)
+}
+
+/// Whether the embedded Lindera is at least `min_version`, or `None` if
+/// `min_version` is not a version.
+fn version_compatible(min_version: &str) -> Option<bool> {
+    let current = parse_version(LINDERA_VERSION)?;
+    Some(current >= parse_version(min_version)?)
+}
+
+extern "C" fn lindera_version_compatible_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) {
+    if let Some(api) = A::get() {
+        if argc != 1 || argv.is_null() {
+            api.result_null(ctx);
+            return;
+        }
+        let min_version = api.value_text(unsafe { *argv });
+        match version_compatible(&min_version) {
+            Some(compatible) => api.result_int64(ctx, compatible as i64),
+            None => api.result_null(ctx),
+        }
+    }
+}
+
+/// `lindera_detect_language(text)` returns `ja`, `ko` or `zh` for text in
+/// the scripts of these languages, `other` for any other text, and `NULL`
+/// for `NULL`.
+extern "C" fn lindera_detect_language_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) {
+    if let Some(api) = A::get() {
+        if argc != 1 || argv.is_null() || api.value_type(unsafe { *argv }) == SQLITE_NULL {
+            api.result_null(ctx);
+            return;
+        }
+        let text = api.value_text(unsafe { *argv });
+        let language = detect_language(&text).map_or("other", Language::code);
+        api.result_text(ctx, language);
+    }
+}
+
+/// `lindera_wakati(text)` returns `text` with a space between its words, as
+/// segmented by the dictionary of `lindera_tokenizer`, or `NULL` for `NULL`
+/// or if tokenizing fails.
+extern "C" fn lindera_wakati_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) {
+    transform_text::<A>(ctx, argc, argv, "lindera_wakati", |pipelines, text| {
+        wakati(&pipelines.index, text)
+    });
+}
+
+/// `lindera_match_query(text)` returns what a user typed as an FTS5 `MATCH`
+/// query for tables of `lindera_tokenizer`, with each term of `text` as a
+/// phrase rows must contain, or `NULL` for `NULL`, for text with no words,
+/// or if tokenizing fails.
+extern "C" fn lindera_match_query_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) {
+    let Some(api) = A::get() else {
+        return;
+    };
+    if argc != 1 || argv.is_null() || api.value_type(unsafe { *argv }) == SQLITE_NULL {
+        return api.result_null(ctx);
+    }
+    let text = api.value_text(unsafe { *argv });
+    let context = unsafe { &*(api.user_data(ctx) as *const TokenizerContext) };
+
+    match with_pipelines(context, "lindera_match_query", |pipelines| {
+        match_query(pipelines.query.as_ref().unwrap_or(&pipelines.index), &text)
+    }) {
+        Ok(Some(query)) => api.result_text(ctx, &query),
+        Ok(None) | Err(_) => api.result_null(ctx),
+    }
+}
+
+/// `lindera_normalize(text)` returns `text` as the character filters of
+/// `lindera_tokenizer` normalize documents before tokenizing them, or `NULL`
+/// for `NULL` or if a filter fails.
+extern "C" fn lindera_normalize_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) {
+    transform_text::<A>(ctx, argc, argv, "lindera_normalize", |pipelines, text| {
+        normalize(&pipelines.index, text)
+    });
+}
+
+/// `lindera_reading(text)` returns the readings of the words of `text`, as
+/// the dictionary of `lindera_tokenizer` gives them, concatenated, or `NULL`
+/// for `NULL` or if tokenizing fails.
+extern "C" fn lindera_reading_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) {
+    transform_text::<A>(ctx, argc, argv, "lindera_reading", |pipelines, text| {
+        reading(
+            &pipelines.index,
+            DetailFields::of(pipelines.settings.dictionary_kind).reading,
+            text,
+        )
+    });
+}
+
+/// `lindera_romanize(text)` returns the readings of the words of `text` in
+/// Hepburn romaji, separated by spaces, or `NULL` for `NULL` or if
+/// tokenizing fails.
+extern "C" fn lindera_romanize_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) {
+    transform_text::<A>(ctx, argc, argv, "lindera_romanize", |pipelines, text| {
+        let readings = readings(
+            &pipelines.index,
+            DetailFields::of(pipelines.settings.dictionary_kind).reading,
+            text,
+        )?;
+        Ok(readings
+            .iter()
+            .map(|reading| reading.trim())
+            .filter(|reading| !reading.is_empty())
+            .map(romanize)
+            .collect::<Vec<_>>()
+            .join(" "))
+    });
+}
+
+/// `lindera_furigana(text, format)` returns `text` with the readings of its
+/// words over their kanji, as HTML ruby markup or with `format` `bracket`,
+/// in brackets after each run of kanji. `NULL` for `NULL`, for an unknown
+/// format, or if tokenizing fails.
+extern "C" fn lindera_furigana_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) {
+    let Some(api) = A::get() else {
+        return;
+    };
+    let values = match argc {
+        1 | 2 if !argv.is_null() => unsafe { core::slice::from_raw_parts(argv, argc as usize) },
+        _ => return api.result_null(ctx),
+    };
+    if values
+        .iter()
+        .any(|value| api.value_type(*value) == SQLITE_NULL)
+    {
+        return api.result_null(ctx);
+    }
+    let format = match values.get(1).map(|value| api.value_text(*value)) {
+        None => FuriganaFormat::Html,
+        Some(name) => match FuriganaFormat::from_name(&name) {
+            Some(format) => format,
+            None => {
+                report_error(
+                    SQLITE_ERROR,
+                    format_args!(
+                        "unknown lindera_furigana format {:?}, expected html or bracket",
+                        name
+                    ),
+                );
+                return api.result_null(ctx);
+            }
+        },
+    };
+    let text = api.value_text(values[0]);
+    let context = unsafe { &*(api.user_data(ctx) as *const TokenizerContext) };
+
+    let result = with_pipelines(context, "lindera_furigana", |pipelines| {
+        let field = DetailFields::of(pipelines.settings.dictionary_kind).reading;
+        let mut furigana = String::with_capacity(text.len() * 2);
+        let mut end = 0;
+        for ((surface, byte_start, byte_end), reading) in
+            word_readings(&pipelines.index, field, &text)?
+        {
+            // Anything the dictionary skips is kept as it is.
+            if let Some(gap) = text.get(end..byte_start) {
+                push_word(&mut furigana, gap, None, format);
+            }
+            push_word(&mut furigana, &surface, reading.as_deref(), format);
+            end = byte_end;
+        }
+        push_word(&mut furigana, &text[end..], None, format);
+        Ok(furigana)
+    });
+    match result {
+        Ok(furigana) => api.result_text(ctx, &furigana),
+        Err(_) => api.result_null(ctx),
+    }
+}
+
+/// `lindera_similar(a, b, method)` returns how similar `a` and `b` are, from
+/// 0 to 1, by the tokens `lindera_tokenizer` indexes for each: the Jaccard
+/// index of their sets of tokens, or with `method` `cosine`, the cosine
+/// similarity of their token counts. `NULL` for `NULL`, for an unknown
+/// method, or if tokenizing fails.
+extern "C" fn lindera_similar_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) {
+    let Some(api) = A::get() else {
+        return;
+    };
+    let values = match argc {
+        2 | 3 if !argv.is_null() => unsafe { core::slice::from_raw_parts(argv, argc as usize) },
+        _ => return api.result_null(ctx),
+    };
+    if values
+        .iter()
+        .any(|value| api.value_type(*value) == SQLITE_NULL)
+    {
+        return api.result_null(ctx);
+    }
+    let method = match values.get(2).map(|value| api.value_text(*value)).as_deref() {
+        None | Some("jaccard") => Similarity::Jaccard,
+        Some("cosine") => Similarity::Cosine,
+        Some(method) => {
+            report_error(
+                SQLITE_ERROR,
+                format_args!(
+                    "unknown lindera_similar method {:?}, expected jaccard or cosine",
+                    method
+                ),
+            );
+            return api.result_null(ctx);
+        }
+    };
+    let (a, b) = (api.value_text(values[0]), api.value_text(values[1]));
+    let context = unsafe { &*(api.user_data(ctx) as *const TokenizerContext) };
+
+    let result = with_pipelines(context, "lindera_similar", |pipelines| {
+        let terms = |text: &str| -> Result<Vec<String>, LinderaSqliteError> {
+            Ok(
+                analyze_tokens(&pipelines.index, text, FTS5_TOKENIZE_DOCUMENT)?
+                    .into_iter()
+                    .map(|token| token.text.into_owned())
+                    .collect(),
+            )
+        };
+        Ok(method.of(&terms(&a)?, &terms(&b)?))
+    });
+    match result {
+        Ok(similarity) => api.result_double(ctx, similarity),
+        Err(_) => api.result_null(ctx),
+    }
+}
+
+/// How `lindera_similar` compares the tokens of two texts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Similarity {
+    /// The number of distinct tokens both have over the number either has.
+    Jaccard,
+    /// The cosine of the angle between the vectors of their token counts.
+    Cosine,
+}
+
+impl Similarity {
+    /// The similarity of the tokens `a` and `b`, or 0 if either has none.
+    fn of(self, a: &[String], b: &[String]) -> f64 {
+        fn counts(tokens: &[String]) -> HashMap<&str, f64> {
+            let mut counts = HashMap::<&str, f64>::new();
+            for token in tokens {
+                *counts.entry(token.as_str()).or_default() += 1.0;
+            }
+            counts
+        }
+        let (a, b) = (counts(a), counts(b));
+        if a.is_empty() || b.is_empty() {
+            return 0.0;
+        }
+
+        match self {
+            Similarity::Jaccard => {
+                let shared = a.keys().filter(|token| b.contains_key(*token)).count();
+                shared as f64 / (a.len() + b.len() - shared) as f64
+            }
+            Similarity::Cosine => {
+                let dot = a
+                    .iter()
+                    .filter_map(|(token, count)| Some(count * b.get(token)?))
+                    .sum::<f64>();
+                let norm = |counts: &HashMap<&str, f64>| {
+                    counts
+                        .values()
+                        .map(|count| count * count)
+                        .sum::<f64>()
+                        .sqrt()
+                };
+                dot / (norm(&a) * norm(&b))
+            }
+        }
+    }
+}
+
+/// Returns what `transform` makes of the text passed to a function
+/// registered with a `TokenizerContext`, from the tokenizers of
+/// `lindera_tokenizer`.
+fn transform_text<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+    name: &str,
+    transform: impl Fn(&Pipelines, &str) -> Result<String, LinderaSqliteError>,
+) {
+    let Some(api) = A::get() else {
+        return;
+    };
+    if argc != 1 || argv.is_null() || api.value_type(unsafe { *argv }) == SQLITE_NULL {
+        api.result_null(ctx);
+        return;
+    }
+    let text = api.value_text(unsafe { *argv });
+    let context = unsafe { &*(api.user_data(ctx) as *const TokenizerContext) };
+
+    match with_pipelines(context, name, |pipelines| transform(pipelines, &text)) {
+        Ok(text) => api.result_text(ctx, &text),
+        Err(_) => api.result_null(ctx),
+    }
+}
+
+/// Runs `f` with the tokenizers of `lindera_tokenizer`, for the function
+/// `name`, reporting errors and panics.
+fn with_pipelines<T>(
+    context: &TokenizerContext,
+    name: &str,
+    f: impl FnOnce(&Pipelines) -> Result<T, LinderaSqliteError>,
+) -> Result<T, LinderaSqliteError> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let pipelines = default_pipelines(context).map_err(report_tokenizer_error)?;
+        f(&pipelines)
+    }))
+    .unwrap_or_else(|_| {
+        report_error(
+            SQLITE_INTERNAL,
+            format_args!("{} panicked while tokenizing", name),
+        );
+        Err(LinderaSqliteError::Sqlite(SQLITE_INTERNAL))
+    })
+}
+
+extern "C" fn drop_config_path(config_path: *mut c_void) {
+    if !config_path.is_null() {
+        drop(unsafe { Box::from_raw(config_path as *mut String) });
+    }
+}
+
+#[cfg(feature = "memory-stats")]
+extern "C" fn lindera_peak_memory_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    _argc: c_int,
+    _argv: *mut *mut Sqlite3Value,
+) {
+    if let Some(api) = A::get() {
+        let peak = crate::memory::lindera_peak_memory_bytes();
+        api.result_int64(ctx, i64::try_from(peak).unwrap_or(i64::MAX));
+    }
+}
+
+/// Describes the tokenizer cache and the live tokenizers, as JSON.
+fn tokenizer_stats() -> Result<String, c_int> {
+    let stats = TokenizerCache::global().stats()?;
+    let config_paths = stats
+        .config_paths
+        .iter()
+        .map(|path| path.as_ref().map(|path| path.to_string_lossy()))
+        .collect::<Vec<_>>();
+    let stats = serde_json::json!({
+        "cached_configs": stats.cached_configs,
+        "total_instances": TOKENIZER_INSTANCES.load(Ordering::Relaxed),
+        "config_paths": config_paths,
+    });
+
+    Ok(stats.to_string())
+}
+
+extern "C" fn lindera_tokenizer_stats_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    _argc: c_int,
+    _argv: *mut *mut Sqlite3Value,
+) {
+    if let Some(api) = A::get() {
+        if let Ok(stats) = tokenizer_stats() {
+            api.result_text(ctx, &stats);
+        }
+    }
+}
+
+/// Makes every tokenizer in the process rebuild itself from its
+/// configuration on its next use, and returns how many there are.
+extern "C" fn lindera_reload_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    _argc: c_int,
+    _argv: *mut *mut Sqlite3Value,
+) {
+    if let Some(api) = A::get() {
+        reload_all();
+        let instances = TOKENIZER_INSTANCES.load(Ordering::Relaxed);
+        api.result_int64(ctx, i64::try_from(instances).unwrap_or(i64::MAX));
+    }
+}
+
+/// Creates the tokenizer for one FTS5 table.
+///
+/// # Safety
+///
+/// `fts5_tokenizer` must be a valid pointer to write the new tokenizer to;
+/// `SQLITE_MISUSE` is returned if it is null. `p_context` must be null or the
+/// context registered by `lindera_fts5_tokenizer_init`. `az_arg` must point
+/// to `n_arg` tokenizer arguments; invalid arguments return `SQLITE_ERROR`.
+// FTS5 calls this through a safe `extern "C" fn` pointer.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn fts5_create_lindera_tokenizer(
+    p_context: *mut c_void,
+    az_arg: *const *const c_uchar,
+    n_arg: c_int,
+    fts5_tokenizer: *mut *mut Fts5Tokenizer,
+) -> c_int {
+    if fts5_tokenizer.is_null() {
+        return SQLITE_MISUSE;
+    }
+
+    let context = unsafe { (p_context as *const TokenizerContext).as_ref() };
+    let tokenizer = match unsafe { TokenizerArgs::parse(az_arg, n_arg) }
+        .map_err(LinderaSqliteError::from)
+        .and_then(|(args, options)| create_tokenizer(context, args, options))
+    {
+        Ok(tokenizer) => tokenizer,
+        Err(e) => {
+            // FTS5 replaces whatever a tokenizer reports with "error in
+            // tokenizer constructor", so the details go to the log and to
+            // `lindera_last_error()`.
+            report_error(e.code(), format_args!("Failed to create tokenizer: {}", e));
+            return e.code();
+        }
+    };
+    unsafe {
+        *fts5_tokenizer = Box::into_raw(tokenizer);
+    }
+    TOKENIZER_INSTANCES.fetch_add(1, Ordering::Relaxed);
+
+    SQLITE_OK
+}
+
+fn create_tokenizer(
+    context: Option<&TokenizerContext>,
+    args: TokenizerArgs,
+    options: EmissionOptions,
+) -> Result<Box<Fts5Tokenizer>, LinderaSqliteError> {
+    let config_name = args.config_name.clone();
+    let mut args = context_args(context, args)?;
+    if let Some(table) = args.user_dictionary_table() {
+        args.database_user_dictionary =
+            context.and_then(|context| context.table_user_dictionary(&table));
+    }
+    let config = stats_config(&args, config_name);
+    let hybrid = args.mode == Some(SegmenterMode::Hybrid);
+    let source = TokenizerSource::new(&args, &options, reload_enabled());
+    let pipelines = TokenizerCache::global().tokenizer(&args)?;
+    // Settings the options leave out come from the configuration the
+    // tokenizers were built from, read once with them.
+    let settings = pipelines.settings;
+    let fields = DetailFields::of(settings.dictionary_kind);
+    let locales = LocaleTokenizers::new(&args, settings.language());
+    Ok(Box::new(Fts5Tokenizer {
+        tokenizer: pipelines.index,
+        query_tokenizer: pipelines.query,
+        decompose_tokenizer: pipelines.decompose,
+        synonyms: pipelines.synonyms,
+        stopwords: pipelines.stopwords,
+        chinese_variants: pipelines.chinese_variants,
+        ascii_fast_path: options.ascii_fast_path,
+        keep_original: options.keep_original,
+        hybrid,
+        base_form: options.base_form.unwrap_or(settings.base_form),
+        base_form_field: fields.base_form,
+        josa: options.josa.unwrap_or(settings.josa),
+        kana: options.kana.unwrap_or(settings.kana),
+        long_vowel: options.long_vowel,
+        small_kana: options.small_kana,
+        unicode61: options.unicode61(),
+        jamo: options.jamo,
+        pinyin: options.pinyin,
+        pinyin_field: fields.pinyin,
+        source: Some(source),
+        locales: Some(locales),
+        stats: Some(TokenizerStats::register(config)),
+    }))
+}
+
+/// How `lindera_stats` names the configuration of a table: the path of its
+/// file, the row of `lindera_config` it uses, or its dictionary.
+fn stats_config(args: &TokenizerArgs, config_name: Option<String>) -> Option<String> {
+    if let Some(config_path) = &args.config_path {
+        return Some(config_path.to_string_lossy().into_owned());
+    }
+    if args.database_config.is_some() {
+        return Some(config_name.unwrap_or_else(|| DEFAULT_DATABASE_CONFIG.to_string()));
+    }
+    args.dictionary
+        .or(args.embedded_dictionary)
+        .map(|dictionary| dictionary.name().to_string())
+}
+
+/// The tokenizers of tables that give no arguments, for the SQL functions
+/// that tokenize like them.
+///
+/// They are resolved once per connection and again only when the cache
+/// changes, since resolving them reads the `lindera_config` table.
+pub(crate) fn default_pipelines(
+    context: &TokenizerContext,
+) -> Result<Arc<Pipelines>, LinderaSqliteError> {
+    let mut resolved = context
+        .default_pipelines
+        .lock()
+        .map_err(|_| LinderaSqliteError::Sqlite(SQLITE_INTERNAL))?;
+    let version = TokenizerCache::global().version();
+    if let Some(resolved) = resolved.as_ref().filter(|r| r.version == version) {
+        return Ok(resolved.pipelines.clone());
+    }
+
+    let args = context_args(Some(context), TokenizerArgs::default())?;
+    let pipelines = Arc::new(TokenizerCache::global().tokenizer(&args)?);
+    *resolved = Some(ResolvedPipelines {
+        version,
+        pipelines: pipelines.clone(),
+    });
+    Ok(pipelines)
+}
+
+/// Completes the arguments of a table with the configuration `context`
+/// gives tables that do not name their own.
+fn context_args(
+    context: Option<&TokenizerContext>,
+    mut args: TokenizerArgs,
+) -> Result<TokenizerArgs, LinderaSqliteError> {
+    // The name only selects a row, so tables using the same configuration
+    // share one cache entry whatever it is called.
+    let config_name = std::mem::take(&mut args.config_name);
+    // A table picks a profile by the name of its tokenizer, so the `default`
+    // row only takes its place when the table asks for it.
+    let profile = context.and_then(|context| context.profile.clone());
+    if args.config_path.is_none() && (config_name.is_some() || profile.is_none()) {
+        let name = config_name.as_deref().unwrap_or(DEFAULT_DATABASE_CONFIG);
+        args.database_config = context.and_then(|context| context.database_config(name));
+        if args.database_config.is_none() && config_name.is_some() {
+            return Err(TokenizerArgumentError::InvalidValue {
+                key: "config_name".to_string(),
+                value: name.to_string(),
+                reason: "no such configuration in lindera_config".to_string(),
+            }
+            .into());
+        }
+    }
+    if args.config_path.is_none() && args.database_config.is_none() {
+        args.config_path = context.and_then(|context| context.config_path.clone());
+        args.embedded_dictionary = context.and_then(|context| context.embedded_dictionary);
+        args.profile = profile;
+    }
+    args.database_user_dictionary = context.and_then(TokenizerContext::database_user_dictionary);
+    Ok(args)
+}
+
+extern "C" fn lindera_last_error_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    _argc: c_int,
+    _argv: *mut *mut Sqlite3Value,
+) {
+    if let Some(api) = A::get() {
+        match last_error() {
+            Some(message) => api.result_text(ctx, &message),
+            None => api.result_null(ctx),
+        }
+    }
+}
+
+// FTS5 calls this through a safe `extern "C" fn` pointer.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn fts5_delete_lindera_tokenizer(fts5_tokenizer: *mut Fts5Tokenizer) {
+    if fts5_tokenizer.is_null() {
+        return;
+    }
     let tokenizer = unsafe { Box::from_raw(fts5_tokenizer) };
     drop(tokenizer);
+    TOKENIZER_INSTANCES.fetch_sub(1, Ordering::Relaxed);
 }
 
 #[no_mangle]
-pub extern "C" fn fts5_destroy_icu_module(_module: *mut c_void) {
-    // no-op
+pub extern "C" fn fts5_destroy_icu_module(module: *mut c_void) {
+    if !module.is_null() {
+        drop(unsafe { Box::from_raw(module as *mut TokenizerContext) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+
+    extern "C" fn mock_create_tokenizer(
+        _fts5_api: *const FTS5API,
+        _z_name: *const c_uchar,
+        p_context: *mut c_void,
+        _fts5_tokenizer: *mut Fts5TokenizerApi,
+        x_destroy: extern "C" fn(module: *mut c_void),
+    ) -> c_int {
+        // Release the context straight away, as closing the connection would.
+        x_destroy(p_context);
+        SQLITE_OK
+    }
+
+    thread_local! {
+        /// The tokenizers registered through `xCreateTokenizer_v2` on this
+        /// thread.
+        static V2_TOKENIZERS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    extern "C" fn mock_create_tokenizer_v2(
+        _fts5_api: *const FTS5API,
+        _z_name: *const c_uchar,
+        p_context: *mut c_void,
+        fts5_tokenizer: *mut Fts5TokenizerV2Api,
+        x_destroy: extern "C" fn(module: *mut c_void),
+    ) -> c_int {
+        assert_eq!(unsafe { (*fts5_tokenizer).i_version }, 2);
+        V2_TOKENIZERS.with(|count| count.set(count.get() + 1));
+        x_destroy(p_context);
+        SQLITE_OK
+    }
+
+    extern "C" fn mock_create_function(
+        _fts5_api: *const FTS5API,
+        _z_name: *const c_char,
+        _p_user_data: *mut c_void,
+        _x_function: Fts5ExtensionFunction,
+        _x_destroy: Option<extern "C" fn(p_user_data: *mut c_void)>,
+    ) -> c_int {
+        SQLITE_OK
+    }
+
+    /// Answers `SELECT fts5(?1)` with an in-memory `fts5_api`, whose version 3
+    /// members are only read if `i_version` says they are there.
+    struct MockSqliteApi {
+        fts5_api: Fts5ApiV3,
+        bound: Cell<*mut *mut FTS5API>,
+    }
+
+    impl MockSqliteApi {
+        fn new(i_version: c_int) -> Self {
+            MockSqliteApi {
+                fts5_api: Fts5ApiV3 {
+                    base: FTS5API {
+                        i_version,
+                        x_create_tokenizer: mock_create_tokenizer,
+                        _x_find_tokenizer: None,
+                        x_create_function: mock_create_function,
+                    },
+                    x_create_tokenizer_v2: mock_create_tokenizer_v2,
+                    _x_find_tokenizer_v2: None,
+                },
+                bound: Cell::new(null_mut()),
+            }
+        }
+    }
+
+    impl SqliteApi for MockSqliteApi {
+        fn get() -> Option<&'static Self> {
+            None
+        }
+
+        fn libversion_number(&self) -> c_int {
+            3045000
+        }
+
+        fn prepare(&self, _db: *mut Sqlite3, _query: &CStr, _stmt: *mut *mut Sqlite3Stmt) -> c_int {
+            SQLITE_OK
+        }
+
+        fn bind_pointer(
+            &self,
+            _stmt: *mut Sqlite3Stmt,
+            _index: c_int,
+            ptr: *mut c_void,
+            _name: &CStr,
+        ) -> c_int {
+            self.bound.set(ptr.cast());
+            SQLITE_OK
+        }
+
+        fn bind_blob(&self, _stmt: *mut Sqlite3Stmt, _index: c_int, _blob: &[u8]) -> c_int {
+            SQLITE_OK
+        }
+
+        fn bind_int64(&self, _stmt: *mut Sqlite3Stmt, _index: c_int, _value: i64) -> c_int {
+            SQLITE_OK
+        }
+
+        fn bind_text(&self, _stmt: *mut Sqlite3Stmt, _index: c_int, _text: &str) -> c_int {
+            SQLITE_OK
+        }
+
+        fn column_text(&self, _stmt: *mut Sqlite3Stmt, _column: c_int) -> String {
+            String::new()
+        }
+
+        fn step(&self, _stmt: *mut Sqlite3Stmt) -> c_int {
+            unsafe {
+                *self.bound.get() = &self.fts5_api.base as *const FTS5API as *mut FTS5API;
+            }
+            SQLITE_OK
+        }
+
+        fn finalize(&self, _stmt: *mut Sqlite3Stmt) -> c_int {
+            SQLITE_OK
+        }
+
+        fn create_function(
+            &self,
+            _db: *mut Sqlite3,
+            _name: &CStr,
+            _n_arg: c_int,
+            _flags: c_int,
+            user_data: *mut c_void,
+            _x_func: ScalarFunction,
+            x_destroy: Option<extern "C" fn(user_data: *mut c_void)>,
+        ) -> c_int {
+            if let Some(x_destroy) = x_destroy {
+                x_destroy(user_data);
+            }
+            SQLITE_OK
+        }
+
+        fn user_data(&self, _ctx: *mut Sqlite3Context) -> *mut c_void {
+            null_mut()
+        }
+
+        fn result_text(&self, _ctx: *mut Sqlite3Context, _text: &str) {}
+
+        fn result_int64(&self, _ctx: *mut Sqlite3Context, _value: i64) {}
+
+        fn result_double(&self, _ctx: *mut Sqlite3Context, _value: f64) {}
+
+        fn result_null(&self, _ctx: *mut Sqlite3Context) {}
+
+        fn value_type(&self, _value: *mut Sqlite3Value) -> c_int {
+            crate::vtab::SQLITE_NULL
+        }
+
+        fn value_int64(&self, _value: *mut Sqlite3Value) -> i64 {
+            0
+        }
+
+        fn value_double(&self, _value: *mut Sqlite3Value) -> f64 {
+            0.0
+        }
+
+        fn value_text(&self, _value: *mut Sqlite3Value) -> String {
+            String::new()
+        }
+
+        fn value_pointer(&self, _value: *mut Sqlite3Value, _name: &CStr) -> *mut c_void {
+            null_mut()
+        }
+
+        fn create_module(
+            &self,
+            _db: *mut Sqlite3,
+            _name: &CStr,
+            _module: *const Sqlite3Module,
+            client_data: *mut c_void,
+            x_destroy: extern "C" fn(client_data: *mut c_void),
+        ) -> c_int {
+            // Release the module straight away, as closing the connection would.
+            x_destroy(client_data);
+            SQLITE_OK
+        }
+
+        fn declare_vtab(&self, _db: *mut Sqlite3, _sql: &CStr) -> c_int {
+            SQLITE_OK
+        }
+    }
+
+    #[test]
+    fn it_accepts_newer_fts5_api() {
+        let api = MockSqliteApi::new(3);
+        assert_eq!(
+            lindera_fts_tokenizer_internal_init(null_mut(), &api),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn it_registers_the_v2_tokenizer_when_fts5_has_it() {
+        V2_TOKENIZERS.with(|count| count.set(0));
+        let api = MockSqliteApi::new(2);
+        assert_eq!(
+            lindera_fts_tokenizer_internal_init(null_mut(), &api),
+            Ok(())
+        );
+        assert_eq!(V2_TOKENIZERS.with(Cell::get), 0);
+
+        let api = MockSqliteApi::new(3);
+        assert_eq!(
+            lindera_fts_tokenizer_internal_init(null_mut(), &api),
+            Ok(())
+        );
+        assert!(V2_TOKENIZERS.with(Cell::get) > 0);
+    }
+
+    #[test]
+    fn it_rejects_null_tokenizer_output() {
+        assert_eq!(
+            fts5_create_lindera_tokenizer(null_mut(), core::ptr::null(), 0, null_mut()),
+            SQLITE_MISUSE
+        );
+    }
+
+    #[test]
+    fn it_ignores_null_tokenizers_on_delete() {
+        fts5_delete_lindera_tokenizer(null_mut());
+    }
+
+    #[test]
+    fn it_rejects_invalid_tokenizer_arguments() {
+        let args = [c"config=badpath".as_ptr() as *const c_uchar];
+        let mut tokenizer = null_mut();
+        assert_eq!(
+            fts5_create_lindera_tokenizer(null_mut(), args.as_ptr(), 1, &mut tokenizer),
+            SQLITE_ERROR
+        );
+        assert!(tokenizer.is_null());
+    }
+
+    #[test]
+    fn it_reports_api_info() {
+        let info = unsafe { CStr::from_ptr(lindera_sqlite_api_info()) };
+        let info: serde_json::Value = serde_json::from_slice(info.to_bytes()).unwrap();
+
+        assert_eq!(info["fts5_api_version"], FTS5_API_VERSION);
+        assert_eq!(info["min_sqlite_version"], "3.20.0");
+        assert!(info["features"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("extension")));
+    }
+
+    #[test]
+    fn it_compares_versions() {
+        assert_eq!(parse_version("0.38.1"), Some([0, 38, 1]));
+        assert_eq!(parse_version("1.2"), Some([1, 2, 0]));
+        assert_eq!(parse_version("0.39.0-beta.1"), Some([0, 39, 0]));
+        assert_eq!(parse_version("1.2.3.4"), None);
+        assert_eq!(parse_version("latest"), None);
+
+        assert_eq!(version_compatible(LINDERA_VERSION), Some(true));
+        assert_eq!(version_compatible("0.1.0"), Some(true));
+        assert_eq!(version_compatible("99.0.0"), Some(false));
+        assert_eq!(version_compatible(""), None);
+    }
+
+    #[test]
+    fn it_measures_token_similarity() {
+        let tokens = |text: &str| text.split(' ').map(str::to_string).collect::<Vec<_>>();
+        let (a, b) = (tokens("辞書 の 辞書"), tokens("辞書 形態素"));
+
+        assert_eq!(Similarity::Jaccard.of(&a, &b), 1.0 / 3.0);
+        assert_eq!(Similarity::Jaccard.of(&a, &a), 1.0);
+        assert!((Similarity::Cosine.of(&a, &b) - 2.0 / (5f64.sqrt() * 2f64.sqrt())).abs() < 1e-9);
+        assert_eq!(Similarity::Cosine.of(&a, &tokens("解析")), 0.0);
+        assert_eq!(Similarity::Jaccard.of(&a, &[]), 0.0);
+    }
+
+    #[test]
+    fn it_rejects_older_fts5_api() {
+        let api = MockSqliteApi::new(1);
+        assert_eq!(
+            lindera_fts_tokenizer_internal_init(null_mut(), &api),
+            Err(InitError::new(
+                SQLITE_MISUSE,
+                "lindera_tokenizer requires version 2 of the FTS5 API, but this is version 1"
+            ))
+        );
+    }
 }
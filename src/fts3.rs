@@ -0,0 +1,267 @@
+use core::ffi::CStr;
+use core::ptr::null_mut;
+
+use libc::{c_char, c_int, c_uchar, c_void};
+
+use crate::common::*;
+use crate::error::InitError;
+use crate::extension::{
+    fts5_create_lindera_tokenizer, fts5_delete_lindera_tokenizer, Sqlite3, Sqlite3Stmt, SqliteApi,
+};
+use crate::lindera_fts5_tokenize_internal;
+
+/// The name FTS3 and FTS4 tables give in `tokenize=` to use Lindera.
+const FTS3_TOKENIZER_NAME: &str = "lindera_tokenizer";
+
+// fts3_tokenizer.h
+#[repr(C)]
+struct Sqlite3TokenizerModule {
+    i_version: c_int, // 0, as xLanguageid is not implemented
+    x_create: extern "C" fn(
+        argc: c_int,
+        argv: *const *const c_char,
+        pp_tokenizer: *mut *mut Fts3Tokenizer,
+    ) -> c_int,
+    x_destroy: extern "C" fn(p_tokenizer: *mut Fts3Tokenizer) -> c_int,
+    x_open: extern "C" fn(
+        p_tokenizer: *mut Fts3Tokenizer,
+        p_input: *const c_char,
+        n_bytes: c_int,
+        pp_cursor: *mut *mut Fts3Cursor,
+    ) -> c_int,
+    x_close: extern "C" fn(p_cursor: *mut Fts3Cursor) -> c_int,
+    x_next: extern "C" fn(
+        p_cursor: *mut Fts3Cursor,
+        pp_token: *mut *const c_char,
+        pn_bytes: *mut c_int,
+        pi_start_offset: *mut c_int,
+        pi_end_offset: *mut c_int,
+        pi_position: *mut c_int,
+    ) -> c_int,
+}
+
+/// `sqlite3_tokenizer`, followed by the Lindera tokenizer it stands for.
+#[repr(C)]
+struct Fts3Tokenizer {
+    p_module: *const Sqlite3TokenizerModule,
+    tokenizer: *mut Fts5Tokenizer,
+}
+
+/// `sqlite3_tokenizer_cursor`, followed by the tokens of the text it reads,
+/// as FTS3 pulls tokens one at a time where Lindera produces them all at
+/// once.
+#[repr(C)]
+struct Fts3Cursor {
+    p_tokenizer: *mut Fts3Tokenizer,
+    tokens: Vec<Fts3Token>,
+    next: usize,
+}
+
+struct Fts3Token {
+    text: Vec<u8>,
+    start: c_int,
+    end: c_int,
+    position: c_int,
+}
+
+/// FTS3 keeps the pointer to the module for as long as the connection, so it
+/// lives in a static.
+static LINDERA_FTS3_MODULE: Sqlite3TokenizerModule = Sqlite3TokenizerModule {
+    i_version: 0,
+    x_create: fts3_create_lindera_tokenizer,
+    x_destroy: fts3_destroy_lindera_tokenizer,
+    x_open: fts3_open_lindera_cursor,
+    x_close: fts3_close_lindera_cursor,
+    x_next: fts3_next_lindera_token,
+};
+
+/// Registers `lindera_tokenizer` for FTS3 and FTS4 tables on `db`, with
+/// `fts3_tokenizer()`.
+///
+/// These tables use the configuration of `LINDERA_CONFIG_PATH`, with the
+/// same arguments as FTS5 tables. FTS3 tokenizes documents and queries
+/// alike and has no colocated tokens, so `keep_original`, `jamo`, `pinyin`,
+/// `mode hybrid`, `base_form colocate`, `josa colocate`, `query` pipelines
+/// and synonyms do not apply to them.
+pub(crate) fn register_lindera_fts3_tokenizer<A: SqliteApi>(
+    db: *mut Sqlite3,
+    api: &A,
+) -> Result<(), InitError> {
+    let mut stmt = null_mut::<Sqlite3Stmt>();
+    let rc = api.prepare(db, c"SELECT fts3_tokenizer(?1, ?2)", &mut stmt);
+    if rc != SQLITE_OK {
+        return Err(InitError::new(
+            rc,
+            "the FTS3 lindera_tokenizer requires FTS3, which this SQLite is built without",
+        ));
+    }
+
+    // SQLite only accepts a module pointer from a bound parameter, unless
+    // SQLITE_DBCONFIG_ENABLE_FTS3_TOKENIZER is set.
+    let module = &LINDERA_FTS3_MODULE as *const Sqlite3TokenizerModule;
+    let mut rc = api.bind_text(stmt, 1, FTS3_TOKENIZER_NAME);
+    if rc == SQLITE_OK {
+        rc = api.bind_blob(stmt, 2, &(module as usize).to_ne_bytes());
+    }
+    if rc != SQLITE_OK {
+        api.finalize(stmt);
+        return Err(rc.into());
+    }
+
+    // Errors of the step are returned again by finalize.
+    api.step(stmt);
+    let rc = api.finalize(stmt);
+    if rc != SQLITE_OK {
+        return Err(InitError::new(
+            rc,
+            "failed to register lindera_tokenizer with FTS3",
+        ));
+    }
+
+    Ok(())
+}
+
+extern "C" fn fts3_create_lindera_tokenizer(
+    argc: c_int,
+    argv: *const *const c_char,
+    pp_tokenizer: *mut *mut Fts3Tokenizer,
+) -> c_int {
+    let mut tokenizer = null_mut::<Fts5Tokenizer>();
+    let rc = fts5_create_lindera_tokenizer(null_mut(), argv.cast(), argc, &mut tokenizer);
+    if rc != SQLITE_OK {
+        return rc;
+    }
+
+    // Colocated tokens would read as a phrase in FTS3 queries.
+    let lindera = unsafe { &mut *tokenizer };
+    lindera.keep_original = false;
+    lindera.jamo = false;
+    lindera.pinyin = false;
+    lindera.hybrid = false;
+    if lindera.base_form == BaseForm::Colocate {
+        lindera.base_form = BaseForm::Off;
+    }
+    if lindera.josa == Josa::Colocate {
+        lindera.josa = Josa::Keep;
+    }
+    lindera.query_tokenizer = None;
+    lindera.synonyms = None;
+
+    unsafe {
+        *pp_tokenizer = Box::into_raw(Box::new(Fts3Tokenizer {
+            p_module: &LINDERA_FTS3_MODULE,
+            tokenizer,
+        }));
+    }
+    SQLITE_OK
+}
+
+extern "C" fn fts3_destroy_lindera_tokenizer(p_tokenizer: *mut Fts3Tokenizer) -> c_int {
+    let tokenizer = unsafe { Box::from_raw(p_tokenizer) };
+    fts5_delete_lindera_tokenizer(tokenizer.tokenizer);
+    SQLITE_OK
+}
+
+extern "C" fn fts3_open_lindera_cursor(
+    p_tokenizer: *mut Fts3Tokenizer,
+    p_input: *const c_char,
+    n_bytes: c_int,
+    pp_cursor: *mut *mut Fts3Cursor,
+) -> c_int {
+    // A negative length means the input is nul-terminated.
+    let n_bytes = if n_bytes < 0 && !p_input.is_null() {
+        match cast_usize_to_c_int(unsafe { CStr::from_ptr(p_input) }.to_bytes().len()) {
+            Ok(n_bytes) => n_bytes,
+            Err(rc) => return rc,
+        }
+    } else {
+        n_bytes
+    };
+
+    let mut tokens = Vec::<Fts3Token>::new();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+        lindera_fts5_tokenize_internal(
+            (*p_tokenizer).tokenizer,
+            (&mut tokens as *mut Vec<Fts3Token>).cast(),
+            0,
+            p_input,
+            n_bytes,
+            collect_fts3_token,
+        )
+    }))
+    .unwrap_or_else(|_| {
+        report_error(
+            SQLITE_INTERNAL,
+            format_args!("lindera_tokenizer panicked while tokenizing"),
+        );
+        Err(SQLITE_INTERNAL)
+    });
+    // Text that is not valid UTF-8 has no tokens, which is not an error.
+    match result {
+        Ok(()) | Err(SQLITE_OK) => {}
+        Err(rc) => return rc,
+    }
+
+    unsafe {
+        *pp_cursor = Box::into_raw(Box::new(Fts3Cursor {
+            p_tokenizer,
+            tokens,
+            next: 0,
+        }));
+    }
+    SQLITE_OK
+}
+
+extern "C" fn collect_fts3_token(
+    p_ctx: *mut c_void,
+    t_flags: c_int,
+    p_token: *const c_char,
+    n_token: c_int,
+    i_start: c_int,
+    i_end: c_int,
+) -> c_int {
+    let tokens = unsafe { &mut *(p_ctx as *mut Vec<Fts3Token>) };
+    let position = match tokens.last() {
+        Some(last) if t_flags & FTS5_TOKEN_COLOCATED != 0 => last.position,
+        Some(last) => last.position + 1,
+        None => 0,
+    };
+    let text = unsafe { core::slice::from_raw_parts(p_token as *const c_uchar, n_token as usize) };
+    tokens.push(Fts3Token {
+        text: text.to_vec(),
+        start: i_start,
+        end: i_end,
+        position,
+    });
+    SQLITE_OK
+}
+
+extern "C" fn fts3_close_lindera_cursor(p_cursor: *mut Fts3Cursor) -> c_int {
+    drop(unsafe { Box::from_raw(p_cursor) });
+    SQLITE_OK
+}
+
+extern "C" fn fts3_next_lindera_token(
+    p_cursor: *mut Fts3Cursor,
+    pp_token: *mut *const c_char,
+    pn_bytes: *mut c_int,
+    pi_start_offset: *mut c_int,
+    pi_end_offset: *mut c_int,
+    pi_position: *mut c_int,
+) -> c_int {
+    let cursor = unsafe { &mut *p_cursor };
+    let Some(token) = cursor.tokens.get(cursor.next) else {
+        return SQLITE_DONE;
+    };
+    cursor.next += 1;
+
+    // The token stays valid until the next call, as FTS3 requires.
+    unsafe {
+        *pp_token = token.text.as_ptr().cast();
+        *pn_bytes = token.text.len() as c_int;
+        *pi_start_offset = token.start;
+        *pi_end_offset = token.end;
+        *pi_position = token.position;
+    }
+    SQLITE_OK
+}
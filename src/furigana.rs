@@ -0,0 +1,204 @@
+//! Readings over the kanji of words, as `lindera_furigana` writes them.
+
+use std::fmt::Write;
+
+/// How `lindera_furigana` writes readings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FuriganaFormat {
+    /// `<ruby>漢字<rt>かんじ</rt></ruby>`, with the rest of the text escaped
+    /// for HTML.
+    Html,
+    /// `漢字[かんじ]`.
+    Bracket,
+}
+
+impl FuriganaFormat {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "html" => Some(FuriganaFormat::Html),
+            "bracket" => Some(FuriganaFormat::Bracket),
+            _ => None,
+        }
+    }
+
+    fn push_text(self, out: &mut String, text: &str) {
+        match self {
+            FuriganaFormat::Html => push_escaped(out, text),
+            FuriganaFormat::Bracket => out.push_str(text),
+        }
+    }
+
+    fn push_ruby(self, out: &mut String, base: &str, reading: &str) {
+        match self {
+            FuriganaFormat::Html => {
+                out.push_str("<ruby>");
+                push_escaped(out, base);
+                out.push_str("<rt>");
+                push_escaped(out, reading);
+                out.push_str("</rt></ruby>");
+            }
+            FuriganaFormat::Bracket => {
+                let _ = write!(out, "{}[{}]", base, reading);
+            }
+        }
+    }
+}
+
+fn push_escaped(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Whether `c` is a kanji, or a mark that stands for one, such as `々`.
+fn is_kanji(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3400}'..='\u{4dbf}'
+            | '\u{4e00}'..='\u{9fff}'
+            | '\u{f900}'..='\u{faff}'
+            | '\u{20000}'..='\u{3134f}'
+            | '々'
+            | '〆'
+            | 'ヵ'
+            | 'ヶ'
+    )
+}
+
+/// `text` with its katakana in hiragana.
+pub(crate) fn to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            c => c,
+        })
+        .collect()
+}
+
+/// Appends `surface` to `out` in `format`, with `reading` in hiragana over
+/// each run of its kanji. The kana between the runs are matched against the
+/// reading to tell what each run reads; if they do not match, the reading
+/// goes over the whole word.
+pub(crate) fn push_word(
+    out: &mut String,
+    surface: &str,
+    reading: Option<&str>,
+    format: FuriganaFormat,
+) {
+    let Some(reading) = reading.filter(|_| surface.chars().any(is_kanji)) else {
+        format.push_text(out, surface);
+        return;
+    };
+    let reading = to_hiragana(reading);
+
+    let runs = kanji_runs(surface);
+    match align(&runs, &reading) {
+        Some(readings) => {
+            let mut readings = readings.into_iter();
+            for (kanji, run) in runs {
+                match readings.next().filter(|_| kanji) {
+                    Some(reading) => format.push_ruby(out, run, reading),
+                    None => format.push_text(out, run),
+                }
+            }
+        }
+        None => format.push_ruby(out, surface, &reading),
+    }
+}
+
+/// Splits `surface` into runs of kanji and of other characters, each marked
+/// with whether it is of kanji.
+fn kanji_runs(surface: &str) -> Vec<(bool, &str)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut chars = surface.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let kanji = is_kanji(c);
+        let end = match chars.peek() {
+            Some((_, next)) if is_kanji(*next) == kanji => continue,
+            Some((next, _)) => *next,
+            None => surface.len(),
+        };
+        runs.push((kanji, &surface[start..end]));
+        start = end;
+    }
+    runs
+}
+
+/// The readings of the runs of `runs`, in order, such that the kana runs
+/// read as themselves, or `None` if there are none. Each kanji run reads as
+/// little as lets the rest match.
+fn align<'a>(runs: &[(bool, &str)], reading: &'a str) -> Option<Vec<&'a str>> {
+    let Some(((kanji, run), rest)) = runs.split_first() else {
+        return reading.is_empty().then(Vec::new);
+    };
+    if !kanji {
+        let kana = to_hiragana(run);
+        let remainder = reading.strip_prefix(kana.as_str())?;
+        let mut readings = align(rest, remainder)?;
+        readings.insert(0, &reading[..kana.len()]);
+        return Some(readings);
+    }
+
+    for (end, c) in reading.char_indices() {
+        let end = end + c.len_utf8();
+        if let Some(mut readings) = align(rest, &reading[end..]) {
+            readings.insert(0, &reading[..end]);
+            return Some(readings);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn furigana(surface: &str, reading: Option<&str>, format: FuriganaFormat) -> String {
+        let mut out = String::new();
+        push_word(&mut out, surface, reading, format);
+        out
+    }
+
+    #[test]
+    fn it_puts_readings_over_kanji_runs() {
+        let bracket = |surface, reading| furigana(surface, reading, FuriganaFormat::Bracket);
+        assert_eq!(bracket("辞書", Some("ジショ")), "辞書[じしょ]");
+        assert_eq!(bracket("食べ物", Some("タベモノ")), "食[た]べ物[もの]");
+        assert_eq!(bracket("お茶", Some("オチャ")), "お茶[ちゃ]");
+        assert_eq!(bracket("人々", Some("ヒトビト")), "人々[ひとびと]");
+        assert_eq!(bracket("です", Some("デス")), "です");
+        assert_eq!(bracket("SQLite", None), "SQLite");
+    }
+
+    #[test]
+    fn it_puts_unaligned_readings_over_whole_words() {
+        assert_eq!(
+            furigana("明後日", Some("アサッテ"), FuriganaFormat::Bracket),
+            "明後日[あさって]"
+        );
+        assert_eq!(
+            furigana("取り扱い", Some("トリアツカイ"), FuriganaFormat::Bracket),
+            "取[と]り扱[あつか]い"
+        );
+        assert_eq!(
+            furigana("付き合う", Some("ツキアエ"), FuriganaFormat::Bracket),
+            "付き合う[つきあえ]"
+        );
+    }
+
+    #[test]
+    fn it_writes_html_ruby() {
+        assert_eq!(
+            furigana("食べ物", Some("タベモノ"), FuriganaFormat::Html),
+            "<ruby>食<rt>た</rt></ruby>べ<ruby>物<rt>もの</rt></ruby>"
+        );
+        assert_eq!(furigana("<b>", None, FuriganaFormat::Html), "&lt;b&gt;");
+    }
+}
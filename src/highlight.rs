@@ -0,0 +1,267 @@
+//! `lindera_highlight()`, an FTS5 auxiliary function like `highlight()` that
+//! never splits a character of the original text.
+//!
+//! Character filters such as `unicode_normalize` change the length of the
+//! text, and the byte ranges of tokens mapped back from the filtered text
+//! may then fall inside a multi-byte character, where `highlight()` inserts
+//! its markers as they are.
+
+use libc::{c_char, c_int, c_void};
+
+use crate::common::*;
+use crate::extension::{Fts5Context, Fts5ExtensionApi, Sqlite3Context, Sqlite3Value, SqliteApi};
+
+/// `lindera_highlight(table, col, open, close)` returns column `col` of the
+/// current row with each phrase of the query that matches in it between
+/// `open` and `close`, or `NULL` if the column is `NULL` or cannot be read.
+pub(crate) extern "C" fn lindera_highlight_aux<A: SqliteApi>(
+    api: *const Fts5ExtensionApi,
+    fts: *mut Fts5Context,
+    ctx: *mut Sqlite3Context,
+    n_val: c_int,
+    ap_val: *mut *mut Sqlite3Value,
+) {
+    let Some(sqlite_api) = A::get() else {
+        return;
+    };
+    if n_val != 3 {
+        report_error(
+            SQLITE_ERROR,
+            format_args!("wrong number of arguments to function lindera_highlight()"),
+        );
+        return sqlite_api.result_null(ctx);
+    }
+    let values = unsafe { core::slice::from_raw_parts(ap_val, 3) };
+    let Ok(col) = c_int::try_from(sqlite_api.value_int64(values[0])) else {
+        return sqlite_api.result_null(ctx);
+    };
+    let open = sqlite_api.value_text(values[1]);
+    let close = sqlite_api.value_text(values[2]);
+    let api = unsafe { &*api };
+
+    let result = column_text(api, fts, col).and_then(|text| {
+        let Some(text) = text else {
+            return Ok(None);
+        };
+        let matches = phrase_matches(api, fts, col)?;
+        let tokens = match matches.is_empty() {
+            true => Vec::new(),
+            false => token_ranges(api, fts, text)?,
+        };
+        let spans = phrase_spans(&matches, &tokens);
+        Ok(Some(insert_markers(
+            text,
+            &spans,
+            open.as_bytes(),
+            close.as_bytes(),
+        )))
+    });
+    match result {
+        Ok(Some(highlighted)) => {
+            sqlite_api.result_text(ctx, &String::from_utf8_lossy(&highlighted));
+        }
+        Ok(None) => sqlite_api.result_null(ctx),
+        Err(rc) => {
+            report_error(
+                rc,
+                format_args!("lindera_highlight() failed to read column {}", col),
+            );
+            sqlite_api.result_null(ctx);
+        }
+    }
+}
+
+/// The text of column `col` of the current row, valid until the auxiliary
+/// function returns, or `None` if it is `NULL`.
+pub(crate) fn column_text<'a>(
+    api: &Fts5ExtensionApi,
+    fts: *mut Fts5Context,
+    col: c_int,
+) -> Result<Option<&'a [u8]>, c_int> {
+    let mut text = core::ptr::null::<c_char>();
+    let mut n_text = 0;
+    let rc = (api.x_column_text)(fts, col, &mut text, &mut n_text);
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+    if text.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(unsafe {
+        core::slice::from_raw_parts(text as *const u8, n_text.max(0) as usize)
+    }))
+}
+
+/// The first and last positions of each match of a phrase of the query in
+/// column `col` of the current row, or in any column if `col` is negative,
+/// with the column.
+pub(crate) fn phrase_matches(
+    api: &Fts5ExtensionApi,
+    fts: *mut Fts5Context,
+    col: c_int,
+) -> Result<Vec<PhraseMatch>, c_int> {
+    let mut n_inst = 0;
+    let rc = (api.x_inst_count)(fts, &mut n_inst);
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+    let mut matches = Vec::new();
+    for i in 0..n_inst {
+        let (mut phrase, mut inst_col, mut offset) = (0, 0, 0);
+        let rc = (api.x_inst)(fts, i, &mut phrase, &mut inst_col, &mut offset);
+        if rc != SQLITE_OK {
+            return Err(rc);
+        }
+        let size = (api.x_phrase_size)(fts, phrase);
+        if (col < 0 || inst_col == col) && offset >= 0 && size > 0 {
+            matches.push(PhraseMatch {
+                col: inst_col,
+                first: offset as usize,
+                last: (offset + size - 1) as usize,
+            });
+        }
+    }
+    Ok(matches)
+}
+
+/// A match of a phrase of the query, by the positions of its first and
+/// last tokens in column `col`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PhraseMatch {
+    pub(crate) col: c_int,
+    pub(crate) first: usize,
+    pub(crate) last: usize,
+}
+
+/// The byte ranges of the tokens of `text`, tokenized as the table does for
+/// auxiliary functions, indexed by their positions.
+pub(crate) fn token_ranges(
+    api: &Fts5ExtensionApi,
+    fts: *mut Fts5Context,
+    text: &[u8],
+) -> Result<Vec<(usize, usize)>, c_int> {
+    let mut tokens = Vec::<(usize, usize)>::new();
+    let rc = (api.x_tokenize)(
+        fts,
+        text.as_ptr().cast(),
+        cast_usize_to_c_int(text.len())?,
+        (&mut tokens as *mut Vec<(usize, usize)>).cast(),
+        collect_token_range,
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+    Ok(tokens)
+}
+
+/// The byte ranges of `matches`, given the ranges of the `tokens` of their
+/// column, sorted by their start. A phrase is highlighted as one, with the
+/// text between its tokens.
+pub(crate) fn phrase_spans(
+    matches: &[PhraseMatch],
+    tokens: &[(usize, usize)],
+) -> Vec<(usize, usize)> {
+    let mut spans = matches
+        .iter()
+        .filter_map(|m| Some((tokens.get(m.first)?.0, tokens.get(m.last)?.1)))
+        .collect::<Vec<_>>();
+    spans.sort_unstable();
+    spans
+}
+
+/// Collects the byte range of each token at its position, as FTS5 counts
+/// them, into a `Vec<(usize, usize)>`.
+extern "C" fn collect_token_range(
+    p_ctx: *mut c_void,
+    t_flags: c_int,
+    _p_token: *const c_char,
+    _n_token: c_int,
+    i_start: c_int,
+    i_end: c_int,
+) -> c_int {
+    let tokens = unsafe { &mut *(p_ctx as *mut Vec<(usize, usize)>) };
+    // Colocated tokens cover the same text as the token before them.
+    if t_flags & FTS5_TOKEN_COLOCATED == 0 {
+        tokens.push((i_start.max(0) as usize, i_end.max(0) as usize));
+    }
+    SQLITE_OK
+}
+
+/// Whether `text` has a character starting at byte `i`, or ends there.
+fn is_char_boundary(text: &[u8], i: usize) -> bool {
+    i >= text.len() || text[i] & 0xc0 != 0x80
+}
+
+/// `text` with each of the byte ranges `spans`, sorted by their start,
+/// between `open` and `close`. The ranges are widened to whole characters
+/// of UTF-8 text, so that no marker lands inside one; ranges that then
+/// overlap are merged.
+pub(crate) fn insert_markers(
+    text: &[u8],
+    spans: &[(usize, usize)],
+    open: &[u8],
+    close: &[u8],
+) -> Vec<u8> {
+    let mut widened = Vec::<(usize, usize)>::with_capacity(spans.len());
+    for &(start, end) in spans {
+        let mut start = start.min(text.len());
+        let mut end = end.clamp(start, text.len());
+        while !is_char_boundary(text, start) {
+            start -= 1;
+        }
+        while !is_char_boundary(text, end) {
+            end += 1;
+        }
+        match widened.last_mut() {
+            Some(last) if start < last.1 => last.1 = last.1.max(end),
+            _ => widened.push((start, end)),
+        }
+    }
+
+    let mut highlighted =
+        Vec::with_capacity(text.len() + widened.len() * (open.len() + close.len()));
+    let mut copied = 0;
+    for (start, end) in widened {
+        highlighted.extend_from_slice(&text[copied..start]);
+        highlighted.extend_from_slice(open);
+        highlighted.extend_from_slice(&text[start..end]);
+        highlighted.extend_from_slice(close);
+        copied = end;
+    }
+    highlighted.extend_from_slice(&text[copied..]);
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlight(text: &str, spans: &[(usize, usize)]) -> String {
+        String::from_utf8(insert_markers(text.as_bytes(), spans, b"[", b"]")).unwrap()
+    }
+
+    #[test]
+    fn it_inserts_markers_around_spans() {
+        assert_eq!(
+            highlight("形態素の辞書", &[(0, 9), (12, 18)]),
+            "[形態素]の[辞書]"
+        );
+        assert_eq!(highlight("辞書", &[]), "辞書");
+        assert_eq!(highlight("abc", &[(0, 3)]), "[abc]");
+    }
+
+    #[test]
+    fn it_widens_spans_to_whole_characters() {
+        // Byte 1 and byte 8 fall inside ＳＱＬ, three bytes each.
+        assert_eq!(highlight("ＳＱＬの辞書", &[(1, 8)]), "[ＳＱＬ]の辞書");
+        assert_eq!(highlight("ＳＱＬの辞書", &[(10, 40)]), "ＳＱＬ[の辞書]");
+    }
+
+    #[test]
+    fn it_merges_spans_that_overlap_once_widened() {
+        assert_eq!(
+            highlight("ＳＱＬの辞書", &[(0, 4), (5, 9)]),
+            "[ＳＱＬ]の辞書"
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! Hangul syllables decomposed into the jamo they are typed with, which the
+//! `jamo` option indexes next to Korean tokens.
+//!
+//! Korean input methods compose syllables as the keys are pressed, so a word
+//! being typed often ends in a different syllable than the word it becomes:
+//! `서울` goes through `성` and `서우`. Written as jamo, `ㅅㅓㅇ` and `ㅅㅓㅇㅜ`
+//! are both prefixes of `ㅅㅓㅇㅜㄹ`, and a prefix query finds the word.
+//! Compound vowels and double final consonants are written as the two keys
+//! they take, so that `고` is a prefix of `과` too.
+
+/// The first Hangul syllable, `가`, and the number of syllables.
+const SYLLABLES_START: u32 = 0xac00;
+const SYLLABLES_COUNT: u32 = 11172;
+
+/// The initial consonants, vowels and final consonants syllables are made
+/// of, in the order of their Unicode code points.
+const INITIALS: [&str; 19] = [
+    "ㄱ", "ㄲ", "ㄴ", "ㄷ", "ㄸ", "ㄹ", "ㅁ", "ㅂ", "ㅃ", "ㅅ", "ㅆ", "ㅇ", "ㅈ", "ㅉ", "ㅊ", "ㅋ",
+    "ㅌ", "ㅍ", "ㅎ",
+];
+const VOWELS: [&str; 21] = [
+    "ㅏ", "ㅐ", "ㅑ", "ㅒ", "ㅓ", "ㅔ", "ㅕ", "ㅖ", "ㅗ", "ㅗㅏ", "ㅗㅐ", "ㅗㅣ", "ㅛ", "ㅜ",
+    "ㅜㅓ", "ㅜㅔ", "ㅜㅣ", "ㅠ", "ㅡ", "ㅡㅣ", "ㅣ",
+];
+const FINALS: [&str; 28] = [
+    "", "ㄱ", "ㄲ", "ㄱㅅ", "ㄴ", "ㄴㅈ", "ㄴㅎ", "ㄷ", "ㄹ", "ㄹㄱ", "ㄹㅁ", "ㄹㅂ", "ㄹㅅ",
+    "ㄹㅌ", "ㄹㅍ", "ㄹㅎ", "ㅁ", "ㅂ", "ㅂㅅ", "ㅅ", "ㅆ", "ㅇ", "ㅈ", "ㅊ", "ㅋ", "ㅌ", "ㅍ",
+    "ㅎ",
+];
+
+/// `text` with its Hangul syllables written as jamo, or `None` if it has no
+/// Hangul syllables. Anything else is kept as it is.
+pub(crate) fn decompose(text: &str) -> Option<String> {
+    let mut jamo = String::with_capacity(text.len() * 2);
+    let mut hangul = false;
+    for c in text.chars() {
+        match (c as u32)
+            .checked_sub(SYLLABLES_START)
+            .filter(|index| *index < SYLLABLES_COUNT)
+        {
+            Some(index) => {
+                hangul = true;
+                jamo.push_str(INITIALS[(index / 588) as usize]);
+                jamo.push_str(VOWELS[(index % 588 / 28) as usize]);
+                jamo.push_str(FINALS[(index % 28) as usize]);
+            }
+            None => jamo.push(c),
+        }
+    }
+    hangul.then_some(jamo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decomposes_syllables_as_typed() {
+        assert_eq!(decompose("서울").as_deref(), Some("ㅅㅓㅇㅜㄹ"));
+        assert_eq!(decompose("과일").as_deref(), Some("ㄱㅗㅏㅇㅣㄹ"));
+        assert_eq!(decompose("닭").as_deref(), Some("ㄷㅏㄹㄱ"));
+        assert_eq!(decompose("Lindera를").as_deref(), Some("Linderaㄹㅡㄹ"));
+        assert_eq!(decompose("Lindera"), None);
+    }
+
+    #[test]
+    fn it_makes_words_being_typed_prefixes() {
+        let word = decompose("서울").unwrap();
+        assert!(word.starts_with(&decompose("성").unwrap()));
+        assert!(word.starts_with(&decompose("서우").unwrap()));
+        assert!(decompose("과")
+            .unwrap()
+            .starts_with(&decompose("고").unwrap()));
+    }
+}
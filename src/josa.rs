@@ -0,0 +1,157 @@
+//! Korean particles (josa) attached to the end of tokens, as in `서울에서`,
+//! which the `josa` option strips so that the token matches `서울`.
+//!
+//! ko-dic gives words it analyzes as a whole, such as inflected forms and
+//! preanalyzed expressions, with the parts they are made of. Words it does
+//! not know are matched against common particles instead, and only when the
+//! particle fits the last syllable before it, as `은` only follows a final
+//! consonant and `는` only a vowel.
+
+use lindera::token::Token;
+
+/// The fields of ko-dic's details: the part of speech, the type of entry,
+/// the part of speech of its last part, and its parts.
+const POS_FIELD: usize = 0;
+const TYPE_FIELD: usize = 4;
+const LAST_POS_FIELD: usize = 6;
+const EXPRESSION_FIELD: usize = 7;
+
+/// Particles that follow any syllable, longest first.
+const PARTICLES: &[&str] = &[
+    "에서는",
+    "에서도",
+    "에게서",
+    "으로는",
+    "으로도",
+    "까지",
+    "부터",
+    "에서",
+    "에게",
+    "한테",
+    "처럼",
+    "보다",
+    "마다",
+    "조차",
+    "으로",
+];
+
+/// Particles that only follow a syllable with a final consonant, and those
+/// that only follow one without, which `PARTICLES` leave aside as they are
+/// easily part of a word.
+const AFTER_CONSONANT: &[&str] = &["은", "을", "이", "과"];
+const AFTER_VOWEL: &[&str] = &["는", "를", "가", "와", "로"];
+
+/// The text of `token` without the particles at its end, if it has any and
+/// something is left without them.
+pub(crate) fn josa_stem(token: &mut Token) -> Option<String> {
+    let text = token.text.to_string();
+    let pos = token.get_detail(POS_FIELD).map(str::to_string);
+    match pos.as_deref() {
+        // A particle on its own is left to the stop tags.
+        Some(pos) if pos.starts_with('J') => None,
+        Some("UNK") | None => unknown_stem(&text),
+        Some(_) => {
+            let entry_type = token.get_detail(TYPE_FIELD).map(str::to_string);
+            let last_pos = token.get_detail(LAST_POS_FIELD).map(str::to_string);
+            let expression = token.get_detail(EXPRESSION_FIELD).map(str::to_string);
+            match (entry_type.as_deref(), last_pos, expression) {
+                (Some("Inflect" | "Preanalysis"), Some(last_pos), Some(expression))
+                    if last_pos.starts_with('J') =>
+                {
+                    expression_stem(&text, &expression)
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+/// `text` without the particles the last parts of its ko-dic `expression`,
+/// such as `서울/NNP/*+에서/JKB/*`, are.
+fn expression_stem(text: &str, expression: &str) -> Option<String> {
+    let particles = expression
+        .split('+')
+        .rev()
+        .map_while(|part| {
+            let mut fields = part.split('/');
+            let surface = fields.next()?;
+            fields
+                .next()
+                .filter(|pos| pos.starts_with('J'))
+                .map(|_| surface)
+        })
+        .collect::<Vec<_>>();
+    let suffix = particles.into_iter().rev().collect::<String>();
+    strip(text, &suffix)
+}
+
+/// `text` without a particle at its end, for words the dictionary does not
+/// know.
+fn unknown_stem(text: &str) -> Option<String> {
+    if let Some(stem) = PARTICLES.iter().find_map(|particle| strip(text, particle)) {
+        return Some(stem);
+    }
+    // A single syllable is only taken for a particle after a word of two.
+    let last = text.chars().last()?;
+    let stem = &text[..text.len() - last.len_utf8()];
+    let final_consonant = has_final_consonant(stem.chars().last()?)?;
+    let particles = match final_consonant {
+        true => AFTER_CONSONANT,
+        false => AFTER_VOWEL,
+    };
+    (stem.chars().count() >= 2 && particles.contains(&&text[stem.len()..]))
+        .then(|| stem.to_string())
+}
+
+/// `text` without `suffix`, if it ends with it and has more than it.
+fn strip(text: &str, suffix: &str) -> Option<String> {
+    text.strip_suffix(suffix)
+        .filter(|stem| !suffix.is_empty() && !stem.is_empty())
+        .map(str::to_string)
+}
+
+/// Whether the Hangul syllable `c` ends in a consonant, or `None` if it is
+/// not a Hangul syllable.
+fn has_final_consonant(c: char) -> Option<bool> {
+    let index = (c as u32).checked_sub(0xac00).filter(|i| *i < 11172)?;
+    Some(index % 28 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_strips_the_particles_of_expressions() {
+        assert_eq!(
+            expression_stem("서울에서", "서울/NNP/*+에서/JKB/*").as_deref(),
+            Some("서울")
+        );
+        assert_eq!(
+            expression_stem("서울에서는", "서울/NNP/*+에서/JKB/*+는/JX/*").as_deref(),
+            Some("서울")
+        );
+        assert_eq!(expression_stem("갔다", "가/VV/*+았/EP/*+다/EF/*"), None);
+    }
+
+    #[test]
+    fn it_strips_particles_of_unknown_words() {
+        assert_eq!(unknown_stem("서울에서").as_deref(), Some("서울"));
+        assert_eq!(unknown_stem("린데라는").as_deref(), Some("린데라"));
+        assert_eq!(unknown_stem("사전을").as_deref(), Some("사전"));
+        assert_eq!(unknown_stem("형태소를").as_deref(), Some("형태소"));
+        assert_eq!(unknown_stem("검색은").as_deref(), Some("검색"));
+        // 을 only follows a final consonant.
+        assert_eq!(unknown_stem("형태소을"), None);
+    }
+
+    #[test]
+    fn it_keeps_words_that_only_look_like_particles() {
+        // 가 only follows a vowel, and a particle needs a word of two
+        // syllables before it.
+        assert_eq!(unknown_stem("소설가"), None);
+        assert_eq!(unknown_stem("국가"), None);
+        assert_eq!(unknown_stem("에서"), None);
+        assert_eq!(unknown_stem("Lindera"), None);
+    }
+}
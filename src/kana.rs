@@ -0,0 +1,185 @@
+//! Hiragana and katakana folded into one script, which the `kana` option
+//! applies to tokens so that `りんご` and `リンゴ` match each other, and the
+//! spelling variants of kana the `long_vowel` and `small_kana` flags
+//! normalize, so that `サーバー` matches `サーバ` and `ウィンドウ` matches
+//! `ウインドウ`.
+//!
+//! Tokens are folded after segmentation, since the dictionary tells words
+//! apart by their script: folding the text before would read particles
+//! written in katakana, or loanwords in hiragana.
+
+use crate::common::Kana;
+
+/// The distance from a hiragana to the katakana for the same sound.
+const KATAKANA_OFFSET: u32 = 0x60;
+
+/// The prolonged sound mark, and the characters written in its place.
+const PROLONGED_SOUND_MARK: char = 'ー';
+/// NFKC turns `～` into `~`.
+const PROLONGED_SOUND_MARK_VARIANTS: [char; 6] = ['ｰ', '〜', '～', '~', '－', '‐'];
+
+/// The fewest characters a word keeps once its last prolonged sound mark is
+/// dropped, so that `カー` is not taken for `カ`, as in Lindera's
+/// `japanese_katakana_stem`.
+const MIN_STEM: usize = 3;
+
+/// `text` with its kana in the script `kana` asks for, or `None` if it has
+/// none in the other script.
+pub(crate) fn fold(text: &str, kana: Kana) -> Option<String> {
+    let convert = |c: char| -> Option<char> {
+        let code = c as u32;
+        match kana {
+            Kana::Keep => None,
+            // ぁ to ゖ, and the iteration marks ゝ and ゞ.
+            Kana::Katakana => matches!(code, 0x3041..=0x3096 | 0x309d..=0x309e)
+                .then(|| char::from_u32(code + KATAKANA_OFFSET))
+                .flatten(),
+            // ァ to ヶ, and the iteration marks ヽ and ヾ. ヷ to ヺ have no
+            // hiragana.
+            Kana::Hiragana => matches!(code, 0x30a1..=0x30f6 | 0x30fd..=0x30fe)
+                .then(|| char::from_u32(code - KATAKANA_OFFSET))
+                .flatten(),
+        }
+    };
+    if !text.chars().any(|c| convert(c).is_some()) {
+        return None;
+    }
+    Some(text.chars().map(|c| convert(c).unwrap_or(c)).collect())
+}
+
+/// `text` with the spelling variants of kana `long_vowel` and `small_kana`
+/// ask for normalized, or `None` if it has none:
+///
+/// - `long_vowel` writes the characters used for the prolonged sound mark
+///   after kana as `ー`, and drops it at the end of a katakana word;
+/// - `small_kana` writes small kana, as in `ウィ` or `ッ`, full size.
+pub(crate) fn normalize_variants(text: &str, long_vowel: bool, small_kana: bool) -> Option<String> {
+    let mut normalized = String::with_capacity(text.len());
+    let mut after_kana = false;
+    for c in text.chars() {
+        let c = match c {
+            c if long_vowel && after_kana && PROLONGED_SOUND_MARK_VARIANTS.contains(&c) => {
+                PROLONGED_SOUND_MARK
+            }
+            c if small_kana => full_size(c).unwrap_or(c),
+            c => c,
+        };
+        after_kana = is_kana(c) || c == PROLONGED_SOUND_MARK;
+        normalized.push(c);
+    }
+    if long_vowel
+        && normalized
+            .chars()
+            .all(|c| is_katakana(c) || c == PROLONGED_SOUND_MARK)
+    {
+        if let Some(stem) = normalized.strip_suffix(PROLONGED_SOUND_MARK) {
+            if stem.chars().count() >= MIN_STEM {
+                normalized.truncate(stem.len());
+            }
+        }
+    }
+    (normalized != text).then_some(normalized)
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{3096}') || is_katakana(c)
+}
+
+fn is_katakana(c: char) -> bool {
+    matches!(c, '\u{30a1}'..='\u{30fa}')
+}
+
+/// The full-size kana for the small kana `c`, or `None` for anything else.
+fn full_size(c: char) -> Option<char> {
+    let full = match c {
+        'ぁ' => 'あ',
+        'ぃ' => 'い',
+        'ぅ' => 'う',
+        'ぇ' => 'え',
+        'ぉ' => 'お',
+        'っ' => 'つ',
+        'ゃ' => 'や',
+        'ゅ' => 'ゆ',
+        'ょ' => 'よ',
+        'ゎ' => 'わ',
+        'ゕ' => 'か',
+        'ゖ' => 'け',
+        'ァ' => 'ア',
+        'ィ' => 'イ',
+        'ゥ' => 'ウ',
+        'ェ' => 'エ',
+        'ォ' => 'オ',
+        'ッ' => 'ツ',
+        'ャ' => 'ヤ',
+        'ュ' => 'ユ',
+        'ョ' => 'ヨ',
+        'ヮ' => 'ワ',
+        'ヵ' => 'カ',
+        'ヶ' => 'ケ',
+        _ => return None,
+    };
+    Some(full)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_folds_kana_into_one_script() {
+        assert_eq!(fold("りんご", Kana::Katakana).as_deref(), Some("リンゴ"));
+        assert_eq!(fold("リンゴ", Kana::Hiragana).as_deref(), Some("りんご"));
+        assert_eq!(
+            fold("ヴァイオリン", Kana::Hiragana).as_deref(),
+            Some("ゔぁいおりん")
+        );
+        // The long vowel mark belongs to both scripts.
+        assert_eq!(
+            fold("らーめん", Kana::Katakana).as_deref(),
+            Some("ラーメン")
+        );
+    }
+
+    #[test]
+    fn it_leaves_other_text_as_it_is() {
+        assert_eq!(fold("リンゴ", Kana::Katakana), None);
+        assert_eq!(fold("林檎", Kana::Hiragana), None);
+        assert_eq!(fold("りんご", Kana::Keep), None);
+        assert_eq!(
+            fold("青いりんご", Kana::Katakana).as_deref(),
+            Some("青イリンゴ")
+        );
+    }
+
+    #[test]
+    fn it_normalizes_prolonged_sound_marks() {
+        assert_eq!(
+            normalize_variants("サーバー", true, false).as_deref(),
+            Some("サーバ")
+        );
+        assert_eq!(
+            normalize_variants("サｰバ～", true, false).as_deref(),
+            Some("サーバ")
+        );
+        assert_eq!(normalize_variants("サーバ", true, false), None);
+        // Short words and words in other scripts keep their last mark.
+        assert_eq!(normalize_variants("カー", true, false), None);
+        assert_eq!(normalize_variants("らーめん", true, false), None);
+        // A wave dash after anything but kana is no prolonged sound mark.
+        assert_eq!(normalize_variants("10〜20", true, false), None);
+    }
+
+    #[test]
+    fn it_writes_small_kana_full_size() {
+        assert_eq!(
+            normalize_variants("ウィンドウ", false, true).as_deref(),
+            Some("ウインドウ")
+        );
+        assert_eq!(
+            normalize_variants("きっぷ", false, true).as_deref(),
+            Some("きつぷ")
+        );
+        assert_eq!(normalize_variants("ウインドウ", false, true), None);
+        assert_eq!(normalize_variants("サーバー", false, false), None);
+    }
+}
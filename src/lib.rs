@@ -1,83 +1,1183 @@
 extern crate alloc;
 
+#[cfg(feature = "extension")]
+mod args;
+#[cfg(feature = "extension")]
+mod cache;
+mod chinese_variants;
 mod common;
+mod config;
+#[cfg(feature = "rusqlite")]
+pub mod connection_extension;
+mod error;
 #[cfg(feature = "extension")]
 mod extension;
+#[cfg(feature = "extension")]
+mod fts3;
+#[cfg(feature = "extension")]
+mod furigana;
+#[cfg(feature = "extension")]
+mod highlight;
+mod jamo;
+mod josa;
+mod kana;
+#[cfg(feature = "extension")]
+mod locale;
+mod logging;
+#[cfg(feature = "memory-stats")]
+mod memory;
+mod offset_map;
+mod pinyin;
+#[cfg(feature = "extension")]
+mod reload;
+#[cfg(feature = "extension")]
+mod romaji;
+#[cfg(feature = "extension")]
+mod sentence;
+#[cfg(feature = "extension")]
+mod snippet;
+#[cfg(feature = "extension")]
+mod stats;
+mod stopwords;
+mod synonyms;
+#[cfg(feature = "extension")]
+mod table_functions;
+mod unicode61;
+#[cfg(feature = "extension")]
+mod userdict;
+#[cfg(feature = "extension")]
+mod vtab;
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::Arc;
 
 use libc::{c_char, c_int, c_uchar, c_void};
 
+use lindera::error::LinderaError;
+use lindera::mode::{Mode, Penalty};
+use lindera::token::Token;
 use lindera::tokenizer::{Tokenizer, TokenizerBuilder};
 
 pub use crate::common::*;
+pub use crate::config::{
+    clear_global_config, lindera_set_config, set_global_config, CharacterFilterConfig,
+    DictionaryType, Fts5TokenizerOptions, LinderaConfig, LinderaConfigBuilder, SegmentationMode,
+    TokenFilterConfig,
+};
+#[cfg(feature = "rusqlite")]
+pub use crate::connection_extension::{register, register_with_db};
+pub use crate::error::{LinderaSqliteError, TokenizerArgumentError};
+#[cfg(feature = "extension")]
+pub use crate::extension::{fts5_create_lindera_tokenizer, fts5_delete_lindera_tokenizer};
+#[cfg(feature = "memory-stats")]
+pub use crate::memory::{lindera_peak_memory_bytes, MemoryTracker};
 
-pub fn load_tokenizer() -> Result<Tokenizer, c_int> {
-    let builder = TokenizerBuilder::new().map_err(|e| {
-        eprintln!("Failed to create tokenizer builder: {}", e);
-        SQLITE_INTERNAL
-    })?;
-    let tokenizer = builder.build().map_err(|e| {
-        eprintln!("Failed to create tokenizer: {}", e);
-        SQLITE_INTERNAL
-    })?;
+/// Exposed for the fuzz targets in `fuzz/`.
+#[cfg(all(fuzzing, feature = "extension"))]
+pub use crate::args::TokenizerArgs;
+use crate::chinese_variants::ChineseVariants;
+use crate::config::{env_config, parse_config, parse_json_config, parse_yaml_config, read_config};
+use crate::jamo::decompose;
+use crate::josa::josa_stem;
+use crate::kana::{fold, normalize_variants};
+use crate::logging::log_debug;
+use crate::offset_map::OffsetMap;
+use crate::pinyin::pinyin;
+use crate::stopwords::Stopwords;
+use crate::synonyms::Synonyms;
+
+// An application linking the library installs the tracker itself, if it
+// wants the statistics, so that it stays free to pick its own allocator.
+#[cfg(any(
+    feature = "memory-stats-allocator",
+    all(test, feature = "memory-stats")
+))]
+#[global_allocator]
+static GLOBAL: MemoryTracker = MemoryTracker::new();
+
+/// Loads the tokenizer configured by the environment, in JSON or YAML, or
+/// Lindera's default one if neither variable is set.
+///
+/// `LINDERA_CONFIG_PATH` names a configuration file, while `LINDERA_CONFIG`
+/// holds the configuration itself. `LINDERA_CONFIG_PATH` wins if both are set,
+/// and [`set_global_config`] takes the place of `LINDERA_CONFIG`. With the
+/// `default-config` feature, `resources/lindera.yml` as compiled in stands in
+/// for Lindera's default.
+pub fn load_tokenizer() -> Result<Tokenizer, LinderaSqliteError> {
+    let tokenizer = match env_config() {
+        Ok(Some(config)) => {
+            Tokenizer::from_config(&config).map_err(LinderaSqliteError::from_build_error)
+        }
+        Ok(None) => build_default_tokenizer(),
+        Err(e) => Err(e),
+    };
+
+    tokenizer.map_err(report_tokenizer_error)
+}
+
+/// Builds a tokenizer from the configuration file at `path`, in JSON or YAML,
+/// regardless of the environment.
+pub fn load_tokenizer_from_path(path: &Path) -> Result<Tokenizer, LinderaSqliteError> {
+    tokenizer_from_path(path).map_err(report_tokenizer_error)
+}
+
+/// Builds a tokenizer from a configuration given as JSON if it starts with
+/// `{`, or as YAML otherwise.
+pub fn load_tokenizer_from_str(config: &str) -> Result<Tokenizer, LinderaSqliteError> {
+    tokenizer_from_str(config).map_err(report_tokenizer_error)
+}
+
+/// Builds a tokenizer from a JSON configuration.
+pub fn load_tokenizer_from_json(json: &str) -> Result<Tokenizer, LinderaSqliteError> {
+    tokenizer_from_json(json).map_err(report_tokenizer_error)
+}
+
+/// Builds a tokenizer from a YAML configuration.
+pub fn load_tokenizer_from_yaml(yaml: &str) -> Result<Tokenizer, LinderaSqliteError> {
+    tokenizer_from_yaml(yaml).map_err(report_tokenizer_error)
+}
+
+fn tokenizer_from_path(path: &Path) -> Result<Tokenizer, LinderaSqliteError> {
+    Tokenizer::from_config(&read_config(path)?).map_err(LinderaSqliteError::from_build_error)
+}
+
+fn tokenizer_from_str(config: &str) -> Result<Tokenizer, LinderaSqliteError> {
+    Tokenizer::from_config(&parse_config(config)?).map_err(LinderaSqliteError::from_build_error)
+}
+
+fn tokenizer_from_json(json: &str) -> Result<Tokenizer, LinderaSqliteError> {
+    Tokenizer::from_config(&parse_json_config(json)?).map_err(LinderaSqliteError::from_build_error)
+}
+
+fn tokenizer_from_yaml(yaml: &str) -> Result<Tokenizer, LinderaSqliteError> {
+    Tokenizer::from_config(&parse_yaml_config(yaml)?).map_err(LinderaSqliteError::from_build_error)
+}
+
+/// A tokenizer for unit tests that needs neither `LINDERA_CONFIG_PATH` nor a
+/// configuration file, with the filters of `resources/lindera.yml` that the
+/// tests rely on.
+#[cfg(test)]
+pub(crate) fn mock_tokenizer() -> Tokenizer {
+    let config = serde_json::json!({
+        "segmenter": {
+            "mode": "normal",
+            "dictionary": { "kind": "ipadic" },
+        },
+        "character_filters": [
+            { "kind": "unicode_normalize", "args": { "kind": "nfkc" } },
+        ],
+        "token_filters": [
+            {
+                "kind": "japanese_stop_tags",
+                "args": { "tags": ["助詞", "助詞,係助詞", "助動詞", "記号", "記号,句点"] },
+            },
+            { "kind": "japanese_katakana_stem", "args": { "min": 3 } },
+        ],
+    });
+    Tokenizer::from_config(&config).expect("mock tokenizer config is valid")
+}
 
-    Ok(tokenizer)
+fn build_default_tokenizer() -> Result<Tokenizer, LinderaSqliteError> {
+    TokenizerBuilder::new()
+        .and_then(|builder| builder.build())
+        .map_err(LinderaSqliteError::from_build_error)
 }
 
+pub(crate) fn report_tokenizer_error(e: LinderaSqliteError) -> LinderaSqliteError {
+    report_error(e.code(), format_args!("Failed to create tokenizer: {}", e));
+    e
+}
+
+fn report_tokenize_error(e: LinderaError) -> LinderaSqliteError {
+    let e = LinderaSqliteError::Tokenize(e.to_string());
+    report_error(e.code(), format_args!("Failed to tokenize text: {}", e));
+    e
+}
+
+// FTS5 calls this through a safe `extern "C" fn` pointer and vouches for the
+// arguments itself.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn lindera_fts5_tokenize(
     tokenizer: *mut Fts5Tokenizer,
     p_ctx: *mut c_void,
-    _flags: c_int,
+    flags: c_int,
     p_text: *const c_char,
     n_text: c_int,
     x_token: TokenFunction,
 ) -> c_int {
-    std::panic::catch_unwind(std::panic::AssertUnwindSafe(
-        || match lindera_fts5_tokenize_internal(tokenizer, p_ctx, p_text, n_text, x_token) {
+    #[cfg(feature = "memory-stats")]
+    let _scope = memory::TrackingScope::start();
+
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        match unsafe {
+            lindera_fts5_tokenize_internal(tokenizer, p_ctx, flags, p_text, n_text, x_token)
+        } {
             Ok(()) => SQLITE_OK,
             Err(code) => code,
-        },
-    ))
-    .unwrap_or(SQLITE_INTERNAL)
+        }
+    }))
+    .unwrap_or_else(|_| {
+        report_error(
+            SQLITE_INTERNAL,
+            format_args!("lindera_tokenizer panicked while tokenizing"),
+        );
+        SQLITE_INTERNAL
+    })
 }
 
-fn lindera_fts5_tokenize_internal(
+/// Tokenizes `p_text` and passes each token to `x_token`, like
+/// `lindera_fts5_tokenize` but without catching panics and with the SQLite
+/// result code as the error.
+///
+/// Text that is not valid UTF-8 produces no tokens and returns
+/// `Err(SQLITE_OK)`.
+///
+/// # Safety
+///
+/// `tokenizer` must point to a live `Fts5Tokenizer`. Unless `n_text` is not
+/// positive, `p_text` must point to `n_text` readable bytes. `x_token` is
+/// called with `p_ctx` as its first argument.
+pub unsafe fn lindera_fts5_tokenize_internal(
     tokenizer: *mut Fts5Tokenizer,
     p_ctx: *mut c_void,
+    flags: c_int,
     p_text: *const c_char,
     n_text: c_int,
     x_token: TokenFunction,
 ) -> Result<(), c_int> {
-    let slice = unsafe { core::slice::from_raw_parts(p_text as *const c_uchar, n_text as usize) };
+    // FTS5 may pass a null `p_text` for empty input, which `from_raw_parts`
+    // must not see.
+    if n_text <= 0 {
+        return Ok(());
+    }
+
+    let slice = core::slice::from_raw_parts(p_text as *const c_uchar, n_text as usize);
 
     // Map errors to SQLITE_OK because failing here means that the database
     // wouldn't accessible.
     let input = core::str::from_utf8(slice).map_err(|_| SQLITE_OK)?;
 
-    match unsafe { (*tokenizer).tokenizer.tokenize(input) } {
-        Ok(tokens) => {
-            for token in tokens {
-                let rc = x_token(
-                    p_ctx,
-                    0,
-                    token.text.as_bytes().as_ptr() as *const c_char,
-                    token.text.len() as c_int,
-                    token.byte_start as c_int,
-                    token.byte_end as c_int,
-                );
-                if rc != SQLITE_OK {
-                    return Err(rc);
+    let tokenizer = &mut *tokenizer;
+    #[cfg(feature = "extension")]
+    tokenizer.reload_if_changed();
+    #[cfg(feature = "extension")]
+    if let Some(stats) = tokenizer.stats.clone() {
+        return stats.record(flags, input.len(), x_token, p_ctx, |x_token, p_ctx| {
+            tokenize_input(tokenizer, input, flags, TokenCallback::new(x_token, p_ctx))
+        });
+    }
+    tokenize_input(tokenizer, input, flags, TokenCallback::new(x_token, p_ctx))
+}
+
+/// Passes the tokens of `input` to `callback`, through the pipeline of
+/// `tokenizer` that `flags` calls for.
+fn tokenize_input(
+    tokenizer: &mut Fts5Tokenizer,
+    input: &str,
+    flags: c_int,
+    callback: TokenCallback,
+) -> Result<(), c_int> {
+    // Documents, and the text of auxiliary functions such as highlight(),
+    // go through the index pipeline.
+    let pipeline = match &tokenizer.query_tokenizer {
+        Some(query_tokenizer) if flags & FTS5_TOKENIZE_QUERY != 0 => query_tokenizer,
+        _ => &tokenizer.tokenizer,
+    };
+    // unicode61 folds ASCII text too, which the fast path would not.
+    if tokenizer.ascii_fast_path && tokenizer.unicode61.is_none() && input.is_ascii() {
+        return tokenize_ascii(input, ascii_case(pipeline), &callback).map_err(|e| e.code());
+    }
+    // Documents are indexed in every form asked for, while queries look for
+    // one form and its synonyms. Auxiliary functions skip colocated tokens
+    // anyway.
+    let document = flags & (FTS5_TOKENIZE_QUERY | FTS5_TOKENIZE_AUX) == 0;
+    let colocated = Colocated {
+        original: tokenizer.keep_original && document,
+        // In queries too, where a prefix written as jamo finds words whose
+        // last syllable is still being typed.
+        jamo: tokenizer.jamo,
+        synonyms: tokenizer
+            .synonyms
+            .as_deref()
+            .filter(|_| flags & FTS5_TOKENIZE_QUERY != 0),
+        forms: &[],
+    };
+    // Other forms replace tokens but for auxiliary functions, which only
+    // read positions, and are only added next to them in documents.
+    let replace = flags & FTS5_TOKENIZE_AUX == 0;
+    let forms = Forms {
+        base_form: tokenizer
+            .base_form_field
+            .map(|field| (tokenizer.base_form, field))
+            .filter(|(base_form, _)| match base_form {
+                BaseForm::Off => false,
+                BaseForm::Replace => replace,
+                BaseForm::Colocate => document,
+            }),
+        josa: match tokenizer.josa {
+            Josa::Strip if !replace => Josa::Keep,
+            Josa::Colocate if !document => Josa::Keep,
+            josa => josa,
+        },
+        stopwords: tokenizer.stopwords.as_deref(),
+        kana: tokenizer.kana,
+        long_vowel: tokenizer.long_vowel,
+        small_kana: tokenizer.small_kana,
+        chinese_variants: tokenizer.chinese_variants.as_deref(),
+        // Pinyin is typed as it is, so queries need not be expanded.
+        pinyin_field: tokenizer
+            .pinyin_field
+            .filter(|_| tokenizer.pinyin && document),
+        unicode61: tokenizer.unicode61.as_ref(),
+    };
+    if tokenizer.hybrid && document {
+        let parts = tokenizer
+            .decompose_tokenizer
+            .get_or_insert_with(|| Arc::new(decomposing(&tokenizer.tokenizer)));
+        return tokenize_hybrid(
+            &tokenizer.tokenizer,
+            parts,
+            input,
+            flags,
+            colocated,
+            forms,
+            &callback,
+        )
+        .map_err(|e| e.code());
+    }
+    tokenize_text(pipeline, input, flags, colocated, forms, &callback).map_err(|e| e.code())
+}
+
+/// A token as FTS5 receives it from the tokenizer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenSpan {
+    pub surface: String,
+    /// The byte range of the token in the text, which the surface may differ
+    /// from after the filters.
+    pub byte_start: usize,
+    pub byte_end: usize,
+    /// The `FTS5_TOKEN_*` flags, such as [`FTS5_TOKEN_COLOCATED`].
+    pub flags: c_int,
+}
+
+impl Fts5Tokenizer {
+    /// Tokenizes `text` as a document, through the same pipeline as
+    /// `lindera_fts5_tokenize`.
+    ///
+    /// Errors are reported to the log, as FTS5 would, and end the tokens.
+    pub fn tokenize_str(&mut self, text: &str) -> impl Iterator<Item = TokenSpan> {
+        self.tokenize_str_with_flags(text, FTS5_TOKENIZE_DOCUMENT)
+    }
+
+    /// Tokenizes `text` like [`Fts5Tokenizer::tokenize_str`], as FTS5 does
+    /// with the `FTS5_TOKENIZE_*` `flags`, for example as a query.
+    pub fn tokenize_str_with_flags(
+        &mut self,
+        text: &str,
+        flags: c_int,
+    ) -> impl Iterator<Item = TokenSpan> {
+        extern "C" fn collect_span(
+            p_ctx: *mut c_void,
+            t_flags: c_int,
+            p_token: *const c_char,
+            n_token: c_int,
+            i_start: c_int,
+            i_end: c_int,
+        ) -> c_int {
+            let spans = unsafe { &mut *(p_ctx as *mut Vec<TokenSpan>) };
+            let token =
+                unsafe { core::slice::from_raw_parts(p_token as *const c_uchar, n_token as usize) };
+            spans.push(TokenSpan {
+                surface: String::from_utf8_lossy(token).into_owned(),
+                byte_start: i_start as usize,
+                byte_end: i_end as usize,
+                flags: t_flags,
+            });
+            SQLITE_OK
+        }
+
+        let mut spans = Vec::<TokenSpan>::new();
+        let Ok(n_text) = c_int::try_from(text.len()) else {
+            report_error(
+                SQLITE_TOOBIG,
+                format_args!("Failed to tokenize text: {} bytes is too long", text.len()),
+            );
+            return spans.into_iter();
+        };
+        // Errors have been reported by the time they are returned.
+        let _ = unsafe {
+            lindera_fts5_tokenize_internal(
+                self,
+                (&mut spans as *mut Vec<TokenSpan>).cast(),
+                flags,
+                text.as_ptr().cast(),
+                n_text,
+                collect_span,
+            )
+        };
+        spans.into_iter()
+    }
+}
+
+/// Tokenizes `text` with the same pipeline as `lindera_fts5_tokenize`, for
+/// Rust callers that hold a [`Tokenizer`] rather than an FTS5 tokenizer.
+pub fn lindera_fts5_tokenize_ref(
+    tokenizer: &mut Tokenizer,
+    text: &str,
+    callback: &TokenCallback,
+) -> Result<(), LinderaSqliteError> {
+    #[cfg(feature = "memory-stats")]
+    let _scope = memory::TrackingScope::start();
+
+    tokenize_text(
+        tokenizer,
+        text,
+        FTS5_TOKENIZE_DOCUMENT,
+        Colocated::default(),
+        Forms::default(),
+        callback,
+    )
+}
+
+/// Describes the tokens `tokenizer` produces for `text`, one per line, as
+/// their byte range in `text` and their text, followed by the original text
+/// if a filter changed it:
+///
+/// ```text
+/// [0..21] 'Lindera' (original: 'Ｌｉｎｄｅｒａ')
+/// [24..33] '形態素'
+/// ```
+#[cfg(any(test, feature = "debug-api"))]
+pub fn debug_tokenize(tokenizer: &mut Fts5Tokenizer, text: &str) -> String {
+    extern "C" fn collect(
+        ctx: *mut c_void,
+        _flags: c_int,
+        token: *const c_char,
+        token_len: c_int,
+        start: c_int,
+        end: c_int,
+    ) -> c_int {
+        let tokens = unsafe { &mut *(ctx as *mut Vec<(String, usize, usize)>) };
+        let token =
+            unsafe { core::slice::from_raw_parts(token as *const c_uchar, token_len as usize) };
+        tokens.push((
+            String::from_utf8_lossy(token).into_owned(),
+            start as usize,
+            end as usize,
+        ));
+        SQLITE_OK
+    }
+
+    let len = match cast_usize_to_c_int(text.len()) {
+        Ok(len) => len,
+        Err(rc) => return format!("error: SQLite error code {}", rc),
+    };
+    let mut tokens: Vec<(String, usize, usize)> = Vec::new();
+    let rc = lindera_fts5_tokenize(
+        tokenizer,
+        &mut tokens as *mut _ as *mut c_void,
+        0,
+        text.as_ptr() as *const c_char,
+        len,
+        collect,
+    );
+    if rc != SQLITE_OK {
+        return format!("error: SQLite error code {}", rc);
+    }
+
+    tokens
+        .iter()
+        .map(|(token, start, end)| match text.get(*start..*end) {
+            Some(original) if original != token => {
+                format!(
+                    "[{}..{}] '{}' (original: '{}')",
+                    start, end, token, original
+                )
+            }
+            _ => format!("[{}..{}] '{}'", start, end, token),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// How the `lowercase` or `uppercase` token filter of `tokenizer`, whichever
+/// comes last, changes the case of ASCII text, so that the fast path agrees
+/// with the tokens Lindera would produce.
+fn ascii_case(tokenizer: &Tokenizer) -> Option<fn(&mut [u8])> {
+    tokenizer
+        .token_filters
+        .iter()
+        .rev()
+        .find_map(|token_filter| match token_filter.name() {
+            "lowercase" => Some(<[u8]>::make_ascii_lowercase as fn(&mut [u8])),
+            "uppercase" => Some(<[u8]>::make_ascii_uppercase as fn(&mut [u8])),
+            _ => None,
+        })
+}
+
+/// Emits each run of ASCII letters and digits in `input` as a token, its
+/// case changed by `case` if given, without allocating otherwise.
+fn tokenize_ascii(
+    input: &str,
+    case: Option<fn(&mut [u8])>,
+    callback: &TokenCallback,
+) -> Result<(), LinderaSqliteError> {
+    let bytes = input.as_bytes();
+    let mut folded = Vec::new();
+    let mut start = None;
+    for (i, byte) in bytes.iter().enumerate().chain([(bytes.len(), &b' ')]) {
+        match (start, byte.is_ascii_alphanumeric()) {
+            (None, true) => start = Some(i),
+            (Some(word_start), false) => {
+                start = None;
+                let word = match case {
+                    Some(case) => {
+                        folded.clear();
+                        folded.extend_from_slice(&bytes[word_start..i]);
+                        case(&mut folded);
+                        &folded[..]
+                    }
+                    None => &bytes[word_start..i],
+                };
+                match callback.emit(word, word_start, i) {
+                    Err(SQLITE_DONE) => break,
+                    result => result?,
                 }
             }
+            _ => {}
         }
-        Err(_) => {
-            return Err(SQLITE_INTERNAL);
+    }
+
+    Ok(())
+}
+
+/// Token filters that only rewrite the text of tokens, never adding, removing
+/// or merging any. FTS5's auxiliary functions, such as highlight(), only
+/// count tokens and read their offsets, so they do without these.
+const TEXT_ONLY_TOKEN_FILTERS: &[&str] = &[
+    "japanese_base_form",
+    "japanese_kana",
+    "japanese_katakana_stem",
+    "japanese_number",
+    "japanese_reading_form",
+    "korean_reading_form",
+    "lowercase",
+    "mapping",
+    "remove_diacritical_mark",
+    "uppercase",
+];
+
+/// A token with its byte range in the text given to the tokenizer.
+pub(crate) type AnalyzedToken<'a> = (Cow<'a, str>, usize, usize);
+
+/// Tokens, with the other forms of each by its index, or none at all.
+type TokensWithForms<'a> = (Vec<AnalyzedToken<'a>>, Vec<Vec<String>>);
+
+/// What else to pass to FTS5 at the position of each token.
+#[derive(Default, Clone, Copy)]
+struct Colocated<'a> {
+    /// The original surface of a token the filters rewrote.
+    original: bool,
+    /// A Hangul token written as jamo.
+    jamo: bool,
+    /// The synonyms of a token.
+    synonyms: Option<&'a Synonyms>,
+    /// The other forms of each token, by its index: its base form, the word
+    /// without its particles or its pinyin.
+    forms: &'a [Vec<String>],
+}
+
+/// What to make of the tokens of the pipeline before they are passed on.
+#[derive(Default, Clone, Copy)]
+struct Forms<'a> {
+    /// How to index base forms, with the field of the details they are in.
+    base_form: Option<(BaseForm, usize)>,
+    /// How to index Korean words with particles attached.
+    josa: Josa,
+    /// The words to leave out.
+    stopwords: Option<&'a Stopwords>,
+    /// The script to fold kana into.
+    kana: Kana,
+    /// Whether to normalize prolonged sound marks and small kana.
+    long_vowel: bool,
+    small_kana: bool,
+    /// What to convert Chinese characters to.
+    chinese_variants: Option<&'a ChineseVariants>,
+    /// The field of the details that holds the pinyin of Chinese words, to
+    /// colocate it.
+    pinyin_field: Option<usize>,
+    /// How to tokenize text outside Chinese, Japanese and Korean as
+    /// `unicode61` does, rather than with Lindera.
+    unicode61: Option<&'a Unicode61>,
+}
+
+/// Tokenizes `input` for FTS5 and passes the tokens on, each followed by
+/// what `colocated` asks for, in the forms `forms` asks for.
+fn tokenize_text(
+    tokenizer: &Tokenizer,
+    input: &str,
+    flags: c_int,
+    colocated: Colocated,
+    forms: Forms,
+    callback: &TokenCallback,
+) -> Result<(), LinderaSqliteError> {
+    let (mut tokens, mut other_forms) = analyze_runs(input, flags, forms, |run, flags| {
+        analyze_forms(tokenizer, run, flags, forms)
+    })?;
+    convert_scripts(&mut tokens, forms);
+    drop_stopwords(&mut tokens, &mut other_forms, forms.stopwords, flags);
+    let colocated = Colocated {
+        forms: &other_forms,
+        ..colocated
+    };
+    emit_tokens(&tokens, &[], input, colocated, callback)
+}
+
+/// A copy of `tokenizer` that segments in Lindera's `decompose` mode, with
+/// the same filters.
+pub(crate) fn decomposing(tokenizer: &Tokenizer) -> Tokenizer {
+    let mut decomposing = tokenizer.clone();
+    decomposing.segmenter.mode = Mode::Decompose(Penalty::default());
+    decomposing
+}
+
+/// Tokenizes a document in the hybrid mode: each token as segmented by
+/// `tokenizer`, followed at its position by the parts `decompose`, the same
+/// tokenizer in Lindera's `decompose` mode, splits it into.
+fn tokenize_hybrid(
+    tokenizer: &Tokenizer,
+    decompose: &Tokenizer,
+    input: &str,
+    flags: c_int,
+    colocated: Colocated,
+    forms: Forms,
+    callback: &TokenCallback,
+) -> Result<(), LinderaSqliteError> {
+    let into_owned = |tokens: Vec<AnalyzedToken>| -> Vec<AnalyzedToken<'static>> {
+        tokens
+            .into_iter()
+            .map(|(text, start, end)| (Cow::Owned(text.into_owned()), start, end))
+            .collect()
+    };
+
+    let (mut words, mut other_forms) = analyze_runs(input, flags, forms, |run, flags| {
+        analyze_forms(tokenizer, run, flags, forms)
+    })?;
+    convert_scripts(&mut words, forms);
+    drop_stopwords(&mut words, &mut other_forms, forms.stopwords, flags);
+    let words = into_owned(words);
+    let parts = analyze_runs(input, flags, forms, |run, flags| {
+        Ok((analyze(decompose, run, flags)?, Vec::new()))
+    })
+    .map(|(mut parts, _)| {
+        convert_scripts(&mut parts, forms);
+        into_owned(parts)
+    });
+
+    let colocated = Colocated {
+        forms: &other_forms,
+        ..colocated
+    };
+    emit_tokens(&words, &parts?, input, colocated, callback)
+}
+
+/// Tokenizes `input` with `analyze`, which returns the tokens of a text and
+/// the other forms of each, if any. With `unicode61` in `forms`, only its
+/// runs in Chinese, Japanese or Korean are, each on its own, and the other
+/// runs are tokenized as `unicode61` does. Only the last run is tokenized as
+/// the end of a prefix query.
+fn analyze_runs<'a>(
+    input: &'a str,
+    flags: c_int,
+    forms: Forms,
+    mut analyze: impl FnMut(&'a str, c_int) -> Result<TokensWithForms<'a>, LinderaSqliteError>,
+) -> Result<TokensWithForms<'a>, LinderaSqliteError> {
+    let Some(options) = forms.unicode61 else {
+        return analyze(input, flags);
+    };
+    let mut tokens = Vec::new();
+    let mut other_forms = Vec::new();
+    let runs = unicode61::runs(input);
+    for (i, (start, end, cjk)) in runs.iter().copied().enumerate() {
+        let run = &input[start..end];
+        let (run_tokens, run_forms) = match cjk {
+            true if i + 1 == runs.len() => analyze(run, flags)?,
+            true => analyze(run, flags & !FTS5_TOKENIZE_PREFIX)?,
+            false => (unicode61::tokenize(run, options), Vec::new()),
+        };
+        // The other forms are by the index of each token, once any has one.
+        if !run_forms.is_empty() {
+            other_forms.resize(tokens.len(), Vec::new());
+            other_forms.extend(run_forms);
+        }
+        tokens.extend(
+            run_tokens
+                .into_iter()
+                .map(|(text, token_start, token_end)| {
+                    (text, start + token_start, start + token_end)
+                }),
+        );
+    }
+    if !other_forms.is_empty() {
+        other_forms.resize(tokens.len(), Vec::new());
+    }
+    Ok((tokens, other_forms))
+}
+
+/// Runs `input` through `tokenizer` like [`analyze`], with the other forms
+/// of the tokens `forms` asks for: the base form the dictionary gives a
+/// token in its details, a Korean word without its particles or the pinyin
+/// of a Chinese word. They take the place of the tokens to replace them, or
+/// else are returned by the index of each token to colocate them. Tokens
+/// without another form, such as unknown Japanese words, are kept as they
+/// are.
+fn analyze_forms<'a>(
+    tokenizer: &'a Tokenizer,
+    input: &'a str,
+    flags: c_int,
+    forms: Forms,
+) -> Result<TokensWithForms<'a>, LinderaSqliteError> {
+    if forms.base_form.is_none() && forms.josa == Josa::Keep && forms.pinyin_field.is_none() {
+        return Ok((analyze(tokenizer, input, flags)?, Vec::new()));
+    }
+    let colocate = forms.josa == Josa::Colocate
+        || matches!(forms.base_form, Some((BaseForm::Colocate, _)))
+        || forms.pinyin_field.is_some();
+    let mut other_forms = Vec::new();
+    let tokens = analyze_tokens(tokenizer, input, flags)?
+        .into_iter()
+        .map(|mut token| {
+            let pinyin = forms
+                .pinyin_field
+                .and_then(|field| token.get_detail(field).and_then(pinyin));
+            let (form, replace) = match forms.base_form {
+                Some((base_form, field)) => (
+                    token
+                        .get_detail(field)
+                        .map(str::to_string)
+                        .filter(|base| !base.is_empty() && base != "*" && *base != token.text),
+                    base_form == BaseForm::Replace,
+                ),
+                None => (None, false),
+            };
+            let (form, replace) = match (form, forms.josa) {
+                (None, Josa::Strip | Josa::Colocate) => {
+                    (josa_stem(&mut token), forms.josa == Josa::Strip)
+                }
+                (form, _) => (form, replace),
+            };
+            let (text, colocated) = match (form, replace) {
+                (Some(form), true) => (Cow::Owned(form), None),
+                (form, _) => (token.text, form),
+            };
+            if colocate {
+                other_forms.push(colocated.into_iter().chain(pinyin).collect());
+            }
+            (text, token.byte_start, token.byte_end)
+        })
+        .collect();
+    Ok((tokens, other_forms))
+}
+
+/// Normalizes and folds the kana of `tokens` and converts their Chinese
+/// characters as `forms` asks for.
+fn convert_scripts(tokens: &mut [AnalyzedToken], forms: Forms) {
+    if forms.kana == Kana::Keep
+        && !forms.long_vowel
+        && !forms.small_kana
+        && forms.chinese_variants.is_none()
+    {
+        return;
+    }
+    for (text, _, _) in tokens {
+        // Before folding, which would hide katakana words.
+        if let Some(normalized) = normalize_variants(text, forms.long_vowel, forms.small_kana) {
+            *text = Cow::Owned(normalized);
+        }
+        if let Some(folded) = fold(text, forms.kana) {
+            *text = Cow::Owned(folded);
+        }
+        if let Some(converted) = forms
+            .chinese_variants
+            .and_then(|chinese_variants| chinese_variants.convert(text))
+        {
+            *text = Cow::Owned(converted);
+        }
+    }
+}
+
+/// Leaves the words of `stopwords` out of `tokens`, and their other forms
+/// out of `forms` if it has one for each token. The last token of a prefix
+/// query is kept, as it may be the start of another word.
+fn drop_stopwords(
+    tokens: &mut Vec<AnalyzedToken>,
+    forms: &mut Vec<Vec<String>>,
+    stopwords: Option<&Stopwords>,
+    flags: c_int,
+) {
+    let Some(stopwords) = stopwords else {
+        return;
+    };
+    let last = tokens.len().saturating_sub(1);
+    let keep = tokens
+        .iter()
+        .enumerate()
+        .map(|(i, (text, _, _))| {
+            (i == last && flags & FTS5_TOKENIZE_PREFIX != 0) || !stopwords.contains(text)
+        })
+        .collect::<Vec<_>>();
+    let mut kept = keep.iter();
+    tokens.retain(|_| *kept.next().unwrap_or(&true));
+    if !forms.is_empty() {
+        let mut kept = keep.iter();
+        forms.retain(|_| *kept.next().unwrap_or(&true));
+    }
+}
+
+/// Runs `input` through `tokenizer` like [`analyze_tokens`], keeping only
+/// the text and the byte range of each token.
+fn analyze<'a>(
+    tokenizer: &'a Tokenizer,
+    input: &'a str,
+    flags: c_int,
+) -> Result<Vec<AnalyzedToken<'a>>, LinderaSqliteError> {
+    Ok(analyze_tokens(tokenizer, input, flags)?
+        .into_iter()
+        .map(|token| (token.text, token.byte_start, token.byte_end))
+        .collect())
+}
+
+/// Runs `input` through `tokenizer`, adapting to the `FTS5_TOKENIZE_*`
+/// `flags`: auxiliary functions skip the filters in
+/// [`TEXT_ONLY_TOKEN_FILTERS`], and the last token of a prefix query is
+/// filtered by [`filter_prefix`]. The byte ranges of the tokens are in
+/// `input`, before the character filters.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn analyze_tokens<'a>(
+    tokenizer: &'a Tokenizer,
+    input: &'a str,
+    flags: c_int,
+) -> Result<Vec<Token<'a>>, LinderaSqliteError> {
+    analyze_traced(tokenizer, input, flags, None)
+}
+
+/// What a stage of [`analyze_traced`] made of the text, with the byte ranges
+/// of tokens in the text given to the tokenizer.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) enum AnalysisStep {
+    /// The text after a character filter.
+    CharacterFilter { name: String, text: String },
+    /// The words the dictionary splits the text into.
+    Segmenter { tokens: Vec<AnalyzedToken<'static>> },
+    /// The tokens after a token filter, without the last token of a prefix
+    /// query.
+    TokenFilter {
+        name: &'static str,
+        tokens: Vec<AnalyzedToken<'static>>,
+    },
+}
+
+/// Runs `input` through `tokenizer` like [`analyze_tokens`], recording in
+/// `steps` what each stage made of it.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn analyze_traced<'a>(
+    tokenizer: &'a Tokenizer,
+    input: &'a str,
+    flags: c_int,
+    mut steps: Option<&mut Vec<AnalysisStep>>,
+) -> Result<Vec<Token<'a>>, LinderaSqliteError> {
+    // Apply the character filters here rather than through `Tokenizer::tokenize`
+    // so that token offsets can be mapped back to the original input.
+    let mut text = Cow::Borrowed(input);
+    let mut offset_map = OffsetMap::new();
+    for character_filter in &tokenizer.character_filters {
+        let original_len = text.len();
+        let (offsets, diffs, _) = character_filter
+            .apply(text.to_mut())
+            .map_err(report_tokenize_error)?;
+        offset_map.record(&offsets, &diffs, original_len);
+        if let Some(steps) = steps.as_deref_mut() {
+            steps.push(AnalysisStep::CharacterFilter {
+                name: character_filter.name().to_string(),
+                text: text.to_string(),
+            });
+        }
+    }
+
+    let snapshot = |tokens: &[Token]| -> Vec<AnalyzedToken<'static>> {
+        tokens
+            .iter()
+            .map(|token| {
+                let (start, end) =
+                    offset_map.original_range(input, token.byte_start, token.byte_end);
+                (Cow::Owned(token.text.to_string()), start, end)
+            })
+            .collect()
+    };
+
+    let mut tokens = tokenizer
+        .segmenter
+        .segment(text)
+        .map_err(report_tokenize_error)?;
+    if let Some(steps) = steps.as_deref_mut() {
+        steps.push(AnalysisStep::Segmenter {
+            tokens: snapshot(&tokens),
+        });
+    }
+    let prefix = match flags & FTS5_TOKENIZE_PREFIX {
+        0 => None,
+        _ => tokens.pop(),
+    };
+    for token_filter in &tokenizer.token_filters {
+        if flags & FTS5_TOKENIZE_AUX != 0 && TEXT_ONLY_TOKEN_FILTERS.contains(&token_filter.name())
+        {
+            continue;
+        }
+        token_filter
+            .apply(&mut tokens)
+            .map_err(report_tokenize_error)?;
+        if let Some(steps) = steps.as_deref_mut() {
+            steps.push(AnalysisStep::TokenFilter {
+                name: token_filter.name(),
+                tokens: snapshot(&tokens),
+            });
+        }
+    }
+    if let Some(prefix) = prefix {
+        tokens.push(filter_prefix(tokenizer, prefix)?);
+    }
+
+    log_debug!(
+        "Tokenized {} bytes into {} tokens",
+        input.len(),
+        tokens.len()
+    );
+
+    for token in &mut tokens {
+        (token.byte_start, token.byte_end) =
+            offset_map.original_range(input, token.byte_start, token.byte_end);
+    }
+
+    Ok(tokens)
+}
+
+/// Runs `input` through the character filters of `tokenizer` alone, as text
+/// is normalized before it is segmented.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn normalize(tokenizer: &Tokenizer, input: &str) -> Result<String, LinderaSqliteError> {
+    let mut text = input.to_string();
+    for character_filter in &tokenizer.character_filters {
+        character_filter
+            .apply(&mut text)
+            .map_err(report_tokenize_error)?;
+    }
+    Ok(text)
+}
+
+/// Rejoins `input` with a space between its words as the dictionary of
+/// `tokenizer` segments them (wakati-gaki). The filters do not apply, so no
+/// part of the text is rewritten or dropped; whitespace between words only
+/// separates them.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn wakati(tokenizer: &Tokenizer, input: &str) -> Result<String, LinderaSqliteError> {
+    Ok(segment(tokenizer, input)?
+        .iter()
+        .map(|token| token.text.trim())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Turns what a user typed into an FTS5 `MATCH` query for tables tokenized
+/// by `tokenizer`: after NFKC normalization of its width, each
+/// whitespace-separated term becomes a phrase that rows must all contain,
+/// quoted when it has more than one token or is no plain bareword. Terms
+/// with no tokens but punctuation are left out, and operators and quotes
+/// lose their meaning. `None` if no term is left, as FTS5 rejects an empty
+/// query.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn match_query(
+    tokenizer: &Tokenizer,
+    input: &str,
+) -> Result<Option<String>, LinderaSqliteError> {
+    use unicode_normalization::UnicodeNormalization;
+
+    let input = input.nfkc().collect::<String>();
+    let mut phrases = Vec::new();
+    for term in input.split_whitespace() {
+        // Punctuation the dictionary keeps as tokens would only narrow the
+        // search, or make an FTS5 operator of the phrase.
+        let tokens = analyze_tokens(tokenizer, term, FTS5_TOKENIZE_QUERY)?
+            .into_iter()
+            .map(|token| (token.byte_start, token.byte_end))
+            .filter(|(start, end)| term[*start..*end].chars().any(char::is_alphanumeric))
+            .collect::<Vec<_>>();
+        let (Some(start), Some(end)) = (
+            tokens.iter().map(|(start, _)| *start).min(),
+            tokens.iter().map(|(_, end)| *end).max(),
+        ) else {
+            continue;
+        };
+        let phrase = &term[start..end];
+        let keyword = matches!(phrase, "AND" | "OR" | "NOT" | "NEAR");
+        if tokens.len() == 1 && !keyword && phrase.chars().all(is_bareword) {
+            phrases.push(phrase.to_string());
+        } else {
+            phrases.push(format!("\"{}\"", phrase.replace('"', "\"\"")));
+        }
+    }
+    Ok((!phrases.is_empty()).then(|| phrases.join(" ")))
+}
+
+/// Whether FTS5 takes `c` as part of a bareword.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn is_bareword(c: char) -> bool {
+    !c.is_ascii() || c.is_ascii_alphanumeric() || c == '_' || c == '\x1a'
+}
+
+/// The readings of the words of `input`, concatenated, as the dictionary of
+/// `tokenizer` gives them in the details of each at `field`. Words it has no
+/// reading for, such as unknown words and symbols, are kept as written.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn reading(
+    tokenizer: &Tokenizer,
+    field: Option<usize>,
+    input: &str,
+) -> Result<String, LinderaSqliteError> {
+    Ok(readings(tokenizer, field, input)?.concat())
+}
+
+/// The reading of each word of `input`, as for [`reading`].
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn readings(
+    tokenizer: &Tokenizer,
+    field: Option<usize>,
+    input: &str,
+) -> Result<Vec<String>, LinderaSqliteError> {
+    Ok(word_readings(tokenizer, field, input)?
+        .into_iter()
+        .map(|((text, _, _), reading)| reading.unwrap_or_else(|| text.into_owned()))
+        .collect())
+}
+
+/// The words of `input` with their byte ranges, each with the reading the
+/// dictionary of `tokenizer` gives in its details at `field`, if any.
+#[cfg_attr(not(feature = "extension"), allow(dead_code))]
+pub(crate) fn word_readings<'a>(
+    tokenizer: &'a Tokenizer,
+    field: Option<usize>,
+    input: &'a str,
+) -> Result<Vec<(AnalyzedToken<'a>, Option<String>)>, LinderaSqliteError> {
+    Ok(segment(tokenizer, input)?
+        .into_iter()
+        .map(|mut token| {
+            let reading = field
+                .and_then(|index| token.get_detail(index))
+                .filter(|reading| !reading.is_empty() && *reading != "*")
+                .map(str::to_string);
+            ((token.text, token.byte_start, token.byte_end), reading)
+        })
+        .collect())
+}
+
+/// Splits `input` into words with the dictionary of `tokenizer` alone.
+fn segment<'a>(
+    tokenizer: &'a Tokenizer,
+    input: &'a str,
+) -> Result<Vec<Token<'a>>, LinderaSqliteError> {
+    tokenizer
+        .segmenter
+        .segment(Cow::Borrowed(input))
+        .map_err(report_tokenize_error)
+}
+
+/// Passes `tokens` to FTS5, each followed by what `colocated` asks for and by
+/// the `parts` within its byte range, all colocated with it.
+fn emit_tokens(
+    tokens: &[AnalyzedToken],
+    parts: &[AnalyzedToken],
+    input: &str,
+    colocated: Colocated,
+    callback: &TokenCallback,
+) -> Result<(), LinderaSqliteError> {
+    // `x_token` takes no lifetime hint like the `SQLITE_STATIC` and
+    // `SQLITE_TRANSIENT` destructors of `sqlite3_result_text`: FTS5 only reads
+    // the token during the call and copies what it keeps. Borrowing `tokens`
+    // rather than consuming it still keeps every token emitted so far intact
+    // until tokenization ends, so no pointer FTS5 was given is ever reused.
+    let mut parts = parts.iter().peekable();
+    for (i, (text, byte_start, byte_end)) in tokens.iter().enumerate() {
+        let (byte_start, byte_end) = (*byte_start, *byte_end);
+        let mut result = callback.emit(text.as_bytes(), byte_start, byte_end);
+        for form in colocated.forms.get(i).into_iter().flatten() {
+            if result.is_ok() {
+                result = callback.emit_colocated(form.as_bytes(), byte_start, byte_end);
+            }
+        }
+        if colocated.original && result.is_ok() {
+            if let Some(original) = input
+                .get(byte_start..byte_end)
+                .filter(|original| original != text)
+            {
+                result = callback.emit_colocated(original.as_bytes(), byte_start, byte_end);
+            }
+        }
+        if colocated.jamo && result.is_ok() {
+            if let Some(jamo) = decompose(text) {
+                result = callback.emit_colocated(jamo.as_bytes(), byte_start, byte_end);
+            }
+        }
+        for synonym in colocated
+            .synonyms
+            .map_or(&[][..], |synonyms| synonyms.get(text))
+        {
+            if result.is_ok() {
+                result = callback.emit_colocated(synonym.as_bytes(), byte_start, byte_end);
+            }
+        }
+        while let Some((part, part_start, part_end)) =
+            parts.next_if(|(_, part_start, _)| *part_start < byte_end)
+        {
+            let within = byte_start <= *part_start && *part_end <= byte_end;
+            if result.is_ok() && within && (*part_start, *part_end) != (byte_start, byte_end) {
+                result = callback.emit_colocated(part.as_bytes(), *part_start, *part_end);
+            }
+        }
+        match result {
+            // The callback has seen enough tokens, which is not an error.
+            Err(SQLITE_DONE) => break,
+            result => result?,
         }
     }
 
     Ok(())
 }
 
+/// Applies the token filters to the last token of a prefix query, except
+/// those that would drop it. An incomplete word is easily taken for a stop
+/// word or a particle, and the query would then match everything or nothing;
+/// the filters that rewrite tokens still apply, so that it matches the form
+/// the index holds.
+fn filter_prefix<'a>(
+    tokenizer: &Tokenizer,
+    prefix: Token<'a>,
+) -> Result<Token<'a>, LinderaSqliteError> {
+    let mut prefix = prefix;
+    for token_filter in &tokenizer.token_filters {
+        let mut filtered = vec![prefix.clone()];
+        token_filter
+            .apply(&mut filtered)
+            .map_err(report_tokenize_error)?;
+        if let Some(token) = filtered.pop() {
+            prefix = token;
+        }
+    }
+
+    Ok(prefix)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -100,7 +1200,121 @@ mod tests {
 
         tokens.push((token, start, end));
 
-        return SQLITE_OK;
+        SQLITE_OK
+    }
+
+    /// The texts of the tokens `tokenizer` emits for `input` with the
+    /// `FTS5_TOKENIZE_*` `flags`.
+    fn token_texts(tokenizer: &mut Fts5Tokenizer, input: &str, flags: c_int) -> Vec<String> {
+        tokenizer
+            .tokenize_str_with_flags(input, flags)
+            .map(|span| span.surface)
+            .collect()
+    }
+
+    #[test]
+    fn it_loads_tokenizers_from_paths() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/lindera.yml");
+        let mut tokenizer = Fts5Tokenizer::new(load_tokenizer_from_path(&path).unwrap());
+        assert_eq!(token_texts(&mut tokenizer, "辞書は", 0), ["辞書"]);
+
+        let missing = load_tokenizer_from_path(Path::new("/nonexistent/lindera.yml"));
+        assert_eq!(missing.err().map(|e| e.code()), Some(SQLITE_CANTOPEN));
+    }
+
+    #[test]
+    fn it_classifies_tokenizer_errors() {
+        let dictionary =
+            load_tokenizer_from_yaml("segmenter:\n  dictionary:\n    path: \"/nonexistent\"\n");
+        assert!(matches!(
+            dictionary,
+            Err(LinderaSqliteError::DictionaryLoad(_))
+        ));
+        assert_eq!(dictionary.err().map(|e| e.code()), Some(SQLITE_INTERNAL));
+
+        let kind = load_tokenizer_from_yaml("segmenter:\n  dictionary:\n    kind: \"klingon\"\n");
+        assert!(matches!(kind, Err(LinderaSqliteError::DictionaryLoad(_))));
+        assert_eq!(kind.err().map(|e| e.code()), Some(SQLITE_INTERNAL));
+
+        let filter = load_tokenizer_from_yaml(
+            "segmenter:\n  dictionary:\n    kind: \"ipadic\"\ntoken_filters:\n  - kind: \"no_such_filter\"\n",
+        );
+        assert!(matches!(filter, Err(LinderaSqliteError::ConfigInvalid(_))));
+    }
+
+    #[test]
+    fn it_tokenizes_strings() {
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        let spans = tokenizer.tokenize_str("ユーザー辞書").collect::<Vec<_>>();
+
+        assert_eq!(
+            spans,
+            [
+                TokenSpan {
+                    surface: "ユーザ".to_string(),
+                    byte_start: 0,
+                    byte_end: 12,
+                    flags: 0,
+                },
+                TokenSpan {
+                    surface: "辞書".to_string(),
+                    byte_start: 12,
+                    byte_end: 18,
+                    flags: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_tokenizes_queries_with_the_query_pipeline() {
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        tokenizer.query_tokenizer = Some(Arc::new(
+            load_tokenizer_from_yaml("segmenter:\n  dictionary:\n    kind: \"ipadic\"\n").unwrap(),
+        ));
+
+        assert_eq!(
+            token_texts(&mut tokenizer, "ユーザー辞書", FTS5_TOKENIZE_DOCUMENT),
+            ["ユーザ", "辞書"]
+        );
+        assert_eq!(
+            token_texts(&mut tokenizer, "ユーザー辞書", FTS5_TOKENIZE_QUERY),
+            ["ユーザー", "辞書"]
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_last_token_of_prefix_queries() {
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        let prefix_query = FTS5_TOKENIZE_QUERY | FTS5_TOKENIZE_PREFIX;
+
+        assert_eq!(
+            token_texts(&mut tokenizer, "辞書は", FTS5_TOKENIZE_QUERY),
+            ["辞書"]
+        );
+        assert_eq!(
+            token_texts(&mut tokenizer, "辞書は", prefix_query),
+            ["辞書", "は"]
+        );
+        // Filters that rewrite the token still apply.
+        assert_eq!(
+            token_texts(&mut tokenizer, "ユーザー", prefix_query),
+            ["ユーザ"]
+        );
+    }
+
+    #[test]
+    fn it_skips_text_only_filters_for_auxiliary_functions() {
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+
+        assert_eq!(
+            token_texts(&mut tokenizer, "ユーザーは辞書", FTS5_TOKENIZE_AUX),
+            ["ユーザー", "辞書"]
+        );
+        assert_eq!(
+            token_texts(&mut tokenizer, "ユーザーは辞書", FTS5_TOKENIZE_DOCUMENT),
+            ["ユーザ", "辞書"]
+        );
     }
 
     #[test]
@@ -108,16 +1322,17 @@ mod tests {
         let input = "Ｌｉｎｄｅｒａは形態素解析ｴﾝｼﾞﾝです。ユーザー辞書も利用可能です。";
         let mut tokens: Vec<(String, c_int, c_int)> = vec![];
 
-        let mut tokenizer = Fts5Tokenizer {
-            tokenizer: load_tokenizer().unwrap(),
-        };
-        lindera_fts5_tokenize_internal(
-            &mut tokenizer,
-            &mut tokens as *mut _ as *mut c_void,
-            input.as_bytes().as_ptr() as *const c_char,
-            input.len() as i32,
-            token_callback,
-        )
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        unsafe {
+            lindera_fts5_tokenize_internal(
+                &mut tokenizer,
+                &mut tokens as *mut _ as *mut c_void,
+                0,
+                input.as_bytes().as_ptr() as *const c_char,
+                input.len() as i32,
+                token_callback,
+            )
+        }
         .expect("tokenize internal should not fail");
 
         assert_eq!(
@@ -137,25 +1352,519 @@ mod tests {
     }
 
     #[test]
-    fn it_ignores_invalid_utf8() {
-        let input = b"\xc3\x28";
+    fn it_reports_correct_offsets_for_multi_byte_cjk() {
+        use unicode_normalization::UnicodeNormalization;
+
+        let input = "東京都庁舎の展望室から富士山を眺める日本語形態素解析器の試験です";
+        assert!(input.chars().all(|c| c.len_utf8() == 3));
         let mut tokens: Vec<(String, c_int, c_int)> = vec![];
 
-        let mut tokenizer = Fts5Tokenizer {
-            tokenizer: load_tokenizer().unwrap(),
-        };
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
         assert_eq!(
-            lindera_fts5_tokenize_internal(
+            lindera_fts5_tokenize(
                 &mut tokenizer,
                 &mut tokens as *mut _ as *mut c_void,
+                0,
                 input.as_ptr() as *const c_char,
-                input.len() as i32,
+                input.len() as c_int,
                 token_callback,
-            )
+            ),
+            SQLITE_OK
+        );
+
+        assert!(!tokens.is_empty());
+        for (surface, start, end) in &tokens {
+            let original = &input[*start as usize..*end as usize];
+            assert!(
+                original == surface || original.nfkc().collect::<String>() == *surface,
+                "{:?} at {}..{} is {:?} in the input",
+                surface,
+                start,
+                end,
+                original
+            );
+        }
+        for pair in tokens.windows(2) {
+            assert!(
+                pair[0].2 <= pair[1].1,
+                "{:?} overlaps {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn it_maps_offsets_of_expanded_text_to_whole_characters() {
+        // NFKC turns each of these into several characters.
+        let input = "辞書㍿解析㌔";
+        let mut tokens: Vec<(String, c_int, c_int)> = vec![];
+
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        assert_eq!(
+            lindera_fts5_tokenize(
+                &mut tokenizer,
+                &mut tokens as *mut _ as *mut c_void,
+                0,
+                input.as_ptr() as *const c_char,
+                input.len() as c_int,
+                token_callback,
+            ),
+            SQLITE_OK
+        );
+
+        assert!(tokens.iter().any(|(surface, _, _)| surface == "解析"));
+        for (surface, start, end) in &tokens {
+            assert!(
+                start < end && input.get(*start as usize..*end as usize).is_some(),
+                "{:?} at {}..{} is not a range of characters of the input",
+                surface,
+                start,
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn it_describes_tokens_for_debugging() {
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+
+        assert_eq!(
+            debug_tokenize(&mut tokenizer, "Ｌｉｎｄｅｒａは形態素解析"),
+            "[0..21] 'Lindera' (original: 'Ｌｉｎｄｅｒａ')\n[24..33] '形態素'\n[33..39] '解析'"
+        );
+    }
+
+    #[test]
+    fn it_tokenizes_through_references() {
+        let input = "形態素解析";
+        let mut tokens: Vec<(String, c_int, c_int)> = vec![];
+
+        let mut tokenizer = mock_tokenizer();
+        let callback = TokenCallback::new(token_callback, &mut tokens as *mut _ as *mut c_void);
+        lindera_fts5_tokenize_ref(&mut tokenizer, input, &callback).unwrap();
+
+        assert_eq!(
+            tokens,
+            [("形態素", 0, 9), ("解析", 9, 15)].map(|(s, start, end)| (s.to_owned(), start, end))
+        );
+    }
+
+    #[test]
+    fn it_loads_json_and_yaml_configs_alike() {
+        fn tokens(tokenizer: Tokenizer) -> Vec<(String, c_int, c_int)> {
+            let input = "Ｌｉｎｄｅｒａは形態素解析ｴﾝｼﾞﾝです。";
+            let mut tokens = vec![];
+            let mut tokenizer = Fts5Tokenizer::new(tokenizer);
+            lindera_fts5_tokenize(
+                &mut tokenizer,
+                &mut tokens as *mut _ as *mut c_void,
+                0,
+                input.as_ptr() as *const c_char,
+                input.len() as c_int,
+                token_callback,
+            );
+            tokens
+        }
+
+        let json = include_str!("../resources/lindera.json");
+        let yaml = include_str!("../resources/lindera.yml");
+        let from_json = tokens(load_tokenizer_from_json(json).unwrap());
+        let from_yaml = tokens(load_tokenizer_from_yaml(yaml).unwrap());
+
+        assert!(!from_json.is_empty());
+        assert_eq!(from_json, from_yaml);
+        assert_eq!(tokens(load_tokenizer_from_str(json).unwrap()), from_json);
+        assert_eq!(tokens(load_tokenizer_from_str(yaml).unwrap()), from_yaml);
+        assert!(matches!(
+            load_tokenizer_from_str("{ not json"),
+            Err(LinderaSqliteError::ConfigInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn it_splits_ascii_text_on_the_fast_path() {
+        let input = "Hello, world! id=42";
+        let mut tokens: Vec<(String, c_int, c_int)> = vec![];
+
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        tokenizer.ascii_fast_path = true;
+        assert_eq!(
+            lindera_fts5_tokenize(
+                &mut tokenizer,
+                &mut tokens as *mut _ as *mut c_void,
+                0,
+                input.as_ptr() as *const c_char,
+                input.len() as c_int,
+                token_callback,
+            ),
+            SQLITE_OK
+        );
+
+        assert_eq!(
+            tokens,
+            [
+                ("Hello", 0, 5),
+                ("world", 7, 12),
+                ("id", 14, 16),
+                ("42", 17, 19)
+            ]
+            .map(|(s, start, end)| (s.to_owned(), start, end))
+        );
+    }
+
+    /// The tokens `tokenizer` emits for `input` with the `FTS5_TOKENIZE_*`
+    /// `flags`, with the `FTS5_TOKEN_*` flags each is emitted with.
+    fn tokens_with_flags(
+        tokenizer: &mut Fts5Tokenizer,
+        input: &str,
+        flags: c_int,
+    ) -> Vec<(String, c_int)> {
+        tokenizer
+            .tokenize_str_with_flags(input, flags)
+            .map(|span| (span.surface, span.flags))
+            .collect()
+    }
+
+    #[test]
+    fn it_keeps_original_surfaces_colocated() {
+        let input = "Ｌｉｎｄｅｒａの辞書ユーザー";
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        tokenizer.keep_original = true;
+        let mut tokenize = |flags| tokens_with_flags(&mut tokenizer, input, flags);
+
+        assert_eq!(
+            tokenize(FTS5_TOKENIZE_DOCUMENT),
+            [
+                ("Lindera", 0),
+                ("Ｌｉｎｄｅｒａ", FTS5_TOKEN_COLOCATED),
+                ("の", 0),
+                ("辞書", 0),
+                ("ユーザ", 0),
+                ("ユーザー", FTS5_TOKEN_COLOCATED),
+            ]
+            .map(|(token, flags)| (token.to_owned(), flags))
+        );
+        assert_eq!(
+            tokenize(FTS5_TOKENIZE_QUERY),
+            [("Lindera", 0), ("の", 0), ("辞書", 0), ("ユーザ", 0)]
+                .map(|(token, flags)| (token.to_owned(), flags))
+        );
+    }
+
+    #[test]
+    fn it_indexes_base_forms() {
+        let input = "辞書も食べました";
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        // IPADIC gives the base form as the seventh field.
+        tokenizer.base_form_field = Some(6);
+        let mut tokenize = |base_form, flags| {
+            tokenizer.base_form = base_form;
+            tokens_with_flags(&mut tokenizer, input, flags)
+        };
+
+        assert_eq!(
+            tokenize(BaseForm::Replace, FTS5_TOKENIZE_DOCUMENT),
+            [("辞書", 0), ("食べる", 0)].map(|(token, flags)| (token.to_owned(), flags))
+        );
+        assert_eq!(
+            tokenize(BaseForm::Replace, FTS5_TOKENIZE_QUERY),
+            [("辞書", 0), ("食べる", 0)].map(|(token, flags)| (token.to_owned(), flags))
+        );
+        assert_eq!(
+            tokenize(BaseForm::Colocate, FTS5_TOKENIZE_DOCUMENT),
+            [("辞書", 0), ("食べ", 0), ("食べる", FTS5_TOKEN_COLOCATED)]
+                .map(|(token, flags)| (token.to_owned(), flags))
+        );
+        assert_eq!(
+            tokenize(BaseForm::Colocate, FTS5_TOKENIZE_QUERY),
+            [("辞書", 0), ("食べ", 0)].map(|(token, flags)| (token.to_owned(), flags))
+        );
+        assert_eq!(
+            tokenize(BaseForm::Off, FTS5_TOKENIZE_DOCUMENT),
+            [("辞書", 0), ("食べ", 0)].map(|(token, flags)| (token.to_owned(), flags))
+        );
+    }
+
+    #[test]
+    fn it_colocates_hangul_written_as_jamo() {
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        tokenizer.jamo = true;
+        let mut tokenize = |input, flags| tokens_with_flags(&mut tokenizer, input, flags);
+
+        assert_eq!(
+            tokenize("서울", FTS5_TOKENIZE_DOCUMENT),
+            [("서울", 0), ("ㅅㅓㅇㅜㄹ", FTS5_TOKEN_COLOCATED)]
+                .map(|(token, flags)| (token.to_owned(), flags))
+        );
+        assert_eq!(
+            tokenize("서우", FTS5_TOKENIZE_QUERY | FTS5_TOKENIZE_PREFIX),
+            [("서우", 0), ("ㅅㅓㅇㅜ", FTS5_TOKEN_COLOCATED)]
+                .map(|(token, flags)| (token.to_owned(), flags))
+        );
+        assert_eq!(
+            tokenize("辞書", FTS5_TOKENIZE_DOCUMENT),
+            [("辞書".to_owned(), 0)]
+        );
+    }
+
+    #[test]
+    fn it_colocates_pinyin() {
+        // IPADIC has no pinyin, so a user dictionary puts it in the reading.
+        let user_dictionary =
+            std::env::temp_dir().join(format!("lindera-sqlite-pinyin-{}.csv", std::process::id()));
+        std::fs::write(&user_dictionary, "北京,名詞,Bei3 jing1\n").unwrap();
+        let mut tokenizer = Fts5Tokenizer::new(
+            load_tokenizer_from_yaml(&format!(
+                "segmenter:\n  dictionary:\n    kind: ipadic\n  user_dictionary:\n    path: {:?}\n    kind: ipadic\n",
+                user_dictionary
+            ))
+            .unwrap(),
+        );
+        std::fs::remove_file(&user_dictionary).unwrap();
+        tokenizer.pinyin = true;
+        tokenizer.pinyin_field = Some(7);
+
+        assert_eq!(
+            tokens_with_flags(&mut tokenizer, "北京", FTS5_TOKENIZE_DOCUMENT),
+            [("北京", 0), ("beijing", FTS5_TOKEN_COLOCATED)]
+                .map(|(token, flags)| (token.to_owned(), flags))
+        );
+        assert_eq!(
+            tokens_with_flags(&mut tokenizer, "北京", FTS5_TOKENIZE_QUERY),
+            [("北京".to_owned(), 0)]
+        );
+    }
+
+    #[test]
+    fn it_folds_kana() {
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        tokenizer.kana = Kana::Katakana;
+        tokenizer.keep_original = true;
+        let mut tokenize = |input, flags| tokens_with_flags(&mut tokenizer, input, flags);
+
+        assert_eq!(
+            tokenize("りんご", FTS5_TOKENIZE_DOCUMENT),
+            [("リンゴ", 0), ("りんご", FTS5_TOKEN_COLOCATED)]
+                .map(|(token, flags)| (token.to_owned(), flags))
+        );
+        assert_eq!(
+            tokenize("りんご", FTS5_TOKENIZE_QUERY),
+            [("リンゴ".to_owned(), 0)]
+        );
+        assert_eq!(
+            tokenize("リンゴ", FTS5_TOKENIZE_DOCUMENT),
+            [("リンゴ".to_owned(), 0)]
+        );
+    }
+
+    #[test]
+    fn it_strips_korean_particles() {
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        let mut tokenize = |josa, input, flags| {
+            tokenizer.josa = josa;
+            tokens_with_flags(&mut tokenizer, input, flags)
+        };
+
+        assert_eq!(
+            tokenize(Josa::Strip, "서울에서", FTS5_TOKENIZE_DOCUMENT),
+            [("서울".to_owned(), 0)]
+        );
+        assert_eq!(
+            tokenize(Josa::Strip, "사전을", FTS5_TOKENIZE_QUERY),
+            [("사전".to_owned(), 0)]
+        );
+        assert_eq!(
+            tokenize(Josa::Colocate, "서울에서", FTS5_TOKENIZE_DOCUMENT),
+            [("서울에서", 0), ("서울", FTS5_TOKEN_COLOCATED)]
+                .map(|(token, flags)| (token.to_owned(), flags))
+        );
+        assert_eq!(
+            tokenize(Josa::Colocate, "서울에서", FTS5_TOKENIZE_QUERY),
+            [("서울에서".to_owned(), 0)]
+        );
+        assert_eq!(
+            tokenize(Josa::Keep, "서울에서", FTS5_TOKENIZE_DOCUMENT),
+            [("서울에서".to_owned(), 0)]
+        );
+    }
+
+    #[test]
+    fn it_converts_chinese_variants() {
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        tokenizer.chinese_variants = Some(std::sync::Arc::new(ChineseVariants::from_dictionary(
+            "資\t资\n庫\t库\n",
+        )));
+        let mut tokenize = |input, flags| token_texts(&mut tokenizer, input, flags);
+
+        // Either script gives the same tokens.
+        assert_eq!(
+            tokenize("資料庫", FTS5_TOKENIZE_DOCUMENT),
+            tokenize("资料库", FTS5_TOKENIZE_QUERY)
+        );
+        assert_eq!(tokenize("資料庫", FTS5_TOKENIZE_QUERY), ["资", "料", "库"]);
+    }
+
+    #[test]
+    fn it_leaves_out_stopwords() {
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        tokenizer.stopwords = Some(std::sync::Arc::new(Stopwords::from_words(&["の", "辞書"])));
+        let mut tokenize = |input, flags| token_texts(&mut tokenizer, input, flags);
+
+        assert_eq!(
+            tokenize("形態素解析の辞書", FTS5_TOKENIZE_DOCUMENT),
+            ["形態素", "解析"]
+        );
+        assert_eq!(tokenize("辞書の解析", FTS5_TOKENIZE_QUERY), ["解析"]);
+        // The last token of a prefix query may be the start of another word.
+        assert_eq!(
+            tokenize("解析の辞書", FTS5_TOKENIZE_QUERY | FTS5_TOKENIZE_PREFIX),
+            ["解析", "辞書"]
+        );
+    }
+
+    #[test]
+    fn it_emits_compound_words_with_their_parts() {
+        let input = "関西国際空港";
+        let mut tokenizer = Fts5Tokenizer::new(
+            load_tokenizer_from_yaml("segmenter:\n  dictionary:\n    kind: \"ipadic\"\n").unwrap(),
+        );
+        tokenizer.hybrid = true;
+
+        assert_eq!(
+            tokens_with_flags(&mut tokenizer, input, FTS5_TOKENIZE_DOCUMENT),
+            [
+                ("関西国際空港", 0),
+                ("関西", FTS5_TOKEN_COLOCATED),
+                ("国際", FTS5_TOKEN_COLOCATED),
+                ("空港", FTS5_TOKEN_COLOCATED),
+            ]
+            .map(|(token, flags)| (token.to_owned(), flags))
+        );
+        assert_eq!(
+            tokens_with_flags(&mut tokenizer, input, FTS5_TOKENIZE_QUERY),
+            [("関西国際空港".to_owned(), 0)]
+        );
+        assert!(!tokenizer.tokenizer.segmenter.mode.is_search());
+    }
+
+    #[test]
+    fn it_ignores_invalid_utf8() {
+        let input = b"\xc3\x28";
+        let mut tokens: Vec<(String, c_int, c_int)> = vec![];
+
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        assert_eq!(
+            unsafe {
+                lindera_fts5_tokenize_internal(
+                    &mut tokenizer,
+                    &mut tokens as *mut _ as *mut c_void,
+                    0,
+                    input.as_ptr() as *const c_char,
+                    input.len() as i32,
+                    token_callback,
+                )
+            }
             .expect_err("tokenize internal should not fail"),
             SQLITE_OK
         );
 
         assert_eq!(tokens, []);
     }
+
+    #[test]
+    fn it_accepts_empty_null_text() {
+        let mut tokens: Vec<(String, c_int, c_int)> = vec![];
+
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        assert_eq!(
+            lindera_fts5_tokenize(
+                &mut tokenizer,
+                &mut tokens as *mut _ as *mut c_void,
+                0,
+                core::ptr::null(),
+                0,
+                token_callback,
+            ),
+            SQLITE_OK
+        );
+
+        assert_eq!(tokens, []);
+    }
+
+    #[test]
+    fn it_keeps_emitted_tokens_intact_until_tokenization_ends() {
+        // Remembers each token's pointer alongside a copy of its bytes, and
+        // checks at every call that the earlier tokens are unchanged.
+        extern "C" fn check_earlier_tokens(
+            ctx: *mut c_void,
+            _flags: c_int,
+            token: *const c_char,
+            token_len: c_int,
+            _start: c_int,
+            _end: c_int,
+        ) -> c_int {
+            let seen = unsafe { &mut *(ctx as *mut Vec<(*const c_uchar, Vec<u8>)>) };
+            for (ptr, bytes) in seen.iter() {
+                let current = unsafe { core::slice::from_raw_parts(*ptr, bytes.len()) };
+                assert_eq!(current, bytes.as_slice());
+            }
+            let token = token as *const c_uchar;
+            let bytes = unsafe { core::slice::from_raw_parts(token, token_len as usize) };
+            seen.push((token, bytes.to_vec()));
+
+            SQLITE_OK
+        }
+
+        let input = "Ｌｉｎｄｅｒａは形態素解析ｴﾝｼﾞﾝです。ユーザー辞書も利用可能です。";
+        let mut seen: Vec<(*const c_uchar, Vec<u8>)> = vec![];
+
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        unsafe {
+            lindera_fts5_tokenize_internal(
+                &mut tokenizer,
+                &mut seen as *mut _ as *mut c_void,
+                0,
+                input.as_ptr() as *const c_char,
+                input.len() as c_int,
+                check_earlier_tokens,
+            )
+        }
+        .expect("tokenize internal should not fail");
+
+        assert_eq!(seen.len(), 8);
+    }
+
+    #[test]
+    fn it_stops_when_callback_is_done() {
+        extern "C" fn first_token(
+            ctx: *mut c_void,
+            flags: c_int,
+            token: *const c_char,
+            token_len: c_int,
+            start: c_int,
+            end: c_int,
+        ) -> c_int {
+            token_callback(ctx, flags, token, token_len, start, end);
+            SQLITE_DONE
+        }
+
+        let input = "形態素解析";
+        let mut tokens: Vec<(String, c_int, c_int)> = vec![];
+
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+        assert_eq!(
+            lindera_fts5_tokenize(
+                &mut tokenizer,
+                &mut tokens as *mut _ as *mut c_void,
+                0,
+                input.as_ptr() as *const c_char,
+                input.len() as c_int,
+                first_token,
+            ),
+            SQLITE_OK
+        );
+
+        assert_eq!(tokens, [("形態素".to_owned(), 0, 9)]);
+    }
 }
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use crate::args::{DetailFields, EmissionOptions, Language, TokenizerArgs};
+use crate::cache::TokenizerCache;
+use crate::common::{report_warning, Fts5Tokenizer};
+use crate::error::LinderaSqliteError;
+use crate::reload::{reload_enabled, TokenizerSource};
+
+/// The tokenizers of a table for the locales of its rows, which FTS5 passes
+/// through the v2 tokenizer API, each built on first use.
+pub(crate) struct LocaleTokenizers {
+    /// The language of the table's own dictionary, whose rows the table's
+    /// tokenizer handles.
+    language: Option<Language>,
+    /// The table's arguments that apply whatever the dictionary.
+    args: TokenizerArgs,
+    /// `None` for languages whose tokenizer failed to build, so that rows in
+    /// them fall back to the table's tokenizer without retrying.
+    tokenizers: HashMap<Language, Option<Box<Fts5Tokenizer>>>,
+}
+
+impl LocaleTokenizers {
+    pub(crate) fn new(args: &TokenizerArgs, language: Option<Language>) -> Self {
+        LocaleTokenizers {
+            language,
+            args: TokenizerArgs {
+                mode: args.mode,
+                lowercase: args.lowercase,
+                normalize: args.normalize,
+                ..TokenizerArgs::default()
+            },
+            tokenizers: HashMap::new(),
+        }
+    }
+
+    /// The arguments for rows in `language`: the embedded dictionary's
+    /// default settings, as the table's configuration is written for its
+    /// own dictionary.
+    fn args(&self, language: Language) -> TokenizerArgs {
+        TokenizerArgs {
+            embedded_dictionary: Some(language.embedded_dictionary()),
+            ..self.args.clone()
+        }
+    }
+}
+
+/// The language of a locale such as `ja`, `ko-KR` or `zh_TW`.
+pub(crate) fn language(locale: &str) -> Option<Language> {
+    let language = locale.split(['-', '_']).next()?;
+    match language.to_ascii_lowercase().as_str() {
+        "ja" => Some(Language::Japanese),
+        "ko" => Some(Language::Korean),
+        "zh" => Some(Language::Chinese),
+        _ => None,
+    }
+}
+
+/// The language of `text` by the scripts it is written in: Japanese if it
+/// has kana, unless it has more hangul, Korean for hangul, and Chinese for
+/// han characters alone. `None` for text in none of these scripts.
+pub(crate) fn detect_language(text: &str) -> Option<Language> {
+    let (mut kana, mut hangul, mut han) = (0usize, 0usize, 0usize);
+    for c in text.chars() {
+        match c {
+            '\u{3040}'..='\u{30ff}' | '\u{31f0}'..='\u{31ff}' | '\u{ff66}'..='\u{ff9f}' => {
+                kana += 1
+            }
+            '\u{1100}'..='\u{11ff}' | '\u{3130}'..='\u{318f}' | '\u{ac00}'..='\u{d7af}' => {
+                hangul += 1
+            }
+            '\u{3400}'..='\u{4dbf}'
+            | '\u{4e00}'..='\u{9fff}'
+            | '\u{f900}'..='\u{faff}'
+            | '\u{20000}'..='\u{3134f}' => han += 1,
+            _ => {}
+        }
+    }
+
+    if hangul > kana {
+        Some(Language::Korean)
+    } else if kana > 0 {
+        Some(Language::Japanese)
+    } else if han > 0 {
+        Some(Language::Chinese)
+    } else {
+        None
+    }
+}
+
+impl Fts5Tokenizer {
+    /// The tokenizer for rows in `locale`: one with the embedded dictionary
+    /// of its language, or this one for other locales, for the table's own
+    /// language, or if that dictionary is not compiled in.
+    pub(crate) fn for_locale(&mut self, locale: &str) -> &mut Fts5Tokenizer {
+        let Some(language) = language(locale) else {
+            return self;
+        };
+        let Some(locales) = &self.locales else {
+            return self;
+        };
+        if locales.language == Some(language) {
+            return self;
+        }
+
+        if !locales.tokenizers.contains_key(&language) {
+            let tokenizer = self
+                .with_args(&locales.args(language))
+                .map_err(|e| {
+                    report_warning(
+                        e.code(),
+                        format_args!(
+                            "Failed to create tokenizer for locale {:?}, using the table's: {}",
+                            locale, e
+                        ),
+                    )
+                })
+                .ok()
+                .map(Box::new);
+            if let Some(locales) = &mut self.locales {
+                locales.tokenizers.insert(language, tokenizer);
+            }
+        }
+
+        let built = self
+            .locales
+            .as_ref()
+            .and_then(|locales| locales.tokenizers.get(&language))
+            .is_some_and(Option::is_some);
+        if !built {
+            return self;
+        }
+        self.locales
+            .as_mut()
+            .and_then(|locales| locales.tokenizers.get_mut(&language))
+            .and_then(Option::as_deref_mut)
+            .expect("the locale's tokenizer was just looked up")
+    }
+
+    /// Builds a tokenizer like this one from `args`.
+    fn with_args(&self, args: &TokenizerArgs) -> Result<Fts5Tokenizer, LinderaSqliteError> {
+        // The settings this tokenizer resolved stay with its locale
+        // tokenizers, which the table's reload rebuilds.
+        let options = EmissionOptions {
+            base_form: Some(self.base_form),
+            josa: Some(self.josa),
+            kana: Some(self.kana),
+            ..EmissionOptions::default()
+        };
+        let source = TokenizerSource::new(args, &options, reload_enabled());
+        let pipelines = TokenizerCache::global().tokenizer(args)?;
+        let fields = DetailFields::of(pipelines.settings.dictionary_kind);
+        Ok(Fts5Tokenizer {
+            tokenizer: pipelines.index,
+            query_tokenizer: pipelines.query,
+            decompose_tokenizer: pipelines.decompose,
+            synonyms: pipelines.synonyms,
+            stopwords: pipelines.stopwords,
+            chinese_variants: pipelines.chinese_variants,
+            ascii_fast_path: self.ascii_fast_path,
+            keep_original: self.keep_original,
+            hybrid: self.hybrid,
+            base_form: self.base_form,
+            base_form_field: fields.base_form,
+            josa: self.josa,
+            kana: self.kana,
+            long_vowel: self.long_vowel,
+            small_kana: self.small_kana,
+            unicode61: self.unicode61.clone(),
+            jamo: self.jamo,
+            pinyin: self.pinyin,
+            pinyin_field: fields.pinyin,
+            source: Some(source),
+            locales: None,
+            stats: self.stats.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_maps_locales_to_languages() {
+        assert_eq!(language("ja"), Some(Language::Japanese));
+        assert_eq!(language("ko-KR"), Some(Language::Korean));
+        assert_eq!(language("zh_TW"), Some(Language::Chinese));
+        assert_eq!(language("JA"), Some(Language::Japanese));
+        assert_eq!(language("en"), None);
+        assert_eq!(language(""), None);
+    }
+
+    #[test]
+    fn it_detects_languages_by_script() {
+        assert_eq!(
+            detect_language("関西国際空港の辞書"),
+            Some(Language::Japanese)
+        );
+        assert_eq!(detect_language("ｶﾀｶﾅ"), Some(Language::Japanese));
+        assert_eq!(
+            detect_language("한국어 형태소 분석기"),
+            Some(Language::Korean)
+        );
+        assert_eq!(detect_language("大韓民國의 憲法"), Some(Language::Korean));
+        assert_eq!(detect_language("中文分词"), Some(Language::Chinese));
+        assert_eq!(detect_language("SQLite 3"), None);
+        assert_eq!(detect_language(""), None);
+    }
+
+    #[cfg(feature = "ipadic")]
+    fn ipadic_table() -> Fts5Tokenizer {
+        let args = TokenizerArgs {
+            embedded_dictionary: Some(crate::args::EmbeddedDictionary::Ipadic),
+            ..TokenizerArgs::default()
+        };
+        let pipelines = TokenizerCache::global().tokenizer(&args).unwrap();
+        let mut tokenizer = Fts5Tokenizer::new(pipelines.index);
+        tokenizer.locales = Some(LocaleTokenizers::new(&args, pipelines.settings.language()));
+        tokenizer
+    }
+
+    #[cfg(feature = "ipadic")]
+    #[test]
+    fn it_keeps_the_table_tokenizer_for_its_own_language() {
+        let mut tokenizer = ipadic_table();
+        let table: *const Fts5Tokenizer = &tokenizer;
+
+        assert!(core::ptr::eq(tokenizer.for_locale("ja-JP"), table));
+        assert!(core::ptr::eq(tokenizer.for_locale("en"), table));
+        assert!(core::ptr::eq(tokenizer.for_locale(""), table));
+    }
+
+    #[cfg(all(feature = "ipadic", not(feature = "ko-dic")))]
+    #[test]
+    fn it_falls_back_to_the_table_tokenizer_without_the_dictionary() {
+        let mut tokenizer = ipadic_table();
+        let table: *const Fts5Tokenizer = &tokenizer;
+
+        assert!(core::ptr::eq(tokenizer.for_locale("ko"), table));
+        let locales = tokenizer.locales.as_ref().unwrap();
+        assert!(locales.tokenizers[&Language::Korean].is_none());
+    }
+}
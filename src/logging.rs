@@ -0,0 +1,103 @@
+use std::env;
+use std::sync::OnceLock;
+
+/// How much the crate writes to stderr, from `LINDERA_LOG_LEVEL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum LogLevel {
+    None,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" | "off" => Some(LogLevel::None),
+            "error" => Some(LogLevel::Error),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+}
+
+static LOG_LEVEL: OnceLock<LogLevel> = OnceLock::new();
+
+/// The level set by `LINDERA_LOG_LEVEL`, `warn` if unset or unrecognized.
+///
+/// The variable is read once so that tokenizing never looks it up.
+pub(crate) fn log_level() -> LogLevel {
+    *LOG_LEVEL.get_or_init(|| {
+        env::var("LINDERA_LOG_LEVEL")
+            .ok()
+            .and_then(|value| LogLevel::parse(&value))
+            .unwrap_or(LogLevel::Warn)
+    })
+}
+
+pub(crate) fn enabled(level: LogLevel) -> bool {
+    level <= log_level()
+}
+
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::LogLevel::Error) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+#[cfg_attr(not(feature = "extension"), allow(unused_macros))]
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::LogLevel::Warn) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+#[cfg_attr(not(feature = "extension"), allow(unused_macros))]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::LogLevel::Info) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::LogLevel::Debug) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use {log_debug, log_error};
+#[cfg(feature = "extension")]
+pub(crate) use {log_info, log_warn};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_log_levels() {
+        assert_eq!(LogLevel::parse("none"), Some(LogLevel::None));
+        assert_eq!(LogLevel::parse("error"), Some(LogLevel::Error));
+        assert_eq!(LogLevel::parse("WARN"), Some(LogLevel::Warn));
+        assert_eq!(LogLevel::parse(" info "), Some(LogLevel::Info));
+        assert_eq!(LogLevel::parse("debug"), Some(LogLevel::Debug));
+        assert_eq!(LogLevel::parse("verbose"), None);
+    }
+
+    #[test]
+    fn it_orders_log_levels_by_verbosity() {
+        assert!(LogLevel::None < LogLevel::Error);
+        assert!(LogLevel::Warn < LogLevel::Info);
+        assert!(LogLevel::Info < LogLevel::Debug);
+    }
+}
@@ -0,0 +1,176 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+use std::alloc::System;
+
+/// Wraps an allocator to measure how much memory each thread has allocated
+/// at its peak while tokenizing.
+///
+/// Only allocations made inside [`TrackingScope`] are counted, so the
+/// overhead elsewhere is a thread-local check per call.
+///
+/// The library does not install it: an application that wants
+/// [`lindera_peak_memory_bytes`] makes it its `#[global_allocator]`, as the
+/// `memory-stats-allocator` feature does for the loadable extension.
+pub struct MemoryTracker<A = System> {
+    inner: A,
+}
+
+impl MemoryTracker<System> {
+    pub const fn new() -> Self {
+        MemoryTracker { inner: System }
+    }
+}
+
+impl Default for MemoryTracker<System> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+thread_local! {
+    static TRACKING: Cell<bool> = const { Cell::new(false) };
+    static CURRENT: Cell<usize> = const { Cell::new(0) };
+    static PEAK: Cell<usize> = const { Cell::new(0) };
+    static LAST_PEAK: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record_alloc(size: usize) {
+    // `try_with` because the allocator may run while thread-locals are torn
+    // down.
+    let _ = TRACKING.try_with(|tracking| {
+        if tracking.get() {
+            let current = CURRENT.with(|current| {
+                current.set(current.get() + size);
+                current.get()
+            });
+            PEAK.with(|peak| peak.set(peak.get().max(current)));
+        }
+    });
+}
+
+fn record_dealloc(size: usize) {
+    let _ = TRACKING.try_with(|tracking| {
+        if tracking.get() {
+            // Memory allocated before the scope may be freed inside it.
+            CURRENT.with(|current| current.set(current.get().saturating_sub(size)));
+        }
+    });
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for MemoryTracker<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record_alloc(new_size);
+            record_dealloc(layout.size());
+        }
+        new_ptr
+    }
+}
+
+/// Counts allocations on the current thread until dropped, then keeps the
+/// peak for [`lindera_peak_memory_bytes`].
+pub(crate) struct TrackingScope {}
+
+impl TrackingScope {
+    pub(crate) fn start() -> Self {
+        CURRENT.with(|current| current.set(0));
+        PEAK.with(|peak| peak.set(0));
+        TRACKING.with(|tracking| tracking.set(true));
+        TrackingScope {}
+    }
+}
+
+impl Drop for TrackingScope {
+    fn drop(&mut self) {
+        TRACKING.with(|tracking| tracking.set(false));
+        LAST_PEAK.with(|last_peak| last_peak.set(PEAK.with(Cell::get)));
+    }
+}
+
+/// Returns the most memory allocated at once during the last
+/// `lindera_fts5_tokenize` call on the current thread, in bytes.
+#[no_mangle]
+pub extern "C" fn lindera_peak_memory_bytes() -> usize {
+    LAST_PEAK.with(Cell::get)
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::{c_char, c_int, c_void};
+
+    use super::*;
+    use crate::{lindera_fts5_tokenize, mock_tokenizer, Fts5Tokenizer, SQLITE_OK};
+
+    extern "C" fn ignore_token(
+        _ctx: *mut c_void,
+        _flags: c_int,
+        _token: *const c_char,
+        _token_len: c_int,
+        _start: c_int,
+        _end: c_int,
+    ) -> c_int {
+        SQLITE_OK
+    }
+
+    fn tokenize(tokenizer: &mut Fts5Tokenizer, input: &str) {
+        lindera_fts5_tokenize(
+            tokenizer,
+            core::ptr::null_mut(),
+            0,
+            input.as_ptr() as *const c_char,
+            input.len() as c_int,
+            ignore_token,
+        );
+    }
+
+    #[test]
+    fn it_reports_peak_memory_of_last_call() {
+        let mut tokenizer = Fts5Tokenizer::new(mock_tokenizer());
+
+        tokenize(&mut tokenizer, "形態素解析");
+        let small = lindera_peak_memory_bytes();
+        tokenize(&mut tokenizer, &"形態素解析".repeat(1000));
+        let large = lindera_peak_memory_bytes();
+
+        assert!(small > 0);
+        assert!(large > small);
+    }
+
+    #[test]
+    fn it_frees_tokenizers_on_drop() {
+        let tokenizer = mock_tokenizer();
+
+        // Allocations the scope sees and frees cancel out, so anything left
+        // is memory a dropped tokenizer still holds.
+        let _scope = TrackingScope::start();
+        for _ in 0..10 {
+            let mut fts5_tokenizer = Fts5Tokenizer::new(tokenizer.clone());
+            fts5_tokenizer.ascii_fast_path = true;
+            drop(fts5_tokenizer);
+        }
+        assert!(PEAK.with(Cell::get) > 0);
+        assert_eq!(CURRENT.with(Cell::get), 0);
+    }
+}
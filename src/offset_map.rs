@@ -0,0 +1,186 @@
+/// Maps byte offsets in character-filtered text back to the input text.
+///
+/// Each character filter reports where it changed the length of the text.
+/// Recording those changes in application order lets token offsets computed
+/// on the filtered text be translated back, one filter at a time.
+#[derive(Debug, Clone, Default)]
+pub struct OffsetMap {
+    layers: Vec<Layer>,
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    /// `(original_pos, filtered_pos)` pairs sorted by `filtered_pos`. Filtered
+    /// offsets from `filtered_pos` up to the next pair keep the same shift.
+    points: Vec<(usize, usize)>,
+    /// Length of the text before the filter was applied.
+    original_len: usize,
+}
+
+impl Layer {
+    fn original_offset(&self, filtered_offset: usize) -> usize {
+        let index = self
+            .points
+            .partition_point(|&(_, filtered_pos)| filtered_pos <= filtered_offset);
+        let offset = match index.checked_sub(1) {
+            Some(i) => {
+                let (original_pos, filtered_pos) = self.points[i];
+                original_pos + (filtered_offset - filtered_pos)
+            }
+            None => filtered_offset,
+        };
+        offset.min(self.original_len)
+    }
+}
+
+impl OffsetMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the `offsets` and `diffs` returned by a character filter that
+    /// was applied to a text of `original_len` bytes.
+    pub fn record(&mut self, offsets: &[usize], diffs: &[i64], original_len: usize) {
+        if offsets.is_empty() {
+            return;
+        }
+
+        let points = offsets
+            .iter()
+            .zip(diffs)
+            .map(|(&filtered_pos, &diff)| ((filtered_pos as i64 + diff) as usize, filtered_pos))
+            .collect();
+
+        self.layers.push(Layer {
+            points,
+            original_len,
+        });
+    }
+
+    /// Translates an offset in the fully filtered text into an offset in the
+    /// text before any filter was applied.
+    pub fn original_offset(&self, filtered_offset: usize) -> usize {
+        self.layers
+            .iter()
+            .rev()
+            .fold(filtered_offset, |offset, layer| {
+                layer.original_offset(offset)
+            })
+    }
+
+    /// Translates the byte range of a token in the fully filtered text into
+    /// the range of `input`, the text before any filter was applied, that it
+    /// was made from.
+    ///
+    /// A filter that replaces text, as NFKC does `㍿` with `株式会社`, only
+    /// tells where the replacement ends, so a token that starts or ends
+    /// within it is widened to the characters of `input` it covers. The
+    /// range always falls on character boundaries of `input`.
+    pub fn original_range(
+        &self,
+        input: &str,
+        filtered_start: usize,
+        filtered_end: usize,
+    ) -> (usize, usize) {
+        let mut start = self.original_offset(filtered_start).min(input.len());
+        while !input.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut end = self.original_offset(filtered_end).clamp(start, input.len());
+        while !input.is_char_boundary(end) {
+            end += 1;
+        }
+        (start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lindera::character_filter::CharacterFilterLoader;
+    use serde_json::json;
+
+    use super::*;
+
+    fn apply(filters: &[(&str, serde_json::Value)], input: &str) -> (String, OffsetMap) {
+        let mut text = input.to_string();
+        let mut offset_map = OffsetMap::new();
+        for (kind, args) in filters {
+            let filter = CharacterFilterLoader::load_from_value(kind, args).unwrap();
+            let original_len = text.len();
+            let (offsets, diffs, _) = filter.apply(&mut text).unwrap();
+            offset_map.record(&offsets, &diffs, original_len);
+        }
+        (text, offset_map)
+    }
+
+    #[test]
+    fn it_maps_offsets_through_width_changing_mapping() {
+        let input = "ﾘﾝﾃﾞﾗは辞書";
+        let (text, offset_map) = apply(
+            &[("mapping", json!({ "mapping": { "ﾘﾝﾃﾞﾗ": "Lindera" } }))],
+            input,
+        );
+        assert_eq!(text, "Linderaは辞書");
+
+        // "Lindera" (7 bytes) replaced "ﾘﾝﾃﾞﾗ" (15 bytes).
+        assert_eq!(offset_map.original_offset(0), 0);
+        assert_eq!(offset_map.original_offset(7), 15);
+        assert_eq!(&input[offset_map.original_offset(7)..], "は辞書");
+        assert_eq!(offset_map.original_offset(10), 18);
+        assert_eq!(offset_map.original_offset(text.len()), input.len());
+    }
+
+    #[test]
+    fn it_maps_offsets_through_multiple_filters() {
+        let input = "ﾘﾝﾃﾞﾗ辞書";
+        let (text, offset_map) = apply(
+            &[
+                ("unicode_normalize", json!({ "kind": "nfkc" })),
+                ("mapping", json!({ "mapping": { "リンデラ": "Lindera" } })),
+            ],
+            input,
+        );
+        assert_eq!(text, "Lindera辞書");
+
+        assert_eq!(offset_map.original_offset(0), 0);
+        assert_eq!(&input[offset_map.original_offset(7)..], "辞書");
+        assert_eq!(offset_map.original_offset(text.len()), input.len());
+    }
+
+    #[test]
+    fn it_keeps_offsets_without_filters() {
+        let offset_map = OffsetMap::new();
+        assert_eq!(offset_map.original_offset(42), 42);
+    }
+
+    #[test]
+    fn it_widens_tokens_within_a_replacement() {
+        let input = "x㍿y";
+        let (text, offset_map) = apply(&[("unicode_normalize", json!({ "kind": "nfkc" }))], input);
+        assert_eq!(text, "x株式会社y");
+
+        // 株式 and 会社 both come from ㍿.
+        assert_eq!(offset_map.original_range(input, 1, 7), (1, 4));
+        assert_eq!(offset_map.original_range(input, 7, 13), (1, 4));
+        assert_eq!(offset_map.original_range(input, 13, 14), (4, 5));
+    }
+
+    #[test]
+    fn it_keeps_ranges_on_character_boundaries() {
+        let input = "xﾘﾝﾃﾞﾗy";
+        let (text, offset_map) = apply(
+            &[("mapping", json!({ "mapping": { "ﾘﾝﾃﾞﾗ": "Lindera" } }))],
+            input,
+        );
+        assert_eq!(text, "xLinderay");
+
+        for start in 0..text.len() {
+            for end in start..=text.len() {
+                let (original_start, original_end) = offset_map.original_range(input, start, end);
+                assert!(original_start <= original_end);
+                assert!(input.get(original_start..original_end).is_some());
+            }
+        }
+        assert_eq!(offset_map.original_range(input, 1, 8), (1, 16));
+    }
+}
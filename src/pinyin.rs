@@ -0,0 +1,46 @@
+//! Pinyin for Chinese tokens, which the `pinyin` flag indexes next to them
+//! so that `beijing` finds `北京`.
+//!
+//! CC-CEDICT gives the pinyin of its words with tone numbers, one syllable
+//! at a time, as in `Bei3 jing1`. It is indexed the way pinyin is typed: in
+//! lower case, without tones or spaces, and with `v` for `ü`, which the
+//! dictionary writes `u:`.
+
+/// The toneless pinyin of `syllables` as CC-CEDICT writes them, or `None`
+/// if they are not all pinyin syllables.
+pub(crate) fn pinyin(syllables: &str) -> Option<String> {
+    let mut pinyin = String::with_capacity(syllables.len());
+    for syllable in syllables.split_whitespace() {
+        let letters = syllable.trim_end_matches(|c: char| ('1'..='5').contains(&c));
+        let letters = letters.replace("u:", "v");
+        if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        pinyin.push_str(&letters.to_ascii_lowercase());
+    }
+    (!pinyin.is_empty()).then_some(pinyin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_writes_pinyin_as_typed() {
+        assert_eq!(pinyin("Bei3 jing1").as_deref(), Some("beijing"));
+        assert_eq!(pinyin("nu:3 er2").as_deref(), Some("nver"));
+        assert_eq!(pinyin("hua4 r5").as_deref(), Some("huar"));
+        assert_eq!(
+            pinyin("Yu3 tian2 ji1 chang3").as_deref(),
+            Some("yutianjichang")
+        );
+    }
+
+    #[test]
+    fn it_leaves_other_details_aside() {
+        assert_eq!(pinyin("*"), None);
+        assert_eq!(pinyin(""), None);
+        assert_eq!(pinyin("ジショ"), None);
+        assert_eq!(pinyin("bei3 ，"), None);
+    }
+}
@@ -0,0 +1,331 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::args::{DetailFields, EmissionOptions, TokenizerArgs};
+use crate::cache::TokenizerCache;
+use crate::common::{report_warning, Fts5Tokenizer};
+use crate::locale::LocaleTokenizers;
+
+/// How often a tokenizer looks at its configuration file at most, so that
+/// tokenizing does not stat the file on every call.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether `LINDERA_CONFIG_RELOAD` asks for tokenizers to follow changes to
+/// their configuration file.
+pub(crate) fn reload_enabled() -> bool {
+    matches!(
+        env::var("LINDERA_CONFIG_RELOAD").as_deref(),
+        Ok("1" | "true")
+    )
+}
+
+/// Makes every live tokenizer rebuild itself from its configuration, and
+/// reload its dictionaries, the next time it is used.
+pub(crate) fn reload_all() {
+    TokenizerCache::global().invalidate();
+}
+
+/// What a tokenizer was built from, so that it can be built again.
+pub(crate) struct TokenizerSource {
+    args: TokenizerArgs,
+    /// The options of the arguments, which take the place of the settings of
+    /// the configuration they give.
+    options: EmissionOptions,
+    /// The cache's generation when the tokenizer was built.
+    generation: u64,
+    /// The configuration file to follow, with `LINDERA_CONFIG_RELOAD` set.
+    file: Option<WatchedFile>,
+}
+
+/// A configuration file as of when a tokenizer was built from it.
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// When the file was last looked at, `None` to look on the next call.
+    checked: Option<Instant>,
+}
+
+impl TokenizerSource {
+    /// Records `args` and `options` before a tokenizer is built from them,
+    /// following the file `args` name if `watch_file` is set.
+    pub(crate) fn new(args: &TokenizerArgs, options: &EmissionOptions, watch_file: bool) -> Self {
+        TokenizerSource {
+            args: args.clone(),
+            options: options.clone(),
+            generation: TokenizerCache::global().generation(),
+            file: watch_file
+                .then(|| config_file(args))
+                .flatten()
+                .map(WatchedFile::new),
+        }
+    }
+}
+
+/// The file `args` are built from: their `config_path`, or else
+/// `LINDERA_CONFIG_PATH` when they name no other configuration.
+fn config_file(args: &TokenizerArgs) -> Option<PathBuf> {
+    match &args.config_path {
+        Some(path) => Some(path.clone()),
+        None if args.database_config.is_none() && args.embedded_dictionary.is_none() => {
+            env::var_os("LINDERA_CONFIG_PATH").map(PathBuf::from)
+        }
+        None => None,
+    }
+}
+
+impl WatchedFile {
+    fn new(path: PathBuf) -> Self {
+        WatchedFile {
+            modified: modified(&path),
+            path,
+            checked: Some(Instant::now()),
+        }
+    }
+
+    /// The file's new modification time if it changed since the tokenizer
+    /// was last built from it, looking at most once per [`CHECK_INTERVAL`].
+    /// It is only recorded once the tokenizer is rebuilt, so that a file that
+    /// fails to load is tried again.
+    fn changed(&mut self) -> Option<SystemTime> {
+        if self
+            .checked
+            .is_some_and(|checked| checked.elapsed() < CHECK_INTERVAL)
+        {
+            return None;
+        }
+        self.checked = Some(Instant::now());
+
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        modified
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl Fts5Tokenizer {
+    /// Rebuilds the tokenizer after [`reload_all`] or, if it follows its
+    /// configuration file, after the file changed. A configuration that
+    /// fails to load leaves the tokenizer as it was.
+    pub(crate) fn reload_if_changed(&mut self) {
+        let Some(source) = &mut self.source else {
+            return;
+        };
+
+        let generation = TokenizerCache::global().generation();
+        let tokenizer = if generation != source.generation {
+            source.generation = generation;
+            if let Some(file) = &mut source.file {
+                *file = WatchedFile::new(file.path.clone());
+            }
+            TokenizerCache::global().tokenizer(&source.args)
+        } else if let Some(modified) = source.file.as_mut().and_then(WatchedFile::changed) {
+            let tokenizer = TokenizerCache::global().reload(&source.args, Some(modified));
+            if let (Ok(_), Some(file)) = (&tokenizer, &mut source.file) {
+                file.modified = Some(modified);
+            }
+            tokenizer
+        } else {
+            return;
+        };
+
+        match tokenizer {
+            Ok(pipelines) => {
+                // The tokenizers for other locales depend on the language of
+                // the configuration, which may have changed with it.
+                if self.locales.is_some() {
+                    self.locales = Some(LocaleTokenizers::new(
+                        &source.args,
+                        pipelines.settings.language(),
+                    ));
+                }
+                let settings = &pipelines.settings;
+                self.base_form = source.options.base_form.unwrap_or(settings.base_form);
+                self.josa = source.options.josa.unwrap_or(settings.josa);
+                self.kana = source.options.kana.unwrap_or(settings.kana);
+                let fields = DetailFields::of(settings.dictionary_kind);
+                self.base_form_field = fields.base_form;
+                self.pinyin_field = fields.pinyin;
+                self.tokenizer = pipelines.index;
+                self.query_tokenizer = pipelines.query;
+                self.decompose_tokenizer = pipelines.decompose;
+                self.synonyms = pipelines.synonyms;
+                self.stopwords = pipelines.stopwords;
+                self.chinese_variants = pipelines.chinese_variants;
+            }
+            Err(e) => report_warning(
+                e.code(),
+                format_args!(
+                    "Failed to reload tokenizer, keeping the previous one: {}",
+                    e
+                ),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::BaseForm;
+
+    const CONFIG: &str = "segmenter:\n  mode: \"normal\"\n  dictionary:\n    kind: \"ipadic\"\n";
+    const NORMALIZING_CONFIG: &str =
+        "segmenter:\n  mode: \"normal\"\n  dictionary:\n    kind: \"ipadic\"\n\
+         character_filters:\n  - kind: \"unicode_normalize\"\n    args:\n      kind: \"nfkc\"\n";
+
+    fn surfaces(tokenizer: &Fts5Tokenizer, text: &str) -> Vec<String> {
+        tokenizer
+            .tokenizer
+            .tokenize(text)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.text.to_string())
+            .collect()
+    }
+
+    fn write_config(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!(
+            "lindera-sqlite-{}-{}.yml",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, CONFIG).unwrap();
+        path
+    }
+
+    fn tokenizer(config: &Path, watch_file: bool) -> Fts5Tokenizer {
+        let args = TokenizerArgs {
+            config_path: Some(config.to_path_buf()),
+            ..Default::default()
+        };
+        let source = TokenizerSource::new(&args, &EmissionOptions::default(), watch_file);
+        let mut tokenizer =
+            Fts5Tokenizer::new(TokenizerCache::global().tokenizer(&args).unwrap().index);
+        tokenizer.source = Some(source);
+        tokenizer
+    }
+
+    #[test]
+    fn it_reloads_changed_configs() {
+        let config = write_config("reload");
+        let mut tokenizer = tokenizer(&config, true);
+        assert_eq!(surfaces(&tokenizer, "ＬＩＮＤＥＲＡ"), ["ＬＩＮＤＥＲＡ"]);
+
+        fs::write(&config, NORMALIZING_CONFIG).unwrap();
+        // Make the change visible even on file systems with coarse mtimes.
+        fs::File::options()
+            .write(true)
+            .open(&config)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+
+        // Within the interval, the file is not looked at.
+        let file = tokenizer.source.as_mut().unwrap().file.as_mut().unwrap();
+        assert_eq!(file.changed(), None);
+
+        file.checked = None;
+        tokenizer.reload_if_changed();
+        fs::remove_file(&config).unwrap();
+        assert_eq!(surfaces(&tokenizer, "ＬＩＮＤＥＲＡ"), ["LINDERA"]);
+    }
+
+    #[test]
+    fn it_reloads_changed_settings() {
+        let config = write_config("reload-settings");
+        let mut tokenizer = tokenizer(&config, true);
+        assert_eq!(tokenizer.base_form, BaseForm::Off);
+
+        fs::write(&config, format!("{}base_form: replace\n", CONFIG)).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&config)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        let source = tokenizer.source.as_mut().unwrap();
+        source.file.as_mut().unwrap().checked = None;
+        tokenizer.reload_if_changed();
+        assert_eq!(tokenizer.base_form, BaseForm::Replace);
+
+        // A setting given in the arguments stays as it was.
+        let source = tokenizer.source.as_mut().unwrap();
+        source.options.base_form = Some(BaseForm::Colocate);
+        fs::write(&config, format!("{}base_form: off\n", CONFIG)).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&config)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(20))
+            .unwrap();
+        source.file.as_mut().unwrap().checked = None;
+        tokenizer.reload_if_changed();
+        fs::remove_file(&config).unwrap();
+        assert_eq!(tokenizer.base_form, BaseForm::Colocate);
+    }
+
+    #[test]
+    fn it_retries_configs_that_failed_to_load() {
+        let config = write_config("reload-retry");
+        let mut tokenizer = tokenizer(&config, true);
+        let set_modified = |modified: SystemTime| {
+            fs::File::options()
+                .write(true)
+                .open(&config)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+        let modified = SystemTime::now() + Duration::from_secs(10);
+
+        // A file caught while it is being written fails to load.
+        fs::write(&config, "segmenter: [").unwrap();
+        set_modified(modified);
+        tokenizer
+            .source
+            .as_mut()
+            .unwrap()
+            .file
+            .as_mut()
+            .unwrap()
+            .checked = None;
+        tokenizer.reload_if_changed();
+        assert_eq!(surfaces(&tokenizer, "ＬＩＮＤＥＲＡ"), ["ＬＩＮＤＥＲＡ"]);
+
+        // Once complete, it loads even if its modification time is the same.
+        fs::write(&config, NORMALIZING_CONFIG).unwrap();
+        set_modified(modified);
+        tokenizer
+            .source
+            .as_mut()
+            .unwrap()
+            .file
+            .as_mut()
+            .unwrap()
+            .checked = None;
+        tokenizer.reload_if_changed();
+        fs::remove_file(&config).unwrap();
+        assert_eq!(surfaces(&tokenizer, "ＬＩＮＤＥＲＡ"), ["LINDERA"]);
+    }
+
+    #[test]
+    fn it_reloads_all_tokenizers_on_request() {
+        let config = write_config("reload-all");
+        let mut tokenizer = tokenizer(&config, false);
+        fs::write(&config, NORMALIZING_CONFIG).unwrap();
+
+        reload_all();
+        tokenizer.reload_if_changed();
+        fs::remove_file(&config).unwrap();
+        assert_eq!(surfaces(&tokenizer, "ＬＩＮＤＥＲＡ"), ["LINDERA"]);
+    }
+}
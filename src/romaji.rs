@@ -0,0 +1,245 @@
+//! Kana to romaji in the Hepburn system, as `lindera_romanize` writes
+//! readings.
+//!
+//! The result is plain ASCII for use in slugs and search keys: long vowels
+//! are spelled out as the kana spell them (`トウキョウ` is `toukyou`), and
+//! `ー` repeats the vowel before it.
+
+/// The romaji of one katakana, or `None` for anything else.
+fn syllable(kana: char) -> Option<&'static str> {
+    let romaji = match kana {
+        'ア' => "a",
+        'イ' => "i",
+        'ウ' => "u",
+        'エ' => "e",
+        'オ' => "o",
+        'カ' => "ka",
+        'キ' => "ki",
+        'ク' => "ku",
+        'ケ' => "ke",
+        'コ' => "ko",
+        'ガ' => "ga",
+        'ギ' => "gi",
+        'グ' => "gu",
+        'ゲ' => "ge",
+        'ゴ' => "go",
+        'サ' => "sa",
+        'シ' => "shi",
+        'ス' => "su",
+        'セ' => "se",
+        'ソ' => "so",
+        'ザ' => "za",
+        'ジ' => "ji",
+        'ズ' => "zu",
+        'ゼ' => "ze",
+        'ゾ' => "zo",
+        'タ' => "ta",
+        'チ' => "chi",
+        'ツ' => "tsu",
+        'テ' => "te",
+        'ト' => "to",
+        'ダ' => "da",
+        'ヂ' => "ji",
+        'ヅ' => "zu",
+        'デ' => "de",
+        'ド' => "do",
+        'ナ' => "na",
+        'ニ' => "ni",
+        'ヌ' => "nu",
+        'ネ' => "ne",
+        'ノ' => "no",
+        'ハ' => "ha",
+        'ヒ' => "hi",
+        'フ' => "fu",
+        'ヘ' => "he",
+        'ホ' => "ho",
+        'バ' => "ba",
+        'ビ' => "bi",
+        'ブ' => "bu",
+        'ベ' => "be",
+        'ボ' => "bo",
+        'パ' => "pa",
+        'ピ' => "pi",
+        'プ' => "pu",
+        'ペ' => "pe",
+        'ポ' => "po",
+        'マ' => "ma",
+        'ミ' => "mi",
+        'ム' => "mu",
+        'メ' => "me",
+        'モ' => "mo",
+        'ヤ' | 'ャ' => "ya",
+        'ユ' | 'ュ' => "yu",
+        'ヨ' | 'ョ' => "yo",
+        'ラ' => "ra",
+        'リ' => "ri",
+        'ル' => "ru",
+        'レ' => "re",
+        'ロ' => "ro",
+        'ワ' | 'ヮ' => "wa",
+        'ヰ' => "i",
+        'ヱ' => "e",
+        'ヲ' => "o",
+        'ヴ' => "vu",
+        'ァ' => "a",
+        'ィ' => "i",
+        'ゥ' => "u",
+        'ェ' => "e",
+        'ォ' => "o",
+        _ => return None,
+    };
+    Some(romaji)
+}
+
+/// The katakana for `kana` if it is a hiragana.
+fn to_katakana(kana: char) -> char {
+    match kana {
+        'ぁ'..='ゖ' => char::from_u32(kana as u32 + 0x60).unwrap_or(kana),
+        _ => kana,
+    }
+}
+
+fn is_small_y(kana: char) -> bool {
+    matches!(kana, 'ャ' | 'ュ' | 'ョ')
+}
+
+fn is_small_vowel(kana: char) -> bool {
+    matches!(kana, 'ァ' | 'ィ' | 'ゥ' | 'ェ' | 'ォ')
+}
+
+/// `romaji` followed by the small kana after it, such as `キャ` (`kya`),
+/// `シャ` (`sha`), `ファ` (`fa`) or `ティ` (`ti`), or `None` if the two do not
+/// combine.
+fn combine(romaji: &str, small: char) -> Option<String> {
+    let vowel = syllable(small)?.chars().last()?;
+    let (stem, last) = romaji.split_at(romaji.len() - 1);
+    if is_small_y(small) {
+        // Only the i column takes ya, yu and yo; sh, ch and j absorb the y.
+        return match last {
+            "i" if stem.ends_with('h') || stem == "j" => Some(format!("{}{}", stem, vowel)),
+            "i" if !stem.is_empty() => Some(format!("{}y{}", stem, vowel)),
+            _ => None,
+        };
+    }
+    match (stem, last, vowel) {
+        // ウィ, ウェ and ウォ.
+        ("", "u", _) => Some(format!("w{}", vowel)),
+        // イェ.
+        ("", "i", 'e') => Some("ye".to_string()),
+        // ファ, ヴィ, ツァ and the like.
+        (stem, "u", _) => Some(format!("{}{}", stem, vowel)),
+        // チェ, シェ and ジェ.
+        (stem, "i", 'e') if stem.ends_with('h') || stem == "j" => Some(format!("{}e", stem)),
+        // ティ and ディ.
+        ("t" | "d", "e", 'i') => Some(format!("{}i", stem)),
+        // トゥ and ドゥ.
+        ("t" | "d", "o", 'u') => Some(format!("{}u", stem)),
+        _ => None,
+    }
+}
+
+/// Writes the kana of `text` in Hepburn romaji. Anything but hiragana and
+/// katakana is kept, in lower case.
+pub(crate) fn romanize(text: &str) -> String {
+    let kana = text.chars().map(to_katakana).collect::<Vec<_>>();
+    let mut romaji = String::with_capacity(text.len());
+    // A small ッ doubles the consonant after it.
+    let mut sokuon = false;
+
+    let mut i = 0;
+    while i < kana.len() {
+        let c = kana[i];
+        i += 1;
+        match c {
+            'ッ' => {
+                sokuon = true;
+                continue;
+            }
+            'ー' => {
+                if let Some(vowel) = romaji.chars().last().filter(|last| "aiueo".contains(*last)) {
+                    romaji.push(vowel);
+                }
+            }
+            'ン' => {
+                romaji.push('n');
+                // An apostrophe keeps ン apart from a vowel or y after it, as
+                // in `kan'i`.
+                let next = kana.get(i).copied().and_then(syllable);
+                if next.is_some_and(|next| next.starts_with(['a', 'i', 'u', 'e', 'o', 'y'])) {
+                    romaji.push('\'');
+                }
+            }
+            c => match syllable(c) {
+                Some(base) => {
+                    let mut syllable = base.to_string();
+                    if let Some(combined) = kana
+                        .get(i)
+                        .filter(|next| is_small_y(**next) || is_small_vowel(**next))
+                        .and_then(|next| combine(base, *next))
+                    {
+                        syllable = combined;
+                        i += 1;
+                    }
+                    if sokuon {
+                        match syllable.as_bytes()[0] {
+                            b'a' | b'i' | b'u' | b'e' | b'o' => {}
+                            b'c' => romaji.push('t'),
+                            consonant => romaji.push(consonant as char),
+                        }
+                    }
+                    romaji.push_str(&syllable);
+                }
+                None => romaji.extend(c.to_lowercase()),
+            },
+        }
+        sokuon = false;
+    }
+    romaji
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_romanizes_kana() {
+        assert_eq!(romanize("カンサイコクサイクウコウ"), "kansaikokusaikuukou");
+        assert_eq!(romanize("しんぶん"), "shinbun");
+        assert_eq!(romanize("ツチ"), "tsuchi");
+        assert_eq!(romanize("フジ"), "fuji");
+        assert_eq!(romanize("ヲ"), "o");
+    }
+
+    #[test]
+    fn it_combines_small_kana() {
+        assert_eq!(romanize("トウキョウ"), "toukyou");
+        assert_eq!(romanize("シャシン"), "shashin");
+        assert_eq!(romanize("チュウイ"), "chuui");
+        assert_eq!(romanize("ジョウホウ"), "jouhou");
+        assert_eq!(romanize("ファイル"), "fairu");
+        assert_eq!(romanize("パーティー"), "paatii");
+        assert_eq!(romanize("チェック"), "chekku");
+        assert_eq!(romanize("ウィキ"), "wiki");
+        assert_eq!(romanize("ァ"), "a");
+    }
+
+    #[test]
+    fn it_doubles_consonants_after_sokuon() {
+        assert_eq!(romanize("ザッシ"), "zasshi");
+        assert_eq!(romanize("マッチャ"), "matcha");
+        assert_eq!(romanize("ホッ"), "ho");
+    }
+
+    #[test]
+    fn it_separates_n_before_vowels() {
+        assert_eq!(romanize("カンイ"), "kan'i");
+        assert_eq!(romanize("キンヨウ"), "kin'you");
+        assert_eq!(romanize("コンニチハ"), "konnichiha");
+    }
+
+    #[test]
+    fn it_keeps_other_text() {
+        assert_eq!(romanize("SQLiteノジショ"), "sqlitenojisho");
+        assert_eq!(romanize("ラーメン。"), "raamen。");
+    }
+}
@@ -0,0 +1,135 @@
+//! Splitting text into sentences, as `lindera_sentences` does.
+
+/// Characters that end a sentence.
+fn is_terminator(c: char) -> bool {
+    matches!(c, '。' | '！' | '？' | '!' | '?' | '．' | '｡')
+}
+
+/// Brackets and quotes whose text is part of the sentence around them.
+fn is_opening(c: char) -> bool {
+    matches!(
+        c,
+        '「' | '『' | '（' | '(' | '“' | '‘' | '【' | '〈' | '《' | '〔' | '［' | '｢'
+    )
+}
+
+fn is_closing(c: char) -> bool {
+    matches!(
+        c,
+        '」' | '』'
+            | '）'
+            | ')'
+            | '”'
+            | '’'
+            | '】'
+            | '〉'
+            | '》'
+            | '〕'
+            | '］'
+            | '｣'
+            | '"'
+            | '\''
+    )
+}
+
+/// The byte ranges of the sentences of `text`, without the whitespace around
+/// them.
+///
+/// A sentence ends after `。`, `！`, `？` and their ASCII and halfwidth
+/// forms, after a `.` that is followed by whitespace, and at a line break.
+/// Runs of these marks, and the closing quotes and brackets right after
+/// them, stay with the sentence they end, while marks inside quotes or
+/// brackets, as in `「はい。」と言った。`, end no sentence.
+pub(crate) fn sentences(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut depth = 0usize;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let end = if c == '\n' {
+            // Quotes left open do not run past the line.
+            depth = 0;
+            Some(i)
+        } else if is_opening(c) {
+            depth += 1;
+            None
+        } else if is_closing(c) && depth > 0 {
+            depth -= 1;
+            None
+        } else if depth == 0
+            && (is_terminator(c)
+                || (c == '.' && chars.peek().is_none_or(|(_, next)| next.is_whitespace())))
+        {
+            let mut end = i + c.len_utf8();
+            while let Some((j, next)) =
+                chars.next_if(|(_, next)| is_terminator(*next) || *next == '.' || is_closing(*next))
+            {
+                end = j + next.len_utf8();
+            }
+            Some(end)
+        } else {
+            None
+        };
+
+        if let Some(end) = end {
+            push_trimmed(&mut ranges, text, start, end);
+            start = end;
+        }
+    }
+    push_trimmed(&mut ranges, text, start, text.len());
+    ranges
+}
+
+/// Adds the range from `start` to `end` to `ranges`, without the whitespace
+/// at either end, unless that leaves nothing.
+fn push_trimmed(ranges: &mut Vec<(usize, usize)>, text: &str, start: usize, end: usize) {
+    let sentence = &text[start..end];
+    let trimmed = sentence.trim_start();
+    let start = start + sentence.len() - trimmed.len();
+    let end = start + trimmed.trim_end().len();
+    if start < end {
+        ranges.push((start, end));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(text: &str) -> Vec<&str> {
+        sentences(text)
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect()
+    }
+
+    #[test]
+    fn it_splits_at_sentence_marks() {
+        assert_eq!(
+            split("今日は晴れ。明日は雨！本当？"),
+            ["今日は晴れ。", "明日は雨！", "本当？"]
+        );
+        assert_eq!(split("本当に！？ええ。"), ["本当に！？", "ええ。"]);
+        assert_eq!(split("終わらない文"), ["終わらない文"]);
+        assert_eq!(split(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn it_keeps_quoted_sentences_together() {
+        assert_eq!(
+            split("彼は「はい。そうです。」と言った。次の文。"),
+            ["彼は「はい。そうです。」と言った。", "次の文。"]
+        );
+        assert_eq!(split("「行こう！」「うん。」"), ["「行こう！」「うん。」"]);
+        assert_eq!(split("そうか。」次へ。"), ["そうか。」", "次へ。"]);
+    }
+
+    #[test]
+    fn it_splits_latin_text_and_lines() {
+        assert_eq!(
+            split("Pi is 3.14. It is known.  Really?"),
+            ["Pi is 3.14.", "It is known.", "Really?"]
+        );
+        assert_eq!(split("見出し\n\n本文です。"), ["見出し", "本文です。"]);
+    }
+}
@@ -0,0 +1,315 @@
+//! `lindera_snippet()`, an FTS5 auxiliary function like `snippet()` that
+//! cuts the text at sentence and bunsetsu boundaries rather than after a
+//! number of tokens, so that previews of Japanese text neither start nor end
+//! in the middle of a word.
+//!
+//! The tokens FTS5 passes to auxiliary functions have no parts of speech,
+//! so a bunsetsu is taken to be a run of tokens with nothing between them,
+//! followed by the text the filters left out after it, such as particles
+//! and punctuation.
+
+use core::ops::Range;
+
+use libc::c_int;
+
+use crate::common::*;
+use crate::extension::{Fts5Context, Fts5ExtensionApi, Sqlite3Context, Sqlite3Value, SqliteApi};
+use crate::highlight::{
+    column_text, insert_markers, phrase_matches, phrase_spans, token_ranges, PhraseMatch,
+};
+use crate::sentence::sentences;
+
+/// `lindera_snippet(table, col, open, close, ellipsis, max_tokens)` returns
+/// the sentence of column `col` of the current row, or of the column with
+/// the most matches if `col` is negative, where the phrases of the query
+/// match most often, with the matches between `open` and `close`. A
+/// sentence of more than `max_tokens` tokens is cut down to the bunsetsu
+/// around the first match in it, and `ellipsis` marks where text is left
+/// out.
+pub(crate) extern "C" fn lindera_snippet_aux<A: SqliteApi>(
+    api: *const Fts5ExtensionApi,
+    fts: *mut Fts5Context,
+    ctx: *mut Sqlite3Context,
+    n_val: c_int,
+    ap_val: *mut *mut Sqlite3Value,
+) {
+    let Some(sqlite_api) = A::get() else {
+        return;
+    };
+    if n_val != 5 {
+        report_error(
+            SQLITE_ERROR,
+            format_args!("wrong number of arguments to function lindera_snippet()"),
+        );
+        return sqlite_api.result_null(ctx);
+    }
+    let values = unsafe { core::slice::from_raw_parts(ap_val, 5) };
+    let col = c_int::try_from(sqlite_api.value_int64(values[0])).unwrap_or(-1);
+    let markers = Markers {
+        open: sqlite_api.value_text(values[1]),
+        close: sqlite_api.value_text(values[2]),
+        ellipsis: sqlite_api.value_text(values[3]),
+    };
+    let max_tokens = usize::try_from(sqlite_api.value_int64(values[4]))
+        .unwrap_or(0)
+        .max(1);
+    let api = unsafe { &*api };
+
+    let result = phrase_matches(api, fts, col).and_then(|matches| {
+        let col = match col {
+            0.. => col,
+            _ => most_matched_column(&matches),
+        };
+        let Some(text) = column_text(api, fts, col)? else {
+            return Ok(None);
+        };
+        let matches = matches
+            .into_iter()
+            .filter(|m| m.col == col)
+            .collect::<Vec<_>>();
+        let tokens = token_ranges(api, fts, text)?;
+        let spans = phrase_spans(&matches, &tokens);
+        Ok(Some(snippet(text, &tokens, &spans, max_tokens, &markers)))
+    });
+    match result {
+        Ok(Some(snippet)) => sqlite_api.result_text(ctx, &String::from_utf8_lossy(&snippet)),
+        Ok(None) => sqlite_api.result_null(ctx),
+        Err(rc) => {
+            report_error(
+                rc,
+                format_args!("lindera_snippet() failed to read column {}", col),
+            );
+            sqlite_api.result_null(ctx);
+        }
+    }
+}
+
+/// The column most of `matches` are in, the first of them on a tie, or the
+/// first column without matches.
+fn most_matched_column(matches: &[PhraseMatch]) -> c_int {
+    let mut counts = Vec::<(c_int, usize)>::new();
+    for m in matches {
+        match counts.iter_mut().find(|(col, _)| *col == m.col) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((m.col, 1)),
+        }
+    }
+    counts.sort_by_key(|(col, _)| *col);
+    counts
+        .iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map_or(0, |(col, _)| *col)
+}
+
+/// What `lindera_snippet` puts around matches and in place of the text it
+/// leaves out.
+struct Markers {
+    open: String,
+    close: String,
+    ellipsis: String,
+}
+
+/// The snippet of `text`, given the byte ranges of its `tokens` and of the
+/// matches of the query, `spans`, sorted by their start.
+fn snippet(
+    text: &[u8],
+    tokens: &[(usize, usize)],
+    spans: &[(usize, usize)],
+    max_tokens: usize,
+    markers: &Markers,
+) -> Vec<u8> {
+    let sentences = match core::str::from_utf8(text) {
+        Ok(text) => sentences(text),
+        Err(_) => vec![(0, text.len())],
+    };
+    // The sentence the most matches start in, the first on a tie.
+    let Some(&(start, end)) = sentences
+        .iter()
+        .rev()
+        .max_by_key(|(start, end)| {
+            spans
+                .iter()
+                .filter(|(span_start, _)| (*start..*end).contains(span_start))
+                .count()
+        })
+        .or(sentences.first())
+    else {
+        return Vec::new();
+    };
+    let range = trim_sentence(text, start..end, tokens, spans, max_tokens);
+
+    let mut snippet = Vec::with_capacity(range.len() + 2 * markers.ellipsis.len());
+    if !text[..range.start].trim_ascii().is_empty() {
+        snippet.extend_from_slice(markers.ellipsis.as_bytes());
+    }
+    let spans = spans
+        .iter()
+        .filter(|(span_start, span_end)| *span_start < range.end && *span_end > range.start)
+        .map(|(span_start, span_end)| {
+            (
+                span_start.max(&range.start) - range.start,
+                span_end.min(&range.end) - range.start,
+            )
+        })
+        .collect::<Vec<_>>();
+    snippet.extend(insert_markers(
+        &text[range.clone()],
+        &spans,
+        markers.open.as_bytes(),
+        markers.close.as_bytes(),
+    ));
+    if !text[range.end..].trim_ascii().is_empty() {
+        snippet.extend_from_slice(markers.ellipsis.as_bytes());
+    }
+    snippet
+}
+
+/// The part of `sentence` to show: all of it if it has at most `max_tokens`
+/// tokens, or else the bunsetsu around its first match, and those after and
+/// then before it that fit in `max_tokens`.
+fn trim_sentence(
+    text: &[u8],
+    sentence: Range<usize>,
+    tokens: &[(usize, usize)],
+    spans: &[(usize, usize)],
+    max_tokens: usize,
+) -> Range<usize> {
+    let tokens = tokens
+        .iter()
+        .filter(|(start, _)| sentence.contains(start))
+        .collect::<Vec<_>>();
+    if tokens.len() <= max_tokens {
+        return sentence;
+    }
+
+    // The index of the first token of each bunsetsu, and then the number of
+    // tokens.
+    let mut bunsetsu = (0..tokens.len())
+        .filter(|&i| i == 0 || tokens[i - 1].1 < tokens[i].0)
+        .collect::<Vec<_>>();
+    bunsetsu.push(tokens.len());
+    let size = |b: usize| bunsetsu[b + 1] - bunsetsu[b];
+
+    let first_match = spans
+        .iter()
+        .find(|(start, _)| sentence.contains(start))
+        .map_or(sentence.start, |(start, _)| *start);
+    let matched = (0..bunsetsu.len() - 1)
+        .rev()
+        .find(|&b| tokens[bunsetsu[b]].0 <= first_match)
+        .unwrap_or(0);
+
+    let (mut first, mut last) = (matched, matched);
+    let mut count = size(matched);
+    while last + 1 < bunsetsu.len() - 1 && count + size(last + 1) <= max_tokens {
+        last += 1;
+        count += size(last);
+    }
+    while first > 0 && count + size(first - 1) <= max_tokens {
+        first -= 1;
+        count += size(first);
+    }
+
+    let start = match first {
+        0 => sentence.start,
+        _ => tokens[bunsetsu[first]].0,
+    };
+    let end = match bunsetsu.get(last + 1).and_then(|i| tokens.get(*i)) {
+        Some((next, _)) => *next,
+        None => sentence.end,
+    };
+    let trimmed = &text[start..end];
+    let trailing = trimmed.len() - trimmed.trim_ascii_end().len();
+    start..end - trailing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The byte ranges of `words` in `text`, each searched after the last.
+    fn ranges(text: &str, words: &[&str]) -> Vec<(usize, usize)> {
+        let mut from = 0;
+        words
+            .iter()
+            .map(|word| {
+                let start = from + text[from..].find(word).unwrap();
+                from = start + word.len();
+                (start, from)
+            })
+            .collect()
+    }
+
+    fn snippet_of(text: &str, tokens: &[&str], matches: &[&str], max_tokens: usize) -> String {
+        let markers = Markers {
+            open: "[".to_string(),
+            close: "]".to_string(),
+            ellipsis: "…".to_string(),
+        };
+        let tokens = ranges(text, tokens);
+        let spans = ranges(text, matches);
+        String::from_utf8(snippet(
+            text.as_bytes(),
+            &tokens,
+            &spans,
+            max_tokens,
+            &markers,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn it_shows_the_sentence_with_the_most_matches() {
+        let text = "辞書を引く。形態素解析の辞書と解析の辞書。解析する。";
+        let tokens = [
+            "辞書",
+            "引く",
+            "形態素",
+            "解析",
+            "辞書",
+            "解析",
+            "辞書",
+            "解析",
+            "する",
+        ];
+        assert_eq!(
+            snippet_of(text, &tokens, &["辞書", "辞書", "辞書"], 10),
+            "…形態素解析の[辞書]と解析の[辞書]。…"
+        );
+        assert_eq!(snippet_of(text, &tokens, &[], 10), "辞書を引く。…");
+    }
+
+    #[test]
+    fn it_cuts_long_sentences_at_bunsetsu() {
+        let text = "関西国際空港の辞書を形態素解析で調べる";
+        let tokens = ["関西国際空港", "辞書", "形態素", "解析", "調べる"];
+        // 形態素解析で is one bunsetsu of two tokens.
+        assert_eq!(snippet_of(text, &tokens, &["解析"], 2), "…形態素[解析]で…");
+        assert_eq!(
+            snippet_of(text, &tokens, &["解析"], 3),
+            "…形態素[解析]で調べる"
+        );
+        assert_eq!(snippet_of(text, &tokens, &["辞書"], 1), "…[辞書]を…");
+        assert_eq!(
+            snippet_of(text, &tokens, &["辞書"], 2),
+            "関西国際空港の[辞書]を…"
+        );
+        assert_eq!(
+            snippet_of(text, &tokens, &["関西国際空港"], 2),
+            "[関西国際空港]の辞書を…"
+        );
+    }
+
+    #[test]
+    fn it_finds_the_column_with_the_most_matches() {
+        let m = |col| PhraseMatch {
+            col,
+            first: 0,
+            last: 0,
+        };
+        assert_eq!(most_matched_column(&[m(2), m(1), m(1)]), 1);
+        assert_eq!(most_matched_column(&[m(2), m(1)]), 1);
+        assert_eq!(most_matched_column(&[]), 0);
+    }
+}
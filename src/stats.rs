@@ -0,0 +1,182 @@
+//! Counters of what each tokenizer has done, for the `lindera_stats` table.
+//!
+//! Every tokenizer an FTS5 or FTS3 table creates registers its counters
+//! here, and they are listed for as long as the tokenizer lives.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
+
+use libc::{c_char, c_int, c_void};
+
+use crate::common::{TokenFunction, FTS5_TOKENIZE_DOCUMENT, FTS5_TOKENIZE_QUERY, SQLITE_OK};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The counters of the tokenizers alive in the process, in the order they
+/// were created.
+static REGISTRY: Mutex<Vec<Weak<TokenizerStats>>> = Mutex::new(Vec::new());
+
+/// The counters of one tokenizer instance.
+#[derive(Debug)]
+pub(crate) struct TokenizerStats {
+    pub(crate) id: u64,
+    /// Where the tokenizer's configuration comes from, if it names one.
+    pub(crate) config: Option<String>,
+    pub(crate) documents: AtomicU64,
+    pub(crate) queries: AtomicU64,
+    pub(crate) tokens: AtomicU64,
+    pub(crate) bytes: AtomicU64,
+    pub(crate) nanos: AtomicU64,
+    pub(crate) errors: AtomicU64,
+}
+
+impl TokenizerStats {
+    /// Counters for a new tokenizer, listed until they are dropped.
+    pub(crate) fn register(config: Option<String>) -> Arc<Self> {
+        let stats = Arc::new(TokenizerStats {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            config,
+            documents: AtomicU64::new(0),
+            queries: AtomicU64::new(0),
+            tokens: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            nanos: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        });
+        let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        registry.retain(|stats| stats.strong_count() > 0);
+        registry.push(Arc::downgrade(&stats));
+        stats
+    }
+
+    /// The counters of the tokenizers alive now.
+    pub(crate) fn live() -> Vec<Arc<Self>> {
+        let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        registry.retain(|stats| stats.strong_count() > 0);
+        registry.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Runs `tokenize` on `bytes` of text with `flags`, counting the call,
+    /// the tokens it passes to `x_token`, how long it takes and whether it
+    /// fails. `tokenize` gets the callback and context to pass tokens to.
+    pub(crate) fn record<F>(
+        &self,
+        flags: c_int,
+        bytes: usize,
+        x_token: TokenFunction,
+        p_ctx: *mut c_void,
+        tokenize: F,
+    ) -> Result<(), c_int>
+    where
+        F: FnOnce(TokenFunction, *mut c_void) -> Result<(), c_int>,
+    {
+        let mut counting = CountingCallback {
+            x_token,
+            p_ctx,
+            tokens: 0,
+        };
+        let started = Instant::now();
+        let result = tokenize(count_token, (&mut counting as *mut CountingCallback).cast());
+        let elapsed = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+
+        if flags & FTS5_TOKENIZE_QUERY != 0 {
+            self.queries.fetch_add(1, Ordering::Relaxed);
+        } else if flags & FTS5_TOKENIZE_DOCUMENT != 0 {
+            self.documents.fetch_add(1, Ordering::Relaxed);
+        }
+        self.tokens.fetch_add(counting.tokens, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.nanos.fetch_add(elapsed, Ordering::Relaxed);
+        if matches!(result, Err(rc) if rc != SQLITE_OK) {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+/// The `xToken` callback and context a tokenize call was given, and how
+/// many tokens it passed on.
+struct CountingCallback {
+    x_token: TokenFunction,
+    p_ctx: *mut c_void,
+    tokens: u64,
+}
+
+extern "C" fn count_token(
+    p_ctx: *mut c_void,
+    t_flags: c_int,
+    p_token: *const c_char,
+    n_token: c_int,
+    i_start: c_int,
+    i_end: c_int,
+) -> c_int {
+    let counting = unsafe { &mut *(p_ctx as *mut CountingCallback) };
+    counting.tokens += 1;
+    (counting.x_token)(counting.p_ctx, t_flags, p_token, n_token, i_start, i_end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn accept(
+        _p_ctx: *mut c_void,
+        _t_flags: c_int,
+        _p_token: *const c_char,
+        _n_token: c_int,
+        _i_start: c_int,
+        _i_end: c_int,
+    ) -> c_int {
+        SQLITE_OK
+    }
+
+    #[test]
+    fn it_counts_calls_tokens_and_errors() {
+        let stats = TokenizerStats::register(Some("test".to_string()));
+        let emit_two = |x_token: TokenFunction, p_ctx: *mut c_void| {
+            for _ in 0..2 {
+                x_token(p_ctx, 0, c"a".as_ptr(), 1, 0, 1);
+            }
+            Ok(())
+        };
+        stats
+            .record(
+                FTS5_TOKENIZE_DOCUMENT,
+                3,
+                accept,
+                core::ptr::null_mut(),
+                emit_two,
+            )
+            .unwrap();
+        stats
+            .record(
+                FTS5_TOKENIZE_QUERY,
+                1,
+                accept,
+                core::ptr::null_mut(),
+                emit_two,
+            )
+            .unwrap();
+        let _ = stats.record(
+            FTS5_TOKENIZE_DOCUMENT,
+            2,
+            accept,
+            core::ptr::null_mut(),
+            |_, _| Err(crate::common::SQLITE_INTERNAL),
+        );
+
+        assert_eq!(stats.documents.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.queries.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.tokens.load(Ordering::Relaxed), 4);
+        assert_eq!(stats.bytes.load(Ordering::Relaxed), 6);
+        assert_eq!(stats.errors.load(Ordering::Relaxed), 1);
+        assert!(TokenizerStats::live()
+            .iter()
+            .any(|live| live.id == stats.id));
+
+        let id = stats.id;
+        drop(stats);
+        assert!(!TokenizerStats::live().iter().any(|live| live.id == id));
+    }
+}
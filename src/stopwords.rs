@@ -0,0 +1,137 @@
+//! Stopwords that are left out of the index and of queries.
+//!
+//! A configuration picks built-in lists with its `stopwords` key, by name or
+//! as an array of names:
+//!
+//! ```yaml
+//! stopwords: ja_default
+//! ```
+//!
+//! The lists are compiled in with the `stopwords` feature. Words are compared
+//! with tokens after the filters and base forms, as they would be indexed.
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::error::LinderaSqliteError;
+
+/// The names of the built-in lists.
+const BUILTIN_NAMES: &str = "ja_default, ko_default or zh_default";
+
+/// The words a tokenizer leaves out.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Stopwords {
+    words: HashSet<String>,
+}
+
+impl Stopwords {
+    /// The built-in lists named by the `stopwords` value of a configuration,
+    /// a name or an array of names, merged.
+    #[cfg_attr(not(feature = "extension"), allow(dead_code))]
+    pub(crate) fn from_config(value: &Value) -> Result<Self, LinderaSqliteError> {
+        let names = match value {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names
+                .iter()
+                .map(|name| name.as_str().ok_or_else(|| invalid(name)))
+                .collect::<Result<_, _>>()?,
+            value => return Err(invalid(value)),
+        };
+        let mut stopwords = Stopwords::default();
+        for name in names {
+            let list = builtin(name).ok_or_else(|| {
+                LinderaSqliteError::ConfigInvalid(match cfg!(feature = "stopwords") {
+                    true => format!(
+                        "unknown stopword list {:?}, expected {}",
+                        name, BUILTIN_NAMES
+                    ),
+                    false => format!(
+                        "stopword list {:?} needs lindera-sqlite built with the stopwords feature",
+                        name
+                    ),
+                })
+            })?;
+            stopwords.add_list(list);
+        }
+        Ok(stopwords)
+    }
+
+    /// Adds the words of `list`, one per line, skipping blank lines and
+    /// comments starting with `#`.
+    fn add_list(&mut self, list: &str) {
+        self.words.extend(
+            list.lines()
+                .map(str::trim)
+                .filter(|word| !word.is_empty() && !word.starts_with('#'))
+                .map(str::to_string),
+        );
+    }
+
+    pub(crate) fn contains(&self, word: &str) -> bool {
+        self.words.contains(word)
+    }
+}
+
+fn invalid(value: &Value) -> LinderaSqliteError {
+    LinderaSqliteError::ConfigInvalid(format!(
+        "stopwords must name {} or be an array of them, not {}",
+        BUILTIN_NAMES, value
+    ))
+}
+
+/// The built-in list called `name`, one word per line.
+#[cfg(feature = "stopwords")]
+fn builtin(name: &str) -> Option<&'static str> {
+    match name {
+        "ja_default" => Some(include_str!("../resources/stopwords/ja_default.txt")),
+        "ko_default" => Some(include_str!("../resources/stopwords/ko_default.txt")),
+        "zh_default" => Some(include_str!("../resources/stopwords/zh_default.txt")),
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "stopwords"))]
+fn builtin(_name: &str) -> Option<&'static str> {
+    None
+}
+
+#[cfg(test)]
+impl Stopwords {
+    pub(crate) fn from_words(words: &[&str]) -> Self {
+        Stopwords {
+            words: words.iter().map(|word| word.to_string()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn it_reads_lists_skipping_comments() {
+        let mut stopwords = Stopwords::default();
+        stopwords.add_list("# particles\nの\n\n は \n");
+        assert_eq!(stopwords, Stopwords::from_words(&["の", "は"]));
+    }
+
+    #[test]
+    fn it_rejects_invalid_configs() {
+        assert!(Stopwords::from_config(&json!("en_default")).is_err());
+        assert!(Stopwords::from_config(&json!(["ja_default", 1])).is_err());
+        assert!(Stopwords::from_config(&json!(true)).is_err());
+    }
+
+    #[cfg(feature = "stopwords")]
+    #[test]
+    fn it_merges_builtin_lists() {
+        let stopwords = Stopwords::from_config(&json!(["ja_default", "zh_default"])).unwrap();
+        assert!(stopwords.contains("の"));
+        assert!(stopwords.contains("的"));
+        assert!(!stopwords.contains("辞書"));
+        assert!(!stopwords.contains("# Japanese stopwords"));
+    }
+}
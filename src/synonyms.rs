@@ -0,0 +1,145 @@
+//! Synonyms that queries are expanded with.
+//!
+//! A configuration names a synonym file with its `synonyms` key. The file is
+//! either TSV, one group of equivalent terms per line separated by tabs, such
+//! as `クルマ`, `自動車` and `車`, with `#` starting a comment line, or,
+//! starting with `[` or `{`, JSON: an array of such groups, or an object
+//! mapping a term to the terms it should also find:
+//!
+//! ```json
+//! { "クルマ": ["自動車"] }
+//! ```
+//!
+//! Terms are compared with tokens after the filters, so they should be
+//! written the way the index holds them.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::error::LinderaSqliteError;
+
+/// The terms each term of a synonym file also finds.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Synonyms {
+    synonyms: HashMap<String, Vec<String>>,
+}
+
+impl Synonyms {
+    #[cfg_attr(not(feature = "extension"), allow(dead_code))]
+    pub(crate) fn load(path: &Path) -> Result<Self, LinderaSqliteError> {
+        let synonyms = std::fs::read_to_string(path).map_err(|e| {
+            LinderaSqliteError::Io(format!("failed to read {}: {}", path.display(), e))
+        })?;
+        Self::parse(&synonyms)
+    }
+
+    /// Parses synonyms as JSON if they start with `[` or `{`, or as TSV
+    /// otherwise.
+    #[cfg_attr(not(feature = "extension"), allow(dead_code))]
+    pub(crate) fn parse(synonyms: &str) -> Result<Self, LinderaSqliteError> {
+        if synonyms.trim_start().starts_with(['[', '{']) {
+            let json: Value = serde_json::from_str(synonyms)
+                .map_err(|e| LinderaSqliteError::ConfigInvalid(e.to_string()))?;
+            Self::from_json(&json)
+        } else {
+            Ok(Self::from_tsv(synonyms))
+        }
+    }
+
+    fn from_tsv(tsv: &str) -> Self {
+        let mut synonyms = Synonyms::default();
+        for line in tsv.lines().filter(|line| !line.starts_with('#')) {
+            synonyms.add_group(line.split('\t').map(str::trim));
+        }
+        synonyms
+    }
+
+    fn from_json(json: &Value) -> Result<Self, LinderaSqliteError> {
+        let invalid = || {
+            LinderaSqliteError::ConfigInvalid(
+                "synonyms must be an array of arrays of strings or an object of arrays of strings"
+                    .to_string(),
+            )
+        };
+        let terms = |terms: &Value| -> Result<Vec<String>, LinderaSqliteError> {
+            terms
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(|term| term.as_str().map(str::to_string).ok_or_else(invalid))
+                .collect()
+        };
+
+        let mut synonyms = Synonyms::default();
+        match json {
+            Value::Array(groups) => {
+                for group in groups {
+                    synonyms.add_group(terms(group)?.iter().map(String::as_str));
+                }
+            }
+            Value::Object(map) => {
+                for (term, expansions) in map {
+                    for expansion in terms(expansions)? {
+                        synonyms.add(term, &expansion);
+                    }
+                }
+            }
+            _ => return Err(invalid()),
+        }
+        Ok(synonyms)
+    }
+
+    /// Makes each of `terms` find the others.
+    fn add_group<'a>(&mut self, terms: impl Iterator<Item = &'a str>) {
+        let terms = terms.filter(|term| !term.is_empty()).collect::<Vec<_>>();
+        for term in &terms {
+            for synonym in &terms {
+                self.add(term, synonym);
+            }
+        }
+    }
+
+    fn add(&mut self, term: &str, synonym: &str) {
+        if term == synonym {
+            return;
+        }
+        let synonyms = self.synonyms.entry(term.to_string()).or_default();
+        if !synonyms.iter().any(|known| known == synonym) {
+            synonyms.push(synonym.to_string());
+        }
+    }
+
+    /// The terms `term` also finds, in the order the file lists them.
+    pub(crate) fn get(&self, term: &str) -> &[String] {
+        self.synonyms.get(term).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_tsv_groups() {
+        let synonyms = Synonyms::parse("# vehicles\nクルマ\t自動車\t車\n\nPC\tパソコン\n").unwrap();
+
+        assert_eq!(synonyms.get("クルマ"), ["自動車", "車"]);
+        assert_eq!(synonyms.get("車"), ["クルマ", "自動車"]);
+        assert_eq!(synonyms.get("パソコン"), ["PC"]);
+        assert!(synonyms.get("バス").is_empty());
+    }
+
+    #[test]
+    fn it_parses_json_groups_and_maps() {
+        let groups = Synonyms::parse(r#"[["クルマ", "自動車"]]"#).unwrap();
+        assert_eq!(groups.get("自動車"), ["クルマ"]);
+
+        let map = Synonyms::parse(r#"{ "クルマ": ["自動車"] }"#).unwrap();
+        assert_eq!(map.get("クルマ"), ["自動車"]);
+        assert!(map.get("自動車").is_empty());
+
+        assert!(Synonyms::parse(r#"{ "クルマ": "自動車" }"#).is_err());
+    }
+}
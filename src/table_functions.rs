@@ -0,0 +1,1237 @@
+use core::ffi::CStr;
+use core::ptr::null_mut;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use libc::{c_char, c_int, c_uchar, c_void};
+
+use crate::args::DetailFields;
+use crate::common::*;
+use crate::extension::{
+    default_pipelines, quote_identifier, Fts5Context, Fts5ExtensionApi, Sqlite3, Sqlite3Context,
+    Sqlite3Stmt, Sqlite3Value, SqliteApi, TokenizerContext,
+};
+use crate::sentence::sentences;
+use crate::stats::TokenizerStats;
+use crate::vtab::{SqlValue, TableFunction};
+use crate::{
+    analyze_tokens, analyze_traced, is_bareword, report_tokenizer_error, AnalysisStep,
+    AnalyzedToken,
+};
+
+/// The pointer type `lindera_tokens` binds for `lindera_tokens_aux`.
+const INDEXED_TOKENS: &CStr = c"lindera_tokens";
+
+/// `SELECT * FROM lindera_tokens(table_name, row_id, col)` lists the tokens
+/// FTS5 indexes for one column of one row, as produced by the table's own
+/// tokenizer.
+pub(crate) struct LinderaTokens;
+
+impl TableFunction for LinderaTokens {
+    type Context = ();
+
+    const SCHEMA: &'static CStr = c"CREATE TABLE x(token, byte_start, byte_end, position, table_name HIDDEN, row_id HIDDEN, col HIDDEN)";
+    const COLUMNS: usize = 4;
+    const ARGUMENTS: usize = 3;
+
+    fn rows<A: SqliteApi>(
+        api: &A,
+        db: *mut Sqlite3,
+        _context: &(),
+        args: &[SqlValue],
+    ) -> Result<Vec<Vec<SqlValue>>, c_int> {
+        let (Some(table_name), Some(row_id), Some(col)) = (
+            args[0].as_text(),
+            args[1].as_integer(),
+            args[2].as_integer(),
+        ) else {
+            return Ok(Vec::new());
+        };
+
+        // Auxiliary functions only run inside a query on the FTS5 table, so
+        // the tokens are collected by one and handed back through a pointer.
+        let table_name = quote_identifier(table_name);
+        let query = CString::new(format!(
+            "SELECT lindera_tokens_aux({}, ?1, ?2) FROM {} WHERE rowid = ?3",
+            table_name, table_name
+        ))
+        .map_err(|_| SQLITE_MISUSE)?;
+
+        let mut stmt = null_mut::<Sqlite3Stmt>();
+        let rc = api.prepare(db, &query, &mut stmt);
+        if rc != SQLITE_OK {
+            return Err(rc);
+        }
+
+        let mut tokens = IndexedTokens::default();
+        let result = query_indexed_tokens(api, stmt, &mut tokens, row_id, col);
+        let rc = api.finalize(stmt);
+        result?;
+        if rc != SQLITE_OK {
+            return Err(rc);
+        }
+        if tokens.rc != SQLITE_OK {
+            return Err(tokens.rc);
+        }
+
+        Ok(tokens.rows)
+    }
+}
+
+fn query_indexed_tokens(
+    api: &impl SqliteApi,
+    stmt: *mut Sqlite3Stmt,
+    tokens: &mut IndexedTokens,
+    row_id: i64,
+    col: i64,
+) -> Result<(), c_int> {
+    let rc = api.bind_pointer(
+        stmt,
+        1,
+        tokens as *mut IndexedTokens as *mut c_void,
+        INDEXED_TOKENS,
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+    let rc = api.bind_int64(stmt, 2, col);
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+    let rc = api.bind_int64(stmt, 3, row_id);
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    match api.step(stmt) {
+        SQLITE_ROW | SQLITE_DONE => Ok(()),
+        rc => Err(rc),
+    }
+}
+
+/// `SELECT * FROM lindera_tokenize(text)` lists the tokens `lindera_tokenizer`
+/// makes of `text` with the connection's configuration, with what the
+/// dictionary says about each.
+pub(crate) struct LinderaTokenize;
+
+impl TableFunction for LinderaTokenize {
+    type Context = TokenizerContext;
+
+    const SCHEMA: &'static CStr = c"CREATE TABLE x(surface, part_of_speech, reading, base_form, byte_start, byte_end, text HIDDEN)";
+    const COLUMNS: usize = 6;
+    const ARGUMENTS: usize = 1;
+
+    fn rows<A: SqliteApi>(
+        _api: &A,
+        _db: *mut Sqlite3,
+        context: &TokenizerContext,
+        args: &[SqlValue],
+    ) -> Result<Vec<Vec<SqlValue>>, c_int> {
+        let Some(text) = args[0].as_text() else {
+            return Ok(Vec::new());
+        };
+
+        let pipelines = default_pipelines(context)
+            .map_err(report_tokenizer_error)
+            .map_err(|e| e.code())?;
+        let fields = DetailFields::of(pipelines.settings.dictionary_kind);
+
+        let tokens =
+            analyze_tokens(&pipelines.index, text, FTS5_TOKENIZE_DOCUMENT).map_err(|e| e.code())?;
+        Ok(tokens
+            .into_iter()
+            .map(|mut token| {
+                let surface = SqlValue::Text(token.text.to_string());
+                let details = token.details();
+                vec![
+                    surface,
+                    part_of_speech(&fields, &details),
+                    text_or_null(fields.reading.and_then(|i| details.get(i).copied())),
+                    text_or_null(fields.base_form.and_then(|i| details.get(i).copied())),
+                    SqlValue::Integer(token.byte_start as i64),
+                    SqlValue::Integer(token.byte_end as i64),
+                ]
+            })
+            .collect())
+    }
+}
+
+/// `SELECT * FROM lindera_dict WHERE surface = ?` lists the entries the
+/// dictionaries of the connection's `lindera_tokenizer` have for a surface,
+/// those of the user dictionary first, with their connection ids and cost.
+pub(crate) struct LinderaDict;
+
+impl TableFunction for LinderaDict {
+    type Context = TokenizerContext;
+
+    const SCHEMA: &'static CStr = c"CREATE TABLE x(dictionary, part_of_speech, reading, base_form, left_id, right_id, cost, details, surface)";
+    const COLUMNS: usize = 8;
+    const ARGUMENTS: usize = 1;
+
+    fn rows<A: SqliteApi>(
+        _api: &A,
+        _db: *mut Sqlite3,
+        context: &TokenizerContext,
+        args: &[SqlValue],
+    ) -> Result<Vec<Vec<SqlValue>>, c_int> {
+        // Listing every entry of a dictionary is not supported.
+        let Some(surface) = args[0].as_text() else {
+            return Ok(Vec::new());
+        };
+
+        let pipelines = default_pipelines(context)
+            .map_err(report_tokenizer_error)
+            .map_err(|e| e.code())?;
+        let fields = DetailFields::of(pipelines.settings.dictionary_kind);
+        let segmenter = &pipelines.index.segmenter;
+
+        // Lindera does not export the type of entries, so their fields are
+        // passed as they are.
+        let row =
+            |dictionary: &str, (left_id, right_id, cost): (u16, u16, i16), details: Vec<&str>| {
+                vec![
+                    SqlValue::Text(dictionary.to_string()),
+                    part_of_speech(&fields, &details),
+                    text_or_null(fields.reading.and_then(|i| details.get(i).copied())),
+                    text_or_null(fields.base_form.and_then(|i| details.get(i).copied())),
+                    SqlValue::Integer(left_id.into()),
+                    SqlValue::Integer(right_id.into()),
+                    SqlValue::Integer(cost.into()),
+                    SqlValue::Text(details.join(",")),
+                ]
+            };
+        let mut rows = Vec::new();
+        if let Some(user_dictionary) = &segmenter.user_dictionary {
+            for entry in user_dictionary.dict.find_surface(surface) {
+                let details = user_dictionary.word_details(entry.word_id.id as usize);
+                let ids = (entry.left_id, entry.right_id, entry.word_cost);
+                rows.push(row("user", ids, details));
+            }
+        }
+        let dictionary = &segmenter.dictionary;
+        for entry in dictionary.prefix_dictionary.find_surface(surface) {
+            let details = dictionary.word_details(entry.word_id.id as usize);
+            let ids = (entry.left_id, entry.right_id, entry.word_cost);
+            rows.push(row("system", ids, details));
+        }
+        Ok(rows)
+    }
+}
+
+/// The leading `details` that make up the part of speech, without those a
+/// dictionary leaves as `*`.
+fn part_of_speech(fields: &DetailFields, details: &[&str]) -> SqlValue {
+    let part_of_speech = details
+        .iter()
+        .take(fields.part_of_speech)
+        .filter(|detail| **detail != "*")
+        .copied()
+        .collect::<Vec<_>>()
+        .join(",");
+    text_or_null(Some(&part_of_speech))
+}
+
+/// `SELECT * FROM lindera_sentences(text)` splits `text` into sentences,
+/// with the byte range of each in `text`.
+pub(crate) struct LinderaSentences;
+
+impl TableFunction for LinderaSentences {
+    type Context = ();
+
+    const SCHEMA: &'static CStr = c"CREATE TABLE x(sentence, byte_start, byte_end, text HIDDEN)";
+    const COLUMNS: usize = 3;
+    const ARGUMENTS: usize = 1;
+
+    fn rows<A: SqliteApi>(
+        _api: &A,
+        _db: *mut Sqlite3,
+        _context: &(),
+        args: &[SqlValue],
+    ) -> Result<Vec<Vec<SqlValue>>, c_int> {
+        let Some(text) = args[0].as_text() else {
+            return Ok(Vec::new());
+        };
+
+        Ok(sentences(text)
+            .into_iter()
+            .map(|(start, end)| {
+                vec![
+                    SqlValue::Text(text[start..end].to_string()),
+                    SqlValue::Integer(start as i64),
+                    SqlValue::Integer(end as i64),
+                ]
+            })
+            .collect())
+    }
+}
+
+/// `SELECT * FROM lindera_stats` lists the tokenizers of the FTS5 and FTS3
+/// tables open in the process, with what each has tokenized since it was
+/// created: how many documents and queries, how many tokens and bytes, how
+/// long it took in milliseconds, and how many calls failed.
+pub(crate) struct LinderaStats;
+
+impl TableFunction for LinderaStats {
+    type Context = ();
+
+    const SCHEMA: &'static CStr =
+        c"CREATE TABLE x(id, config, documents, queries, tokens, bytes, time_ms, errors)";
+    const COLUMNS: usize = 8;
+    const ARGUMENTS: usize = 0;
+
+    fn rows<A: SqliteApi>(
+        _api: &A,
+        _db: *mut Sqlite3,
+        _context: &(),
+        _args: &[SqlValue],
+    ) -> Result<Vec<Vec<SqlValue>>, c_int> {
+        let count = |counter: &AtomicU64| {
+            SqlValue::Integer(i64::try_from(counter.load(Ordering::Relaxed)).unwrap_or(i64::MAX))
+        };
+        Ok(TokenizerStats::live()
+            .iter()
+            .map(|stats| {
+                vec![
+                    SqlValue::Integer(i64::try_from(stats.id).unwrap_or(i64::MAX)),
+                    text_or_null(stats.config.as_deref()),
+                    count(&stats.documents),
+                    count(&stats.queries),
+                    count(&stats.tokens),
+                    count(&stats.bytes),
+                    SqlValue::Real(stats.nanos.load(Ordering::Relaxed) as f64 / 1e6),
+                    count(&stats.errors),
+                ]
+            })
+            .collect())
+    }
+}
+
+/// The number of keywords `lindera_keywords` lists without `n`.
+const DEFAULT_KEYWORDS: i64 = 10;
+
+/// Subclasses of nouns and verbs that only lean on other words, such as
+/// `こと` or `これ`, and numbers, which are no keywords.
+const DEPENDENT_WORDS: &[&str] = &["非自立", "代名詞", "数", "数詞", "接尾"];
+
+/// `SELECT * FROM lindera_keywords(text, n)` lists the `n` content words
+/// that occur most often in `text`, or 10 without `n`, with how often they
+/// do. Words are tokenized like the documents of `lindera_tokenizer`, and
+/// verbs and adjectives are counted by their base form.
+pub(crate) struct LinderaKeywords;
+
+impl TableFunction for LinderaKeywords {
+    type Context = TokenizerContext;
+
+    const SCHEMA: &'static CStr = c"CREATE TABLE x(keyword, frequency, text HIDDEN, n HIDDEN)";
+    const COLUMNS: usize = 2;
+    const ARGUMENTS: usize = 2;
+
+    fn rows<A: SqliteApi>(
+        _api: &A,
+        _db: *mut Sqlite3,
+        context: &TokenizerContext,
+        args: &[SqlValue],
+    ) -> Result<Vec<Vec<SqlValue>>, c_int> {
+        let Some(text) = args[0].as_text() else {
+            return Ok(Vec::new());
+        };
+        let n = args[1].as_integer().unwrap_or(DEFAULT_KEYWORDS);
+        let Ok(n) = usize::try_from(n) else {
+            return Ok(Vec::new());
+        };
+
+        let pipelines = default_pipelines(context)
+            .map_err(report_tokenizer_error)
+            .map_err(|e| e.code())?;
+        let fields = DetailFields::of(pipelines.settings.dictionary_kind);
+
+        let tokens =
+            analyze_tokens(&pipelines.index, text, FTS5_TOKENIZE_DOCUMENT).map_err(|e| e.code())?;
+        // Keywords in the order they first occur, which breaks ties.
+        let mut keywords = Vec::<(String, i64)>::new();
+        let mut indexes = HashMap::<String, usize>::new();
+        for mut token in tokens {
+            let text = token.text.to_string();
+            let Some(keyword) = keyword(&fields, &text, &token.details()) else {
+                continue;
+            };
+            let index = *indexes.entry(keyword.clone()).or_insert_with(|| {
+                keywords.push((keyword, 0));
+                keywords.len() - 1
+            });
+            keywords[index].1 += 1;
+        }
+
+        keywords.sort_by_key(|(_, frequency)| core::cmp::Reverse(*frequency));
+        keywords.truncate(n);
+        Ok(keywords
+            .into_iter()
+            .map(|(keyword, frequency)| vec![SqlValue::Text(keyword), SqlValue::Integer(frequency)])
+            .collect())
+    }
+}
+
+/// The keyword a token with `text` and `details` counts as, or `None` if it
+/// is no content word.
+fn keyword(fields: &DetailFields, text: &str, details: &[&str]) -> Option<String> {
+    if !text.chars().any(char::is_alphanumeric) {
+        return None;
+    }
+    let part_of_speech = details.first().copied().unwrap_or_default();
+    // Unknown words have no part of speech to tell them by.
+    if !fields.content_words.is_empty() && part_of_speech != "UNK" {
+        if !fields.content_words.contains(&part_of_speech) {
+            return None;
+        }
+        if details
+            .get(1)
+            .is_some_and(|subclass| DEPENDENT_WORDS.contains(subclass))
+        {
+            return None;
+        }
+    }
+
+    let base_form = fields
+        .base_form
+        .filter(|_| fields.inflected_words.contains(&part_of_speech))
+        .and_then(|i| details.get(i).copied())
+        .filter(|base_form| !base_form.is_empty() && *base_form != "*");
+    Some(base_form.unwrap_or(text).to_string())
+}
+
+/// `SELECT * FROM lindera_explain(query)` shows how the connection's
+/// `lindera_tokenizer` tokenizes each phrase of an FTS5 `MATCH` query: what
+/// each filter changed, the words the dictionary found, the tokens each
+/// token filter rewrote or dropped, and the tokens FTS5 looks up in the end.
+pub(crate) struct LinderaExplain;
+
+impl TableFunction for LinderaExplain {
+    type Context = TokenizerContext;
+
+    const SCHEMA: &'static CStr = c"CREATE TABLE x(phrase, prefix, stage, filter, token, original, byte_start, byte_end, action, query HIDDEN)";
+    const COLUMNS: usize = 9;
+    const ARGUMENTS: usize = 1;
+
+    fn rows<A: SqliteApi>(
+        _api: &A,
+        _db: *mut Sqlite3,
+        context: &TokenizerContext,
+        args: &[SqlValue],
+    ) -> Result<Vec<Vec<SqlValue>>, c_int> {
+        let Some(query) = args[0].as_text() else {
+            return Ok(Vec::new());
+        };
+
+        let pipelines = default_pipelines(context)
+            .map_err(report_tokenizer_error)
+            .map_err(|e| e.code())?;
+        let tokenizer = pipelines.query.as_ref().unwrap_or(&pipelines.index);
+
+        let mut rows = Vec::new();
+        for phrase in query_phrases(query) {
+            let mut flags = FTS5_TOKENIZE_QUERY;
+            if phrase.prefix {
+                flags |= FTS5_TOKENIZE_PREFIX;
+            }
+            let mut steps = Vec::new();
+            let tokens = analyze_traced(tokenizer, &phrase.text, flags, Some(&mut steps))
+                .map_err(|e| e.code())?;
+
+            let mut explain = Explanation {
+                phrase: &phrase,
+                rows: &mut rows,
+            };
+            let mut text = phrase.text.clone();
+            let mut words = Vec::new();
+            for step in steps {
+                match step {
+                    AnalysisStep::CharacterFilter {
+                        name,
+                        text: filtered,
+                    } => {
+                        let action = if filtered == text {
+                            "unchanged"
+                        } else {
+                            "changed"
+                        };
+                        explain.row(
+                            "character_filter",
+                            Some(&name),
+                            Some(&filtered),
+                            Some(&text),
+                            None,
+                            action,
+                        );
+                        text = filtered;
+                    }
+                    AnalysisStep::Segmenter { mut tokens } => {
+                        for token in &tokens {
+                            explain.row(
+                                "segmenter",
+                                None,
+                                Some(&token.0),
+                                None,
+                                Some(token),
+                                "segmented",
+                            );
+                        }
+                        // The last token of a prefix query is filtered apart.
+                        if phrase.prefix {
+                            tokens.pop();
+                        }
+                        words = tokens;
+                    }
+                    AnalysisStep::TokenFilter { name, tokens } => {
+                        explain.token_filter(name, &words, &tokens);
+                        words = tokens;
+                    }
+                }
+            }
+
+            let last = tokens.len().saturating_sub(1);
+            for (i, token) in tokens.iter().enumerate() {
+                let token = (token.text.clone(), token.byte_start, token.byte_end);
+                let action = if phrase.prefix && i == last {
+                    "prefix"
+                } else {
+                    "token"
+                };
+                explain.row("query", None, Some(&token.0), None, Some(&token), action);
+                let synonyms = pipelines
+                    .synonyms
+                    .as_deref()
+                    .map_or(&[][..], |synonyms| synonyms.get(&token.0));
+                for synonym in synonyms {
+                    explain.row(
+                        "query",
+                        None,
+                        Some(synonym),
+                        Some(&token.0),
+                        Some(&token),
+                        "synonym",
+                    );
+                }
+            }
+        }
+        Ok(rows)
+    }
+}
+
+/// The rows of `lindera_explain` for one phrase.
+struct Explanation<'a> {
+    phrase: &'a QueryPhrase,
+    rows: &'a mut Vec<Vec<SqlValue>>,
+}
+
+impl Explanation<'_> {
+    fn row(
+        &mut self,
+        stage: &str,
+        filter: Option<&str>,
+        token: Option<&str>,
+        original: Option<&str>,
+        range: Option<&AnalyzedToken>,
+        action: &str,
+    ) {
+        let text = |text: Option<&str>| {
+            text.map_or(SqlValue::Null, |text| SqlValue::Text(text.to_string()))
+        };
+        let offset = |offset: Option<usize>| {
+            offset.map_or(SqlValue::Null, |offset| SqlValue::Integer(offset as i64))
+        };
+        self.rows.push(vec![
+            SqlValue::Text(self.phrase.text.clone()),
+            SqlValue::Integer(self.phrase.prefix.into()),
+            SqlValue::Text(stage.to_string()),
+            text(filter),
+            text(token),
+            text(original),
+            offset(range.map(|token| token.1)),
+            offset(range.map(|token| token.2)),
+            SqlValue::Text(action.to_string()),
+        ]);
+    }
+
+    /// Rows for what the token filter `name` did to `before`, leaving
+    /// `after`, telling tokens apart by their byte range: those it dropped,
+    /// changed or added, such as compounds, or a single row if it did
+    /// nothing.
+    fn token_filter(&mut self, name: &str, before: &[AnalyzedToken], after: &[AnalyzedToken]) {
+        let same_range = |a: &AnalyzedToken, b: &AnalyzedToken| (a.1, a.2) == (b.1, b.2);
+        let n_rows = self.rows.len();
+        for token in before {
+            match after.iter().find(|filtered| same_range(filtered, token)) {
+                None => self.row(
+                    "token_filter",
+                    Some(name),
+                    Some(&token.0),
+                    None,
+                    Some(token),
+                    "dropped",
+                ),
+                Some(filtered) if filtered.0 != token.0 => self.row(
+                    "token_filter",
+                    Some(name),
+                    Some(&filtered.0),
+                    Some(&token.0),
+                    Some(filtered),
+                    "changed",
+                ),
+                Some(_) => {}
+            }
+        }
+        for token in after {
+            if !before.iter().any(|original| same_range(original, token)) {
+                self.row(
+                    "token_filter",
+                    Some(name),
+                    Some(&token.0),
+                    None,
+                    Some(token),
+                    "added",
+                );
+            }
+        }
+        if self.rows.len() == n_rows {
+            self.row("token_filter", Some(name), None, None, None, "unchanged");
+        }
+    }
+}
+
+/// A phrase of an FTS5 query, as FTS5 passes it to the tokenizer.
+#[derive(Debug, PartialEq, Eq)]
+struct QueryPhrase {
+    text: String,
+    /// Whether the phrase is followed by `*`, making its last token a prefix.
+    prefix: bool,
+}
+
+/// A token of the FTS5 query syntax.
+enum QueryToken {
+    /// A string or a bareword, which is only a keyword if it is not quoted.
+    Word {
+        text: String,
+        quoted: bool,
+    },
+    Punctuation(char),
+}
+
+/// The phrases of the FTS5 query `query`, leaving out its keywords, column
+/// filters and `NEAR` distances. Characters FTS5 would reject separate
+/// phrases.
+fn query_phrases(query: &str) -> Vec<QueryPhrase> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                // A string runs to the next lone quote; `""` is a quote.
+                let mut text = String::new();
+                while let Some(c) = chars.next() {
+                    if c == '"' && chars.next_if_eq(&'"').is_none() {
+                        break;
+                    }
+                    text.push(c);
+                }
+                tokens.push(QueryToken::Word { text, quoted: true });
+            }
+            '(' | ')' | '{' | '}' | ':' | ',' | '+' | '*' | '-' | '^' => {
+                tokens.push(QueryToken::Punctuation(c))
+            }
+            c if is_bareword(c) => {
+                let mut text = c.to_string();
+                while let Some(c) = chars.next_if(|c| is_bareword(*c)) {
+                    text.push(c);
+                }
+                tokens.push(QueryToken::Word {
+                    text,
+                    quoted: false,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let punctuation = |i: usize| match tokens.get(i) {
+        Some(QueryToken::Punctuation(c)) => Some(*c),
+        _ => None,
+    };
+    let mut phrases = Vec::new();
+    let mut in_column_set = false;
+    for (i, token) in tokens.iter().enumerate() {
+        let (text, quoted) = match token {
+            QueryToken::Punctuation(c) => {
+                match c {
+                    '{' => in_column_set = true,
+                    '}' => in_column_set = false,
+                    _ => {}
+                }
+                continue;
+            }
+            QueryToken::Word { text, quoted } => (text, *quoted),
+        };
+        let next = punctuation(i + 1);
+        let keyword = !quoted
+            && (matches!(text.as_str(), "AND" | "OR" | "NOT")
+                || (text == "NEAR" && next == Some('(')));
+        let column = in_column_set || next == Some(':');
+        let distance = i > 0 && punctuation(i - 1) == Some(',');
+        if keyword || column || distance || text.is_empty() {
+            continue;
+        }
+        phrases.push(QueryPhrase {
+            text: text.clone(),
+            prefix: next == Some('*'),
+        });
+    }
+    phrases
+}
+
+/// `NULL` for details a dictionary leaves empty or as `*`.
+fn text_or_null(detail: Option<&str>) -> SqlValue {
+    match detail {
+        Some(detail) if !detail.is_empty() && detail != "*" => SqlValue::Text(detail.to_string()),
+        _ => SqlValue::Null,
+    }
+}
+
+/// Rows of `lindera_tokens`, filled in by `lindera_tokens_aux`.
+#[derive(Default)]
+struct IndexedTokens {
+    rows: Vec<Vec<SqlValue>>,
+    position: i64,
+    rc: c_int,
+}
+
+/// `lindera_tokens_aux(tokens, col)` tokenizes column `col` of the current
+/// row into the `IndexedTokens` bound as `tokens`.
+pub(crate) extern "C" fn lindera_tokens_aux<A: SqliteApi>(
+    api: *const Fts5ExtensionApi,
+    fts: *mut Fts5Context,
+    _ctx: *mut Sqlite3Context,
+    n_val: c_int,
+    ap_val: *mut *mut Sqlite3Value,
+) {
+    let Some(sqlite_api) = A::get() else {
+        return;
+    };
+    if n_val != 2 {
+        return;
+    }
+    let values = unsafe { core::slice::from_raw_parts(ap_val, 2) };
+    let tokens = sqlite_api.value_pointer(values[0], INDEXED_TOKENS) as *mut IndexedTokens;
+    let Some(tokens) = (unsafe { tokens.as_mut() }) else {
+        return;
+    };
+    let Ok(col) = c_int::try_from(sqlite_api.value_int64(values[1])) else {
+        tokens.rc = SQLITE_RANGE;
+        return;
+    };
+    let api = unsafe { &*api };
+
+    let mut text = core::ptr::null::<c_char>();
+    let mut n_text = 0;
+    let rc = (api.x_column_text)(fts, col, &mut text, &mut n_text);
+    if rc != SQLITE_OK {
+        tokens.rc = rc;
+        return;
+    }
+    if text.is_null() {
+        return;
+    }
+
+    tokens.rc = (api.x_tokenize)(
+        fts,
+        text,
+        n_text,
+        tokens as *mut IndexedTokens as *mut c_void,
+        collect_token,
+    );
+}
+
+extern "C" fn collect_token(
+    p_ctx: *mut c_void,
+    t_flags: c_int,
+    p_token: *const c_char,
+    n_token: c_int,
+    i_start: c_int,
+    i_end: c_int,
+) -> c_int {
+    let tokens = unsafe { &mut *(p_ctx as *mut IndexedTokens) };
+    let token = unsafe { core::slice::from_raw_parts(p_token as *const c_uchar, n_token as usize) };
+
+    // Colocated tokens share the position of the token before them.
+    if t_flags & FTS5_TOKEN_COLOCATED != 0 {
+        tokens.position -= 1;
+    }
+    tokens.rows.push(vec![
+        SqlValue::Text(String::from_utf8_lossy(token).into_owned()),
+        SqlValue::Integer(i_start.into()),
+        SqlValue::Integer(i_end.into()),
+        SqlValue::Integer(tokens.position),
+    ]);
+    tokens.position += 1;
+
+    SQLITE_OK
+}
+
+#[cfg(all(test, feature = "rusqlite"))]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+    use crate::connection_extension::LinderaConnectionExt;
+    use crate::{lindera_fts5_tokenize, load_tokenizer};
+
+    #[test]
+    fn it_lists_indexed_tokens() {
+        let text = "Ｌｉｎｄｅｒａは形態素解析ｴﾝｼﾞﾝです。";
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.create_lindera_table("example", &["title", "content"])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO example(rowid, title, content) VALUES (7, '辞書', ?1)",
+            [text],
+        )
+        .unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT token, byte_start, byte_end, position FROM lindera_tokens('example', 7, 1)",
+            )
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(vec![
+                    SqlValue::Text(row.get(0)?),
+                    SqlValue::Integer(row.get(1)?),
+                    SqlValue::Integer(row.get(2)?),
+                    SqlValue::Integer(row.get(3)?),
+                ])
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+
+        let mut expected = IndexedTokens::default();
+        let mut tokenizer = Fts5Tokenizer::new(load_tokenizer().unwrap());
+        lindera_fts5_tokenize(
+            &mut tokenizer,
+            &mut expected as *mut IndexedTokens as *mut c_void,
+            0,
+            text.as_ptr() as *const c_char,
+            text.len() as c_int,
+            collect_token,
+        );
+
+        assert!(!rows.is_empty());
+        assert_eq!(rows, expected.rows);
+    }
+
+    #[test]
+    fn it_tokenizes_text_with_details() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT surface, part_of_speech, reading, base_form, byte_start, byte_end \
+                 FROM lindera_tokenize('関西国際空港の辞書')",
+            )
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, i64>(4)?,
+                    row.get::<_, i64>(5)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+
+        let some = |text: &str| Some(text.to_string());
+        assert_eq!(
+            rows,
+            [
+                (
+                    "関西国際空港".to_string(),
+                    some("名詞,固有名詞,組織"),
+                    some("カンサイコクサイクウコウ"),
+                    some("関西国際空港"),
+                    0,
+                    18
+                ),
+                (
+                    "辞書".to_string(),
+                    some("名詞,一般"),
+                    some("ジショ"),
+                    some("辞書"),
+                    21,
+                    27
+                ),
+            ]
+        );
+
+        let count: i64 = conn
+            .query_row("SELECT count(*) FROM lindera_tokenize(NULL)", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn it_resolves_the_configuration_once_per_connection() {
+        let plain = "segmenter:\n  mode: \"normal\"\n  dictionary:\n    kind: \"ipadic\"\n";
+        let filtered = std::fs::read_to_string("./resources/lindera.yml").unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute_batch("CREATE TABLE lindera_config(name TEXT PRIMARY KEY, yaml TEXT);")
+            .unwrap();
+        conn.execute("INSERT INTO lindera_config VALUES ('default', ?1)", [plain])
+            .unwrap();
+        let surfaces = |conn: &Connection| -> Vec<String> {
+            let mut stmt = conn
+                .prepare("SELECT surface FROM lindera_tokenize('辞書は利用可能です。')")
+                .unwrap();
+            stmt.query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .unwrap()
+        };
+        assert!(surfaces(&conn).contains(&"は".to_string()));
+
+        conn.execute("UPDATE lindera_config SET yaml = ?1", [filtered.as_str()])
+            .unwrap();
+        assert!(surfaces(&conn).contains(&"は".to_string()));
+
+        conn.query_row("SELECT lindera_reload()", [], |_| Ok(()))
+            .unwrap();
+        assert!(!surfaces(&conn).contains(&"は".to_string()));
+    }
+
+    #[test]
+    fn it_splits_text_into_sentences() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let mut stmt = conn
+            .prepare("SELECT * FROM lindera_sentences('彼は「はい。」と言った。 次の文！')")
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                ("彼は「はい。」と言った。".to_string(), 0, 36),
+                ("次の文！".to_string(), 37, 49),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_counts_what_tokenizers_do() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE lindera_config(name TEXT PRIMARY KEY, yaml TEXT)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO lindera_config VALUES ('stats_test', 'segmenter:\n  dictionary:\n    kind: ipadic\n')",
+            [],
+        )
+        .unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.execute(
+            "CREATE VIRTUAL TABLE example USING fts5(content, tokenize='lindera_tokenizer config_name stats_test')",
+            [],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO example VALUES ('関西国際空港の辞書')", [])
+            .unwrap();
+        conn.query_row(
+            "SELECT count(*) FROM example WHERE example MATCH '辞書'",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap();
+
+        let (documents, queries, tokens, bytes, time_ms, errors) = conn
+            .query_row(
+                "SELECT documents, queries, tokens, bytes, time_ms, errors \
+                 FROM lindera_stats WHERE config = 'stats_test'",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?,
+                        row.get::<_, f64>(4)?,
+                        row.get::<_, i64>(5)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!((documents, queries, errors), (1, 1, 0));
+        // 関西国際空港, の and 辞書, then 辞書 again for the query.
+        assert_eq!(tokens, 4);
+        assert_eq!(bytes, ("関西国際空港の辞書".len() + "辞書".len()) as i64);
+        assert!(time_ms >= 0.0);
+
+        conn.execute("DROP TABLE example", []).unwrap();
+        let remaining: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM lindera_stats WHERE config = 'stats_test'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn it_looks_up_dictionary_entries() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE lindera_config(name TEXT PRIMARY KEY, yaml TEXT)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO lindera_config VALUES ('default', ?1)",
+            [format!(
+                "segmenter:\n  dictionary:\n    kind: ipadic\n  user_dictionary:\n    path: {}/resources/ipadic_simple.csv\n    kind: ipadic\n",
+                env!("CARGO_MANIFEST_DIR")
+            )],
+        )
+        .unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let entries = |surface: &str| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT dictionary, part_of_speech, reading, base_form, cost \
+                     FROM lindera_dict WHERE surface = ?1",
+                )
+                .unwrap();
+            stmt.query_map([surface], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap()
+        };
+        let entry = |dictionary: &str, part_of_speech: &str, reading: &str, base_form: &str| {
+            (
+                dictionary.to_string(),
+                part_of_speech.to_string(),
+                reading.to_string(),
+                base_form.to_string(),
+            )
+        };
+        let without_cost = |entries: Vec<(String, String, String, String, i64)>| {
+            entries
+                .into_iter()
+                .map(|(dictionary, part_of_speech, reading, base_form, _)| {
+                    (dictionary, part_of_speech, reading, base_form)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            without_cost(entries("辞書")),
+            [entry("system", "名詞,一般", "ジショ", "辞書")]
+        );
+        let user = entries("東京スカイツリー");
+        assert_eq!(
+            without_cost(user.clone()),
+            [entry(
+                "user",
+                "カスタム名詞",
+                "トウキョウスカイツリー",
+                "東京スカイツリー"
+            )]
+        );
+        assert!(user[0].4 < 0);
+        assert!(entries("存在しない").is_empty());
+
+        let count: i64 = conn
+            .query_row("SELECT count(*) FROM lindera_dict", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn it_extracts_keywords() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let keywords = |sql: &str| {
+            let mut stmt = conn.prepare(sql).unwrap();
+            stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap()
+        };
+        let text = "形態素解析の辞書、関西国際空港の辞書。解析";
+        assert_eq!(
+            keywords(&format!("SELECT * FROM lindera_keywords('{}')", text)),
+            [
+                ("解析".to_string(), 2),
+                ("辞書".to_string(), 2),
+                ("形態素".to_string(), 1),
+                ("関西国際空港".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            keywords(&format!("SELECT * FROM lindera_keywords('{}', 1)", text)),
+            [("解析".to_string(), 2)]
+        );
+        assert!(keywords("SELECT * FROM lindera_keywords(NULL)").is_empty());
+    }
+
+    #[test]
+    fn it_explains_queries() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT phrase, prefix, stage, filter, token, action \
+                 FROM lindera_explain('title:ﾘﾝﾃﾞﾗの辞書 OR 解析*') \
+                 WHERE action != 'unchanged'",
+            )
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, bool>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+
+        let row =
+            |phrase: &str, prefix, stage: &str, filter: Option<&str>, token: &str, action: &str| {
+                (
+                    phrase.to_string(),
+                    prefix,
+                    stage.to_string(),
+                    filter.map(str::to_string),
+                    token.to_string(),
+                    action.to_string(),
+                )
+            };
+        let phrase = "ﾘﾝﾃﾞﾗの辞書";
+        assert_eq!(
+            rows,
+            [
+                row(
+                    phrase,
+                    false,
+                    "character_filter",
+                    Some("unicode_normalize"),
+                    "リンデラの辞書",
+                    "changed"
+                ),
+                row(
+                    phrase,
+                    false,
+                    "character_filter",
+                    Some("mapping"),
+                    "Linderaの辞書",
+                    "changed"
+                ),
+                row(phrase, false, "segmenter", None, "Lindera", "segmented"),
+                row(phrase, false, "segmenter", None, "の", "segmented"),
+                row(phrase, false, "segmenter", None, "辞書", "segmented"),
+                row(
+                    phrase,
+                    false,
+                    "token_filter",
+                    Some("japanese_stop_tags"),
+                    "の",
+                    "dropped"
+                ),
+                row(phrase, false, "query", None, "Lindera", "token"),
+                row(phrase, false, "query", None, "辞書", "token"),
+                row("解析", true, "segmenter", None, "解析", "segmented"),
+                row("解析", true, "query", None, "解析", "prefix"),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_splits_queries_into_phrases() {
+        let phrase = |text: &str, prefix| QueryPhrase {
+            text: text.to_string(),
+            prefix,
+        };
+        assert_eq!(
+            query_phrases(
+                "title:辞書 AND \"形態素 \"\"解析\"\"\"* OR NEAR(関西 空港, 5) NOT {a b}:x"
+            ),
+            [
+                phrase("辞書", false),
+                phrase("形態素 \"解析\"", true),
+                phrase("関西", false),
+                phrase("空港", false),
+                phrase("x", false),
+            ]
+        );
+        assert_eq!(
+            query_phrases("\"AND\" near"),
+            [phrase("AND", false), phrase("near", false)]
+        );
+        assert_eq!(query_phrases(""), []);
+    }
+
+    #[test]
+    fn it_lists_nothing_for_missing_row() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.load_lindera_tokenizer().unwrap();
+        conn.create_lindera_table("example", &["content"]).unwrap();
+
+        let count: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM lindera_tokens('example', 1, 0)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+}
@@ -0,0 +1,237 @@
+//! Text outside Chinese, Japanese and Korean tokenized as SQLite's default
+//! `unicode61` tokenizer does, which the `unicode61` option applies so that
+//! a table tokenized with Lindera searches English, or Russian or Greek, no
+//! worse than one using the default.
+//!
+//! The text is split into runs of CJK characters, which Lindera segments,
+//! and runs of anything else. In the latter, as in `unicode61`, tokens are
+//! runs of letters, numbers and private use characters, with the combining
+//! diacritics that follow them; anything else separates them, with the
+//! exceptions `tokenchars` and `separators` make. Tokens are case folded
+//! and, as `remove_diacritics` asks, the diacritics of Latin letters are
+//! removed.
+
+use std::borrow::Cow;
+
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+use crate::common::{RemoveDiacritics, Unicode61};
+use crate::AnalyzedToken;
+
+/// The byte ranges of the runs `text` is made of, each with whether it is
+/// written in Chinese, Japanese or Korean. Combining marks, such as
+/// variation selectors, belong to the run of the character before them.
+pub(crate) fn runs(text: &str) -> Vec<(usize, usize, bool)> {
+    let mut runs: Vec<(usize, usize, bool)> = Vec::new();
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        match runs.last_mut() {
+            Some((_, run_end, cjk)) if is_combining_mark(c) || *cjk == is_cjk(c) => *run_end = end,
+            _ => runs.push((i, end, is_cjk(c))),
+        }
+    }
+    runs
+}
+
+/// The tokens `unicode61` splits `text` into with `options`, with their byte
+/// ranges in it.
+pub(crate) fn tokenize<'a>(text: &'a str, options: &Unicode61) -> Vec<AnalyzedToken<'a>> {
+    let remove_diacritics = options.remove_diacritics;
+    let mut tokens = Vec::new();
+    let mut token: Option<(usize, String)> = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match &mut token {
+            None if is_token_char(c, options) => {
+                let mut folded = String::new();
+                push_folded(&mut folded, c, remove_diacritics);
+                token = Some((i, folded));
+            }
+            None => {}
+            Some((_, folded)) if is_token_char(c, options) || is_diacritic(c) => {
+                push_folded(folded, c, remove_diacritics)
+            }
+            Some(_) => {
+                let (start, folded) = token.take().expect("a token was started");
+                let text = match &text[start..i] {
+                    original if *original == folded => Cow::Borrowed(original),
+                    _ => Cow::Owned(folded),
+                };
+                tokens.push((text, start, i));
+            }
+        }
+    }
+    tokens
+}
+
+/// Whether `c` is in one of the scripts Lindera segments: han characters,
+/// kana and Hangul, with the symbols, punctuation and full-width forms of
+/// CJK text.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{11ff}'
+        | '\u{2e80}'..='\u{2fdf}'
+        | '\u{3000}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{a960}'..='\u{a97f}'
+        | '\u{ac00}'..='\u{d7ff}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{fe30}'..='\u{fe4f}'
+        | '\u{ff00}'..='\u{ffef}'
+        | '\u{1b000}'..='\u{1b16f}'
+        | '\u{20000}'..='\u{3134f}')
+}
+
+/// Whether `unicode61` takes `c` as part of a token: a letter, a number or
+/// a private use character, unless `options` make an exception. Marks are
+/// not, nor are the circled and squared letters Unicode counts as
+/// alphabetic, which are symbols.
+fn is_token_char(c: char, options: &Unicode61) -> bool {
+    if options.separators.contains(c) {
+        return false;
+    }
+    if options.tokenchars.contains(c) {
+        return true;
+    }
+    if c.is_ascii() {
+        return c.is_ascii_alphanumeric();
+    }
+    let symbol = matches!(c, '\u{24b6}'..='\u{24e9}' | '\u{1f130}'..='\u{1f189}');
+    let private_use = matches!(c,
+        '\u{e000}'..='\u{f8ff}' | '\u{f0000}'..='\u{ffffd}' | '\u{100000}'..='\u{10fffd}');
+    (c.is_alphanumeric() && !is_combining_mark(c) && !symbol) || private_use
+}
+
+/// Whether `c` is one of the combining diacritics `unicode61` keeps in the
+/// token of the letter before it, or drops when removing diacritics.
+fn is_diacritic(c: char) -> bool {
+    const MASK_LOW: u32 = 0x0802_9fdf;
+    const MASK_HIGH: u32 = 0x0003_61f8;
+    match c as u32 {
+        code @ 768..=799 => MASK_LOW & (1 << (code - 768)) != 0,
+        code @ 800..=817 => MASK_HIGH & (1 << (code - 800)) != 0,
+        _ => false,
+    }
+}
+
+/// Pushes `c` onto `token` case folded and, as `remove_diacritics` asks,
+/// without its diacritics. Like `unicode61`, only characters of the Basic
+/// Multilingual Plane and Deseret are folded, and only into one character.
+fn push_folded(token: &mut String, c: char, remove_diacritics: RemoveDiacritics) {
+    let code = c as u32;
+    let c = match c.to_lowercase() {
+        mut lower if code < 0x10000 || (0x10400..0x10428).contains(&code) => {
+            match (lower.next(), lower.next()) {
+                (Some(lower), None) => lower,
+                _ => c,
+            }
+        }
+        _ => c,
+    };
+    if remove_diacritics == RemoveDiacritics::Off {
+        token.push(c);
+        return;
+    }
+    if is_diacritic(c) {
+        return;
+    }
+    let mut base = None;
+    let mut marks = 0;
+    decompose_canonical(c, |part| match base {
+        None => base = Some(part),
+        Some(_) => marks += 1,
+    });
+    match base {
+        Some(base)
+            if base.is_ascii_alphabetic()
+                && (marks == 1
+                    || (marks > 1 && remove_diacritics == RemoveDiacritics::Complex)) =>
+        {
+            token.push(base)
+        }
+        _ => token.push(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(text: &str, remove_diacritics: RemoveDiacritics) -> Vec<String> {
+        let options = Unicode61 {
+            remove_diacritics,
+            ..Default::default()
+        };
+        tokenize(text, &options)
+            .into_iter()
+            .map(|(text, _, _)| text.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn it_splits_text_into_runs_by_script() {
+        let text = "SQLiteの全文検索 (FTS5) 한국어";
+        let split = runs(text)
+            .into_iter()
+            .map(|(start, end, cjk)| (&text[start..end], cjk))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            split,
+            [
+                ("SQLite", false),
+                ("の全文検索", true),
+                (" (FTS5) ", false),
+                ("한국어", true),
+            ]
+        );
+        // A variation selector stays with its kanji.
+        assert_eq!(runs("葛\u{e0100}城").len(), 1);
+    }
+
+    #[test]
+    fn it_tokenizes_like_unicode61() {
+        assert_eq!(
+            texts("Don't stop_me now, O'Brien!", RemoveDiacritics::Simple),
+            ["don", "t", "stop", "me", "now", "o", "brien"]
+        );
+        assert_eq!(
+            texts("МОСКВА Ἀθῆναι x² ⓐ", RemoveDiacritics::Simple),
+            ["москва", "ἀθῆναι", "x²"]
+        );
+        let tokens = tokenize("a Crème", &Unicode61::default());
+        assert_eq!(tokens[1], (Cow::Owned("creme".to_string()), 2, 8));
+        assert!(matches!(tokens[0].0, Cow::Borrowed("a")));
+    }
+
+    #[test]
+    fn it_removes_diacritics() {
+        let text = "Crème brûlée naïve ǖ e\u{301}";
+        assert_eq!(
+            texts(text, RemoveDiacritics::Off),
+            ["crème", "brûlée", "naïve", "ǖ", "e\u{301}"]
+        );
+        assert_eq!(
+            texts(text, RemoveDiacritics::Simple),
+            ["creme", "brulee", "naive", "ǖ", "e"]
+        );
+        assert_eq!(
+            texts(text, RemoveDiacritics::Complex),
+            ["creme", "brulee", "naive", "u", "e"]
+        );
+        // Greek letters keep their accents, as in unicode61.
+        assert_eq!(texts("ά", RemoveDiacritics::Complex), ["ά"]);
+    }
+
+    #[test]
+    fn it_makes_exceptions_for_tokenchars_and_separators() {
+        let options = Unicode61 {
+            tokenchars: "-.".to_string(),
+            separators: "xé".to_string(),
+            ..Default::default()
+        };
+        let texts = tokenize("E-Mail v1.2 0x1F café", &options)
+            .into_iter()
+            .map(|(text, _, _)| text.into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["e-mail", "v1.2", "0", "1f", "caf"]);
+    }
+}
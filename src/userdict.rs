@@ -0,0 +1,287 @@
+//! A user dictionary kept in the database's `lindera_userdict` table, which
+//! `lindera_userdict_add()` and `lindera_userdict_remove()` edit and
+//! `lindera_userdict_compile()` applies to the tokenizers in use.
+//!
+//! The words are written to a CSV file in the simple user dictionary format,
+//! one per connection, that tables and functions of the connection use as
+//! their `user_dictionary` while it exists, unless they name their own.
+//!
+//! A configuration may name another table with the same columns as
+//! `user_dictionary_table`, which tables using it compile the same way when
+//! they are created or opened.
+
+use core::ptr::null_mut;
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+
+use libc::c_int;
+
+use crate::common::*;
+use crate::extension::{
+    quote_identifier, Sqlite3, Sqlite3Context, Sqlite3Stmt, Sqlite3Value, SqliteApi,
+    TokenizerContext,
+};
+use crate::reload::reload_all;
+use crate::vtab::SQLITE_NULL;
+
+/// The table `lindera_userdict_add()` and `lindera_userdict_remove()` edit.
+pub(crate) const USER_DICTIONARY_TABLE: &str = "lindera_userdict";
+
+/// The file the user dictionary in `table` of the connection `db` is
+/// compiled to.
+pub(crate) fn user_dictionary_path(db: *mut Sqlite3, table: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    table.hash(&mut hasher);
+    env::temp_dir().join(format!(
+        "lindera-sqlite-userdict-{}-{:x}-{:x}.csv",
+        std::process::id(),
+        db as usize,
+        hasher.finish()
+    ))
+}
+
+/// A CSV field, quoted if it has to be.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Runs `sql`, with `params` bound in order, and returns its rows as text.
+fn query<A: SqliteApi>(
+    api: &A,
+    db: *mut Sqlite3,
+    sql: &core::ffi::CStr,
+    params: &[&str],
+    columns: c_int,
+) -> Result<Vec<Vec<String>>, c_int> {
+    let mut stmt = null_mut::<Sqlite3Stmt>();
+    let rc = api.prepare(db, sql, &mut stmt);
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+    for (index, param) in params.iter().enumerate() {
+        let rc = api.bind_text(stmt, index as c_int + 1, param);
+        if rc != SQLITE_OK {
+            api.finalize(stmt);
+            return Err(rc);
+        }
+    }
+    let mut rows = Vec::new();
+    while api.step(stmt) == SQLITE_ROW {
+        rows.push((0..columns).map(|i| api.column_text(stmt, i)).collect());
+    }
+    // Errors of the steps are returned again by finalize.
+    match api.finalize(stmt) {
+        SQLITE_OK => Ok(rows),
+        rc => Err(rc),
+    }
+}
+
+/// Writes the words of the database's `table`, with the columns of
+/// `lindera_userdict`, to the file of the connection, or removes the file if
+/// there are none, and returns how many there are. Tokenizers are rebuilt
+/// when the file changes.
+pub(crate) fn compile_user_dictionary<A: SqliteApi>(
+    api: &A,
+    db: *mut Sqlite3,
+    table: &str,
+) -> Result<usize, c_int> {
+    let path = user_dictionary_path(db, table);
+    let sql = CString::new(format!(
+        "SELECT surface, part_of_speech, reading FROM {} ORDER BY surface",
+        quote_identifier(table)
+    ))
+    .map_err(|_| SQLITE_ERROR)?;
+    // Most databases have no `lindera_userdict` table.
+    let rows = query(api, db, &sql, &[], 3).unwrap_or_default();
+    if rows.is_empty() {
+        if fs::remove_file(&path).is_ok() {
+            reload_all();
+        }
+        return Ok(0);
+    }
+
+    let csv = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if fs::read_to_string(&path).ok().as_deref() != Some(csv.as_str()) {
+        fs::write(&path, csv).map_err(|e| {
+            report_error(
+                SQLITE_IOERR,
+                format_args!("Failed to write the user dictionary {:?}: {}", path, e),
+            );
+            SQLITE_IOERR
+        })?;
+        // Tokenizers only read the file when they are built.
+        reload_all();
+    }
+    Ok(rows.len())
+}
+
+/// The connection of the running function.
+fn connection<A: SqliteApi>(api: &A, ctx: *mut Sqlite3Context) -> Option<*mut Sqlite3> {
+    let context = unsafe { &*(api.user_data(ctx) as *const TokenizerContext) };
+    context.db()
+}
+
+/// The text of the function's arguments, or `None` if one is `NULL`.
+fn text_args<A: SqliteApi>(
+    api: &A,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) -> Option<Vec<String>> {
+    if argv.is_null() {
+        return Some(Vec::new());
+    }
+    let values = unsafe { core::slice::from_raw_parts(argv, argc.max(0) as usize) };
+    values
+        .iter()
+        .map(|value| (api.value_type(*value) != SQLITE_NULL).then(|| api.value_text(*value)))
+        .collect()
+}
+
+/// `lindera_userdict_add(surface, reading, part_of_speech)` adds a word to
+/// the database's user dictionary, or replaces the one with the same
+/// surface, and returns 1, or `NULL` if an argument is `NULL` or empty or
+/// the word cannot be stored. It applies from `lindera_userdict_compile()`.
+pub(crate) extern "C" fn lindera_userdict_add_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) {
+    let Some(api) = A::get() else {
+        return;
+    };
+    let Some(db) = connection(api, ctx) else {
+        return api.result_null(ctx);
+    };
+    let Some(args) = text_args(api, argc, argv).filter(|args| args.len() == 3) else {
+        return api.result_null(ctx);
+    };
+    if args.iter().any(|arg| arg.trim().is_empty()) {
+        report_error(
+            SQLITE_ERROR,
+            format_args!("lindera_userdict_add() needs a surface, a reading and a part of speech"),
+        );
+        return api.result_null(ctx);
+    }
+
+    let result = query(
+        api,
+        db,
+        c"CREATE TABLE IF NOT EXISTS lindera_userdict(surface TEXT PRIMARY KEY, reading TEXT NOT NULL, part_of_speech TEXT NOT NULL)",
+        &[],
+        0,
+    )
+    .and_then(|_| {
+        query(
+            api,
+            db,
+            c"INSERT OR REPLACE INTO lindera_userdict(surface, reading, part_of_speech) VALUES (?1, ?2, ?3)",
+            &[&args[0], &args[1], &args[2]],
+            0,
+        )
+    });
+    match result {
+        Ok(_) => api.result_int64(ctx, 1),
+        Err(rc) => {
+            report_error(
+                rc,
+                format_args!("Failed to add {:?} to lindera_userdict", args[0]),
+            );
+            api.result_null(ctx);
+        }
+    }
+}
+
+/// `lindera_userdict_remove(surface)` removes a word from the database's
+/// user dictionary and returns how many words it removed, 1 or 0, or `NULL`
+/// for `NULL`. It applies from `lindera_userdict_compile()`.
+pub(crate) extern "C" fn lindera_userdict_remove_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) {
+    let Some(api) = A::get() else {
+        return;
+    };
+    let Some(db) = connection(api, ctx) else {
+        return api.result_null(ctx);
+    };
+    let Some(args) = text_args(api, argc, argv).filter(|args| args.len() == 1) else {
+        return api.result_null(ctx);
+    };
+
+    let surface = args[0].as_str();
+    let removed = query(
+        api,
+        db,
+        c"SELECT count(*) FROM lindera_userdict WHERE surface = ?1",
+        &[surface],
+        1,
+    )
+    .and_then(|rows| {
+        query(
+            api,
+            db,
+            c"DELETE FROM lindera_userdict WHERE surface = ?1",
+            &[surface],
+            0,
+        )?;
+        Ok(rows
+            .first()
+            .and_then(|row| row[0].parse::<i64>().ok())
+            .unwrap_or(0))
+    });
+    match removed {
+        Ok(removed) => api.result_int64(ctx, removed),
+        // Without a dictionary, there is nothing to remove.
+        Err(_) => api.result_int64(ctx, 0),
+    }
+}
+
+/// `lindera_userdict_compile()` applies the database's user dictionary to
+/// the tokenizers of the connection and returns how many words it has, or
+/// `NULL` if it cannot be written.
+pub(crate) extern "C" fn lindera_userdict_compile_func<A: SqliteApi>(
+    ctx: *mut Sqlite3Context,
+    _argc: c_int,
+    _argv: *mut *mut Sqlite3Value,
+) {
+    let Some(api) = A::get() else {
+        return;
+    };
+    let Some(db) = connection(api, ctx) else {
+        return api.result_null(ctx);
+    };
+
+    match compile_user_dictionary(api, db, USER_DICTIONARY_TABLE) {
+        Ok(words) => api.result_int64(ctx, i64::try_from(words).unwrap_or(i64::MAX)),
+        Err(_) => api.result_null(ctx),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_quotes_csv_fields() {
+        assert_eq!(csv_field("東京スカイツリー"), "東京スカイツリー");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
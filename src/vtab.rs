@@ -0,0 +1,431 @@
+use core::ffi::CStr;
+use core::ptr::null_mut;
+
+use libc::{c_char, c_int, c_uchar, c_void};
+
+use crate::common::*;
+use crate::extension::{Sqlite3, Sqlite3Context, Sqlite3Value, SqliteApi};
+
+// sqlite3.h
+const SQLITE_INDEX_CONSTRAINT_EQ: c_uchar = 2;
+const SQLITE_INTEGER: c_int = 1;
+const SQLITE_FLOAT: c_int = 2;
+pub(crate) const SQLITE_NULL: c_int = 5;
+
+#[repr(C)]
+pub(crate) struct Sqlite3Module {
+    i_version: c_int,
+    x_create: Option<XConnect>,
+    x_connect: Option<XConnect>,
+    x_best_index:
+        Option<extern "C" fn(vtab: *mut Sqlite3Vtab, info: *mut Sqlite3IndexInfo) -> c_int>,
+    x_disconnect: Option<extern "C" fn(vtab: *mut Sqlite3Vtab) -> c_int>,
+    x_destroy: Option<extern "C" fn(vtab: *mut Sqlite3Vtab) -> c_int>,
+    x_open:
+        Option<extern "C" fn(vtab: *mut Sqlite3Vtab, cursor: *mut *mut Sqlite3VtabCursor) -> c_int>,
+    x_close: Option<extern "C" fn(cursor: *mut Sqlite3VtabCursor) -> c_int>,
+    x_filter: Option<
+        extern "C" fn(
+            cursor: *mut Sqlite3VtabCursor,
+            idx_num: c_int,
+            idx_str: *const c_char,
+            argc: c_int,
+            argv: *mut *mut Sqlite3Value,
+        ) -> c_int,
+    >,
+    x_next: Option<extern "C" fn(cursor: *mut Sqlite3VtabCursor) -> c_int>,
+    x_eof: Option<extern "C" fn(cursor: *mut Sqlite3VtabCursor) -> c_int>,
+    x_column: Option<
+        extern "C" fn(cursor: *mut Sqlite3VtabCursor, ctx: *mut Sqlite3Context, i: c_int) -> c_int,
+    >,
+    x_rowid: Option<extern "C" fn(cursor: *mut Sqlite3VtabCursor, rowid: *mut i64) -> c_int>,
+    x_update: Option<extern "C" fn()>,
+    x_begin: Option<extern "C" fn()>,
+    x_sync: Option<extern "C" fn()>,
+    x_commit: Option<extern "C" fn()>,
+    x_rollback: Option<extern "C" fn()>,
+    x_find_function: Option<extern "C" fn()>,
+    x_rename: Option<extern "C" fn()>,
+}
+
+type XConnect = extern "C" fn(
+    db: *mut Sqlite3,
+    p_aux: *mut c_void,
+    argc: c_int,
+    argv: *const *const c_char,
+    pp_vtab: *mut *mut Sqlite3Vtab,
+    pz_err: *mut *mut c_char,
+) -> c_int;
+
+#[repr(C)]
+pub(crate) struct Sqlite3Vtab {
+    p_module: *const Sqlite3Module,
+    n_ref: c_int,
+    z_err_msg: *mut c_char,
+}
+
+#[repr(C)]
+pub(crate) struct Sqlite3VtabCursor {
+    p_vtab: *mut Sqlite3Vtab,
+}
+
+#[repr(C)]
+struct Sqlite3IndexConstraint {
+    i_column: c_int,
+    op: c_uchar,
+    usable: c_uchar,
+    i_term_offset: c_int,
+}
+
+#[repr(C)]
+struct Sqlite3IndexConstraintUsage {
+    argv_index: c_int,
+    omit: c_uchar,
+}
+
+#[repr(C)]
+pub(crate) struct Sqlite3IndexInfo {
+    n_constraint: c_int,
+    a_constraint: *const Sqlite3IndexConstraint,
+    n_order_by: c_int,
+    a_order_by: *const c_void,
+    a_constraint_usage: *mut Sqlite3IndexConstraintUsage,
+    idx_num: c_int,
+    idx_str: *mut c_char,
+    need_to_free_idx_str: c_int,
+    order_by_consumed: c_int,
+    estimated_cost: f64,
+}
+
+/// A value read from or returned to SQLite.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SqlValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+impl SqlValue {
+    fn read<A: SqliteApi>(api: &A, value: *mut Sqlite3Value) -> Self {
+        match api.value_type(value) {
+            SQLITE_NULL => SqlValue::Null,
+            SQLITE_INTEGER => SqlValue::Integer(api.value_int64(value)),
+            SQLITE_FLOAT => SqlValue::Real(api.value_double(value)),
+            _ => SqlValue::Text(api.value_text(value)),
+        }
+    }
+
+    fn result<A: SqliteApi>(&self, api: &A, ctx: *mut Sqlite3Context) {
+        match self {
+            SqlValue::Null => api.result_null(ctx),
+            SqlValue::Integer(value) => api.result_int64(ctx, *value),
+            SqlValue::Real(value) => api.result_double(ctx, *value),
+            SqlValue::Text(value) => api.result_text(ctx, value),
+        }
+    }
+
+    pub(crate) fn as_text(&self) -> Option<&str> {
+        match self {
+            SqlValue::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_integer(&self) -> Option<i64> {
+        match self {
+            SqlValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// An eponymous table-valued function such as `SELECT * FROM name(arg, ...)`.
+///
+/// The declared table lists the output columns first, followed by one
+/// column per argument. Argument columns are usually `HIDDEN`; one that is
+/// not, like `surface` in `lindera_dict`, shows the argument in each row.
+pub(crate) trait TableFunction: 'static {
+    /// What the function is registered with, kept for as long as the
+    /// connection.
+    type Context: 'static;
+
+    /// The `CREATE TABLE` statement passed to `sqlite3_declare_vtab`.
+    const SCHEMA: &'static CStr;
+    /// The number of output columns.
+    const COLUMNS: usize;
+    /// The number of arguments. Arguments that are not given are `Null`.
+    const ARGUMENTS: usize;
+
+    fn rows<A: SqliteApi>(
+        api: &A,
+        db: *mut Sqlite3,
+        context: &Self::Context,
+        args: &[SqlValue],
+    ) -> Result<Vec<Vec<SqlValue>>, c_int>;
+}
+
+/// The module of a table function, followed by its context.
+#[repr(C)]
+struct TableFunctionModule<T: TableFunction> {
+    base: Sqlite3Module,
+    context: T::Context,
+}
+
+#[repr(C)]
+struct TableFunctionVtab {
+    base: Sqlite3Vtab,
+    db: *mut Sqlite3,
+    /// The `TableFunctionModule` the table was connected through.
+    module: *const c_void,
+}
+
+#[repr(C)]
+struct TableFunctionCursor {
+    base: Sqlite3VtabCursor,
+    args: Vec<SqlValue>,
+    rows: Vec<Vec<SqlValue>>,
+    index: usize,
+}
+
+/// Registers `T` as the eponymous virtual table `name`, with `context`.
+pub(crate) fn register_table_function<A: SqliteApi, T: TableFunction>(
+    db: *mut Sqlite3,
+    api: &A,
+    name: &CStr,
+    context: T::Context,
+) -> Result<(), c_int> {
+    let base = Sqlite3Module {
+        i_version: 0,
+        x_create: None,
+        x_connect: Some(x_connect::<A, T>),
+        x_best_index: Some(x_best_index::<T>),
+        x_disconnect: Some(x_disconnect),
+        x_destroy: None,
+        x_open: Some(x_open),
+        x_close: Some(x_close),
+        x_filter: Some(x_filter::<A, T>),
+        x_next: Some(x_next),
+        x_eof: Some(x_eof),
+        x_column: Some(x_column::<A, T>),
+        x_rowid: Some(x_rowid),
+        x_update: None,
+        x_begin: None,
+        x_sync: None,
+        x_commit: None,
+        x_rollback: None,
+        x_find_function: None,
+        x_rename: None,
+    };
+    let module = Box::into_raw(Box::new(TableFunctionModule::<T> { base, context }));
+
+    // SQLite calls `destroy_module` when the connection closes, including
+    // when registration itself fails.
+    let rc = api.create_module(
+        db,
+        name,
+        module as *const Sqlite3Module,
+        module as *mut c_void,
+        destroy_module::<T>,
+    );
+    if rc != SQLITE_OK {
+        return Err(rc);
+    }
+
+    Ok(())
+}
+
+extern "C" fn destroy_module<T: TableFunction>(module: *mut c_void) {
+    drop(unsafe { Box::from_raw(module as *mut TableFunctionModule<T>) });
+}
+
+extern "C" fn x_connect<A: SqliteApi, T: TableFunction>(
+    db: *mut Sqlite3,
+    p_aux: *mut c_void,
+    _argc: c_int,
+    _argv: *const *const c_char,
+    pp_vtab: *mut *mut Sqlite3Vtab,
+    _pz_err: *mut *mut c_char,
+) -> c_int {
+    let Some(api) = A::get() else {
+        return SQLITE_INTERNAL;
+    };
+    let rc = api.declare_vtab(db, T::SCHEMA);
+    if rc != SQLITE_OK {
+        return rc;
+    }
+
+    let vtab = Box::new(TableFunctionVtab {
+        base: Sqlite3Vtab {
+            p_module: core::ptr::null(),
+            n_ref: 0,
+            z_err_msg: null_mut(),
+        },
+        db,
+        module: p_aux,
+    });
+    unsafe {
+        *pp_vtab = Box::into_raw(vtab) as *mut Sqlite3Vtab;
+    }
+
+    SQLITE_OK
+}
+
+extern "C" fn x_disconnect(vtab: *mut Sqlite3Vtab) -> c_int {
+    drop(unsafe { Box::from_raw(vtab as *mut TableFunctionVtab) });
+    SQLITE_OK
+}
+
+/// Passes every argument given as `arg = value` to `x_filter`, in argument
+/// order, and records which ones were given in `idx_num`.
+extern "C" fn x_best_index<T: TableFunction>(
+    _vtab: *mut Sqlite3Vtab,
+    info: *mut Sqlite3IndexInfo,
+) -> c_int {
+    let info = unsafe { &mut *info };
+    let constraints =
+        unsafe { core::slice::from_raw_parts(info.a_constraint, info.n_constraint as usize) };
+    let usages = unsafe {
+        core::slice::from_raw_parts_mut(info.a_constraint_usage, info.n_constraint as usize)
+    };
+
+    let mut arguments = vec![None; T::ARGUMENTS];
+    let mut unusable = 0;
+    for (i, constraint) in constraints.iter().enumerate() {
+        if constraint.i_column < 0 {
+            continue;
+        }
+        let Some(argument) = (constraint.i_column as usize).checked_sub(T::COLUMNS) else {
+            continue;
+        };
+        if argument >= T::ARGUMENTS {
+            continue;
+        }
+        if constraint.usable == 0 {
+            unusable |= 1 << argument;
+        } else if constraint.op == SQLITE_INDEX_CONSTRAINT_EQ {
+            arguments[argument] = Some(i);
+        }
+    }
+
+    let mut idx_num = 0;
+    let mut argv_index = 0;
+    for (argument, constraint) in arguments.into_iter().enumerate() {
+        if let Some(i) = constraint {
+            argv_index += 1;
+            usages[i].argv_index = argv_index;
+            usages[i].omit = 1;
+            idx_num |= 1 << argument;
+        }
+    }
+    if unusable & !idx_num != 0 {
+        // The argument is only available to a plan that runs this table later.
+        return SQLITE_CONSTRAINT;
+    }
+    info.idx_num = idx_num;
+    info.estimated_cost = 1.0;
+
+    SQLITE_OK
+}
+
+extern "C" fn x_open(_vtab: *mut Sqlite3Vtab, cursor: *mut *mut Sqlite3VtabCursor) -> c_int {
+    let table_cursor = Box::new(TableFunctionCursor {
+        base: Sqlite3VtabCursor { p_vtab: null_mut() },
+        args: Vec::new(),
+        rows: Vec::new(),
+        index: 0,
+    });
+    unsafe {
+        *cursor = Box::into_raw(table_cursor) as *mut Sqlite3VtabCursor;
+    }
+
+    SQLITE_OK
+}
+
+extern "C" fn x_close(cursor: *mut Sqlite3VtabCursor) -> c_int {
+    drop(unsafe { Box::from_raw(cursor as *mut TableFunctionCursor) });
+    SQLITE_OK
+}
+
+extern "C" fn x_filter<A: SqliteApi, T: TableFunction>(
+    cursor: *mut Sqlite3VtabCursor,
+    idx_num: c_int,
+    _idx_str: *const c_char,
+    argc: c_int,
+    argv: *mut *mut Sqlite3Value,
+) -> c_int {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let Some(api) = A::get() else {
+            return SQLITE_INTERNAL;
+        };
+        let cursor = unsafe { &mut *(cursor as *mut TableFunctionCursor) };
+        let vtab = unsafe { &*(cursor.base.p_vtab as *mut TableFunctionVtab) };
+        let module = unsafe { &*(vtab.module as *const TableFunctionModule<T>) };
+        let values = match argc {
+            0 => &[][..],
+            _ => unsafe { core::slice::from_raw_parts(argv, argc as usize) },
+        };
+
+        let mut values = values.iter();
+        cursor.args = (0..T::ARGUMENTS)
+            .map(|argument| match idx_num & (1 << argument) {
+                0 => SqlValue::Null,
+                _ => values
+                    .next()
+                    .map_or(SqlValue::Null, |&value| SqlValue::read(api, value)),
+            })
+            .collect();
+        cursor.index = 0;
+        cursor.rows = match T::rows(api, vtab.db, &module.context, &cursor.args) {
+            Ok(rows) => rows,
+            Err(code) => return code,
+        };
+
+        SQLITE_OK
+    }))
+    .unwrap_or_else(|_| {
+        report_error(
+            SQLITE_INTERNAL,
+            format_args!("Table function panicked while filtering"),
+        );
+        SQLITE_INTERNAL
+    })
+}
+
+extern "C" fn x_next(cursor: *mut Sqlite3VtabCursor) -> c_int {
+    let cursor = unsafe { &mut *(cursor as *mut TableFunctionCursor) };
+    cursor.index += 1;
+    SQLITE_OK
+}
+
+extern "C" fn x_eof(cursor: *mut Sqlite3VtabCursor) -> c_int {
+    let cursor = unsafe { &*(cursor as *mut TableFunctionCursor) };
+    (cursor.index >= cursor.rows.len()) as c_int
+}
+
+extern "C" fn x_column<A: SqliteApi, T: TableFunction>(
+    cursor: *mut Sqlite3VtabCursor,
+    ctx: *mut Sqlite3Context,
+    i: c_int,
+) -> c_int {
+    let Some(api) = A::get() else {
+        return SQLITE_INTERNAL;
+    };
+    let cursor = unsafe { &*(cursor as *mut TableFunctionCursor) };
+    let i = i as usize;
+    let value = match i.checked_sub(T::COLUMNS) {
+        None => cursor.rows[cursor.index].get(i),
+        Some(argument) => cursor.args.get(argument),
+    };
+    value.unwrap_or(&SqlValue::Null).result(api, ctx);
+
+    SQLITE_OK
+}
+
+extern "C" fn x_rowid(cursor: *mut Sqlite3VtabCursor, rowid: *mut i64) -> c_int {
+    let cursor = unsafe { &*(cursor as *mut TableFunctionCursor) };
+    unsafe {
+        *rowid = cursor.index as i64 + 1;
+    }
+    SQLITE_OK
+}
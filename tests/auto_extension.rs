@@ -0,0 +1,29 @@
+//! Checks that `register_auto_extension` makes the tokenizer available on
+//! new connections. Automatic extensions apply to every connection of the
+//! process, so this runs in its own.
+#![cfg(all(feature = "rusqlite", feature = "ipadic"))]
+
+use lindera_sqlite::connection_extension::register_auto_extension;
+use rusqlite::Connection;
+
+#[test]
+fn it_registers_the_tokenizer_on_new_connections() {
+    register_auto_extension().unwrap();
+    register_auto_extension().unwrap();
+
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE example USING fts5(content, tokenize='lindera_tokenizer');
+         INSERT INTO example(content) VALUES ('ユーザー辞書も利用可能です。');",
+    )
+    .unwrap();
+
+    let count: i64 = conn
+        .query_row(
+            "SELECT count(*) FROM example WHERE content MATCH '辞書'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(count, 1);
+}
@@ -0,0 +1,274 @@
+//! Drives the tokenizer through in-memory copies of FTS5's `fts5_api` and
+//! `fts5_tokenizer`, the way FTS5 itself would, without linking SQLite.
+//!
+//! The leak check counts allocations with its own global allocator, which
+//! `memory-stats-allocator` would replace.
+#![cfg(all(
+    feature = "extension",
+    feature = "ipadic",
+    not(feature = "memory-stats-allocator")
+))]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::ffi::{CStr, CString};
+use std::ptr::null_mut;
+
+use libc::{c_char, c_int, c_uchar, c_void};
+use lindera_sqlite::{
+    fts5_create_lindera_tokenizer, fts5_delete_lindera_tokenizer, lindera_fts5_tokenize,
+    Fts5Tokenizer, TokenFunction, SQLITE_OK,
+};
+
+// fts5.h
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Fts5TokenizerApi {
+    x_create: extern "C" fn(
+        p_context: *mut c_void,
+        az_arg: *const *const c_uchar,
+        n_arg: c_int,
+        fts5_tokenizer: *mut *mut Fts5Tokenizer,
+    ) -> c_int,
+    x_delete: extern "C" fn(fts5_tokenizer: *mut Fts5Tokenizer),
+    x_tokenize: extern "C" fn(
+        tokenizer: *mut Fts5Tokenizer,
+        p_ctx: *mut c_void,
+        flags: c_int,
+        p_text: *const c_char,
+        n_text: c_int,
+        x_token: TokenFunction,
+    ) -> c_int,
+}
+
+#[repr(C)]
+struct Fts5Api {
+    i_version: c_int,
+    x_create_tokenizer: extern "C" fn(
+        fts5_api: *const Fts5Api,
+        z_name: *const c_uchar,
+        p_context: *mut c_void,
+        fts5_tokenizer: *mut Fts5TokenizerApi,
+        x_destroy: extern "C" fn(module: *mut c_void),
+    ) -> c_int,
+}
+
+struct RegisteredTokenizer {
+    name: CString,
+    context: *mut c_void,
+    api: Fts5TokenizerApi,
+    destroy: extern "C" fn(module: *mut c_void),
+}
+
+/// An `fts5_api` that keeps registered tokenizers in memory. `fts5_api` must
+/// stay the first field, as callbacks cast its address back to the harness.
+#[repr(C)]
+struct SimulatedFts5 {
+    fts5_api: Fts5Api,
+    tokenizers: Vec<RegisteredTokenizer>,
+}
+
+extern "C" fn create_tokenizer(
+    fts5_api: *const Fts5Api,
+    z_name: *const c_uchar,
+    p_context: *mut c_void,
+    fts5_tokenizer: *mut Fts5TokenizerApi,
+    x_destroy: extern "C" fn(module: *mut c_void),
+) -> c_int {
+    let fts5 = unsafe { &mut *(fts5_api as *mut SimulatedFts5) };
+    fts5.tokenizers.push(RegisteredTokenizer {
+        name: unsafe { CStr::from_ptr(z_name.cast()) }.to_owned(),
+        context: p_context,
+        api: unsafe { *fts5_tokenizer },
+        destroy: x_destroy,
+    });
+    SQLITE_OK
+}
+
+extern "C" fn destroy_nothing(_module: *mut c_void) {}
+
+extern "C" fn collect_token(
+    ctx: *mut c_void,
+    _flags: c_int,
+    token: *const c_char,
+    token_len: c_int,
+    start: c_int,
+    end: c_int,
+) -> c_int {
+    let tokens = unsafe { &mut *(ctx as *mut Vec<(String, c_int, c_int)>) };
+    let token = unsafe { std::slice::from_raw_parts(token as *const c_uchar, token_len as usize) };
+    tokens.push((String::from_utf8_lossy(token).into_owned(), start, end));
+    SQLITE_OK
+}
+
+impl SimulatedFts5 {
+    fn new() -> Box<Self> {
+        Box::new(SimulatedFts5 {
+            fts5_api: Fts5Api {
+                i_version: 2,
+                x_create_tokenizer: create_tokenizer,
+            },
+            tokenizers: Vec::new(),
+        })
+    }
+
+    /// Registers the Lindera tokenizer under `name`, as
+    /// `lindera_fts5_tokenizer_init` does.
+    fn register_lindera(&mut self, name: &CStr) -> c_int {
+        let mut api = Fts5TokenizerApi {
+            x_create: fts5_create_lindera_tokenizer,
+            x_delete: fts5_delete_lindera_tokenizer,
+            x_tokenize: lindera_fts5_tokenize,
+        };
+        let fts5_api = &self.fts5_api as *const Fts5Api;
+        (self.fts5_api.x_create_tokenizer)(
+            fts5_api,
+            name.as_ptr().cast(),
+            null_mut(),
+            &mut api,
+            destroy_nothing,
+        )
+    }
+
+    /// Creates the tokenizer `name` with `args`, tokenizes `text` with it and
+    /// deletes it, as FTS5 does for a table's lifetime.
+    fn tokenize(
+        &self,
+        name: &CStr,
+        args: &[&CStr],
+        text: &str,
+    ) -> Result<Vec<(String, c_int, c_int)>, c_int> {
+        let registered = self
+            .tokenizers
+            .iter()
+            .find(|tokenizer| tokenizer.name.as_c_str() == name)
+            .expect("tokenizer is registered");
+        let args = args
+            .iter()
+            .map(|arg| arg.as_ptr().cast())
+            .collect::<Vec<_>>();
+
+        let mut tokenizer = null_mut();
+        let rc = (registered.api.x_create)(
+            registered.context,
+            args.as_ptr(),
+            args.len() as c_int,
+            &mut tokenizer,
+        );
+        if rc != SQLITE_OK {
+            return Err(rc);
+        }
+
+        let mut tokens = Vec::new();
+        let rc = (registered.api.x_tokenize)(
+            tokenizer,
+            &mut tokens as *mut _ as *mut c_void,
+            0,
+            text.as_ptr().cast(),
+            text.len() as c_int,
+            collect_token,
+        );
+        (registered.api.x_delete)(tokenizer);
+        if rc != SQLITE_OK {
+            return Err(rc);
+        }
+
+        Ok(tokens)
+    }
+}
+
+impl Drop for SimulatedFts5 {
+    fn drop(&mut self) {
+        for tokenizer in &self.tokenizers {
+            (tokenizer.destroy)(tokenizer.context);
+        }
+    }
+}
+
+/// Counts the bytes each thread holds, so that tests running in parallel do
+/// not see each other's allocations.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+}
+
+fn record(size: isize) {
+    // `try_with` because the allocator may run while thread-locals are torn
+    // down.
+    let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + size));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record(-(layout.size() as isize));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record(new_size as isize - layout.size() as isize);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[test]
+fn it_tokenizes_through_simulated_fts5() {
+    let mut fts5 = SimulatedFts5::new();
+    assert_eq!(fts5.register_lindera(c"lindera_tokenizer"), SQLITE_OK);
+
+    let tokens = fts5
+        .tokenize(
+            c"lindera_tokenizer",
+            &[c"ja", c"normalize"],
+            "Ｌｉｎｄｅｒａは形態素解析エンジンです。",
+        )
+        .unwrap();
+    let texts = tokens
+        .iter()
+        .map(|(token, _, _)| token.as_str())
+        .collect::<Vec<_>>();
+    assert!(texts.contains(&"Lindera"));
+    assert!(texts.contains(&"形態素"));
+    assert_eq!(tokens[0], ("Lindera".to_string(), 0, 21));
+}
+
+#[test]
+fn it_rejects_invalid_arguments_through_simulated_fts5() {
+    let mut fts5 = SimulatedFts5::new();
+    assert_eq!(fts5.register_lindera(c"lindera_tokenizer"), SQLITE_OK);
+
+    assert!(fts5
+        .tokenize(c"lindera_tokenizer", &[c"lang=fr"], "text")
+        .is_err());
+}
+
+#[test]
+fn it_frees_everything_it_creates() {
+    let mut fts5 = SimulatedFts5::new();
+    assert_eq!(fts5.register_lindera(c"lindera_tokenizer"), SQLITE_OK);
+    let text = "Ｌｉｎｄｅｒａは形態素解析エンジンです。";
+
+    // The first tokenizer loads the dictionary into the shared cache, which
+    // keeps it for later tables.
+    fts5.tokenize(c"lindera_tokenizer", &[c"ja"], text).unwrap();
+
+    let before = ALLOCATED.with(Cell::get);
+    for _ in 0..10 {
+        fts5.tokenize(c"lindera_tokenizer", &[c"ja"], text).unwrap();
+    }
+    assert_eq!(ALLOCATED.with(Cell::get), before);
+}
@@ -0,0 +1,36 @@
+//! Checks that errors reach SQLite's error log. The log callback can only be
+//! configured before SQLite initializes, so this runs in its own process.
+#![cfg(all(feature = "rusqlite", feature = "ipadic"))]
+
+use std::sync::Mutex;
+
+use libc::c_int;
+use lindera_sqlite::connection_extension::LinderaConnectionExt;
+use lindera_sqlite::SQLITE_ERROR;
+use rusqlite::Connection;
+
+static MESSAGES: Mutex<Vec<(c_int, String)>> = Mutex::new(Vec::new());
+
+fn record(code: c_int, message: &str) {
+    MESSAGES.lock().unwrap().push((code, message.to_string()));
+}
+
+#[test]
+fn it_reports_errors_to_the_sqlite_log() {
+    unsafe { rusqlite::trace::config_log(Some(record)) }.unwrap();
+
+    let conn = Connection::open_in_memory().unwrap();
+    conn.load_lindera_tokenizer().unwrap();
+    assert!(conn
+        .execute_batch(
+            "CREATE VIRTUAL TABLE example USING fts5(content, tokenize = 'lindera_tokenizer config_name missing');",
+        )
+        .is_err());
+
+    let messages = MESSAGES.lock().unwrap();
+    assert!(messages.iter().any(|(code, message)| {
+        *code == SQLITE_ERROR
+            && message.starts_with("Failed to create tokenizer: ")
+            && message.contains("config_name")
+    }));
+}